@@ -0,0 +1,174 @@
+//! # Band-limited Resampling
+//!
+//! The APU's output changes level at CPU clock timestamps, far above any
+//! output sample rate. Sampling that directly (or averaging it) aliases
+//! the harmonics of high pitched square waves back into the audible
+//! range. Instead, like [blip_buf], each change in level is added to the
+//! output as a band-limited step: a windowed sinc impulse, positioned
+//! with sub-sample accuracy, which is integrated when samples are read.
+//!
+//! [blip_buf]: http://slack.net/~ant/libs/audio.html#Blip_Buffer
+
+use std::f64::consts::PI;
+
+/// Width of the impulse in output samples
+const KERNEL_WIDTH: usize = 16;
+
+/// Number of sub-sample positions the impulse is pre-computed for
+const KERNEL_PHASES: usize = 64;
+
+/// Fraction of the Nyquist frequency to pass through, leaving room for
+/// the kernel's roll off so that it doesn't alias
+const CUTOFF: f64 = 0.9;
+
+pub struct BlipBuffer {
+  /// Output samples per clock
+  factor: f64,
+
+  /// Position (in output samples) of the start of the current frame
+  offset: f64,
+
+  /// Differences between each output sample and the previous one
+  deltas: Vec<f32>,
+
+  /// Running total of the deltas read so far, ie, the last output sample
+  level: f32,
+
+  kernel: Vec<[f32; KERNEL_WIDTH]>,
+}
+
+impl BlipBuffer {
+  /// Create a buffer converting from a clock (eg, the CPU's) to an output
+  /// sample rate
+  pub fn new(clock_rate: u32, sample_rate: u32) -> Self {
+    BlipBuffer {
+      factor: f64::from(sample_rate) / f64::from(clock_rate),
+      offset: 0.0,
+      deltas: vec![0.0; KERNEL_WIDTH],
+      level: 0.0,
+      kernel: (0..KERNEL_PHASES).map(kernel_phase).collect(),
+    }
+  }
+
+  /// Add a change in level at a number of clocks into the current frame
+  pub fn add_delta(&mut self, time: u32, delta: f32) {
+    let position = self.offset + f64::from(time) * self.factor;
+    let index = position as usize;
+    let phase = ((position - index as f64) * KERNEL_PHASES as f64) as usize;
+
+    if self.deltas.len() < index + KERNEL_WIDTH {
+      self.deltas.resize(index + KERNEL_WIDTH, 0.0);
+    }
+    let deltas = &mut self.deltas[index..index + KERNEL_WIDTH];
+    for (sample, weight) in deltas.iter_mut().zip(self.kernel[phase].iter()) {
+      *sample += delta * weight;
+    }
+  }
+
+  /// End the current frame after a number of clocks, making the samples
+  /// in it available. Later deltas are timed from the end of this frame.
+  pub fn end_frame(&mut self, clocks: u32) {
+    self.offset += f64::from(clocks) * self.factor;
+    let needed = self.offset as usize + KERNEL_WIDTH;
+    if self.deltas.len() < needed {
+      self.deltas.resize(needed, 0.0);
+    }
+  }
+
+  /// The number of samples which can be read
+  pub fn samples_available(&self) -> usize {
+    self.offset as usize
+  }
+
+  /// Read all the available samples
+  pub fn read_samples(&mut self, samples: &mut Vec<f32>) {
+    let count = self.samples_available();
+    for delta in self.deltas.drain(..count) {
+      self.level += delta;
+      samples.push(self.level);
+    }
+    self.offset -= count as f64;
+  }
+}
+
+/// A windowed sinc impulse (normalised to sum to 1) for a step which is
+/// `phase / KERNEL_PHASES` of the way between two samples
+fn kernel_phase(phase: usize) -> [f32; KERNEL_WIDTH] {
+  let mut kernel = [0.0; KERNEL_WIDTH];
+  let center = (KERNEL_WIDTH / 2) as f64 + phase as f64 / KERNEL_PHASES as f64;
+  for (i, weight) in kernel.iter_mut().enumerate() {
+    let x = i as f64 - center;
+    let sinc = if x == 0.0 {
+      1.0
+    } else {
+      (PI * CUTOFF * x).sin() / (PI * CUTOFF * x)
+    };
+    // Blackman window over the width of the kernel
+    let t = x / KERNEL_WIDTH as f64 + 0.5;
+    let window = if !(0.0..=1.0).contains(&t) {
+      0.0
+    } else {
+      0.42 - 0.5 * (2.0 * PI * t).cos() + 0.08 * (4.0 * PI * t).cos()
+    };
+    *weight = (sinc * window) as f32;
+  }
+
+  let sum: f32 = kernel.iter().sum();
+  for weight in kernel.iter_mut() {
+    *weight /= sum;
+  }
+  kernel
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const CLOCK_RATE: u32 = 1_789_773;
+  const SAMPLE_RATE: u32 = 48_000;
+
+  #[test]
+  fn produces_samples_at_sample_rate() {
+    let mut blip = BlipBuffer::new(CLOCK_RATE, SAMPLE_RATE);
+    let mut samples = vec![];
+    for _ in 0..60 {
+      blip.end_frame(CLOCK_RATE / 60);
+      blip.read_samples(&mut samples);
+    }
+    assert!((samples.len() as i32 - SAMPLE_RATE as i32).abs() <= 1);
+  }
+
+  #[test]
+  fn step_settles_at_new_level() {
+    let mut blip = BlipBuffer::new(CLOCK_RATE, SAMPLE_RATE);
+    blip.add_delta(100, 0.5);
+    blip.end_frame(CLOCK_RATE / 100);
+
+    let mut samples = vec![];
+    blip.read_samples(&mut samples);
+    assert!(samples[0].abs() < 0.01);
+    for sample in &samples[KERNEL_WIDTH + 2..] {
+      assert!((sample - 0.5).abs() < 1e-4, "{}", sample);
+    }
+  }
+
+  #[test]
+  fn ultrasonic_square_wave_does_not_alias() {
+    // A square wave at ~90kHz, above the output's Nyquist frequency,
+    // should be filtered down to its average level
+    let mut blip = BlipBuffer::new(CLOCK_RATE, SAMPLE_RATE);
+    let mut level = 0.0;
+    for time in (0..CLOCK_RATE / 10).step_by(10) {
+      let next = if level == 0.0 { 1.0 } else { 0.0 };
+      blip.add_delta(time, next - level);
+      level = next;
+    }
+    blip.end_frame(CLOCK_RATE / 10);
+
+    let mut samples = vec![];
+    blip.read_samples(&mut samples);
+    for sample in &samples[KERNEL_WIDTH * 2..] {
+      assert!((sample - 0.5).abs() < 0.1, "{}", sample);
+    }
+  }
+}
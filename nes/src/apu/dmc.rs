@@ -0,0 +1,250 @@
+//! # Delta Modulation Channel (DMC)
+//!
+//! Plays 1 bit delta encoded samples straight out of CPU memory, moving
+//! a 7 bit output level up or down by 2 for each bit. The sample bytes
+//! are fetched by DMA, which halts the CPU for a few cycles each time.
+//! The output level can also be written directly, which games use to
+//! play PCM audio. [Read more here][DMC].
+//!
+//!  Register | Legend    | Bits
+//! ----------|-----------|-------------------------------------------
+//!   $4010   | IL-- RRRR | IRQ enable, Loop, Rate index
+//!   $4011   | -DDD DDDD | Direct load of the output level
+//!   $4012   | AAAA AAAA | Sample address, $C000 + A * 64
+//!   $4013   | LLLL LLLL | Sample length, L * 16 + 1 bytes
+//!
+//! [DMC]: https://wiki.nesdev.com/w/index.php/APU_DMC
+
+/// Timer periods (in CPU cycles) indexed by the R bits
+const RATE_TABLE: [u16; 16] = [
+  428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+
+#[derive(Default)]
+pub struct Dmc {
+  irq_enabled: bool,
+  looping: bool,
+  rate: usize,
+  timer: u16,
+
+  /// Set when a non-looping sample finishes with IRQs enabled
+  interrupt: bool,
+
+  sample_address: u16,
+  sample_length: u16,
+
+  /// Memory reader
+  current_address: u16,
+  bytes_remaining: u16,
+  sample_buffer: Option<u8>,
+
+  /// Output unit
+  shift_register: u8,
+  bits_remaining: u8,
+  /// Clear while the output unit is silenced by an empty sample buffer
+  playing: bool,
+  level: u8,
+}
+
+impl Dmc {
+  /// Write one of the channel's 4 registers, where `register` is 0-3
+  pub fn write(&mut self, register: u16, value: u8) {
+    match register {
+      0 => {
+        self.irq_enabled = value & 0b1000_0000 != 0;
+        self.looping = value & 0b0100_0000 != 0;
+        self.rate = (value & 0b1111) as usize;
+        if !self.irq_enabled {
+          self.interrupt = false;
+        }
+      }
+      1 => self.level = value & 0b0111_1111,
+      2 => self.sample_address = 0xC000 | (u16::from(value) << 6),
+      3 => self.sample_length = (u16::from(value) << 4) + 1,
+      _ => unreachable!(),
+    }
+  }
+
+  /// Enable or disable the channel through $4015. Enabling restarts the
+  /// sample only if it had finished, and either way clears the interrupt.
+  pub fn set_enabled(&mut self, enabled: bool) {
+    self.interrupt = false;
+    if !enabled {
+      self.bytes_remaining = 0;
+    } else if self.bytes_remaining == 0 {
+      self.restart();
+    }
+  }
+
+  /// Whether there are still sample bytes left to read
+  pub fn is_active(&self) -> bool {
+    self.bytes_remaining > 0
+  }
+
+  /// Whether the DMC is asserting an IRQ
+  pub fn interrupt(&self) -> bool {
+    self.interrupt
+  }
+
+  /// The address of the next sample byte, when the sample buffer needs
+  /// filling by DMA
+  pub fn dma_address(&self) -> Option<u16> {
+    if self.sample_buffer.is_none() && self.bytes_remaining > 0 {
+      Some(self.current_address)
+    } else {
+      None
+    }
+  }
+
+  /// Fill the sample buffer with the byte read from `dma_address`
+  pub fn fill_sample_buffer(&mut self, value: u8) {
+    self.sample_buffer = Some(value);
+    self.current_address = self.current_address.checked_add(1).unwrap_or(0x8000);
+    self.bytes_remaining -= 1;
+
+    if self.bytes_remaining == 0 {
+      if self.looping {
+        self.restart();
+      } else if self.irq_enabled {
+        self.interrupt = true;
+      }
+    }
+  }
+
+  /// Clocked every CPU cycle
+  pub fn clock_timer(&mut self) {
+    if self.timer > 0 {
+      self.timer -= 1;
+      return;
+    }
+    self.timer = RATE_TABLE[self.rate] - 1;
+
+    if self.playing {
+      if self.shift_register & 1 != 0 {
+        if self.level <= 125 {
+          self.level += 2;
+        }
+      } else if self.level >= 2 {
+        self.level -= 2;
+      }
+    }
+    self.shift_register >>= 1;
+
+    if self.bits_remaining > 0 {
+      self.bits_remaining -= 1;
+    }
+    if self.bits_remaining == 0 {
+      self.bits_remaining = 8;
+      match self.sample_buffer.take() {
+        Some(sample) => {
+          self.playing = true;
+          self.shift_register = sample;
+        }
+        None => self.playing = false,
+      }
+    }
+  }
+
+  /// The current output level, from 0 to 127
+  pub fn output(&self) -> u8 {
+    self.level
+  }
+
+  fn restart(&mut self) {
+    self.current_address = self.sample_address;
+    self.bytes_remaining = self.sample_length;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Play a sample from memory, returning the levels after each bit
+  fn play(dmc: &mut Dmc, memory: &[u8], bits: usize) -> Vec<u8> {
+    let mut levels = vec![];
+    let mut cycles = 0;
+    while levels.len() < bits {
+      if let Some(addr) = dmc.dma_address() {
+        dmc.fill_sample_buffer(memory[(addr - 0xC000) as usize]);
+      }
+      dmc.clock_timer();
+      cycles += 1;
+      if cycles % 54 == 0 {
+        levels.push(dmc.output());
+      }
+    }
+    levels
+  }
+
+  fn sample_dmc(flags: u8) -> Dmc {
+    let mut dmc = Dmc::default();
+    dmc.write(0, flags | 0x0F);
+    dmc.write(1, 64);
+    dmc.write(2, 0x00);
+    dmc.write(3, 0x00);
+    dmc.set_enabled(true);
+    dmc
+  }
+
+  #[test]
+  fn register_values() {
+    let mut dmc = Dmc::default();
+    dmc.write(2, 0x01);
+    dmc.write(3, 0x02);
+    dmc.set_enabled(true);
+    assert_eq!(dmc.dma_address(), Some(0xC040));
+    assert_eq!(dmc.bytes_remaining, 33);
+  }
+
+  #[test]
+  fn direct_load() {
+    let mut dmc = Dmc::default();
+    dmc.write(1, 0xFF);
+    assert_eq!(dmc.output(), 0x7F);
+  }
+
+  #[test]
+  fn plays_deltas() {
+    let mut dmc = sample_dmc(0x00);
+    // Bits are played from the lowest, then the output holds once the
+    // sample has finished
+    let levels = play(&mut dmc, &[0b0000_1011], 10);
+    assert_eq!(levels, [64, 66, 68, 66, 68, 66, 64, 62, 60, 60]);
+    assert!(!dmc.is_active());
+  }
+
+  #[test]
+  fn interrupt_at_end_of_sample() {
+    let mut dmc = sample_dmc(0x80);
+    assert!(!dmc.interrupt());
+    play(&mut dmc, &[0x00], 1);
+    assert!(dmc.interrupt());
+
+    dmc.set_enabled(true);
+    assert!(!dmc.interrupt());
+  }
+
+  #[test]
+  fn loops_without_interrupt() {
+    let mut dmc = sample_dmc(0xC0);
+    play(&mut dmc, &[0xFF], 16);
+    assert!(dmc.is_active());
+    assert!(!dmc.interrupt());
+    assert_eq!(dmc.output(), 64 + 2 * 15);
+  }
+
+  #[test]
+  fn address_wraps_to_8000() {
+    let mut dmc = Dmc::default();
+    dmc.write(2, 0xFF);
+    dmc.write(3, 0xFF);
+    dmc.set_enabled(true);
+    assert_eq!(dmc.dma_address(), Some(0xFFC0));
+    for _ in 0..0x40 {
+      dmc.fill_sample_buffer(0x00);
+      dmc.sample_buffer = None;
+    }
+    assert_eq!(dmc.dma_address(), Some(0x8000));
+  }
+}
@@ -0,0 +1,113 @@
+//! # Envelope
+//!
+//! The pulse and noise channels either play at a constant volume, or at
+//! a volume which decays from 15 to 0, stepping down each time the
+//! envelope's divider counts down (clocked by quarter frames). With the
+//! loop flag set the decay starts over at 15. [Read more here][Env].
+//!
+//! [Env]: https://wiki.nesdev.com/w/index.php/APU_Envelope
+
+#[derive(Default)]
+pub struct Envelope {
+  /// Set by writes to the channel's length counter, restarting the decay
+  start: bool,
+  looping: bool,
+  constant_volume: bool,
+
+  /// The constant volume, or the period of the divider
+  volume: u8,
+  divider: u8,
+  decay: u8,
+}
+
+impl Envelope {
+  /// Write the `--LC VVVV` bits of the channel's first register
+  pub fn write(&mut self, value: u8) {
+    self.looping = value & 0b0010_0000 != 0;
+    self.constant_volume = value & 0b0001_0000 != 0;
+    self.volume = value & 0b0000_1111;
+  }
+
+  /// Restart the decay on the next quarter frame
+  pub fn restart(&mut self) {
+    self.start = true;
+  }
+
+  /// Clocked by the frame counter every quarter frame
+  pub fn clock(&mut self) {
+    if self.start {
+      self.start = false;
+      self.decay = 15;
+      self.divider = self.volume;
+    } else if self.divider == 0 {
+      self.divider = self.volume;
+      if self.decay > 0 {
+        self.decay -= 1;
+      } else if self.looping {
+        self.decay = 15;
+      }
+    } else {
+      self.divider -= 1;
+    }
+  }
+
+  /// The current volume, from 0 to 15
+  pub fn output(&self) -> u8 {
+    if self.constant_volume {
+      self.volume
+    } else {
+      self.decay
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn constant_volume() {
+    let mut envelope = Envelope::default();
+    envelope.write(0b0001_1010);
+    envelope.restart();
+    envelope.clock();
+    envelope.clock();
+    assert_eq!(envelope.output(), 10);
+  }
+
+  #[test]
+  fn decays_once_per_period() {
+    let mut envelope = Envelope::default();
+    envelope.write(0b0000_0001);
+    envelope.restart();
+    envelope.clock();
+    assert_eq!(envelope.output(), 15);
+
+    // The divider reloads with a period of 1, so it takes 2 clocks to decay
+    envelope.clock();
+    assert_eq!(envelope.output(), 15);
+    envelope.clock();
+    assert_eq!(envelope.output(), 14);
+
+    for _ in 0..28 {
+      envelope.clock();
+    }
+    assert_eq!(envelope.output(), 0);
+    envelope.clock();
+    envelope.clock();
+    assert_eq!(envelope.output(), 0);
+  }
+
+  #[test]
+  fn loops_back_to_15() {
+    let mut envelope = Envelope::default();
+    envelope.write(0b0010_0000);
+    envelope.restart();
+    for _ in 0..16 {
+      envelope.clock();
+    }
+    assert_eq!(envelope.output(), 0);
+    envelope.clock();
+    assert_eq!(envelope.output(), 15);
+  }
+}
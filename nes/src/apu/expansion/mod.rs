@@ -0,0 +1,28 @@
+//! # Expansion Audio
+//!
+//! Sound chips on the cartridge, whose output goes through the cartridge
+//! connector to be mixed with the APU's (see `Apu::set_expansion_audio`).
+//! Each is clocked once per CPU cycle by its mapper, which forwards
+//! writes to the chip's registers.
+//!
+//! Chips report the level of each of their voices separately, so they can
+//! be controlled in the mix like the APU's channels (see `VoiceLevels`).
+//!
+//!  Chip | Mappers | Channels                            | Voices
+//! ------|---------|-------------------------------------|--------------------
+//! VRC6  | 24, 26  | 2 pulses, sawtooth                  | Pulse 1, 2, saw
+//! VRC7  | 85      | 6 FM channels (an OPLL, see `opll`) | Channels 0-5
+//! N163  | 19      | Up to 8 wavetable channels          | Channels 7 down to 0
+//! 5B    | 69      | 3 squares, noise, envelope          | Channels A, B, C
+
+pub mod namco163;
+pub mod opll;
+pub mod sunsoft5b;
+pub mod vrc6;
+
+/// The most voices a chip has (the N163's 8)
+pub const MAX_VOICES: usize = 8;
+
+/// The level of each of a chip's voices, scaled to be mixed linearly with
+/// the APU's channels. Voices the chip doesn't have are 0.0.
+pub type VoiceLevels = [f32; MAX_VOICES];
@@ -0,0 +1,245 @@
+//! # Namco 163 Audio
+//!
+//! The Namco 163 plays up to 8 wavetable channels from 128 bytes of RAM
+//! inside the chip, which also holds the channels' registers. The game
+//! reads and writes the RAM through a port. [Read more here][N163].
+//!
+//!  Port  | Legend    | Bits
+//! -------|-----------|-----------------------------------------------
+//! $F800  | IAAA AAAA | auto Increment, Address
+//! $4800  | DDDD DDDD | Data at the address, which is incremented after
+//!        |           | each read or write if I is set
+//!
+//! Each channel's registers are the 8 bytes at $40 + 8 × the channel:
+//!
+//!  Offset | Legend    | Bits
+//! --------|-----------|----------------------------------------------
+//!  0      | FFFF FFFF | Frequency low
+//!  1      | PPPP PPPP | Phase low
+//!  2      | FFFF FFFF | Frequency middle
+//!  3      | PPPP PPPP | Phase middle
+//!  4      | LLLL LLFF | Length (256 - 4 × L samples), Frequency high
+//!  5      | PPPP PPPP | Phase high
+//!  6      | OOOO OOOO | Offset of the wave, in 4 bit samples
+//!  7      | .CCC VVVV | (channel 7 only) enabled Channels - 1, Volume
+//!
+//! The waves are packed 2 samples to a byte, low nibble first, anywhere
+//! in the RAM, even over the registers.
+//!
+//! The chip only has one output. It updates the enabled channels in turn,
+//! from channel 7 down, taking 15 CPU cycles for each, and outputs the
+//! channel it last updated. So the more channels enabled, the quieter each
+//! is. The switching is too fast to hear (apart from a whine with 7 or 8
+//! channels), so the output here is the average of the channels' levels.
+//!
+//! [N163]: https://wiki.nesdev.com/w/index.php/Namco_163_audio
+
+use apu::expansion::{VoiceLevels, MAX_VOICES};
+
+/// CPU cycles spent updating each channel
+const CYCLES_PER_CHANNEL: u8 = 15;
+
+const SIZE_RAM: usize = 128;
+
+/// Registers of channel 0, with each channel's following
+const CHANNEL_REGISTERS: usize = 0x40;
+
+/// Level of one step of a channel's output, so a single channel at full
+/// volume is about as loud as the APU's pulses
+const LEVEL_SCALE: f32 = 0.00125;
+
+pub struct Namco163Audio {
+  ram: [u8; SIZE_RAM],
+  address: u8,
+  auto_increment: bool,
+
+  cycles: u8,
+  /// The channel being updated
+  channel: usize,
+  /// The level of each channel when it was last updated
+  outputs: [i8; 8],
+}
+
+impl Default for Namco163Audio {
+  fn default() -> Self {
+    Namco163Audio {
+      ram: [0; SIZE_RAM],
+      address: 0,
+      auto_increment: false,
+      cycles: 0,
+      channel: 7,
+      outputs: [0; 8],
+    }
+  }
+}
+
+impl Namco163Audio {
+  /// Write $F800, selecting the RAM address
+  pub fn write_address(&mut self, value: u8) {
+    self.auto_increment = value & 0x80 != 0;
+    self.address = value & 0x7F;
+  }
+
+  /// Read $4800, from the RAM
+  pub fn read_data(&mut self) -> u8 {
+    let value = self.ram[usize::from(self.address)];
+    self.increment();
+    value
+  }
+
+  /// Write $4800, to the RAM
+  pub fn write_data(&mut self, value: u8) {
+    self.ram[usize::from(self.address)] = value;
+    self.increment();
+  }
+
+  fn increment(&mut self) {
+    if self.auto_increment {
+      self.address = (self.address + 1) & 0x7F;
+    }
+  }
+
+  /// Number of channels enabled, from 1 to 8
+  fn enabled_channels(&self) -> usize {
+    usize::from((self.ram[0x7F] >> 4) & 0b111) + 1
+  }
+
+  /// Clocked every CPU cycle
+  pub fn clock(&mut self) {
+    self.cycles += 1;
+    if self.cycles < CYCLES_PER_CHANNEL {
+      return;
+    }
+    self.cycles = 0;
+
+    let channel = self.channel;
+    self.outputs[channel] = self.update_channel(channel);
+    self.channel = if channel <= 8 - self.enabled_channels() {
+      7
+    } else {
+      channel - 1
+    };
+  }
+
+  /// Step a channel's phase on and return its level, from -120 to 105
+  fn update_channel(&mut self, channel: usize) -> i8 {
+    let base = CHANNEL_REGISTERS + channel * 8;
+    let registers = &mut self.ram[base..base + 8];
+
+    let frequency =
+      u32::from(registers[0]) | u32::from(registers[2]) << 8 | u32::from(registers[4] & 0b11) << 16;
+    let phase =
+      u32::from(registers[1]) | u32::from(registers[3]) << 8 | u32::from(registers[5]) << 16;
+    let length = 256 - u32::from(registers[4] & 0xFC);
+    let phase = (phase + frequency) % (length << 16);
+    registers[1] = phase as u8;
+    registers[3] = (phase >> 8) as u8;
+    registers[5] = (phase >> 16) as u8;
+
+    let index = ((phase >> 16) + u32::from(registers[6])) as u8;
+    let volume = (registers[7] & 0x0F) as i8;
+    let sample = (self.ram[usize::from(index >> 1)] >> ((index & 1) * 4)) & 0x0F;
+    (sample as i8 - 8) * volume
+  }
+
+  /// The average level of the enabled channels, from about -0.15 to 0.15
+  pub fn output(&self) -> f32 {
+    self.voices().iter().sum()
+  }
+
+  /// Each enabled channel's share of the average, from channel 7 (which
+  /// is always enabled) down
+  pub fn voices(&self) -> VoiceLevels {
+    let enabled = self.enabled_channels();
+    let mut voices = [0.0; MAX_VOICES];
+    for (voice, &level) in voices
+      .iter_mut()
+      .zip(self.outputs.iter().rev())
+      .take(enabled)
+    {
+      *voice = f32::from(level) / enabled as f32 * LEVEL_SCALE;
+    }
+    voices
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn write(audio: &mut Namco163Audio, addr: u8, values: &[u8]) {
+    audio.write_address(0x80 | addr);
+    for &value in values {
+      audio.write_data(value);
+    }
+  }
+
+  /// Channel 7 playing a 4 sample wave at $00 at full volume, advancing a
+  /// sample each update
+  fn single_channel() -> Namco163Audio {
+    let mut audio = Namco163Audio::default();
+    // Samples 0, 15, 8, 4
+    write(&mut audio, 0x00, &[0xF0, 0x48]);
+    write(
+      &mut audio,
+      0x78,
+      &[0x00, 0x00, 0x00, 0x00, 0xFD, 0x00, 0x00, 0x0F],
+    );
+    audio
+  }
+
+  #[test]
+  fn ram_port_auto_increments() {
+    let mut audio = Namco163Audio::default();
+    write(&mut audio, 0x7E, &[1, 2, 3]);
+    audio.write_address(0x7E);
+    assert_eq!(audio.read_data(), 1);
+    assert_eq!(audio.read_data(), 1);
+    audio.write_address(0xFF);
+    assert_eq!(audio.read_data(), 2);
+    assert_eq!(audio.read_data(), 3);
+    assert_eq!(audio.address, 0x01);
+  }
+
+  #[test]
+  fn plays_wave() {
+    let mut audio = single_channel();
+    let mut levels = vec![];
+    for _ in 0..5 {
+      for _ in 0..CYCLES_PER_CHANNEL {
+        audio.clock();
+      }
+      levels.push(audio.outputs[7]);
+    }
+    assert_eq!(levels, [105, 0, -60, -120, 105]);
+    assert_eq!(audio.output(), 105.0 * LEVEL_SCALE);
+    // The phase is written back to the RAM
+    audio.write_address(0x7D);
+    assert_eq!(audio.read_data(), 1);
+  }
+
+  #[test]
+  fn multiplexes_enabled_channels() {
+    let mut audio = single_channel();
+    // 2 channels enabled, with channel 6 silent
+    write(&mut audio, 0x7F, &[0x1F]);
+    let mut updated = vec![];
+    for _ in 0..4 {
+      for _ in 0..CYCLES_PER_CHANNEL {
+        audio.clock();
+      }
+      updated.push(audio.channel);
+    }
+    assert_eq!(updated, [6, 7, 6, 7]);
+    assert_eq!(audio.outputs[7], 0);
+    audio.outputs[7] = 100;
+    assert_eq!(audio.output(), 50.0 * LEVEL_SCALE);
+
+    // Each channel has its share as a voice, from channel 7
+    audio.outputs[6] = -20;
+    let voices = audio.voices();
+    assert_eq!(voices[0], 50.0 * LEVEL_SCALE);
+    assert_eq!(voices[1], -10.0 * LEVEL_SCALE);
+    assert_eq!(&voices[2..], &[0.0; 6]);
+  }
+}
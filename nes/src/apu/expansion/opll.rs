@@ -0,0 +1,663 @@
+//! # OPLL FM Synthesis
+//!
+//! The VRC7's audio is a cut down Yamaha YM2413 (OPLL): 6 FM channels
+//! (of the YM2413's 9, without its rhythm mode), with 15 built-in
+//! instruments and one custom instrument. [Read more here][VRC7].
+//!
+//! Each channel has two operators, each a sine wave oscillator with an
+//! envelope. The modulator's output shifts the phase of the carrier,
+//! whose output is the channel's. The modulator can also feed back into
+//! itself. Levels are worked out as attenuation in decibels, like the
+//! chip does.
+//!
+//! The game writes a register number to $9010, then a value to $9030.
+//!
+//!  Register | Legend    | Bits
+//! ----------|-----------|-------------------------------------------------
+//! $00-$07   |           | Custom instrument (see `Patch::from_bytes`)
+//! $10-$15   | FFFF FFFF | Frequency number low, for channels 0-5
+//! $20-$25   | ..SK BBBF | Sustain, Key on, Block (octave), Frequency high
+//! $30-$35   | IIII VVVV | Instrument (0 for custom), Volume (attenuation)
+//!
+//! The chip runs from a 3.58MHz clock (twice the CPU's) and produces a
+//! sample every 72 clocks, about 49.7kHz.
+//!
+//! [VRC7]: https://wiki.nesdev.com/w/index.php/VRC7_audio
+
+use apu::expansion::{VoiceLevels, MAX_VOICES};
+use std::f32::consts::PI;
+
+/// CPU cycles between samples
+const CYCLES_PER_SAMPLE: u8 = 36;
+
+const SAMPLE_RATE: f32 = 49_716.0;
+
+/// The phase counts up to this for each cycle of a wave
+const PHASE_BITS: u32 = 19;
+
+const SINE_BITS: u32 = 10;
+
+/// Range of an envelope, from full volume to silent
+const ENVELOPE_RANGE: f32 = 48.0;
+
+/// Attenuation treated as silent
+const SILENT: f32 = 96.0;
+
+/// Level of a channel at full volume, mixed linearly with the APU
+const LEVEL_SCALE: f32 = 0.15;
+
+/// Frequency multiples, doubled
+const MULTIPLE_TABLE: [u32; 16] = [1, 2, 4, 6, 8, 10, 12, 14, 16, 18, 20, 20, 24, 24, 30, 30];
+
+/// Key scale level attenuation in dB for the top 4 bits of the frequency
+/// number in block 7, falling 6dB for each lower block
+const KEY_SCALE_TABLE: [f32; 16] = [
+  0.0, 18.0, 24.0, 27.75, 30.0, 32.25, 33.75, 35.25, 36.0, 37.5, 38.25, 39.0, 39.75, 40.5, 41.25,
+  42.0,
+];
+
+/// Tremolo depth in dB, and rate in Hz
+const TREMOLO_DEPTH: f32 = 4.875;
+const TREMOLO_RATE: f32 = 3.6;
+
+/// Vibrato depth in cents, and rate in Hz
+const VIBRATO_DEPTH: f32 = 7.0;
+const VIBRATO_RATE: f32 = 6.4;
+
+/// The VRC7's built-in instruments 1-15, as dumped from the chip
+#[rustfmt::skip]
+pub const VRC7_PATCHES: [[u8; 8]; 15] = [
+  [0x03, 0x21, 0x05, 0x06, 0xE8, 0x81, 0x42, 0x27], // Buzzy bell
+  [0x13, 0x41, 0x14, 0x0D, 0xD8, 0xF6, 0x23, 0x12], // Guitar
+  [0x11, 0x11, 0x08, 0x08, 0xFA, 0xB2, 0x20, 0x12], // Wurly
+  [0x31, 0x61, 0x0C, 0x07, 0xA8, 0x64, 0x61, 0x27], // Flute
+  [0x32, 0x21, 0x1E, 0x06, 0xE1, 0x76, 0x01, 0x28], // Clarinet
+  [0x02, 0x01, 0x06, 0x00, 0xA3, 0xE2, 0xF4, 0xF4], // Synth
+  [0x21, 0x61, 0x1D, 0x07, 0x82, 0x81, 0x11, 0x07], // Trumpet
+  [0x23, 0x21, 0x22, 0x17, 0xA2, 0x72, 0x01, 0x17], // Organ
+  [0x35, 0x11, 0x25, 0x00, 0x40, 0x73, 0x72, 0x01], // Bells
+  [0xB5, 0x01, 0x0F, 0x0F, 0xA8, 0xA5, 0x51, 0x02], // Vibes
+  [0x17, 0xC1, 0x24, 0x07, 0xF8, 0xF8, 0x22, 0x12], // Vibraphone
+  [0x71, 0x23, 0x11, 0x06, 0x65, 0x74, 0x18, 0x16], // Tutti
+  [0x01, 0x02, 0xD3, 0x05, 0xC9, 0x95, 0x03, 0x02], // Fretless
+  [0x61, 0x63, 0x0C, 0x00, 0x94, 0xC0, 0x33, 0xF6], // Synth bass
+  [0x21, 0x72, 0x0D, 0x00, 0xC1, 0xD5, 0x56, 0x06], // Sweep
+];
+
+/// The settings for one operator of an instrument
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct OperatorPatch {
+  tremolo: bool,
+  vibrato: bool,
+  /// Hold at the sustain level until key off, rather than carrying on
+  /// decaying at the release rate
+  sustained: bool,
+  /// Scale the envelope rates by the full key, rather than just the block
+  key_scale_rate: bool,
+  multiple: u8,
+  key_scale_level: u8,
+  /// Output only the positive half of the sine wave
+  rectified: bool,
+  attack_rate: u8,
+  decay_rate: u8,
+  sustain_level: u8,
+  release_rate: u8,
+}
+
+impl OperatorPatch {
+  fn from_bytes(flags: u8, key_scale: u8, rates: u8, levels: u8, rectified: bool) -> Self {
+    OperatorPatch {
+      tremolo: flags & 0x80 != 0,
+      vibrato: flags & 0x40 != 0,
+      sustained: flags & 0x20 != 0,
+      key_scale_rate: flags & 0x10 != 0,
+      multiple: flags & 0x0F,
+      key_scale_level: key_scale >> 6,
+      rectified,
+      attack_rate: rates >> 4,
+      decay_rate: rates & 0x0F,
+      sustain_level: levels >> 4,
+      release_rate: levels & 0x0F,
+    }
+  }
+}
+
+/// An instrument
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Patch {
+  modulator: OperatorPatch,
+  carrier: OperatorPatch,
+  /// Attenuation of the modulator, in steps of 0.75dB
+  total_level: u8,
+  feedback: u8,
+}
+
+impl Patch {
+  /// Decode an instrument from its 8 bytes:
+  ///
+  ///  Byte | Legend    | Bits
+  /// ------|-----------|---------------------------------------------------
+  ///  0, 1 | TVSK MMMM | Tremolo, Vibrato, Sustained, Key scale rate,
+  ///       |           | Multiple for the modulator, carrier
+  ///  2    | KKLL LLLL | Modulator key scale level, Total level
+  ///  3    | KK.C MFFF | Carrier key scale level, Carrier and Modulator
+  ///       |           | rectified, Feedback
+  ///  4, 5 | AAAA DDDD | Attack rate, Decay rate for the modulator, carrier
+  ///  6, 7 | SSSS RRRR | Sustain level, Release rate for the modulator,
+  ///       |           | carrier
+  fn from_bytes(bytes: &[u8; 8]) -> Self {
+    Patch {
+      modulator: OperatorPatch::from_bytes(
+        bytes[0],
+        bytes[2],
+        bytes[4],
+        bytes[6],
+        bytes[3] & 0x08 != 0,
+      ),
+      carrier: OperatorPatch::from_bytes(
+        bytes[1],
+        bytes[3],
+        bytes[5],
+        bytes[7],
+        bytes[3] & 0x10 != 0,
+      ),
+      total_level: bytes[2] & 0x3F,
+      feedback: bytes[3] & 0x07,
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum EnvelopeState {
+  Attack,
+  Decay,
+  Sustain,
+  Release,
+  Off,
+}
+
+#[derive(Clone, Copy)]
+struct Operator {
+  /// Position in the wave, in 1/2^19ths of a cycle
+  phase: u32,
+  /// Attenuation from the envelope in dB
+  envelope: f32,
+  state: EnvelopeState,
+  /// The last two outputs, for feedback
+  outputs: [f32; 2],
+}
+
+impl Default for Operator {
+  fn default() -> Self {
+    Operator {
+      phase: 0,
+      envelope: ENVELOPE_RANGE,
+      state: EnvelopeState::Off,
+      outputs: [0.0; 2],
+    }
+  }
+}
+
+impl Operator {
+  fn key_on(&mut self) {
+    self.phase = 0;
+    self.state = EnvelopeState::Attack;
+  }
+
+  fn key_off(&mut self) {
+    if self.state != EnvelopeState::Off {
+      self.state = EnvelopeState::Release;
+    }
+  }
+
+  /// Move the envelope on by a sample, where `rks` is the key's rate
+  /// scaling and `release_rate` the rate after key off
+  fn clock_envelope(&mut self, patch: &OperatorPatch, rks: u8, release_rate: u8) {
+    match self.state {
+      EnvelopeState::Attack => {
+        let rate = effective_rate(patch.attack_rate, rks);
+        if rate >= 60 {
+          self.envelope = 0.0;
+        } else {
+          self.envelope -= (self.envelope + 1.0) * attack_factor(rate);
+        }
+        if self.envelope <= 0.0 {
+          self.envelope = 0.0;
+          self.state = EnvelopeState::Decay;
+        }
+      }
+      EnvelopeState::Decay => {
+        let sustain_level = 3.0 * f32::from(patch.sustain_level);
+        self.envelope += decay_step(effective_rate(patch.decay_rate, rks));
+        if self.envelope >= sustain_level {
+          self.envelope = sustain_level;
+          self.state = EnvelopeState::Sustain;
+        }
+      }
+      EnvelopeState::Sustain => {
+        if !patch.sustained {
+          self.envelope += decay_step(effective_rate(patch.release_rate, rks));
+        }
+      }
+      EnvelopeState::Release => {
+        self.envelope += decay_step(effective_rate(release_rate, rks));
+      }
+      EnvelopeState::Off => {}
+    }
+
+    if self.envelope >= ENVELOPE_RANGE {
+      self.envelope = ENVELOPE_RANGE;
+      if self.state != EnvelopeState::Attack {
+        self.state = EnvelopeState::Off;
+      }
+    }
+  }
+
+  /// Produce the next output, from -1.0 to 1.0, given an offset to the
+  /// phase in cycles and the attenuation apart from the envelope
+  fn output(&mut self, sine: &[f32], patch: &OperatorPatch, offset: f32, attenuation: f32) -> f32 {
+    let attenuation = attenuation + self.envelope;
+    let output = if self.state == EnvelopeState::Off || attenuation >= SILENT {
+      0.0
+    } else {
+      let cycles = self.phase as f32 / (1 << PHASE_BITS) as f32 + offset;
+      let index = (cycles * sine.len() as f32).floor() as i64 as usize & (sine.len() - 1);
+      let wave = sine[index];
+      let wave = if patch.rectified && wave < 0.0 {
+        0.0
+      } else {
+        wave
+      };
+      wave * 10f32.powf(-attenuation / 20.0)
+    };
+    self.outputs = [self.outputs[1], output];
+    output
+  }
+}
+
+/// An envelope rate (0-15) adjusted for the key (0-63), where 0 never
+/// changes
+fn effective_rate(rate: u8, rks: u8) -> u8 {
+  if rate == 0 {
+    0
+  } else {
+    (rate * 4 + rks).min(63)
+  }
+}
+
+/// The fraction of the distance to full volume covered each sample while
+/// attacking. Attacks take 2.8s at rate 4, halving every 4 rates.
+fn attack_factor(rate: u8) -> f32 {
+  if rate == 0 {
+    return 0.0;
+  }
+  let millis = 2826.0 * rate_time(rate);
+  (ENVELOPE_RANGE + 1.0).ln() / (millis / 1000.0 * SAMPLE_RATE)
+}
+
+/// The dB decayed each sample. A 96dB decay takes 39.3s at rate 4,
+/// halving every 4 rates.
+fn decay_step(rate: u8) -> f32 {
+  if rate == 0 {
+    return 0.0;
+  }
+  let millis = 39280.0 * rate_time(rate);
+  96.0 / (millis / 1000.0 * SAMPLE_RATE)
+}
+
+/// Time taken by an envelope at a rate, relative to rate 4
+fn rate_time(rate: u8) -> f32 {
+  4.0 / f32::from(4 + rate % 4) / 2f32.powi(i32::from(rate / 4) - 1)
+}
+
+#[derive(Clone, Copy, Default)]
+struct Channel {
+  frequency: u16,
+  block: u8,
+  key_on: bool,
+  sustain: bool,
+  instrument: u8,
+  volume: u8,
+  modulator: Operator,
+  carrier: Operator,
+}
+
+impl Channel {
+  fn write_key(&mut self, value: u8) {
+    self.frequency = (self.frequency & 0xFF) | (u16::from(value & 0x01) << 8);
+    self.block = (value >> 1) & 0b111;
+    self.sustain = value & 0x20 != 0;
+
+    let key_on = value & 0x10 != 0;
+    if key_on && !self.key_on {
+      self.modulator.key_on();
+      self.carrier.key_on();
+    } else if !key_on && self.key_on {
+      self.modulator.key_off();
+      self.carrier.key_off();
+    }
+    self.key_on = key_on;
+  }
+
+  /// The key's envelope rate scaling, from its block and top frequency bit
+  fn rks(&self, patch: &OperatorPatch) -> u8 {
+    let key = (self.block << 1) | (self.frequency >> 8) as u8;
+    if patch.key_scale_rate {
+      key
+    } else {
+      key >> 2
+    }
+  }
+
+  /// Attenuation from the key scale level, which is higher for higher keys
+  fn key_scale_attenuation(&self, patch: &OperatorPatch) -> f32 {
+    if patch.key_scale_level == 0 {
+      return 0.0;
+    }
+    let level = KEY_SCALE_TABLE[usize::from(self.frequency >> 5)] - 6.0 * f32::from(7 - self.block);
+    level.max(0.0) / f32::from(1u8 << (3 - patch.key_scale_level))
+  }
+
+  fn phase_increment(&self, patch: &OperatorPatch, vibrato: f32) -> u32 {
+    let increment =
+      (u32::from(self.frequency) << self.block) * MULTIPLE_TABLE[usize::from(patch.multiple)] / 2;
+    if patch.vibrato {
+      (increment as f32 * vibrato) as u32
+    } else {
+      increment
+    }
+  }
+
+  fn release_rate(&self, patch: &OperatorPatch) -> u8 {
+    if self.sustain {
+      5
+    } else if patch.sustained {
+      patch.release_rate
+    } else {
+      7
+    }
+  }
+
+  /// Produce the channel's next sample, from -1.0 to 1.0
+  fn sample(&mut self, patch: &Patch, sine: &[f32], tremolo: f32, vibrato: f32) -> f32 {
+    let phase_mask = (1 << PHASE_BITS) - 1;
+    let (modulator, carrier) = (&patch.modulator, &patch.carrier);
+
+    let feedback = if patch.feedback == 0 {
+      0.0
+    } else {
+      // Up to ±2 cycles (4π) at the highest feedback
+      let outputs = self.modulator.outputs;
+      (outputs[0] + outputs[1]) / 2.0 * 2.0 / f32::from(1u8 << (7 - patch.feedback))
+    };
+    let attenuation = 0.75 * f32::from(patch.total_level)
+      + self.key_scale_attenuation(modulator)
+      + if modulator.tremolo { tremolo } else { 0.0 };
+    let modulation = self
+      .modulator
+      .output(sine, modulator, feedback, attenuation);
+
+    let attenuation = 3.0 * f32::from(self.volume)
+      + self.key_scale_attenuation(carrier)
+      + if carrier.tremolo { tremolo } else { 0.0 };
+    let output = self
+      .carrier
+      .output(sine, carrier, 2.0 * modulation, attenuation);
+
+    self.modulator.phase =
+      (self.modulator.phase + self.phase_increment(modulator, vibrato)) & phase_mask;
+    self.carrier.phase = (self.carrier.phase + self.phase_increment(carrier, vibrato)) & phase_mask;
+    let (modulator_rks, carrier_rks) = (self.rks(modulator), self.rks(carrier));
+    let (modulator_release, carrier_release) =
+      (self.release_rate(modulator), self.release_rate(carrier));
+    self
+      .modulator
+      .clock_envelope(modulator, modulator_rks, modulator_release);
+    self
+      .carrier
+      .clock_envelope(carrier, carrier_rks, carrier_release);
+
+    output
+  }
+}
+
+pub struct Opll {
+  /// The built-in instruments 1-15
+  rom: [Patch; 15],
+  custom: [u8; 8],
+  custom_patch: Patch,
+  channels: Vec<Channel>,
+  address: u8,
+
+  cycles: u8,
+  /// Time in samples, for the tremolo and vibrato
+  samples: u32,
+  voices: VoiceLevels,
+
+  sine: Vec<f32>,
+}
+
+impl Opll {
+  /// The VRC7's OPLL, with 6 channels
+  pub fn vrc7() -> Self {
+    Opll::new(6, &VRC7_PATCHES)
+  }
+
+  pub fn new(channels: usize, patches: &[[u8; 8]; 15]) -> Self {
+    let mut rom = [Patch::default(); 15];
+    for (patch, bytes) in rom.iter_mut().zip(patches.iter()) {
+      *patch = Patch::from_bytes(bytes);
+    }
+    let sine_size = 1 << SINE_BITS;
+    Opll {
+      rom,
+      custom: [0; 8],
+      custom_patch: Patch::default(),
+      channels: vec![Channel::default(); channels],
+      address: 0,
+      cycles: 0,
+      samples: 0,
+      voices: [0.0; MAX_VOICES],
+      sine: (0..sine_size)
+        .map(|i| (2.0 * PI * (i as f32 + 0.5) / sine_size as f32).sin())
+        .collect(),
+    }
+  }
+
+  /// Silence every channel and clear the registers
+  pub fn reset(&mut self) {
+    let channels = self.channels.len();
+    self.custom = [0; 8];
+    self.custom_patch = Patch::default();
+    self.channels = vec![Channel::default(); channels];
+    self.voices = [0.0; MAX_VOICES];
+  }
+
+  /// Select the register for the next `write_data`
+  pub fn write_address(&mut self, value: u8) {
+    self.address = value;
+  }
+
+  pub fn write_data(&mut self, value: u8) {
+    let register = usize::from(self.address & 0x0F);
+    match self.address & 0xF0 {
+      0x00 if register < 8 => {
+        self.custom[register] = value;
+        self.custom_patch = Patch::from_bytes(&self.custom);
+      }
+      0x10 => {
+        if let Some(channel) = self.channels.get_mut(register) {
+          channel.frequency = (channel.frequency & 0x100) | u16::from(value);
+        }
+      }
+      0x20 => {
+        if let Some(channel) = self.channels.get_mut(register) {
+          channel.write_key(value);
+        }
+      }
+      0x30 => {
+        if let Some(channel) = self.channels.get_mut(register) {
+          channel.instrument = value >> 4;
+          channel.volume = value & 0x0F;
+        }
+      }
+      _ => {}
+    }
+  }
+
+  /// Clocked every CPU cycle
+  pub fn clock(&mut self) {
+    self.cycles += 1;
+    if self.cycles < CYCLES_PER_SAMPLE {
+      return;
+    }
+    self.cycles = 0;
+
+    let time = self.samples as f32 / SAMPLE_RATE;
+    self.samples = self.samples.wrapping_add(1);
+    let tremolo = TREMOLO_DEPTH * triangle(time * TREMOLO_RATE);
+    let vibrato = 2f32.powf(VIBRATO_DEPTH / 1200.0 * (2.0 * triangle(time * VIBRATO_RATE) - 1.0));
+
+    self.voices = [0.0; MAX_VOICES];
+    for (i, channel) in self.channels.iter_mut().enumerate() {
+      let patch = match channel.instrument {
+        0 => &self.custom_patch,
+        instrument => &self.rom[usize::from(instrument) - 1],
+      };
+      // Any channels past the last voice share it
+      self.voices[i.min(MAX_VOICES - 1)] +=
+        channel.sample(patch, &self.sine, tremolo, vibrato) * LEVEL_SCALE;
+    }
+  }
+
+  /// The level of all the channels, from about -0.9 to 0.9
+  pub fn output(&self) -> f32 {
+    self.voices.iter().sum()
+  }
+
+  /// The level of each channel
+  pub fn voices(&self) -> VoiceLevels {
+    self.voices
+  }
+}
+
+/// A triangle wave from 0.0 to 1.0 and back over each cycle
+fn triangle(cycles: f32) -> f32 {
+  let position = cycles.fract();
+  if position < 0.5 {
+    2.0 * position
+  } else {
+    2.0 - 2.0 * position
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use clock::CPU_FREQUENCY;
+
+  /// A custom instrument which is a plain sine wave at the key's
+  /// frequency, starting and stopping immediately
+  const SINE: [u8; 8] = [0x01, 0x21, 0x3F, 0x00, 0xF0, 0xF0, 0x0F, 0x0F];
+
+  fn write(opll: &mut Opll, register: u8, value: u8) {
+    opll.write_address(register);
+    opll.write_data(value);
+  }
+
+  fn outputs(opll: &mut Opll, cycles: u32) -> Vec<f32> {
+    (0..cycles)
+      .map(|_| {
+        opll.clock();
+        opll.output()
+      })
+      .collect()
+  }
+
+  /// Play a note on channel 0, with frequency number 288 in block 4
+  /// (about 437Hz)
+  fn play(opll: &mut Opll, instrument: u8) {
+    write(opll, 0x10, 0x20);
+    write(opll, 0x30, instrument << 4);
+    write(opll, 0x20, 0b0001_1001);
+  }
+
+  #[test]
+  fn decodes_patches() {
+    let patch = Patch::from_bytes(&VRC7_PATCHES[0]);
+    assert_eq!(patch.modulator.multiple, 3);
+    assert!(patch.carrier.sustained);
+    assert_eq!(patch.carrier.multiple, 1);
+    assert_eq!(patch.total_level, 5);
+    assert_eq!(patch.feedback, 6);
+    assert_eq!(patch.modulator.attack_rate, 0xE);
+    assert_eq!(patch.carrier.release_rate, 7);
+  }
+
+  #[test]
+  fn silent_until_keyed_on() {
+    let mut opll = Opll::vrc7();
+    assert!(outputs(&mut opll, 10_000)
+      .iter()
+      .all(|&sample| sample == 0.0));
+  }
+
+  #[test]
+  fn plays_at_key_frequency() {
+    let mut opll = Opll::vrc7();
+    for (register, &value) in SINE.iter().enumerate() {
+      write(&mut opll, register as u8, value);
+    }
+    play(&mut opll, 0);
+
+    let outputs = outputs(&mut opll, CPU_FREQUENCY);
+    let crossings = outputs
+      .windows(2)
+      .filter(|pair| (pair[0] < 0.0) != (pair[1] < 0.0))
+      .count();
+    assert!((crossings as i32 - 874).abs() < 10, "{}", crossings);
+    let peak = outputs
+      .iter()
+      .fold(0.0f32, |peak, &sample| peak.max(sample));
+    assert!((peak - LEVEL_SCALE).abs() < 0.01, "{}", peak);
+  }
+
+  #[test]
+  fn volume_attenuates() {
+    let mut opll = Opll::vrc7();
+    for (register, &value) in SINE.iter().enumerate() {
+      write(&mut opll, register as u8, value);
+    }
+    play(&mut opll, 0);
+    write(&mut opll, 0x30, 0x02);
+
+    // 6dB quieter
+    let peak = outputs(&mut opll, 10_000)
+      .iter()
+      .fold(0.0f32, |peak, &sample| peak.max(sample));
+    assert!((peak - LEVEL_SCALE / 2.0).abs() < 0.01, "{}", peak);
+  }
+
+  #[test]
+  fn built_in_instruments_play_and_release() {
+    let mut opll = Opll::vrc7();
+    play(&mut opll, 3);
+    let outputs_on = outputs(&mut opll, CPU_FREQUENCY / 10);
+    assert!(outputs_on.iter().any(|&sample| sample.abs() > 0.01));
+
+    // Key off, then the release fades it out
+    write(&mut opll, 0x20, 0b0000_1001);
+    outputs(&mut opll, CPU_FREQUENCY);
+    assert_eq!(opll.channels[0].carrier.state, EnvelopeState::Off);
+    assert_eq!(opll.output(), 0.0);
+  }
+
+  #[test]
+  fn reset_silences() {
+    let mut opll = Opll::vrc7();
+    play(&mut opll, 1);
+    outputs(&mut opll, 1000);
+    opll.reset();
+    assert!(outputs(&mut opll, 1000).iter().all(|&sample| sample == 0.0));
+  }
+}
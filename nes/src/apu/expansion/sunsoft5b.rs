@@ -0,0 +1,380 @@
+//! # Sunsoft 5B Audio
+//!
+//! The 5B is an FME-7 with a Yamaha YM2149F (a licensed AY-3-8910) added:
+//! three square channels, which can each mix in a shared noise generator,
+//! and a shared envelope generator. [Read more here][5B].
+//!
+//! The game writes a register number to $C000, then a value to $E000.
+//!
+//!  Register | Legend    | Bits
+//! ----------|-----------|---------------------------------------------------
+//! $00-$05   | PPPP PPPP | Tone period low, ....PPPP high for channels A-C
+//! $06       | ...P PPPP | Noise period
+//! $07       | ..NN NTTT | Noise and Tone disable for channels C, B, A
+//! $08-$0A   | ...E VVVV | Envelope (rather than Volume), Volume for A-C
+//! $0B-$0C   | PPPP PPPP | Envelope period low, high
+//! $0D       | .... CAaH | Envelope shape: Continue, Attack, alternate, Hold
+//!
+//! The tones and noise are clocked every 16 CPU cycles, so a tone with
+//! period P is CPU / (32 × P) Hz. The envelope steps through 32 levels,
+//! one every 8 × P CPU cycles. Volumes are logarithmic, 1.5dB a level,
+//! with each of the 16 channel volumes at every other envelope level.
+//!
+//! [5B]: https://wiki.nesdev.com/w/index.php/Sunsoft_5B_audio
+
+use apu::expansion::{VoiceLevels, MAX_VOICES};
+
+/// CPU cycles between clocks of the tones and noise
+const TONE_DIVIDER: u8 = 16;
+
+/// CPU cycles between clocks of the envelope
+const ENVELOPE_DIVIDER: u8 = 8;
+
+/// The loudest of the 32 levels
+const MAX_LEVEL: u8 = 31;
+
+/// Attenuation per level, in dB
+const LEVEL_STEP: f32 = 1.5;
+
+/// Level of a channel at full volume, about as loud as the APU's pulses
+const LEVEL_SCALE: f32 = 0.15;
+
+#[derive(Default)]
+struct Tone {
+  period: u16,
+  counter: u16,
+  high: bool,
+  /// 0-15, or using the envelope
+  volume: u8,
+  envelope: bool,
+}
+
+impl Tone {
+  fn clock(&mut self) {
+    self.counter += 1;
+    if self.counter >= self.period.max(1) {
+      self.counter = 0;
+      self.high = !self.high;
+    }
+  }
+}
+
+struct Noise {
+  period: u8,
+  counter: u8,
+  /// A 17 bit linear feedback shift register
+  shift: u32,
+}
+
+impl Default for Noise {
+  fn default() -> Self {
+    Noise {
+      period: 0,
+      counter: 0,
+      shift: 1,
+    }
+  }
+}
+
+impl Noise {
+  /// Clocked with the tones, at twice the period as the AY-3-8910 has a
+  /// further divider for its noise
+  fn clock(&mut self) {
+    self.counter += 1;
+    if self.counter >= self.period.max(1) * 2 {
+      self.counter = 0;
+      let feedback = (self.shift ^ (self.shift >> 3)) & 1;
+      self.shift = (self.shift >> 1) | (feedback << 16);
+    }
+  }
+
+  fn high(&self) -> bool {
+    self.shift & 1 != 0
+  }
+}
+
+#[derive(Default)]
+struct Envelope {
+  period: u16,
+  counter: u16,
+  /// Steps through the current ramp, from 0 to 31
+  step: u8,
+  attack: bool,
+  alternate: bool,
+  hold: bool,
+  continues: bool,
+  /// The level held at the end, once the envelope has stopped
+  held: Option<u8>,
+}
+
+impl Envelope {
+  fn write_shape(&mut self, value: u8) {
+    self.continues = value & 0b1000 != 0;
+    self.attack = value & 0b0100 != 0;
+    self.alternate = value & 0b0010 != 0;
+    self.hold = value & 0b0001 != 0;
+    self.step = 0;
+    self.counter = 0;
+    self.held = None;
+  }
+
+  fn clock(&mut self) {
+    if self.held.is_some() {
+      return;
+    }
+    self.counter += 1;
+    if self.counter < self.period.max(1) {
+      return;
+    }
+    self.counter = 0;
+
+    if self.step < MAX_LEVEL {
+      self.step += 1;
+    } else if !self.continues {
+      self.held = Some(0);
+    } else if self.hold {
+      // The ramp ended at 31 for an attack, then alternating flips it
+      self.held = Some(if self.attack != self.alternate {
+        MAX_LEVEL
+      } else {
+        0
+      });
+    } else {
+      if self.alternate {
+        self.attack = !self.attack;
+      }
+      self.step = 0;
+    }
+  }
+
+  fn level(&self) -> u8 {
+    match self.held {
+      Some(level) => level,
+      None if self.attack => self.step,
+      None => MAX_LEVEL - self.step,
+    }
+  }
+}
+
+pub struct Sunsoft5bAudio {
+  tones: [Tone; 3],
+  noise: Noise,
+  envelope: Envelope,
+  /// Register $07
+  disable: u8,
+  register: u8,
+  cycles: u8,
+  /// Amplitudes of the 32 levels
+  amplitudes: [f32; 32],
+}
+
+impl Default for Sunsoft5bAudio {
+  fn default() -> Self {
+    let mut amplitudes = [0.0; 32];
+    for (level, amplitude) in amplitudes.iter_mut().enumerate().skip(1) {
+      let attenuation = f32::from(MAX_LEVEL - level as u8) * LEVEL_STEP;
+      *amplitude = 10f32.powf(-attenuation / 20.0);
+    }
+    Sunsoft5bAudio {
+      tones: Default::default(),
+      noise: Noise::default(),
+      envelope: Envelope::default(),
+      disable: 0,
+      register: 0,
+      cycles: 0,
+      amplitudes,
+    }
+  }
+}
+
+impl Sunsoft5bAudio {
+  /// Write $C000, selecting the register for `write_data`
+  pub fn write_register(&mut self, value: u8) {
+    self.register = value;
+  }
+
+  /// Write $E000, to the selected register
+  pub fn write_data(&mut self, value: u8) {
+    match self.register {
+      register @ 0x00...0x05 => {
+        let tone = &mut self.tones[usize::from(register / 2)];
+        tone.period = if register % 2 == 0 {
+          (tone.period & 0xF00) | u16::from(value)
+        } else {
+          (tone.period & 0x0FF) | (u16::from(value & 0x0F) << 8)
+        };
+      }
+      0x06 => self.noise.period = value & 0x1F,
+      0x07 => self.disable = value,
+      register @ 0x08...0x0A => {
+        let tone = &mut self.tones[usize::from(register - 0x08)];
+        tone.volume = value & 0x0F;
+        tone.envelope = value & 0x10 != 0;
+      }
+      0x0B => self.envelope.period = (self.envelope.period & 0xFF00) | u16::from(value),
+      0x0C => self.envelope.period = (self.envelope.period & 0x00FF) | (u16::from(value) << 8),
+      0x0D => self.envelope.write_shape(value),
+      // $0E and $0F are the YM2149F's I/O ports, unconnected on the 5B
+      _ => {}
+    }
+  }
+
+  /// Clocked every CPU cycle
+  pub fn clock(&mut self) {
+    self.cycles += 1;
+    if self.cycles.is_multiple_of(ENVELOPE_DIVIDER) {
+      self.envelope.clock();
+    }
+    if self.cycles == TONE_DIVIDER {
+      self.cycles = 0;
+      for tone in self.tones.iter_mut() {
+        tone.clock();
+      }
+      self.noise.clock();
+    }
+  }
+
+  /// The current level of a channel, from 0 to 31
+  fn level(&self, channel: usize) -> u8 {
+    let tone = &self.tones[channel];
+    let tone_on = tone.high || self.disable & (0b001 << channel) != 0;
+    let noise_on = self.noise.high() || self.disable & (0b1000 << channel) != 0;
+    if !(tone_on && noise_on) {
+      0
+    } else if tone.envelope {
+      self.envelope.level()
+    } else if tone.volume == 0 {
+      0
+    } else {
+      tone.volume * 2 + 1
+    }
+  }
+
+  /// The level of all three channels, from 0.0 to about 0.45
+  pub fn output(&self) -> f32 {
+    self.voices().iter().sum()
+  }
+
+  /// The levels of channels A, B and C
+  pub fn voices(&self) -> VoiceLevels {
+    let mut voices = [0.0; MAX_VOICES];
+    for (channel, voice) in voices.iter_mut().take(3).enumerate() {
+      *voice = self.amplitudes[usize::from(self.level(channel))] * LEVEL_SCALE;
+    }
+    voices
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn write(audio: &mut Sunsoft5bAudio, register: u8, value: u8) {
+    audio.write_register(register);
+    audio.write_data(value);
+  }
+
+  /// The level of channel A after each of a number of cycles
+  fn levels(audio: &mut Sunsoft5bAudio, cycles: usize) -> Vec<u8> {
+    (0..cycles)
+      .map(|_| {
+        audio.clock();
+        audio.level(0)
+      })
+      .collect()
+  }
+
+  #[test]
+  fn tone_period() {
+    let mut audio = Sunsoft5bAudio::default();
+    // Period 2, noise disabled, full volume
+    write(&mut audio, 0x00, 2);
+    write(&mut audio, 0x07, 0b11_1000);
+    write(&mut audio, 0x08, 0x0F);
+
+    let levels = levels(&mut audio, 128);
+    // Toggles every 32 cycles
+    assert_eq!(levels[30], 0);
+    assert_eq!(levels[31], 31);
+    assert_eq!(levels[62], 31);
+    assert_eq!(levels[63], 0);
+    assert_eq!(levels[95], 31);
+  }
+
+  #[test]
+  fn volume_is_logarithmic() {
+    let mut audio = Sunsoft5bAudio::default();
+    write(&mut audio, 0x07, 0b11_1111);
+    write(&mut audio, 0x08, 0x0F);
+    assert_eq!(audio.output(), LEVEL_SCALE);
+
+    // 4 volumes lower is 12dB quieter
+    write(&mut audio, 0x08, 0x0B);
+    assert!((audio.output() - LEVEL_SCALE / 3.98).abs() < 0.001);
+
+    write(&mut audio, 0x08, 0x00);
+    assert_eq!(audio.output(), 0.0);
+  }
+
+  #[test]
+  fn noise_gates_channel() {
+    let mut audio = Sunsoft5bAudio::default();
+    // Tone disabled, noise enabled
+    write(&mut audio, 0x06, 1);
+    write(&mut audio, 0x07, 0b11_0111);
+    write(&mut audio, 0x08, 0x0F);
+    let levels = levels(&mut audio, 16 * 200);
+    assert!(levels.contains(&0));
+    assert!(levels.contains(&31));
+  }
+
+  /// The envelope's level after each step, with period 1
+  fn envelope_steps(shape: u8, steps: usize) -> Vec<u8> {
+    let mut audio = Sunsoft5bAudio::default();
+    write(&mut audio, 0x0B, 1);
+    write(&mut audio, 0x0D, shape);
+    let mut levels = vec![audio.envelope.level()];
+    for _ in 0..steps {
+      for _ in 0..ENVELOPE_DIVIDER {
+        audio.clock();
+      }
+      levels.push(audio.envelope.level());
+    }
+    levels
+  }
+
+  #[test]
+  fn envelope_shapes() {
+    // \___
+    let levels = envelope_steps(0b0000, 40);
+    assert_eq!(&levels[..3], [31, 30, 29]);
+    assert_eq!(&levels[31..], [0; 10]);
+
+    // /|/|
+    let levels = envelope_steps(0b1100, 40);
+    assert_eq!(&levels[30..34], [30, 31, 0, 1]);
+
+    // /\/\
+    let levels = envelope_steps(0b1110, 40);
+    assert_eq!(&levels[30..34], [30, 31, 31, 30]);
+
+    // /~~~
+    let levels = envelope_steps(0b1101, 40);
+    assert_eq!(&levels[31..], [31; 10]);
+
+    // \/~~~
+    let levels = envelope_steps(0b1011, 40);
+    assert_eq!(&levels[31..], [0, 31, 31, 31, 31, 31, 31, 31, 31, 31]);
+  }
+
+  #[test]
+  fn channels_use_envelope() {
+    let mut audio = Sunsoft5bAudio::default();
+    write(&mut audio, 0x07, 0b11_1111);
+    write(&mut audio, 0x0D, 0b1101);
+    write(&mut audio, 0x09, 0x10);
+    assert_eq!(audio.level(1), 0);
+    audio.envelope.step = 20;
+    assert_eq!(audio.level(1), 20);
+  }
+}
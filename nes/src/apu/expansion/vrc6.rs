@@ -0,0 +1,283 @@
+//! # VRC6 Audio
+//!
+//! Konami's VRC6 adds two pulse channels and a sawtooth channel. They have
+//! no length counters, envelopes or sweeps, the game sets their volumes
+//! directly. Their timers count CPU cycles. [Read more here][VRC6].
+//!
+//!  Pulse 1 | Pulse 2 | Sawtooth | Legend    | Bits
+//! ---------|---------|----------|-----------|------------------------------
+//!   $9000  |  $A000  |          | MDDD VVVV | Mode (ignore duty), Duty,
+//!          |         |          |           | Volume
+//!          |         |  $B000   | ..AA AAAA | Accumulator rate
+//!   $9001  |  $A001  |  $B001   | FFFF FFFF | Period low
+//!   $9002  |  $A002  |  $B002   | E... FFFF | Enable, Period high
+//!
+//! $9003 controls all three: bit 0 halts them, and bits 1 and 2 speed
+//! them up by shifting their periods right by 4 or 8 bits (bit 2 wins).
+//!
+//! [VRC6]: https://wiki.nesdev.com/w/index.php/VRC6_audio
+
+use apu::expansion::{VoiceLevels, MAX_VOICES};
+
+/// Level of one step of a channel's output, the same as the APU's pulses
+/// in a linear mix
+const LEVEL_SCALE: f32 = 0.00752;
+
+/// Counts down CPU cycles, shared by all the channels
+#[derive(Default)]
+struct Timer {
+  period: u16,
+  counter: u16,
+  enabled: bool,
+}
+
+impl Timer {
+  fn write_low(&mut self, value: u8) {
+    self.period = (self.period & 0xF00) | u16::from(value);
+  }
+
+  fn write_high(&mut self, value: u8) {
+    self.period = (self.period & 0x0FF) | (u16::from(value & 0x0F) << 8);
+    self.enabled = value & 0x80 != 0;
+  }
+
+  /// Count a cycle, returning whether the period has elapsed
+  fn clock(&mut self, shift: u8) -> bool {
+    if self.counter == 0 {
+      self.counter = self.period >> shift;
+      true
+    } else {
+      self.counter -= 1;
+      false
+    }
+  }
+}
+
+#[derive(Default)]
+struct Pulse {
+  timer: Timer,
+  volume: u8,
+  duty: u8,
+  /// Output the volume all the time, ignoring the duty
+  ignore_duty: bool,
+  /// Position in the 16 step duty cycle, counting down
+  step: u8,
+}
+
+impl Pulse {
+  fn write(&mut self, register: u16, value: u8) {
+    match register {
+      0 => {
+        self.ignore_duty = value & 0x80 != 0;
+        self.duty = (value >> 4) & 0b111;
+        self.volume = value & 0x0F;
+      }
+      1 => self.timer.write_low(value),
+      2 => {
+        self.timer.write_high(value);
+        if !self.timer.enabled {
+          self.step = 15;
+        }
+      }
+      _ => unreachable!(),
+    }
+  }
+
+  fn clock(&mut self, shift: u8) {
+    if self.timer.enabled && self.timer.clock(shift) {
+      self.step = self.step.wrapping_sub(1) & 0x0F;
+    }
+  }
+
+  fn output(&self) -> u8 {
+    if self.timer.enabled && (self.ignore_duty || self.step <= self.duty) {
+      self.volume
+    } else {
+      0
+    }
+  }
+}
+
+#[derive(Default)]
+struct Sawtooth {
+  timer: Timer,
+  rate: u8,
+  /// Clocks of the timer since the accumulator was reset, from 0 to 13
+  step: u8,
+  accumulator: u8,
+}
+
+impl Sawtooth {
+  fn write(&mut self, register: u16, value: u8) {
+    match register {
+      0 => self.rate = value & 0x3F,
+      1 => self.timer.write_low(value),
+      2 => {
+        self.timer.write_high(value);
+        if !self.timer.enabled {
+          self.step = 0;
+          self.accumulator = 0;
+        }
+      }
+      _ => unreachable!(),
+    }
+  }
+
+  /// The rate is added to the accumulator on every other clock of the
+  /// timer, 6 times, then the 7th time it's reset instead
+  fn clock(&mut self, shift: u8) {
+    if self.timer.enabled && self.timer.clock(shift) {
+      self.step += 1;
+      if self.step == 14 {
+        self.step = 0;
+        self.accumulator = 0;
+      } else if self.step.is_multiple_of(2) {
+        self.accumulator = self.accumulator.wrapping_add(self.rate);
+      }
+    }
+  }
+
+  /// The top 5 bits of the accumulator
+  fn output(&self) -> u8 {
+    self.accumulator >> 3
+  }
+}
+
+#[derive(Default)]
+pub struct Vrc6Audio {
+  pulses: [Pulse; 2],
+  sawtooth: Sawtooth,
+  halted: bool,
+  /// Bits to shift the periods right by
+  shift: u8,
+}
+
+impl Vrc6Audio {
+  /// Write a register, where `addr` is $9000-$B002 as wired on VRC6a
+  pub fn write(&mut self, addr: u16, value: u8) {
+    let register = addr & 0x0003;
+    match addr {
+      0x9000...0x9002 => self.pulses[0].write(register, value),
+      0x9003 => {
+        self.halted = value & 0b001 != 0;
+        self.shift = if value & 0b100 != 0 {
+          8
+        } else if value & 0b010 != 0 {
+          4
+        } else {
+          0
+        };
+      }
+      0xA000...0xA002 => self.pulses[1].write(register, value),
+      0xB000...0xB002 => self.sawtooth.write(register, value),
+      _ => {}
+    }
+  }
+
+  /// Clocked every CPU cycle
+  pub fn clock(&mut self) {
+    if self.halted {
+      return;
+    }
+    for pulse in self.pulses.iter_mut() {
+      pulse.clock(self.shift);
+    }
+    self.sawtooth.clock(self.shift);
+  }
+
+  /// The level of all three channels, from 0.0 to about 0.46
+  pub fn output(&self) -> f32 {
+    self.voices().iter().sum()
+  }
+
+  /// The levels of pulse 1, pulse 2 and the sawtooth
+  pub fn voices(&self) -> VoiceLevels {
+    let mut voices = [0.0; MAX_VOICES];
+    voices[0] = f32::from(self.pulses[0].output()) * LEVEL_SCALE;
+    voices[1] = f32::from(self.pulses[1].output()) * LEVEL_SCALE;
+    voices[2] = f32::from(self.sawtooth.output()) * LEVEL_SCALE;
+    voices
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// The levels read by `level` after each of a number of cycles
+  fn levels<F: Fn(&Vrc6Audio) -> u8>(audio: &mut Vrc6Audio, cycles: usize, level: F) -> Vec<u8> {
+    (0..cycles)
+      .map(|_| {
+        audio.clock();
+        level(audio)
+      })
+      .collect()
+  }
+
+  #[test]
+  fn pulse_duty_cycle() {
+    let mut audio = Vrc6Audio::default();
+    // Volume 10, duty 3 (4 in 16 steps), period 1 (every 2 cycles)
+    audio.write(0x9000, 0b0011_1010);
+    audio.write(0x9001, 0x01);
+    audio.write(0x9002, 0x80);
+
+    let levels = levels(&mut audio, 32, |audio| audio.pulses[0].output());
+    let high = levels.iter().filter(|&&level| level == 10).count();
+    assert_eq!(high, 8);
+    assert!(levels.iter().all(|&level| level == 0 || level == 10));
+
+    // Ignoring the duty
+    audio.write(0x9000, 0b1011_1010);
+    assert_eq!(audio.pulses[0].output(), 10);
+
+    // Disabled
+    audio.write(0x9002, 0x00);
+    assert_eq!(audio.pulses[0].output(), 0);
+  }
+
+  #[test]
+  fn sawtooth_ramps_and_resets() {
+    let mut audio = Vrc6Audio::default();
+    audio.write(0xB000, 42);
+    audio.write(0xB002, 0x80);
+
+    let levels = levels(&mut audio, 14, |audio| audio.sawtooth.accumulator);
+    assert_eq!(
+      levels,
+      [0, 42, 42, 84, 84, 126, 126, 168, 168, 210, 210, 252, 252, 0]
+    );
+    assert_eq!(audio.sawtooth.output(), 0);
+  }
+
+  #[test]
+  fn halt_and_frequency_shift() {
+    let mut audio = Vrc6Audio::default();
+    audio.write(0xB000, 8);
+    audio.write(0xB001, 0xFF);
+    audio.write(0xB002, 0x8F);
+
+    audio.write(0x9003, 0b001);
+    levels(&mut audio, 100, |audio| audio.sawtooth.accumulator);
+    assert_eq!(audio.sawtooth.step, 0);
+
+    // $FFF >> 8 = a period of 16 cycles
+    audio.write(0x9003, 0b110);
+    audio.clock();
+    assert_eq!(audio.sawtooth.step, 1);
+    let levels = levels(&mut audio, 32, |audio| audio.sawtooth.step);
+    assert_eq!(levels[14], 1);
+    assert_eq!(levels[15], 2);
+    assert_eq!(levels[31], 3);
+  }
+
+  #[test]
+  fn mixes_all_channels() {
+    let mut audio = Vrc6Audio::default();
+    audio.write(0x9000, 0x8F);
+    audio.write(0x9002, 0x80);
+    audio.write(0xA000, 0x85);
+    audio.write(0xA002, 0x80);
+    assert_eq!(audio.output(), 20.0 * LEVEL_SCALE);
+  }
+}
@@ -0,0 +1,131 @@
+//! # Output Filters
+//!
+//! Between the 2A03 and the audio output, the NES has a few simple RC
+//! filters: two high-pass filters (at about 90Hz and 440Hz) which remove
+//! the DC offset and some bass, and a low-pass filter at about 14kHz.
+//! [Read more here][Mixer].
+//!
+//! [Mixer]: https://wiki.nesdev.com/w/index.php/APU_Mixer
+
+use std::f32::consts::PI;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+  HighPass,
+  LowPass,
+}
+
+/// A first order (RC) filter
+pub struct Filter {
+  kind: Kind,
+  alpha: f32,
+  previous_input: f32,
+  previous_output: f32,
+}
+
+impl Filter {
+  pub fn high_pass(sample_rate: u32, cutoff: f32) -> Self {
+    let (rc, dt) = rc_and_dt(sample_rate, cutoff);
+    Filter::new(Kind::HighPass, rc / (rc + dt))
+  }
+
+  pub fn low_pass(sample_rate: u32, cutoff: f32) -> Self {
+    let (rc, dt) = rc_and_dt(sample_rate, cutoff);
+    Filter::new(Kind::LowPass, dt / (rc + dt))
+  }
+
+  fn new(kind: Kind, alpha: f32) -> Self {
+    Filter {
+      kind,
+      alpha,
+      previous_input: 0.0,
+      previous_output: 0.0,
+    }
+  }
+
+  pub fn apply(&mut self, input: f32) -> f32 {
+    let output = match self.kind {
+      Kind::HighPass => self.alpha * (self.previous_output + input - self.previous_input),
+      Kind::LowPass => self.previous_output + self.alpha * (input - self.previous_output),
+    };
+    self.previous_input = input;
+    self.previous_output = output;
+    output
+  }
+}
+
+fn rc_and_dt(sample_rate: u32, cutoff: f32) -> (f32, f32) {
+  (1.0 / (2.0 * PI * cutoff), 1.0 / sample_rate as f32)
+}
+
+/// The NES's filters, applied in order
+pub struct FilterChain {
+  filters: Vec<Filter>,
+}
+
+impl FilterChain {
+  pub fn nes(sample_rate: u32) -> Self {
+    FilterChain {
+      filters: vec![
+        Filter::high_pass(sample_rate, 90.0),
+        Filter::high_pass(sample_rate, 440.0),
+        Filter::low_pass(sample_rate, 14_000.0),
+      ],
+    }
+  }
+
+  pub fn apply(&mut self, sample: f32) -> f32 {
+    self
+      .filters
+      .iter_mut()
+      .fold(sample, |sample, filter| filter.apply(sample))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const SAMPLE_RATE: u32 = 48_000;
+
+  /// The peak output of a filter for a sine wave of amplitude 1
+  fn response(filter: &mut FnMut(f32) -> f32, frequency: f32) -> f32 {
+    (0..SAMPLE_RATE / 10)
+      .map(|n| {
+        let t = n as f32 / SAMPLE_RATE as f32;
+        filter((2.0 * PI * frequency * t).sin())
+      })
+      .skip(SAMPLE_RATE as usize / 20)
+      .fold(0.0, |peak: f32, sample| peak.max(sample.abs()))
+  }
+
+  #[test]
+  fn high_pass_removes_dc() {
+    let mut chain = FilterChain::nes(SAMPLE_RATE);
+    let mut output = 1.0;
+    for _ in 0..SAMPLE_RATE / 10 {
+      output = chain.apply(0.5);
+    }
+    assert!(output.abs() < 1e-3, "{}", output);
+  }
+
+  #[test]
+  fn high_pass_cutoff() {
+    let mut filter = Filter::high_pass(SAMPLE_RATE, 440.0);
+    let at_cutoff = response(&mut |sample| filter.apply(sample), 440.0);
+    assert!((at_cutoff - 0.707).abs() < 0.05, "{}", at_cutoff);
+
+    let mut filter = Filter::high_pass(SAMPLE_RATE, 440.0);
+    assert!(response(&mut |sample| filter.apply(sample), 5000.0) > 0.95);
+  }
+
+  #[test]
+  fn low_pass_cutoff() {
+    let mut filter = Filter::low_pass(SAMPLE_RATE, 2000.0);
+    let at_cutoff = response(&mut |sample| filter.apply(sample), 2000.0);
+    assert!((at_cutoff - 0.707).abs() < 0.05, "{}", at_cutoff);
+
+    let mut filter = Filter::low_pass(SAMPLE_RATE, 2000.0);
+    assert!(response(&mut |sample| filter.apply(sample), 100.0) > 0.95);
+  }
+}
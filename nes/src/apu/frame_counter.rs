@@ -0,0 +1,239 @@
+//! # Frame Counter
+//!
+//! Divides the CPU clock down to roughly 240Hz "quarter frames", which
+//! clock the envelopes and the triangle's linear counter, and 120Hz "half
+//! frames", which clock the length counters and sweeps. In 4 step mode
+//! it also raises an IRQ at the end of each sequence, unless inhibited.
+//! [Read more here][Frame].
+//!
+//!  Register | Legend    | Bits
+//! ----------|-----------|---------------------------------------
+//!   $4017   | MI-- ---- | Mode (0: 4 step, 1: 5 step), IRQ inhibit
+//!
+//! Below are the CPU cycles (since the sequence was reset) at which each
+//! step happens.
+//!
+//!  4 step | 5 step | Clocks
+//! --------|--------|-----------------------------
+//!   7457  |  7457  | Quarter frame
+//!  14913  | 14913  | Quarter and half frame
+//!  22371  | 22371  | Quarter frame
+//!  29828  |        | IRQ
+//!  29829  |        | Quarter and half frame, IRQ
+//!  29830  |        | IRQ, sequence restarts
+//!         | 37281  | Quarter and half frame
+//!         | 37282  | Sequence restarts
+//!
+//! [Frame]: https://wiki.nesdev.com/w/index.php/APU_Frame_Counter
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+  FourStep,
+  FiveStep,
+}
+
+/// The frame units to clock on a cycle
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct FrameClock {
+  pub quarter: bool,
+  pub half: bool,
+}
+
+impl FrameClock {
+  fn quarter() -> Self {
+    FrameClock {
+      quarter: true,
+      half: false,
+    }
+  }
+
+  fn half() -> Self {
+    FrameClock {
+      quarter: true,
+      half: true,
+    }
+  }
+}
+
+pub struct FrameCounter {
+  mode: Mode,
+  irq_inhibit: bool,
+  interrupt: bool,
+
+  /// CPU cycles since the sequence was reset
+  cycle: u32,
+
+  /// CPU cycles until a $4017 write resets the sequence
+  reset_delay: u8,
+}
+
+impl Default for FrameCounter {
+  fn default() -> Self {
+    FrameCounter {
+      mode: Mode::FourStep,
+      irq_inhibit: false,
+      interrupt: false,
+      cycle: 0,
+      reset_delay: 0,
+    }
+  }
+}
+
+impl FrameCounter {
+  /// Write $4017. The sequence resets 3 CPU cycles later if written on
+  /// an APU cycle (`odd_cycle` false), or 4 cycles later otherwise.
+  pub fn write(&mut self, value: u8, odd_cycle: bool) {
+    self.mode = if value & 0b1000_0000 != 0 {
+      Mode::FiveStep
+    } else {
+      Mode::FourStep
+    };
+    self.irq_inhibit = value & 0b0100_0000 != 0;
+    if self.irq_inhibit {
+      self.interrupt = false;
+    }
+    self.reset_delay = if odd_cycle { 4 } else { 3 };
+  }
+
+  /// Whether the frame IRQ is being asserted
+  pub fn interrupt(&self) -> bool {
+    self.interrupt
+  }
+
+  /// Acknowledge the frame IRQ, by reading $4015
+  pub fn clear_interrupt(&mut self) {
+    self.interrupt = false;
+  }
+
+  /// Clocked every CPU cycle
+  pub fn clock(&mut self) -> FrameClock {
+    if self.reset_delay > 0 {
+      self.reset_delay -= 1;
+      if self.reset_delay == 0 {
+        self.cycle = 0;
+        // Writing 5 step mode immediately clocks all the units
+        if self.mode == Mode::FiveStep {
+          return FrameClock::half();
+        }
+        return FrameClock::default();
+      }
+    }
+
+    self.cycle += 1;
+    match (self.mode, self.cycle) {
+      (_, 7457) => FrameClock::quarter(),
+      (_, 14913) => FrameClock::half(),
+      (_, 22371) => FrameClock::quarter(),
+      (Mode::FourStep, 29828) => {
+        self.raise_interrupt();
+        FrameClock::default()
+      }
+      (Mode::FourStep, 29829) => {
+        self.raise_interrupt();
+        FrameClock::half()
+      }
+      (Mode::FourStep, 29830) => {
+        self.raise_interrupt();
+        self.cycle = 0;
+        FrameClock::default()
+      }
+      (Mode::FiveStep, 37281) => FrameClock::half(),
+      (Mode::FiveStep, 37282) => {
+        self.cycle = 0;
+        FrameClock::default()
+      }
+      _ => FrameClock::default(),
+    }
+  }
+
+  fn raise_interrupt(&mut self) {
+    if !self.irq_inhibit {
+      self.interrupt = true;
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// The cycles (counting from 1) on which quarter and half frames are
+  /// clocked over a number of cycles
+  fn clocks(counter: &mut FrameCounter, cycles: u32) -> (Vec<u32>, Vec<u32>) {
+    let mut quarters = vec![];
+    let mut halves = vec![];
+    for cycle in 1..=cycles {
+      let clock = counter.clock();
+      if clock.quarter {
+        quarters.push(cycle);
+      }
+      if clock.half {
+        halves.push(cycle);
+      }
+    }
+    (quarters, halves)
+  }
+
+  #[test]
+  fn four_step_sequence() {
+    let mut counter = FrameCounter::default();
+    let (quarters, halves) = clocks(&mut counter, 29830 * 2);
+    assert_eq!(
+      quarters,
+      [7457, 14913, 22371, 29829, 37287, 44743, 52201, 59659]
+    );
+    assert_eq!(halves, [14913, 29829, 44743, 59659]);
+  }
+
+  #[test]
+  fn five_step_sequence() {
+    let mut counter = FrameCounter::default();
+    counter.write(0x80, false);
+    let (quarters, halves) = clocks(&mut counter, 3 + 37282);
+
+    // Clocked straight away by the write
+    assert_eq!(quarters, [3, 3 + 7457, 3 + 14913, 3 + 22371, 3 + 37281]);
+    assert_eq!(halves, [3, 3 + 14913, 3 + 37281]);
+    assert!(!counter.interrupt());
+  }
+
+  #[test]
+  fn write_delay() {
+    let mut counter = FrameCounter::default();
+    counter.write(0x00, true);
+    let (quarters, _) = clocks(&mut counter, 4 + 7457);
+    assert_eq!(quarters, [4 + 7457]);
+  }
+
+  #[test]
+  fn irq_at_end_of_four_step_sequence() {
+    let mut counter = FrameCounter::default();
+    clocks(&mut counter, 29827);
+    assert!(!counter.interrupt());
+    counter.clock();
+    assert!(counter.interrupt());
+
+    // Still set on the next 2 cycles after being cleared
+    counter.clear_interrupt();
+    counter.clock();
+    assert!(counter.interrupt());
+    counter.clear_interrupt();
+    counter.clock();
+    assert!(counter.interrupt());
+    counter.clear_interrupt();
+    counter.clock();
+    assert!(!counter.interrupt());
+  }
+
+  #[test]
+  fn inhibit_clears_irq() {
+    let mut counter = FrameCounter::default();
+    clocks(&mut counter, 29830);
+    assert!(counter.interrupt());
+
+    counter.write(0x40, false);
+    assert!(!counter.interrupt());
+    clocks(&mut counter, 29830 * 2);
+    assert!(!counter.interrupt());
+  }
+}
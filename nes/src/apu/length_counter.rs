@@ -0,0 +1,99 @@
+//! # Length Counter
+//!
+//! Silences a channel automatically after a number of half frames, loaded
+//! from a lookup table by the top 5 bits of the channel's last register.
+//! [Read more here][Length].
+//!
+//! [Length]: https://wiki.nesdev.com/w/index.php/APU_Length_Counter
+
+/// Lengths (in half frames) indexed by the `LLLL L---` bits
+const LENGTH_TABLE: [u8; 32] = [
+  10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14, 12, 16, 24, 18, 48, 20, 96, 22,
+  192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+#[derive(Default)]
+pub struct LengthCounter {
+  enabled: bool,
+  halted: bool,
+  counter: u8,
+}
+
+impl LengthCounter {
+  /// Enable or disable the channel through $4015. Disabling the channel
+  /// silences it immediately.
+  pub fn set_enabled(&mut self, enabled: bool) {
+    self.enabled = enabled;
+    if !enabled {
+      self.counter = 0;
+    }
+  }
+
+  /// Stop the counter (while still letting it be loaded)
+  pub fn set_halted(&mut self, halted: bool) {
+    self.halted = halted;
+  }
+
+  /// Load the counter from the `LLLL L---` bits of the channel's last
+  /// register. Ignored while the channel is disabled.
+  pub fn load(&mut self, value: u8) {
+    if self.enabled {
+      self.counter = LENGTH_TABLE[(value >> 3) as usize];
+    }
+  }
+
+  /// Clocked by the frame counter every half frame
+  pub fn clock(&mut self) {
+    if !self.halted && self.counter > 0 {
+      self.counter -= 1;
+    }
+  }
+
+  /// Whether the channel is still sounding
+  pub fn is_active(&self) -> bool {
+    self.counter > 0
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn loads_from_table() {
+    let mut length = LengthCounter::default();
+    length.set_enabled(true);
+    length.load(0b0000_1000);
+    for _ in 0..253 {
+      length.clock();
+    }
+    assert!(length.is_active());
+    length.clock();
+    assert!(!length.is_active());
+  }
+
+  #[test]
+  fn ignores_loads_when_disabled() {
+    let mut length = LengthCounter::default();
+    length.load(0b1111_1000);
+    assert!(!length.is_active());
+
+    length.set_enabled(true);
+    length.load(0b1111_1000);
+    assert!(length.is_active());
+    length.set_enabled(false);
+    assert!(!length.is_active());
+  }
+
+  #[test]
+  fn halt_stops_counting() {
+    let mut length = LengthCounter::default();
+    length.set_enabled(true);
+    length.set_halted(true);
+    length.load(0b0001_1000);
+    for _ in 0..10 {
+      length.clock();
+    }
+    assert!(length.is_active());
+  }
+}
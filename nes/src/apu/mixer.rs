@@ -0,0 +1,459 @@
+//! # Mixer
+//!
+//! Combines the channel levels into a single sample the way the NES's
+//! resistor networks do: the pulses are mixed together non-linearly, as
+//! are the triangle, noise and DMC, using the lookup tables described
+//! [here][Mixer]. Alternatively the channels can be mixed linearly, for a
+//! cleaner sound without the intermodulation of the real hardware.
+//!
+//! Expansion audio from the cartridge (eg, VRC6 or Namco 163) is mixed in
+//! linearly after the APU's own channels.
+//!
+//! For debugging, each channel, and each voice of the expansion audio, can
+//! be muted, soloed or have its volume changed. This only affects the mix,
+//! not the state of the channels.
+//!
+//! [Mixer]: https://wiki.nesdev.com/w/index.php/APU_Mixer
+
+use apu::expansion::{VoiceLevels, MAX_VOICES};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MixMode {
+  /// The 2A03's non-linear DAC, as heard on hardware
+  NonLinear,
+  /// A linear approximation of the same levels
+  Linear,
+}
+
+/// The output levels of the APU channels, from 0-15 (or 0-127 for the DMC)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ChannelLevels {
+  pub pulse_1: u8,
+  pub pulse_2: u8,
+  pub triangle: u8,
+  pub noise: u8,
+  pub dmc: u8,
+}
+
+/// A channel which can be controlled in the mix
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Channel {
+  Pulse1,
+  Pulse2,
+  Triangle,
+  Noise,
+  Dmc,
+  /// A voice of the expansion audio, from 0 to `MAX_VOICES - 1` (see
+  /// `apu::expansion` for each chip's)
+  Expansion(u8),
+}
+
+/// The APU's channels, then the expansion voices
+const NUM_CHANNELS: usize = 5 + MAX_VOICES;
+
+const EXPANSION_NAMES: [&str; MAX_VOICES] = [
+  "expansion voice 1",
+  "expansion voice 2",
+  "expansion voice 3",
+  "expansion voice 4",
+  "expansion voice 5",
+  "expansion voice 6",
+  "expansion voice 7",
+  "expansion voice 8",
+];
+
+impl Channel {
+  pub const ALL: [Channel; NUM_CHANNELS] = [
+    Channel::Pulse1,
+    Channel::Pulse2,
+    Channel::Triangle,
+    Channel::Noise,
+    Channel::Dmc,
+    Channel::Expansion(0),
+    Channel::Expansion(1),
+    Channel::Expansion(2),
+    Channel::Expansion(3),
+    Channel::Expansion(4),
+    Channel::Expansion(5),
+    Channel::Expansion(6),
+    Channel::Expansion(7),
+  ];
+
+  pub fn name(self) -> &'static str {
+    match self {
+      Channel::Pulse1 => "pulse 1",
+      Channel::Pulse2 => "pulse 2",
+      Channel::Triangle => "triangle",
+      Channel::Noise => "noise",
+      Channel::Dmc => "DMC",
+      Channel::Expansion(voice) => EXPANSION_NAMES[usize::from(voice) % MAX_VOICES],
+    }
+  }
+
+  fn index(self) -> usize {
+    match self {
+      Channel::Pulse1 => 0,
+      Channel::Pulse2 => 1,
+      Channel::Triangle => 2,
+      Channel::Noise => 3,
+      Channel::Dmc => 4,
+      Channel::Expansion(voice) => 5 + usize::from(voice) % MAX_VOICES,
+    }
+  }
+}
+
+/// Changes to the channel controls, eg, sent from a frontend's hotkeys
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MixerEvent {
+  ToggleMute(Channel),
+  ToggleSolo(Channel),
+  /// Add to a channel's gain (which is kept from 0.0 to `MAX_GAIN`)
+  AdjustGain(Channel, f32),
+  /// Unmute and unsolo every channel, and set their gains back to 1.0
+  ResetControls,
+}
+
+pub const MAX_GAIN: f32 = 2.0;
+
+pub struct Mixer {
+  mode: MixMode,
+
+  /// Indexed by `Channel::index`
+  gains: [f32; NUM_CHANNELS],
+  muted: [bool; NUM_CHANNELS],
+  soloed: [bool; NUM_CHANNELS],
+
+  /// Indexed by pulse 1 + pulse 2
+  pulse_table: [f32; 31],
+
+  /// Indexed by 3 * triangle + 2 * noise + DMC
+  tnd_table: [f32; 203],
+}
+
+impl Mixer {
+  pub fn new(mode: MixMode) -> Self {
+    let mut pulse_table = [0.0; 31];
+    for (n, level) in pulse_table.iter_mut().enumerate().skip(1) {
+      *level = 95.52 / (8128.0 / n as f32 + 100.0);
+    }
+
+    let mut tnd_table = [0.0; 203];
+    for (n, level) in tnd_table.iter_mut().enumerate().skip(1) {
+      *level = 163.67 / (24329.0 / n as f32 + 100.0);
+    }
+
+    Mixer {
+      mode,
+      gains: [1.0; NUM_CHANNELS],
+      muted: [false; NUM_CHANNELS],
+      soloed: [false; NUM_CHANNELS],
+      pulse_table,
+      tnd_table,
+    }
+  }
+
+  pub fn mode(&self) -> MixMode {
+    self.mode
+  }
+
+  pub fn set_mode(&mut self, mode: MixMode) {
+    self.mode = mode;
+  }
+
+  pub fn gain(&self, channel: Channel) -> f32 {
+    self.gains[channel.index()]
+  }
+
+  pub fn set_gain(&mut self, channel: Channel, gain: f32) {
+    self.gains[channel.index()] = gain.clamp(0.0, MAX_GAIN);
+  }
+
+  pub fn is_muted(&self, channel: Channel) -> bool {
+    self.muted[channel.index()]
+  }
+
+  pub fn set_muted(&mut self, channel: Channel, muted: bool) {
+    self.muted[channel.index()] = muted;
+  }
+
+  pub fn is_soloed(&self, channel: Channel) -> bool {
+    self.soloed[channel.index()]
+  }
+
+  /// While any channels are soloed, only they are heard
+  pub fn set_soloed(&mut self, channel: Channel, soloed: bool) {
+    self.soloed[channel.index()] = soloed;
+  }
+
+  pub fn apply(&mut self, event: MixerEvent) {
+    match event {
+      MixerEvent::ToggleMute(channel) => {
+        let muted = self.is_muted(channel);
+        self.set_muted(channel, !muted);
+      }
+      MixerEvent::ToggleSolo(channel) => {
+        let soloed = self.is_soloed(channel);
+        self.set_soloed(channel, !soloed);
+      }
+      MixerEvent::AdjustGain(channel, change) => {
+        let gain = self.gain(channel);
+        self.set_gain(channel, gain + change);
+      }
+      MixerEvent::ResetControls => {
+        self.gains = [1.0; NUM_CHANNELS];
+        self.muted = [false; NUM_CHANNELS];
+        self.soloed = [false; NUM_CHANNELS];
+      }
+    }
+  }
+
+  /// The gain each channel is mixed at, after muting and soloing
+  fn effective_gains(&self) -> [f32; NUM_CHANNELS] {
+    let any_soloed = self.soloed.iter().any(|&soloed| soloed);
+    let mut gains = self.gains;
+    for (channel, gain) in gains.iter_mut().enumerate() {
+      if self.muted[channel] || (any_soloed && !self.soloed[channel]) {
+        *gain = 0.0;
+      }
+    }
+    gains
+  }
+
+  /// Mix the channel levels and any expansion audio into a sample, where
+  /// the APU channels alone range from 0.0 to about 1.0
+  pub fn mix(&self, levels: &ChannelLevels, expansion: &VoiceLevels) -> f32 {
+    self.mix_with_gains(levels, expansion, &self.effective_gains())
+  }
+
+  /// The level of each channel as if it were playing alone, in the order
+  /// pulse 1, pulse 2, triangle, noise and DMC, ignoring the channel
+  /// controls
+  pub fn mix_channels(&self, levels: &ChannelLevels) -> [f32; 5] {
+    let alone = [
+      ChannelLevels {
+        pulse_1: levels.pulse_1,
+        ..ChannelLevels::default()
+      },
+      ChannelLevels {
+        pulse_2: levels.pulse_2,
+        ..ChannelLevels::default()
+      },
+      ChannelLevels {
+        triangle: levels.triangle,
+        ..ChannelLevels::default()
+      },
+      ChannelLevels {
+        noise: levels.noise,
+        ..ChannelLevels::default()
+      },
+      ChannelLevels {
+        dmc: levels.dmc,
+        ..ChannelLevels::default()
+      },
+    ];
+    let mut mixed = [0.0; 5];
+    for (level, channel) in mixed.iter_mut().zip(alone.iter()) {
+      *level = self.mix_with_gains(channel, &[0.0; MAX_VOICES], &[1.0; NUM_CHANNELS]);
+    }
+    mixed
+  }
+
+  fn mix_with_gains(
+    &self,
+    levels: &ChannelLevels,
+    expansion: &VoiceLevels,
+    gains: &[f32; NUM_CHANNELS],
+  ) -> f32 {
+    let apu = match self.mode {
+      MixMode::NonLinear => {
+        let pulse = [levels.pulse_1, levels.pulse_2];
+        let tnd = [3 * levels.triangle, 2 * levels.noise, levels.dmc];
+        group_mix(&self.pulse_table, &pulse, &gains[0..2])
+          + group_mix(&self.tnd_table, &tnd, &gains[2..5])
+      }
+      MixMode::Linear => {
+        0.00752 * f32::from(levels.pulse_1) * gains[0]
+          + 0.00752 * f32::from(levels.pulse_2) * gains[1]
+          + 0.00851 * f32::from(levels.triangle) * gains[2]
+          + 0.00494 * f32::from(levels.noise) * gains[3]
+          + 0.00335 * f32::from(levels.dmc) * gains[4]
+      }
+    };
+    let expansion: f32 = expansion
+      .iter()
+      .zip(&gains[5..])
+      .map(|(level, gain)| level * gain)
+      .sum();
+    apu + expansion
+  }
+}
+
+/// Look up the non-linear output of a group of channels which share a DAC
+/// (given their weighted levels). Silenced channels are left out, as if
+/// they weren't playing, and the output is split between the rest in
+/// proportion to their levels to apply their gains.
+fn group_mix(table: &[f32], levels: &[u8], gains: &[f32]) -> f32 {
+  let total: usize = levels
+    .iter()
+    .zip(gains)
+    .filter(|&(_, &gain)| gain > 0.0)
+    .map(|(&level, _)| usize::from(level))
+    .sum();
+  let output = table[total];
+  if total == 0 || gains.iter().all(|&gain| gain == 1.0 || gain == 0.0) {
+    return output;
+  }
+  levels
+    .iter()
+    .zip(gains)
+    .map(|(&level, gain)| output * f32::from(level) / total as f32 * gain)
+    .sum()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const NO_EXPANSION: VoiceLevels = [0.0; MAX_VOICES];
+
+  /// Expansion audio with the first voices at these levels
+  fn expansion(levels: &[f32]) -> VoiceLevels {
+    let mut voices = NO_EXPANSION;
+    voices[..levels.len()].copy_from_slice(levels);
+    voices
+  }
+
+  fn all_channels() -> ChannelLevels {
+    ChannelLevels {
+      pulse_1: 15,
+      pulse_2: 15,
+      triangle: 15,
+      noise: 15,
+      dmc: 127,
+    }
+  }
+
+  #[test]
+  fn silence() {
+    let mixer = Mixer::new(MixMode::NonLinear);
+    assert_eq!(mixer.mix(&ChannelLevels::default(), &NO_EXPANSION), 0.0);
+  }
+
+  #[test]
+  fn full_volume() {
+    let sample = Mixer::new(MixMode::NonLinear).mix(&all_channels(), &NO_EXPANSION);
+    assert!(sample > 0.99 && sample < 1.01, "{}", sample);
+
+    // The linear approximation is a little quieter at the top end
+    let sample = Mixer::new(MixMode::Linear).mix(&all_channels(), &NO_EXPANSION);
+    assert!(sample > 0.8 && sample < 1.0, "{}", sample);
+  }
+
+  #[test]
+  fn non_linear() {
+    let mixer = Mixer::new(MixMode::NonLinear);
+    let one = ChannelLevels {
+      pulse_1: 15,
+      ..ChannelLevels::default()
+    };
+    let both = ChannelLevels { pulse_2: 15, ..one };
+    assert!(mixer.mix(&both, &NO_EXPANSION) < 2.0 * mixer.mix(&one, &NO_EXPANSION));
+
+    let mixer = Mixer::new(MixMode::Linear);
+    assert!((mixer.mix(&both, &NO_EXPANSION) - 2.0 * mixer.mix(&one, &NO_EXPANSION)).abs() < 1e-6);
+  }
+
+  #[test]
+  fn adds_expansion_audio() {
+    let mixer = Mixer::new(MixMode::NonLinear);
+    let voices = expansion(&[0.25, 0.125]);
+    assert_eq!(mixer.mix(&ChannelLevels::default(), &voices), 0.375);
+  }
+
+  #[test]
+  fn expansion_voice_controls() {
+    let mut mixer = Mixer::new(MixMode::NonLinear);
+    let levels = ChannelLevels::default();
+    let voices = expansion(&[0.25, 0.125, 0.0625]);
+
+    mixer.set_muted(Channel::Expansion(0), true);
+    assert_eq!(mixer.mix(&levels, &voices), 0.1875);
+
+    mixer.set_soloed(Channel::Expansion(2), true);
+    assert_eq!(mixer.mix(&levels, &voices), 0.0625);
+
+    mixer.apply(MixerEvent::ResetControls);
+    mixer.apply(MixerEvent::AdjustGain(Channel::Expansion(1), 1.0));
+    assert_eq!(mixer.mix(&levels, &voices), 0.5625);
+  }
+
+  #[test]
+  fn channel_names() {
+    assert_eq!(Channel::Dmc.name(), "DMC");
+    assert_eq!(Channel::Expansion(7).name(), "expansion voice 8");
+    let indices: Vec<usize> = Channel::ALL.iter().map(|channel| channel.index()).collect();
+    assert_eq!(indices, (0..NUM_CHANNELS).collect::<Vec<_>>());
+  }
+
+  #[test]
+  fn mute_and_solo() {
+    let mut mixer = Mixer::new(MixMode::NonLinear);
+    let levels = all_channels();
+    let voices = expansion(&[0.5]);
+    let full = mixer.mix(&levels, &voices);
+
+    mixer.set_muted(Channel::Expansion(0), true);
+    assert!((mixer.mix(&levels, &voices) - (full - 0.5)).abs() < 1e-6);
+
+    mixer.set_soloed(Channel::Triangle, true);
+    let triangle = mixer.mix_channels(&levels)[2];
+    assert!((mixer.mix(&levels, &voices) - triangle).abs() < 1e-6);
+
+    // Muting wins over soloing
+    mixer.set_muted(Channel::Triangle, true);
+    assert_eq!(mixer.mix(&levels, &voices), 0.0);
+
+    mixer.apply(MixerEvent::ResetControls);
+    assert_eq!(mixer.mix(&levels, &voices), full);
+  }
+
+  #[test]
+  fn gain() {
+    let mut mixer = Mixer::new(MixMode::NonLinear);
+    let levels = ChannelLevels {
+      pulse_1: 10,
+      pulse_2: 5,
+      ..ChannelLevels::default()
+    };
+    let full = mixer.mix(&levels, &NO_EXPANSION);
+
+    mixer.apply(MixerEvent::AdjustGain(Channel::Pulse2, -1.0));
+    assert_eq!(mixer.gain(Channel::Pulse2), 0.0);
+    let pulse_1 = mixer.mix(&levels, &NO_EXPANSION);
+    assert_eq!(pulse_1, mixer.mix_channels(&levels)[0]);
+
+    mixer.apply(MixerEvent::AdjustGain(Channel::Pulse1, 5.0));
+    assert_eq!(mixer.gain(Channel::Pulse1), MAX_GAIN);
+    assert!((mixer.mix(&levels, &NO_EXPANSION) - 2.0 * pulse_1).abs() < 1e-6);
+
+    // The output of the shared DAC is split by level
+    mixer.set_gain(Channel::Pulse2, 1.0);
+    assert!((mixer.mix(&levels, &NO_EXPANSION) - full * 25.0 / 15.0).abs() < 1e-6);
+
+    // The stems are unaffected
+    let alone = Mixer::new(MixMode::NonLinear).mix_channels(&levels);
+    assert_eq!(mixer.mix_channels(&levels), alone);
+  }
+
+  #[test]
+  fn channels_alone() {
+    let mixer = Mixer::new(MixMode::NonLinear);
+    let levels = ChannelLevels {
+      triangle: 15,
+      ..ChannelLevels::default()
+    };
+    let channels = mixer.mix_channels(&levels);
+    assert_eq!(channels[2], mixer.mix(&levels, &NO_EXPANSION));
+    assert_eq!(channels[0] + channels[1] + channels[3] + channels[4], 0.0);
+  }
+}
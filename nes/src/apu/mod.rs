@@ -1,7 +1,56 @@
+//! # Register Information
+//!
+//! This table is a mapping of the register locations with the roles of said registers.
+//!
+//!  Registers  | Channels | Units
+//! ------------|----------|-----------------------------------------------------------------
+//! $4000-$4003 | Pulse 1  | Timer, length counter, envelope, sweep
+//! $4004-$4007 | Pulse 2  | Timer, length counter, envelope, sweep
+//! $4008-$400B | Triangle | Timer, length counter, linear counter
+//! $400C-$400F | Noise    | Timer, length counter, envelope, linear feedback shift register
+//! $4010-$4013 | DMC      | Timer, memory reader, sample buffer, output unit
+//! $4015       | All      | Channel enable and length counter status
+//! $4017       | All      | Frame counter
+
+use apu::expansion::VoiceLevels;
 use memory::WriteAddr;
 
-pub mod channel;
-pub mod channel_differ;
+pub mod blip_buffer;
+pub mod dmc;
+pub mod envelope;
+pub mod expansion;
+pub mod filter;
+pub mod frame_counter;
+pub mod length_counter;
+pub mod mixer;
+pub mod noise;
 pub mod processor;
+pub mod pulse;
+pub mod sweep;
+pub mod triangle;
+
+/// The console variant, which changes some of the APU's timings
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Region {
+  #[default]
+  Ntsc,
+  Pal,
+}
+
+pub trait Apu: WriteAddr {
+  /// Run a single APU cycle, once for every CPU cycle
+  fn cycle(&mut self);
+
+  /// Whether the APU is asserting the CPU's IRQ line
+  fn irq(&self) -> bool;
+
+  /// The address of a byte of CPU memory the APU needs read by DMA
+  fn dma_address(&self) -> Option<u16>;
+
+  /// Deliver the byte read from `dma_address`
+  fn dma_fill(&mut self, value: u8);
 
-pub trait Apu: WriteAddr {}
+  /// Set the current level of each voice of any expansion audio on the
+  /// cartridge, which is mixed in with the APU's own channels
+  fn set_expansion_audio(&mut self, voices: VoiceLevels);
+}
@@ -0,0 +1,206 @@
+//! # Noise
+//!
+//! Pseudo-random noise from a 15 bit linear feedback shift register,
+//! clocked at one of 16 rates, with a volume envelope and a length
+//! counter. [Read more here][Noise].
+//!
+//!  Register | Legend    | Bits
+//! ----------|-----------|------------------------------------------------
+//!   $400C   | --LC VVVV | envelope Loop / length counter halt, Constant
+//!           |           | volume, Volume / envelope period
+//!   $400E   | M--- PPPP | Mode, Period
+//!   $400F   | LLLL L--- | Length counter load
+//!
+//! In mode 0 the shift register's feedback comes from bits 0 and 1, giving
+//! a sequence 32767 steps long which sounds like white noise. Mode 1 uses
+//! bits 0 and 6 for a sequence only 93 (or 31) steps long, which sounds
+//! metallic or buzzy.
+//!
+//! [Noise]: https://wiki.nesdev.com/w/index.php/APU_Noise
+
+use apu::envelope::Envelope;
+use apu::length_counter::LengthCounter;
+use apu::Region;
+
+/// Timer periods (in CPU cycles) indexed by the P bits
+const NTSC_PERIOD_TABLE: [u16; 16] = [
+  4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+];
+const PAL_PERIOD_TABLE: [u16; 16] = [
+  4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778,
+];
+
+pub struct Noise {
+  pub length: LengthCounter,
+  envelope: Envelope,
+  period_table: &'static [u16; 16],
+  period: u16,
+  timer: u16,
+  short_mode: bool,
+  shift_register: u16,
+}
+
+impl Default for Noise {
+  fn default() -> Self {
+    Noise {
+      length: LengthCounter::default(),
+      envelope: Envelope::default(),
+      period_table: &NTSC_PERIOD_TABLE,
+      period: NTSC_PERIOD_TABLE[0],
+      timer: 0,
+      short_mode: false,
+      // Loaded with 1 at power on
+      shift_register: 1,
+    }
+  }
+}
+
+impl Noise {
+  /// Use the period table of the given console (the current period is
+  /// kept until $400E is next written)
+  pub fn set_region(&mut self, region: Region) {
+    self.period_table = match region {
+      Region::Ntsc => &NTSC_PERIOD_TABLE,
+      Region::Pal => &PAL_PERIOD_TABLE,
+    };
+  }
+
+  /// Write one of the channel's 4 registers, where `register` is 0-3
+  pub fn write(&mut self, register: u16, value: u8) {
+    match register {
+      0 => {
+        self.length.set_halted(value & 0b0010_0000 != 0);
+        self.envelope.write(value);
+      }
+      1 => {}
+      2 => {
+        self.short_mode = value & 0b1000_0000 != 0;
+        self.period = self.period_table[(value & 0b1111) as usize];
+      }
+      3 => {
+        self.length.load(value);
+        self.envelope.restart();
+      }
+      _ => unreachable!(),
+    }
+  }
+
+  /// Clocked every CPU cycle
+  pub fn clock_timer(&mut self) {
+    if self.timer == 0 {
+      self.timer = self.period - 1;
+      self.clock_shift_register();
+    } else {
+      self.timer -= 1;
+    }
+  }
+
+  fn clock_shift_register(&mut self) {
+    let tap = if self.short_mode { 6 } else { 1 };
+    let feedback = (self.shift_register ^ (self.shift_register >> tap)) & 1;
+    self.shift_register = (self.shift_register >> 1) | (feedback << 14);
+  }
+
+  pub fn clock_quarter_frame(&mut self) {
+    self.envelope.clock();
+  }
+
+  pub fn clock_half_frame(&mut self) {
+    self.length.clock();
+  }
+
+  /// The current output level, from 0 to 15
+  pub fn output(&self) -> u8 {
+    if self.shift_register & 1 != 0 || !self.length.is_active() {
+      return 0;
+    }
+    self.envelope.output()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// The number of steps before the shift register repeats
+  fn sequence_length(noise: &mut Noise) -> usize {
+    // Skip past any lead in to the repeating sequence
+    for _ in 0..100 {
+      noise.clock_shift_register();
+    }
+    let start = noise.shift_register;
+    let mut steps = 0;
+    loop {
+      noise.clock_shift_register();
+      steps += 1;
+      if noise.shift_register == start {
+        return steps;
+      }
+    }
+  }
+
+  fn playing_noise(mode: u8) -> Noise {
+    let mut noise = Noise::default();
+    noise.length.set_enabled(true);
+    noise.write(0, 0b0001_1111);
+    noise.write(2, mode);
+    noise.write(3, 0b1111_1000);
+    noise
+  }
+
+  fn levels(noise: &mut Noise, count: usize) -> Vec<u8> {
+    (0..count * 4)
+      .filter_map(|cycle| {
+        noise.clock_timer();
+        if cycle % 4 == 0 {
+          Some(noise.output())
+        } else {
+          None
+        }
+      })
+      .collect()
+  }
+
+  #[test]
+  fn long_mode_sequence() {
+    let mut noise = playing_noise(0x00);
+    assert_eq!(sequence_length(&mut noise), 32767);
+  }
+
+  #[test]
+  fn short_mode_sequence() {
+    let mut noise = playing_noise(0x80);
+    assert_eq!(sequence_length(&mut noise), 93);
+  }
+
+  #[test]
+  fn output_is_reproducible() {
+    let mut noise = playing_noise(0x00);
+    let mut other = playing_noise(0x00);
+    let levels_1 = levels(&mut noise, 64);
+    assert_eq!(levels_1, levels(&mut other, 64));
+    assert!(levels_1.iter().all(|&level| level == 0 || level == 15));
+    assert!(levels_1.contains(&15));
+
+    // The first steps from power on
+    assert_eq!(levels_1[..8], [15, 15, 15, 15, 15, 15, 15, 15]);
+  }
+
+  #[test]
+  fn silent_until_length_loaded() {
+    let mut noise = playing_noise(0x00);
+    noise.length.set_enabled(false);
+    noise.length.set_enabled(true);
+    assert!(levels(&mut noise, 64).iter().all(|&level| level == 0));
+  }
+
+  #[test]
+  fn region_period_tables() {
+    let mut noise = Noise::default();
+    noise.write(2, 0x0F);
+    assert_eq!(noise.period, 4068);
+    noise.set_region(Region::Pal);
+    noise.write(2, 0x0F);
+    assert_eq!(noise.period, 3778);
+  }
+}
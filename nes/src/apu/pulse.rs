@@ -0,0 +1,162 @@
+//! # Pulse 1 & 2
+//!
+//! A square wave with a choice of 4 duty cycles, a volume envelope, a
+//! sweep unit and a length counter. [Read more here][Pulse].
+//!
+//!  Pulse 1 | Pulse 2 | Legend    | Bits
+//! ---------|---------|-----------|--------------------------------------------
+//!   $4000  |  $4004  | DDLC VVVV | Duty, envelope Loop / length counter halt,
+//!          |         |           | Constant volume, Volume / envelope period
+//!   $4001  |  $4005  | EPPP NSSS | Sweep (see `apu::sweep`)
+//!   $4002  |  $4006  | TTTT TTTT | Timer low
+//!   $4003  |  $4007  | LLLL LTTT | Length counter load, Timer high
+//!
+//! Below is the pulse shape based on the D bits.
+//!
+//!  Value | Repr | Percentage | Wave Shape
+//! -------|------|------------|-----------------
+//!    0   |  00  |    12.5%   | 0 1 0 0 0 0 0 0
+//!    1   |  01  |    25%     | 0 1 1 0 0 0 0 0
+//!    2   |  10  |    50%     | 0 1 1 1 1 0 0 0
+//!    3   |  11  |    75%     | 1 0 0 1 1 1 1 1
+//!
+//! [Pulse]: https://wiki.nesdev.com/w/index.php/APU_Pulse
+
+use apu::envelope::Envelope;
+use apu::length_counter::LengthCounter;
+use apu::sweep::{Negate, Sweep};
+
+const DUTY_TABLE: [[u8; 8]; 4] = [
+  [0, 1, 0, 0, 0, 0, 0, 0],
+  [0, 1, 1, 0, 0, 0, 0, 0],
+  [0, 1, 1, 1, 1, 0, 0, 0],
+  [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+pub struct Pulse {
+  pub length: LengthCounter,
+  envelope: Envelope,
+  sweep: Sweep,
+  duty: usize,
+  step: usize,
+  period: u16,
+  timer: u16,
+}
+
+impl Pulse {
+  pub fn new(negate_mode: Negate) -> Self {
+    Pulse {
+      length: LengthCounter::default(),
+      envelope: Envelope::default(),
+      sweep: Sweep::new(negate_mode),
+      duty: 0,
+      step: 0,
+      period: 0,
+      timer: 0,
+    }
+  }
+
+  /// Write one of the channel's 4 registers, where `register` is 0-3
+  pub fn write(&mut self, register: u16, value: u8) {
+    match register {
+      0 => {
+        self.duty = (value >> 6) as usize;
+        self.length.set_halted(value & 0b0010_0000 != 0);
+        self.envelope.write(value);
+      }
+      1 => self.sweep.write(value),
+      2 => self.period = (self.period & 0x700) | u16::from(value),
+      3 => {
+        self.period = (self.period & 0x0FF) | (u16::from(value & 0b111) << 8);
+        self.length.load(value);
+        self.envelope.restart();
+        self.step = 0;
+      }
+      _ => unreachable!(),
+    }
+  }
+
+  /// Clocked every APU cycle (every other CPU cycle)
+  pub fn clock_timer(&mut self) {
+    if self.timer == 0 {
+      self.timer = self.period;
+      self.step = (self.step + 1) % 8;
+    } else {
+      self.timer -= 1;
+    }
+  }
+
+  pub fn clock_quarter_frame(&mut self) {
+    self.envelope.clock();
+  }
+
+  pub fn clock_half_frame(&mut self) {
+    self.length.clock();
+    self.period = self.sweep.clock(self.period);
+  }
+
+  /// The current output level, from 0 to 15
+  pub fn output(&self) -> u8 {
+    if DUTY_TABLE[self.duty][self.step] == 0
+      || !self.length.is_active()
+      || self.sweep.is_muting(self.period)
+    {
+      return 0;
+    }
+    self.envelope.output()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn playing_pulse(duty: u8, period: u16) -> Pulse {
+    let mut pulse = Pulse::new(Negate::OnesComplement);
+    pulse.length.set_enabled(true);
+    pulse.write(0, (duty << 6) | 0b0001_1111);
+    pulse.write(2, period as u8);
+    pulse.write(3, (period >> 8) as u8);
+    pulse
+  }
+
+  fn waveform(pulse: &mut Pulse, period: u16) -> Vec<u8> {
+    (0..8)
+      .map(|_| {
+        for _ in 0..=period {
+          pulse.clock_timer();
+        }
+        pulse.output()
+      })
+      .collect()
+  }
+
+  #[test]
+  fn duty_cycles() {
+    let period = 8;
+    let mut pulse = playing_pulse(0, period);
+    assert_eq!(waveform(&mut pulse, period), [15, 0, 0, 0, 0, 0, 0, 0]);
+
+    let mut pulse = playing_pulse(3, period);
+    assert_eq!(waveform(&mut pulse, period), [0, 0, 15, 15, 15, 15, 15, 15]);
+  }
+
+  #[test]
+  fn silent_at_low_periods() {
+    let mut pulse = playing_pulse(2, 7);
+    let levels = waveform(&mut pulse, 7);
+    assert!(levels.iter().all(|&level| level == 0));
+  }
+
+  #[test]
+  fn silent_when_length_runs_out() {
+    let mut pulse = playing_pulse(2, 0x100);
+    // Loads a length of 10
+    pulse.write(3, 0b0000_0001);
+    for _ in 0..10 {
+      pulse.clock_half_frame();
+    }
+    let levels = waveform(&mut pulse, 0x100);
+    assert!(levels.iter().all(|&level| level == 0));
+  }
+}
@@ -0,0 +1,129 @@
+//! # Sweep
+//!
+//! Each pulse channel has a sweep unit which bends the channel's pitch
+//! up or down by periodically adding a fraction of the timer period to
+//! itself. [Read more here][Sweep].
+//!
+//! ### $4001 & $4005
+//!
+//!  Legend    | Bits
+//! -----------|---------------------------------------------
+//!  EPPP NSSS | Enabled, divider Period, Negate, Shift count
+//!
+//! [Sweep]: https://wiki.nesdev.com/w/index.php/APU_Sweep
+
+/// Which pulse channel a sweep belongs to, as they negate differently
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Negate {
+  /// Pulse 1 subtracts the change amount and one (one's complement)
+  OnesComplement,
+  /// Pulse 2 subtracts just the change amount (two's complement)
+  TwosComplement,
+}
+
+pub struct Sweep {
+  negate_mode: Negate,
+  enabled: bool,
+  period: u8,
+  negate: bool,
+  shift: u8,
+  divider: u8,
+  reload: bool,
+}
+
+impl Sweep {
+  pub fn new(negate_mode: Negate) -> Self {
+    Sweep {
+      negate_mode,
+      enabled: false,
+      period: 0,
+      negate: false,
+      shift: 0,
+      divider: 0,
+      reload: false,
+    }
+  }
+
+  /// Write the `EPPP NSSS` register
+  pub fn write(&mut self, value: u8) {
+    self.enabled = value & 0b1000_0000 != 0;
+    self.period = (value >> 4) & 0b111;
+    self.negate = value & 0b0000_1000 != 0;
+    self.shift = value & 0b0000_0111;
+    self.reload = true;
+  }
+
+  /// The period the sweep is bending the timer period towards. This is
+  /// continuously calculated, even when the sweep is disabled.
+  pub fn target_period(&self, timer_period: u16) -> u16 {
+    let change = timer_period >> self.shift;
+    if !self.negate {
+      return timer_period + change;
+    }
+    match self.negate_mode {
+      Negate::OnesComplement => timer_period.saturating_sub(change + 1),
+      Negate::TwosComplement => timer_period.saturating_sub(change),
+    }
+  }
+
+  /// Whether the channel is silenced, because the period is too small to
+  /// be audible or the target period overflows the 11 bit timer
+  pub fn is_muting(&self, timer_period: u16) -> bool {
+    timer_period < 8 || self.target_period(timer_period) > 0x7FF
+  }
+
+  /// Clocked by the frame counter every half frame, returning the new
+  /// timer period
+  pub fn clock(&mut self, timer_period: u16) -> u16 {
+    let mut period = timer_period;
+    if self.divider == 0 && self.enabled && self.shift > 0 && !self.is_muting(timer_period) {
+      period = self.target_period(timer_period);
+    }
+
+    if self.divider == 0 || self.reload {
+      self.divider = self.period;
+      self.reload = false;
+    } else {
+      self.divider -= 1;
+    }
+    period
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn negate_quirk() {
+    let mut sweep_1 = Sweep::new(Negate::OnesComplement);
+    let mut sweep_2 = Sweep::new(Negate::TwosComplement);
+    sweep_1.write(0b0000_1001);
+    sweep_2.write(0b0000_1001);
+
+    assert_eq!(sweep_1.target_period(0x100), 0x100 - 0x80 - 1);
+    assert_eq!(sweep_2.target_period(0x100), 0x100 - 0x80);
+  }
+
+  #[test]
+  fn mutes_on_overflow() {
+    let mut sweep = Sweep::new(Negate::TwosComplement);
+    assert!(sweep.is_muting(7));
+    assert!(!sweep.is_muting(0x3FF));
+
+    // The target period overflows even though the sweep is disabled
+    sweep.write(0b0000_0000);
+    assert!(sweep.is_muting(0x400));
+  }
+
+  #[test]
+  fn bends_period_every_divider_period() {
+    let mut sweep = Sweep::new(Negate::TwosComplement);
+    sweep.write(0b1001_0010);
+
+    // Divider period 1 bends the period every other half frame
+    assert_eq!(sweep.clock(0x100), 0x140);
+    assert_eq!(sweep.clock(0x140), 0x140);
+    assert_eq!(sweep.clock(0x140), 0x190);
+  }
+}
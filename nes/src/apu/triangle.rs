@@ -0,0 +1,139 @@
+//! # Triangle
+//!
+//! Steps through a 32 step triangle wave with no volume control. Besides
+//! the length counter, it has a finer grained linear counter clocked by
+//! quarter frames. [Read more here][Triangle].
+//!
+//!  Register | Legend    | Bits
+//! ----------|-----------|----------------------------------------------
+//!   $4008   | CRRR RRRR | Length counter halt / linear Control, Reload
+//!   $400A   | TTTT TTTT | Timer low
+//!   $400B   | LLLL LTTT | Length counter load, Timer high
+//!
+//! [Triangle]: https://wiki.nesdev.com/w/index.php/APU_Triangle
+
+use apu::length_counter::LengthCounter;
+
+const SEQUENCE: [u8; 32] = [
+  15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12,
+  13, 14, 15,
+];
+
+#[derive(Default)]
+pub struct Triangle {
+  pub length: LengthCounter,
+  control: bool,
+  linear_reload_value: u8,
+  linear_reload: bool,
+  linear_counter: u8,
+  step: usize,
+  period: u16,
+  timer: u16,
+}
+
+impl Triangle {
+  /// Write one of the channel's 4 registers, where `register` is 0-3
+  pub fn write(&mut self, register: u16, value: u8) {
+    match register {
+      0 => {
+        self.control = value & 0b1000_0000 != 0;
+        self.length.set_halted(self.control);
+        self.linear_reload_value = value & 0b0111_1111;
+      }
+      1 => {}
+      2 => self.period = (self.period & 0x700) | u16::from(value),
+      3 => {
+        self.period = (self.period & 0x0FF) | (u16::from(value & 0b111) << 8);
+        self.length.load(value);
+        self.linear_reload = true;
+      }
+      _ => unreachable!(),
+    }
+  }
+
+  /// Clocked every CPU cycle
+  pub fn clock_timer(&mut self) {
+    if self.timer == 0 {
+      self.timer = self.period;
+      if self.linear_counter > 0 && self.length.is_active() {
+        self.step = (self.step + 1) % 32;
+      }
+    } else {
+      self.timer -= 1;
+    }
+  }
+
+  pub fn clock_quarter_frame(&mut self) {
+    if self.linear_reload {
+      self.linear_counter = self.linear_reload_value;
+    } else if self.linear_counter > 0 {
+      self.linear_counter -= 1;
+    }
+
+    if !self.control {
+      self.linear_reload = false;
+    }
+  }
+
+  pub fn clock_half_frame(&mut self) {
+    self.length.clock();
+  }
+
+  /// The current output level, from 0 to 15. Silencing the triangle only
+  /// stops the sequencer, so the level holds wherever it stopped.
+  pub fn output(&self) -> u8 {
+    SEQUENCE[self.step]
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn playing_triangle(linear: u8) -> Triangle {
+    let mut triangle = Triangle::default();
+    triangle.length.set_enabled(true);
+    triangle.write(0, linear);
+    triangle.write(2, 0x00);
+    triangle.write(3, 0b1111_1000);
+    triangle.clock_quarter_frame();
+    triangle
+  }
+
+  #[test]
+  fn steps_through_sequence() {
+    let mut triangle = playing_triangle(0x7F);
+    let levels: Vec<u8> = (0..32)
+      .map(|_| {
+        triangle.clock_timer();
+        triangle.output()
+      })
+      .collect();
+    assert_eq!(levels[..3], [14, 13, 12]);
+    assert_eq!(levels[14..18], [0, 0, 1, 2]);
+    assert_eq!(levels[31], 15);
+  }
+
+  #[test]
+  fn linear_counter_stops_sequencer() {
+    let mut triangle = playing_triangle(2);
+    triangle.clock_quarter_frame();
+    triangle.clock_timer();
+    assert_eq!(triangle.output(), 14);
+
+    triangle.clock_quarter_frame();
+    triangle.clock_timer();
+    triangle.clock_timer();
+    assert_eq!(triangle.output(), 14);
+  }
+
+  #[test]
+  fn control_flag_keeps_reloading() {
+    let mut triangle = playing_triangle(0b1000_0001);
+    for _ in 0..4 {
+      triangle.clock_quarter_frame();
+    }
+    triangle.clock_timer();
+    assert_eq!(triangle.output(), 14);
+  }
+}
@@ -1,12 +1,15 @@
-extern crate bitflags;
 extern crate nes;
 extern crate sdl2;
 
-use nes::apu::channel::*;
+use nes::apu::processor::ApuImpl;
+use nes::apu::Apu;
+use nes::clock::CPU_FREQUENCY;
+use nes::io::audio;
 use nes::io::audio::NesAudioProcess;
+use nes::memory::WriteAddr;
 use sdl2::audio::AudioSpecDesired;
-use std::sync::mpsc;
-use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::Duration;
 
 fn main() {
   let sdl_context = sdl2::init().unwrap();
@@ -17,57 +20,65 @@ fn main() {
     samples: Some(800),
   };
 
-  let (send, recv) = mpsc::channel();
+  let (producer, consumer) = audio::sample_buffer(48000);
 
   let device = audio_subsystem
     .open_playback(None, &desired_spec, |spec| {
-      NesAudioProcess::new(recv, spec.freq as u32)
+      NesAudioProcess::new(consumer, spec.freq as u32)
     })
     .unwrap();
+  let mut apu = ApuImpl::create(producer, device.spec().freq as u32);
 
   device.resume();
-  _arp(&send);
-  _random_tune(&send);
-  _decend(&send);
+  _arp(&mut apu);
+  _random_tune(&mut apu);
+  _sweep(&mut apu);
 }
 
-fn _arp(send: &Sender<ApuChannelDelta>) {
+fn _arp(apu: &mut ApuImpl) {
+  // Pulse 1 at a constant volume with a 12.5% duty cycle
+  apu.write_addr(0x4015, 0b0001);
+  apu.write_addr(0x4000, 0b0011_1000);
+
   for note in 40..80 {
     for offset in [0, 4, 7, 4].iter() {
-      pew_all(
-        send,
-        vec![
-          ApuChannelDelta::Pulse1(PulseDelta::SetVolume(64)),
-          ApuChannelDelta::Pulse1(PulseDelta::SetPeriod(from_note(note + *offset))),
-          ApuChannelDelta::Pulse1(PulseDelta::SetPulseWidth(PulseWidth::Duty0)),
-        ],
-      );
-      std::thread::sleep(std::time::Duration::from_millis(20));
+      set_period(apu, 0x4002, from_note(note + *offset));
+      play(apu, 20);
     }
   }
 }
 
-fn _random_tune(send: &Sender<ApuChannelDelta>) {
+fn _random_tune(apu: &mut ApuImpl) {
+  // Pulse 1 with a decaying envelope, and the triangle
+  apu.write_addr(0x4015, 0b0101);
+  apu.write_addr(0x4000, 0b1000_0100);
+  apu.write_addr(0x4008, 0b1111_1111);
+
   for note in 0..80 {
-    pew_all(
-      send,
-      vec![
-        ApuChannelDelta::Pulse1(PulseDelta::SetVolume(64)),
-        ApuChannelDelta::Pulse1(PulseDelta::SetPeriod(from_note(note))),
-        ApuChannelDelta::Pulse1(PulseDelta::SetPulseWidth(PulseWidth::Duty0)),
-        ApuChannelDelta::Triangle(TriangleDelta::SetPeriod(from_note(note))),
-        ApuChannelDelta::Triangle(TriangleDelta::SetControlFlag(true)),
-      ],
-    );
-    std::thread::sleep(std::time::Duration::from_millis(200));
+    set_period(apu, 0x4002, from_note(note));
+    set_period(apu, 0x400A, from_note(note));
+    play(apu, 200);
   }
 
-  pew_all(
-    send,
-    vec![ApuChannelDelta::Triangle(TriangleDelta::SetControlFlag(
-      false,
-    ))],
-  );
+  apu.write_addr(0x4015, 0b0000);
+}
+
+fn _sweep(apu: &mut ApuImpl) {
+  // Both pulses sweeping down, and noise with a looping envelope
+  apu.write_addr(0x4015, 0b1011);
+  apu.write_addr(0x4000, 0b0011_1000);
+  apu.write_addr(0x4001, 0b1111_0111);
+  apu.write_addr(0x4004, 0b1111_1000);
+  apu.write_addr(0x4005, 0b1111_0110);
+  apu.write_addr(0x400C, 0b0010_0010);
+  apu.write_addr(0x400E, 0b0000_0101);
+  apu.write_addr(0x400F, 0b0000_0000);
+
+  for note in 0..20 {
+    set_period(apu, 0x4002, from_note(note * 2));
+    set_period(apu, 0x4006, from_note(note * 2 + 7));
+    play(apu, 500);
+  }
 }
 
 fn from_note(note: u16) -> u16 {
@@ -77,52 +88,17 @@ fn from_note(note: u16) -> u16 {
   return (ntsc_octave_base / rel_freq).round() as u16 - 1;
 }
 
-fn _decend(send: &Sender<ApuChannelDelta>) {
-  let total_steps = 1u32 << 16;
-  let start_step = 1u32 << 4;
-  let _scale_volume = <u8>::max_value() as f32 / total_steps as f32;
-  let scale_peroid = ((1 << 11) - 1) as f32 / total_steps as f32;
-
-  pew_all(
-    send,
-    vec![
-      ApuChannelDelta::Pulse1(PulseDelta::SetVolume(64)),
-      ApuChannelDelta::Pulse2(PulseDelta::SetVolume(64)),
-      ApuChannelDelta::Pulse1(PulseDelta::SetPulseWidth(PulseWidth::Duty0)),
-      ApuChannelDelta::Pulse2(PulseDelta::SetPulseWidth(PulseWidth::Duty3)),
-    ],
-  );
-
-  for step in start_step..total_steps {
-    let f_step = step as f32;
-    let f_step_left = (total_steps - step) as f32;
-
-    let noise_volume = (32.0 * ((step % 64) as f32 / 64.0)) as u8;
-    //  if step < (total_steps / 2) { f_step * scale_volume }
-    //  else { f_step_left * scale_volume };
-
-    let pulse_1_pitch = f_step * scale_peroid;
-    let pulse_2_pitch = (f_step * 0.75) * scale_peroid;
-    let triangle_pitch = f_step_left * scale_peroid;
-
-    println!(
-      "p1 {} p2 {} nv {}",
-      pulse_1_pitch, pulse_2_pitch, noise_volume
-    );
-
-    pew_all(
-      send,
-      vec![
-        ApuChannelDelta::Noise(NoiseDelta::SetVolume(noise_volume as u8)),
-        ApuChannelDelta::Pulse1(PulseDelta::SetPeriod(pulse_1_pitch as u16)),
-        ApuChannelDelta::Pulse2(PulseDelta::SetPeriod(pulse_2_pitch as u16)),
-        ApuChannelDelta::Triangle(TriangleDelta::SetPeriod(triangle_pitch as u16)),
-      ],
-    );
-    std::thread::sleep(std::time::Duration::from_millis(1));
-  }
+/// Write a timer period to a channel's timer low and high registers,
+/// which also restarts the channel's length counter
+fn set_period(apu: &mut ApuImpl, timer_low_addr: u16, period: u16) {
+  apu.write_addr(timer_low_addr, period as u8);
+  apu.write_addr(timer_low_addr + 1, (period >> 8) as u8 & 0b111);
 }
 
-fn pew_all(send: &Sender<ApuChannelDelta>, deltas: Vec<ApuChannelDelta>) {
-  send.send(ApuChannelDelta::Many(deltas)).unwrap();
+/// Run the APU for a number of milliseconds, roughly in real time
+fn play(apu: &mut ApuImpl, millis: u32) {
+  for _ in 0..(CPU_FREQUENCY / 1000 * millis) {
+    apu.cycle();
+  }
+  thread::sleep(Duration::from_millis(u64::from(millis)));
 }
@@ -6,27 +6,51 @@ extern crate sdl2;
 use std::env;
 use std::fs::File;
 use std::io::Read;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc;
+use std::sync::Arc;
 use std::thread;
 use std::time::Instant;
 
+use nes::apu::mixer::{Channel, MixerEvent};
 use nes::apu::processor::ApuImpl;
 use nes::console::Console;
 use nes::controller::joypad;
+use nes::io::audio;
 use nes::io::audio::NesAudioProcess;
+use nes::io::filter;
+use nes::io::overscan::Overscan;
 use nes::io::video;
 use sdl2::audio::AudioSpecDesired;
-use sdl2::keyboard::Keycode;
+use sdl2::keyboard::{Keycode, Mod};
 use sdl2::pixels::PixelFormatEnum;
 use sdl2::rect::Rect;
 
 fn main() {
-  let args: Vec<String> = env::args().collect();
+  let mut args: Vec<String> = env::args().collect();
+  let recording = take_flag_value(&mut args, "--record");
+  let stems = take_flag(&mut args, "--stems");
   if args.len() < 2 {
     panic!("Must supply ROM filename.");
   }
 
   let filename = &args[1];
+  let filter_name = args.get(2).map_or("scanlines", |name| name.as_str());
+  let mut video_filter = match filter::from_name(filter_name) {
+    Some(f) => f,
+    None => panic!("Unknown video filter: {}", filter_name),
+  };
+  let overscan = match args.get(3) {
+    Some(name) => match Overscan::from_name(name) {
+      Some(overscan) => overscan,
+      None => panic!("Unknown overscan: {}", name),
+    },
+    None => Overscan::default(),
+  };
+  let (video_output, vid_receiver) = video::ChannelVideoOutput::with_overscan(overscan);
+  let (visible_width, visible_height) = video_output.frame_size();
+  let (frame_width, frame_height) = video_filter.output_size(visible_width, visible_height);
+
   println!("Loading ROM: {}", filename);
   let mut f = File::open(filename).expect("File not found");
   let mut data: Vec<u8> = vec![];
@@ -46,35 +70,58 @@ fn main() {
   };
 
   let window = video_subsystem
-    .window("WeAreRust Nes", 256, 240)
+    .window("WeAreRust Nes", frame_width as u32, frame_height as u32)
     .position_centered()
     .opengl()
     .build()
     .unwrap();
 
-  let (audio_tx, audio_rx) = mpsc::channel();
-  let mut apu = ApuImpl::create(audio_tx);
+  // The buffer is sized for the rate the device actually plays at, which
+  // may not be the one asked for
+  let mut audio_producer = None;
   let apu_playback = audio_subsystem
     .open_playback(None, &audio_spec_desired, |spec| {
-      NesAudioProcess::new(audio_rx, spec.freq as u32)
+      let (producer, consumer) = audio::sample_buffer(spec.freq as u32);
+      audio_producer = Some(producer);
+      NesAudioProcess::new(consumer, spec.freq as u32)
     })
     .unwrap();
+  let audio_producer = audio_producer.unwrap();
+  let audio_stats = audio_producer.stats();
+  let mut apu = ApuImpl::create(audio_producer, apu_playback.spec().freq as u32);
+  if let Some(path) = recording {
+    println!("Recording audio: {}", path);
+    apu
+      .start_recording(&path, stems)
+      .expect("Couldn't create recording");
+  }
+
+  let (mixer_tx, mixer_rx) = mpsc::channel();
+  apu.set_mixer_events(mixer_rx);
 
   apu_playback.resume();
 
   let (event_tx, event_rx) = mpsc::channel();
   let mut controller1 = joypad::Joypad::new(event_rx);
   let controller2: Option<&mut joypad::Joypad> = None;
-  let (video_output, vid_receiver) = video::ChannelVideoOutput::new();
 
   let mut canvas = window.into_canvas().build().unwrap();
   let texture_creator = canvas.texture_creator();
   let mut texture = texture_creator
-    .create_texture_streaming(PixelFormatEnum::RGB24, 256, 240)
+    .create_texture_streaming(
+      PixelFormatEnum::RGB24,
+      frame_width as u32,
+      frame_height as u32,
+    )
     .unwrap();
 
+  // Cleared when the window closes, so the console thread can finish the
+  // recording
+  let running = Arc::new(AtomicBool::new(true));
+  let console_running = running.clone();
+
   // Spawn console thread
-  thread::spawn(move || {
+  let console_thread = thread::spawn(move || {
     let mut cartridge = nes::cartridge::parse_rom_file(&data).unwrap();
     // print!("PRG ROM DUMP");
     // for i in 0x8000..0xC000 {
@@ -104,18 +151,30 @@ fn main() {
 
     // Run the controller loop
     let mut ticks = 0u32;
-    loop {
+    while console_running.load(Ordering::Relaxed) {
       console.tick();
       ticks += 1;
       if report_throttle.test() {
         let now = Instant::now();
         let span = now.duration_since(start);
         let clock_rate = ticks as f32 / span.as_millis() as f32;
-        println!("{} Hertz", clock_rate * 1_000 as f32);
+        println!(
+          "{} Hertz, audio buffer {}/{} ({} underruns, {} samples dropped)",
+          clock_rate * 1_000 as f32,
+          audio_stats.len(),
+          audio_stats.capacity(),
+          audio_stats.underruns(),
+          audio_stats.overruns()
+        );
         ticks = 0;
         start = now;
       }
     }
+    drop(console);
+
+    if let Err(err) = apu.stop_recording() {
+      eprintln!("Recording failed: {}", err);
+    }
   });
 
   'running: loop {
@@ -132,12 +191,19 @@ fn main() {
         } => break 'running,
         Event::KeyDown {
           keycode: Some(keycode),
+          keymod,
           ..
-        } => event_tx
-          .send(ControllerEvent::ButtonDown {
-            button: controller1_keymap(keycode),
-          })
-          .unwrap(),
+        } => match mixer_hotkey(keycode, keymod) {
+          Some(mixer_event) => {
+            println!("{}", describe_mixer_event(mixer_event));
+            mixer_tx.send(mixer_event).unwrap();
+          }
+          None => event_tx
+            .send(ControllerEvent::ButtonDown {
+              button: controller1_keymap(keycode),
+            })
+            .unwrap(),
+        },
         Event::KeyUp {
           keycode: Some(keycode),
           ..
@@ -159,25 +225,67 @@ fn main() {
       Ok(f) => f,
     };
 
-    // Write frame data to texture data
+    // Write filtered frame data to texture data
+    let frame = video_filter.apply(&frame);
     texture
       .with_lock(None, |buffer: &mut [u8], pitch: usize| {
         frame.write_to_buffer(buffer, pitch);
       })
       .unwrap();
 
-    // Draw the texture to the window
+    // Draw the texture to the window, keeping the visible area's
+    // aspect ratio
     let (width, height) = canvas.output_size().unwrap();
     canvas.clear();
     canvas
       .copy(
         &texture,
-        Some(Rect::new(0, 0, 255, 240)),
-        Some(Rect::new(0, 0, width, height)),
+        None,
+        Some(fit_rect(width, height, visible_width, visible_height)),
       )
       .unwrap();
     canvas.present();
   }
+
+  // Keep taking frames until the console thread stops and drops its end
+  // of the channel
+  running.store(false, Ordering::Relaxed);
+  while vid_receiver.recv().is_ok() {}
+  let _ = console_thread.join();
+}
+
+/// Remove a flag from the arguments, returning whether it was present
+fn take_flag(args: &mut Vec<String>, flag: &str) -> bool {
+  let len = args.len();
+  args.retain(|arg| arg != flag);
+  args.len() != len
+}
+
+/// Remove a flag and the value following it from the arguments
+fn take_flag_value(args: &mut Vec<String>, flag: &str) -> Option<String> {
+  let index = args.iter().position(|arg| arg == flag)?;
+  if index + 1 >= args.len() {
+    panic!("Missing value for {}", flag);
+  }
+  args.remove(index);
+  Some(args.remove(index))
+}
+
+/// The largest rectangle with the aspect ratio of `frame_width` x
+/// `frame_height` that fits centred in the window
+fn fit_rect(width: u32, height: u32, frame_width: usize, frame_height: usize) -> Rect {
+  let (frame_width, frame_height) = (frame_width as u32, frame_height as u32);
+  let (fit_width, fit_height) = if width * frame_height > height * frame_width {
+    (height * frame_width / frame_height, height)
+  } else {
+    (width, width * frame_height / frame_width)
+  };
+  Rect::new(
+    ((width - fit_width) / 2) as i32,
+    ((height - fit_height) / 2) as i32,
+    fit_width,
+    fit_height,
+  )
 }
 
 fn controller1_keymap(keycode: Keycode) -> u8 {
@@ -194,6 +302,53 @@ fn controller1_keymap(keycode: Keycode) -> u8 {
   }
 }
 
+/// Change in gain for each press of a gain hotkey
+const GAIN_STEP: f32 = 0.25;
+
+/// Audio channel controls: 1-5 toggle muting pulse 1, pulse 2, triangle,
+/// noise and DMC, and F1-F8 each voice of the expansion audio, with Shift
+/// to toggle soloing instead, Ctrl to lower the gain or Ctrl+Shift to
+/// raise it. 0 resets them all.
+fn mixer_hotkey(keycode: Keycode, keymod: Mod) -> Option<MixerEvent> {
+  let channel = match keycode {
+    Keycode::Num0 => return Some(MixerEvent::ResetControls),
+    Keycode::Num1 => Channel::Pulse1,
+    Keycode::Num2 => Channel::Pulse2,
+    Keycode::Num3 => Channel::Triangle,
+    Keycode::Num4 => Channel::Noise,
+    Keycode::Num5 => Channel::Dmc,
+    Keycode::F1 => Channel::Expansion(0),
+    Keycode::F2 => Channel::Expansion(1),
+    Keycode::F3 => Channel::Expansion(2),
+    Keycode::F4 => Channel::Expansion(3),
+    Keycode::F5 => Channel::Expansion(4),
+    Keycode::F6 => Channel::Expansion(5),
+    Keycode::F7 => Channel::Expansion(6),
+    Keycode::F8 => Channel::Expansion(7),
+    _ => return None,
+  };
+  let shift = keymod.intersects(sdl2::keyboard::LSHIFTMOD | sdl2::keyboard::RSHIFTMOD);
+  let ctrl = keymod.intersects(sdl2::keyboard::LCTRLMOD | sdl2::keyboard::RCTRLMOD);
+  Some(match (ctrl, shift) {
+    (false, false) => MixerEvent::ToggleMute(channel),
+    (false, true) => MixerEvent::ToggleSolo(channel),
+    (true, false) => MixerEvent::AdjustGain(channel, -GAIN_STEP),
+    (true, true) => MixerEvent::AdjustGain(channel, GAIN_STEP),
+  })
+}
+
+fn describe_mixer_event(event: MixerEvent) -> String {
+  match event {
+    MixerEvent::ToggleMute(channel) => format!("Toggled muting {}", channel.name()),
+    MixerEvent::ToggleSolo(channel) => format!("Toggled soloing {}", channel.name()),
+    MixerEvent::AdjustGain(channel, change) if change < 0.0 => {
+      format!("Lowered the gain of {}", channel.name())
+    }
+    MixerEvent::AdjustGain(channel, _) => format!("Raised the gain of {}", channel.name()),
+    MixerEvent::ResetControls => "Reset the audio channel controls".to_string(),
+  }
+}
+
 struct Throttle {
   rate: u32,
   cursor: u32,
@@ -0,0 +1,181 @@
+//! Plays the songs in an NSF or NSFe music file.
+//!
+//! Usage:
+//!
+//! - `nsf-player FILE` lists the songs
+//! - `nsf-player FILE SONG [seconds]` plays a song (counting from 1)
+//! - `nsf-player FILE SONG [seconds] --wav OUTPUT.wav [--stems]` renders
+//!   a song to a WAV file (and each channel to its own file with
+//!   `--stems`) as fast as possible, rather than playing it
+//!
+//! Songs play for their length from an NSFe, or 2 minutes otherwise,
+//! unless a number of seconds is given.
+
+extern crate nes;
+extern crate sdl2;
+
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::process;
+
+use nes::apu::processor::ApuImpl;
+use nes::cartridge::nsf;
+use nes::cartridge::nsf::Nsf;
+use nes::cartridge::Cartridge;
+use nes::clock::MASTER_FREQUENCY;
+use nes::console::Console;
+use nes::controller::joypad::Joypad;
+use nes::io::audio;
+use nes::io::audio::{NesAudioProcess, NullAudioOutput};
+use nes::io::video::IndexedVideoOutput;
+use sdl2::audio::AudioSpecDesired;
+
+const SAMPLE_RATE: u32 = 48_000;
+const DEFAULT_SECONDS: u64 = 120;
+
+fn main() {
+  let mut args: Vec<String> = env::args().collect();
+  let wav = take_flag_value(&mut args, "--wav");
+  let stems = take_flag(&mut args, "--stems");
+  if args.len() < 2 {
+    panic!("Usage: nsf-player FILE [SONG [seconds] [--wav OUTPUT.wav [--stems]]]");
+  }
+
+  let mut data = vec![];
+  File::open(&args[1])
+    .expect("File not found")
+    .read_to_end(&mut data)
+    .unwrap();
+  let nsf = nsf::parse(&data).expect("Invalid NSF");
+
+  let song = match args.get(2) {
+    Some(arg) => match arg.parse::<u8>() {
+      Ok(song) if song >= 1 && song <= nsf.songs => song - 1,
+      _ => panic!("Song must be from 1 to {}", nsf.songs),
+    },
+    None => {
+      list_songs(&nsf);
+      return;
+    }
+  };
+  let seconds = match args.get(3) {
+    Some(arg) => arg.parse().expect("Invalid number of seconds"),
+    None => song_length(&nsf, song).unwrap_or(DEFAULT_SECONDS),
+  };
+
+  let unplayed = nsf.unplayed_chip_names();
+  if !unplayed.is_empty() {
+    println!("Expansion audio isn't played: {}", unplayed.join(", "));
+  }
+  println!("Playing {} for {} seconds", nsf.song_name(song), seconds);
+
+  match wav {
+    Some(path) => {
+      // Nothing plays the samples, they're only recorded
+      let mut apu = ApuImpl::create(NullAudioOutput, SAMPLE_RATE);
+      apu
+        .start_recording(&path, stems)
+        .expect("Couldn't create recording");
+      play(&nsf, song, seconds, &mut apu, false);
+      if let Err(err) = apu.stop_recording() {
+        eprintln!("Recording failed: {}", err);
+        process::exit(1);
+      }
+    }
+    None => {
+      let sdl_context = sdl2::init().unwrap();
+      let audio_subsystem = sdl_context.audio().unwrap();
+      let audio_spec_desired = AudioSpecDesired {
+        freq: Some(SAMPLE_RATE as i32),
+        channels: Some(1),
+        samples: Some(800),
+      };
+
+      // Sized for the rate the device actually plays at, which may not be
+      // the one asked for
+      let mut producer = None;
+      let playback = audio_subsystem
+        .open_playback(None, &audio_spec_desired, |spec| {
+          let (samples, consumer) = audio::sample_buffer(spec.freq as u32);
+          producer = Some(samples);
+          NesAudioProcess::new(consumer, spec.freq as u32)
+        })
+        .unwrap();
+      let producer = producer.unwrap();
+      let mut apu = ApuImpl::create(producer, playback.spec().freq as u32);
+      playback.resume();
+      play(&nsf, song, seconds, &mut apu, true);
+    }
+  }
+}
+
+fn list_songs(nsf: &Nsf) {
+  println!("{}", nsf.title);
+  println!("{}", nsf.artist);
+  println!("{}", nsf.copyright);
+  if nsf.chips != 0 {
+    println!("Expansion audio: {}", nsf.chip_names().join(", "));
+  }
+  println!();
+
+  for song in 0..nsf.songs {
+    let length = match song_length(nsf, song) {
+      Some(seconds) => format!(" ({}:{:02})", seconds / 60, seconds % 60),
+      None => String::new(),
+    };
+    let start = if song == nsf.starting_song { "*" } else { " " };
+    println!("{}{:3}. {}{}", start, song + 1, nsf.song_name(song), length);
+  }
+}
+
+/// The length of a song in seconds, if the NSF knows it
+fn song_length(nsf: &Nsf, song: u8) -> Option<u64> {
+  match nsf.song_lengths.get(usize::from(song)) {
+    Some(&Some(millis)) => Some(u64::from(millis).div_ceil(1000)),
+    _ => None,
+  }
+}
+
+/// Run a song, raising an NMI to call PLAY at the NSF's rate
+fn play(nsf: &Nsf, song: u8, seconds: u64, apu: &mut ApuImpl, throttled: bool) {
+  apu.set_region(nsf.region);
+  let mut cartridge = Cartridge::from_nsf(nsf, song, nsf.region);
+  let mut console = Console::new(
+    apu,
+    &mut cartridge,
+    None::<&mut Joypad>,
+    None::<&mut Joypad>,
+    IndexedVideoOutput::new(|_, _| {}),
+  );
+  console.set_throttled(throttled);
+  console.reset();
+
+  // Master clock ticks between calls to PLAY (never 0, as parsing
+  // replaces a period of 0 with the default)
+  let play_period =
+    (u64::from(nsf.play_period(nsf.region)) * u64::from(MASTER_FREQUENCY) / 1_000_000).max(1);
+  for tick in 1..=seconds * u64::from(MASTER_FREQUENCY) {
+    console.tick();
+    if tick % play_period == 0 {
+      console.nmi();
+    }
+  }
+}
+
+/// Remove a flag from the arguments, returning whether it was present
+fn take_flag(args: &mut Vec<String>, flag: &str) -> bool {
+  let len = args.len();
+  args.retain(|arg| arg != flag);
+  args.len() != len
+}
+
+/// Remove a flag and the value following it from the arguments
+fn take_flag_value(args: &mut Vec<String>, flag: &str) -> Option<String> {
+  let index = args.iter().position(|arg| arg == flag)?;
+  if index + 1 >= args.len() {
+    panic!("Missing value for {}", flag);
+  }
+  args.remove(index);
+  Some(args.remove(index))
+}
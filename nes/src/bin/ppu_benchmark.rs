@@ -0,0 +1,124 @@
+//! Measures how many frames per second the PPU renders in each render
+//! mode, using a busy synthetic scene (random tiles, 64 sprites and a
+//! scroll position that changes every frame). Only the PPU is run, so
+//! the numbers don't include any CPU or APU emulation.
+//!
+//! Each mode is also run with rendering disabled, which leaves only the
+//! cost of stepping through the dots, to show how much of each frame is
+//! spent drawing. Every measurement is the fastest of a few runs, as the
+//! slower ones are mostly noise from the rest of the machine.
+//!
+//! Usage: `ppu-benchmark [frames]`
+
+extern crate nes;
+
+use std::env;
+use std::time::Instant;
+
+use nes::cartridge;
+use nes::cartridge::Cartridge;
+use nes::io::video::IndexedVideoOutput;
+use nes::ppu::{Core, RenderMode};
+
+const DEFAULT_FRAMES: u64 = 600;
+const CYCLES_PER_FRAME: u64 = 341 * 262;
+const RUNS: usize = 5;
+
+fn main() {
+  let frames = env::args().nth(1).map_or(DEFAULT_FRAMES, |arg| {
+    arg.parse().expect("Invalid frame count")
+  });
+
+  let mut cartridge = create_cartridge();
+  for mode in [RenderMode::Accurate, RenderMode::Scanline].iter() {
+    let fps = best_of(&mut cartridge, *mode, true, frames);
+    let idle_fps = best_of(&mut cartridge, *mode, false, frames);
+    println!(
+      "{:?}: {:.1} frames/second ({:.0}µs a frame, {:.0}µs of it drawing)",
+      mode,
+      fps,
+      1_000_000.0 / fps,
+      1_000_000.0 / fps - 1_000_000.0 / idle_fps
+    );
+  }
+}
+
+fn best_of(cartridge: &mut Cartridge, mode: RenderMode, rendering: bool, frames: u64) -> f64 {
+  (0..RUNS)
+    .map(|_| benchmark(cartridge, mode, rendering, frames))
+    .fold(0.0, f64::max)
+}
+
+fn benchmark(cartridge: &mut Cartridge, mode: RenderMode, rendering: bool, frames: u64) -> f64 {
+  let mut ppu = Core::new(Box::new(IndexedVideoOutput::new(|_, _| {})));
+  ppu.set_render_mode(mode);
+  load_scene(&mut ppu, cartridge);
+  if !rendering {
+    ppu.write_register(0x2001, 0x00, cartridge);
+  }
+
+  let start = Instant::now();
+  for frame in 0..frames {
+    // Scroll diagonally, as a game would during vertical blanking
+    ppu.write_register(0x2005, frame as u8, cartridge);
+    ppu.write_register(0x2005, (frame / 2) as u8 % 240, cartridge);
+
+    for _ in 0..CYCLES_PER_FRAME {
+      ppu.cycle(cartridge);
+    }
+  }
+
+  let elapsed = start.elapsed().as_micros() as f64 / 1_000_000.0;
+  frames as f64 / elapsed
+}
+
+/// An NROM cartridge with random patterns in its CHR-ROM
+fn create_cartridge() -> Cartridge {
+  let mut rng = Lcg(0x1234_5678);
+  let mut data = vec![0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0x00, 0x00];
+  data.resize(16 + 16 * 1024, 0x00);
+  data.extend((0..8 * 1024).map(|_| rng.next()));
+
+  cartridge::parse_rom_file(&data).unwrap()
+}
+
+fn load_scene(ppu: &mut Core, cartridge: &mut Cartridge) {
+  let mut rng = Lcg(0x9ABC_DEF0);
+
+  // Palettes, then all 4 name tables (including attributes)
+  write_memory(
+    ppu,
+    cartridge,
+    0x3F00,
+    &(0..0x20).map(|i| i as u8).collect::<Vec<_>>(),
+  );
+  let name_tables: Vec<u8> = (0..0x1000).map(|_| rng.next()).collect();
+  write_memory(ppu, cartridge, 0x2000, &name_tables);
+
+  ppu.write_register(0x2003, 0x00, cartridge);
+  for _ in 0..0x100 {
+    ppu.write_register(0x2004, rng.next(), cartridge);
+  }
+
+  // Show the background and sprites everywhere
+  ppu.write_register(0x2000, 0x00, cartridge);
+  ppu.write_register(0x2001, 0x1E, cartridge);
+}
+
+fn write_memory(ppu: &mut Core, cartridge: &mut Cartridge, addr: u16, values: &[u8]) {
+  ppu.write_register(0x2006, (addr >> 8) as u8, cartridge);
+  ppu.write_register(0x2006, addr as u8, cartridge);
+  for &value in values {
+    ppu.write_register(0x2007, value, cartridge);
+  }
+}
+
+/// Tiny random number generator so runs are repeatable
+struct Lcg(u32);
+
+impl Lcg {
+  fn next(&mut self) -> u8 {
+    self.0 = self.0.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+    (self.0 >> 16) as u8
+  }
+}
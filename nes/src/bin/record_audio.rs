@@ -0,0 +1,70 @@
+//! Runs a ROM as fast as possible without a window or audio device,
+//! recording its audio to a WAV file (eg, to compare a game's music
+//! between versions).
+//!
+//! Usage: `record-audio ROM OUTPUT.wav [seconds] [--stems]`
+//!
+//! With `--stems`, each APU channel is also recorded to its own file next
+//! to the output (`OUTPUT.pulse1.wav`, `OUTPUT.pulse2.wav`, etc).
+
+extern crate nes;
+
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::process;
+
+use nes::apu::processor::ApuImpl;
+use nes::clock::MASTER_FREQUENCY;
+use nes::console::Console;
+use nes::controller::joypad::Joypad;
+use nes::io::audio::NullAudioOutput;
+use nes::io::video::IndexedVideoOutput;
+
+const SAMPLE_RATE: u32 = 48_000;
+const DEFAULT_SECONDS: u64 = 30;
+
+fn main() {
+  let mut args: Vec<String> = env::args().collect();
+  let stems = args.iter().any(|arg| arg == "--stems");
+  args.retain(|arg| arg != "--stems");
+  if args.len() < 3 {
+    panic!("Usage: record-audio ROM OUTPUT.wav [seconds] [--stems]");
+  }
+  let seconds = args.get(3).map_or(DEFAULT_SECONDS, |arg| {
+    arg.parse().expect("Invalid number of seconds")
+  });
+
+  let mut data = vec![];
+  File::open(&args[1])
+    .expect("File not found")
+    .read_to_end(&mut data)
+    .unwrap();
+  let mut cartridge = nes::cartridge::parse_rom_file(&data).unwrap();
+
+  // Nothing plays the samples, they're only recorded
+  let mut apu = ApuImpl::create(NullAudioOutput, SAMPLE_RATE);
+  apu
+    .start_recording(&args[2], stems)
+    .expect("Couldn't create recording");
+
+  println!("Recording {} seconds to {}", seconds, args[2]);
+  {
+    let mut console = Console::new(
+      &mut apu,
+      &mut cartridge,
+      None::<&mut Joypad>,
+      None::<&mut Joypad>,
+      IndexedVideoOutput::new(|_, _| {}),
+    );
+    console.set_throttled(false);
+    console.reset();
+    for _ in 0..seconds * u64::from(MASTER_FREQUENCY) {
+      console.tick();
+    }
+  }
+  if let Err(err) = apu.stop_recording() {
+    eprintln!("Recording failed: {}", err);
+    process::exit(1);
+  }
+}
@@ -6,6 +6,13 @@ use memory::block::BlockMemory;
 use memory::{ReadAddr, WriteAddr};
 use ppu;
 
+/// Number of CPU cycles the CPU is halted for during OAM DMA
+const OAM_DMA_CYCLES: usize = 513;
+
+/// Number of CPU cycles the CPU is halted for while the DMC reads a sample
+/// byte (this varies from 1 to 4 depending on what the CPU was doing)
+const DMC_DMA_CYCLES: usize = 4;
+
 pub struct Bus<'a, C1: 'a + Controller, C2: 'a + Controller, A1: 'a + Apu> {
   cartridge: &'a mut Cartridge,
   ram: Box<BlockMemory>,
@@ -13,6 +20,9 @@ pub struct Bus<'a, C1: 'a + Controller, C2: 'a + Controller, A1: 'a + Apu> {
   pub ppu: ppu::Core,
   controller1: Option<&'a mut C1>,
   controller2: Option<&'a mut C2>,
+
+  /// CPU cycles used by DMA since the last call to `take_stall_cycles`
+  stall_cycles: usize,
 }
 
 impl<'a, C1: Controller, C2: Controller, A1: Apu> Bus<'a, C1, C2, A1> {
@@ -31,7 +41,57 @@ impl<'a, C1: Controller, C2: Controller, A1: Apu> Bus<'a, C1, C2, A1> {
       ppu: ppu::Core::new(Box::new(video_output)),
       controller1: controller1,
       controller2: controller2,
+      stall_cycles: 0,
+    }
+  }
+
+  /// Run a single APU cycle, reading the next DMC sample byte by DMA if
+  /// the APU needs one
+  pub fn cycle_apu(&mut self) {
+    self.apu.cycle();
+
+    if let Some(addr) = self.apu.dma_address() {
+      let value = self.read_addr(addr);
+      self.apu.dma_fill(value);
+      self.stall_cycles += DMC_DMA_CYCLES;
+    }
+  }
+
+  /// Run a single CPU cycle of the cartridge's mapper, passing any
+  /// expansion audio on to the APU
+  pub fn cycle_cartridge(&mut self) {
+    self.cartridge.mapper.cycle();
+    self
+      .apu
+      .set_expansion_audio(self.cartridge.mapper.expansion_audio());
+  }
+
+  /// Whether any device is asserting the CPU's IRQ line
+  pub fn irq(&self) -> bool {
+    self.apu.irq() || self.cartridge.mapper.irq()
+  }
+
+  /// Run a single PPU cycle
+  pub fn cycle_ppu(&mut self) {
+    self.ppu.cycle(self.cartridge);
+  }
+
+  /// The number of cycles the CPU should be halted for, because DMA has
+  /// taken over the bus
+  pub fn take_stall_cycles(&mut self) -> usize {
+    let cycles = self.stall_cycles;
+    self.stall_cycles = 0;
+    cycles
+  }
+
+  /// Copy a page of CPU memory into OAM (sprite RAM) through $2004
+  fn oam_dma(&mut self, page: u8) {
+    let start = u16::from(page) << 8;
+    for offset in 0x00..0x100 {
+      let value = self.read_addr(start | offset);
+      self.ppu.write_register(0x2004, value, self.cartridge);
     }
+    self.stall_cycles += OAM_DMA_CYCLES;
   }
 }
 
@@ -43,7 +103,7 @@ impl<'a, C1: Controller, C2: Controller, A1: Apu> ReadAddr for Bus<'a, C1, C2, A
       // I/O Registers
       0x2000...0x3FFF => {
         let mirrored_addr = addr & 0x2007;
-        self.ppu.read_addr(mirrored_addr)
+        self.ppu.read_register(mirrored_addr, self.cartridge)
       }
       0x4000...0x4013 => self.apu.read_addr(addr),
       0x4014 => panic!("Attempted illegal read from {:04X}", addr),
@@ -60,13 +120,8 @@ impl<'a, C1: Controller, C2: Controller, A1: Apu> ReadAddr for Bus<'a, C1, C2, A
       },
       // APU and I/O functionality that is usually disabled
       0x4018...0x401F => panic!("Attempted access to disabled I/O ${:04X}", addr),
-      // TODO: Expansion ROM
-      0x4020...0x5FFF => panic!("Attempted access to expansion ROM ${:04X}", addr),
-      // TODO: Save RAM
-      0x6000...0x7FFF => panic!("Attempted access to Save RAM ${:04X}", addr),
-      // Catridge ROM
-      0x8000...0xFFFF => self.cartridge.mapper.read_addr(addr),
-      _ => panic!("Bus addr not implemented for ${:04X}", addr),
+      // Cartridge expansion area, save RAM and ROM
+      0x4020...0xFFFF => self.cartridge.mapper.read_addr(addr),
     }
   }
 }
@@ -79,33 +134,115 @@ impl<'a, C1: Controller, C2: Controller, A1: Apu> WriteAddr for Bus<'a, C1, C2,
       // I/O Registers
       0x2000...0x3FFF => {
         let mirrored_addr = addr & 0x2007;
-        self.ppu.write_addr(mirrored_addr, value)
+        self
+          .ppu
+          .write_register(mirrored_addr, value, self.cartridge);
+        0x00
       }
       0x4000...0x4013 => self.apu.write_addr(addr, value),
       0x4014 => {
-        // TODO: DMA to ppu
-        unimplemented!("DMA not implemented");
+        self.oam_dma(value);
+        0x00
       }
       0x4015 => self.apu.write_addr(addr, value),
-      // Controller 1
-      0x4016 => match &mut self.controller1 {
-        Some(controller) => controller.write_addr(addr, value),
-        None => 0x00,
-      },
-      // Controller 2
-      0x4017 => match &mut self.controller2 {
-        Some(controller) => controller.write_addr(addr, value),
-        None => 0x00,
-      },
+      // Both controllers are strobed together
+      0x4016 => {
+        if let Some(controller) = &mut self.controller1 {
+          controller.write_addr(addr, value);
+        }
+        if let Some(controller) = &mut self.controller2 {
+          controller.write_addr(addr + 1, value);
+        }
+        0x00
+      }
+      // Frame counter (reads are from controller 2)
+      0x4017 => self.apu.write_addr(addr, value),
       // APU and I/O functionality that is usually disabled
       0x4018...0x401F => panic!("Attempted access to disabled I/O ${:04X}", addr),
-      // TODO: Expansion ROM
-      0x4020...0x5FFF => panic!("Attempted access to expansion ROM ${:04X}", addr),
-      // TODO: Save RAM
-      0x6000...0x7FFF => panic!("Attempted access to Save RAM ${:04X}", addr),
-      // Catridge ROM
-      0x8000...0xFFFF => self.cartridge.mapper.write_addr(addr, value),
-      _ => panic!("Bus addr not implemented for ${:04X}", addr),
+      // Cartridge expansion area, save RAM and ROM
+      0x4020...0xFFFF => {
+        if self.cartridge.mapper.write_switches_chr(addr) {
+          self.ppu.invalidate_tile_cache();
+        }
+        self.cartridge.mapper.write_addr(addr, value)
+      }
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use apu::processor::ApuImpl;
+  use cartridge::parse_rom_file;
+  use controller::joypad::Joypad;
+  use io::audio::NullAudioOutput;
+  use io::video::{unpack_index, IndexedVideoOutput};
+  use ppu::RenderMode;
+  use std::sync::{Arc, Mutex};
+
+  const CYCLES_PER_FRAME: usize = 341 * 262;
+
+  #[test]
+  fn render_modes_match_after_nina001_chr_switch() {
+    // A NINA-001 cartridge, whose 4KB CHR bank 0 has tile 1 in colour 1
+    // and bank 1 has it in colour 2
+    let mut rom = vec![0x4E, 0x45, 0x53, 0x1A, 0x02, 0x02, 0x20, 0x20];
+    rom.resize(16 + 0x8000, 0);
+    let mut chr = vec![0; 0x4000];
+    chr[0x0010..0x0018].copy_from_slice(&[0xFF; 8]);
+    chr[0x1018..0x1020].copy_from_slice(&[0xFF; 8]);
+    rom.extend(chr);
+
+    let mut last_frames = vec![];
+    for mode in [RenderMode::Accurate, RenderMode::Scanline].iter() {
+      let mut cartridge = parse_rom_file(&rom).unwrap();
+      let mut apu = ApuImpl::create(NullAudioOutput, 48_000);
+      let frames = Arc::new(Mutex::new(vec![]));
+      let output_frames = frames.clone();
+      let output = IndexedVideoOutput::new(move |_, frame: &[u16]| {
+        let indices: Vec<u8> = frame.iter().map(|&pixel| unpack_index(pixel).0).collect();
+        output_frames.lock().unwrap().push(indices);
+      });
+      let mut bus = Bus::new(
+        &mut cartridge,
+        Box::new(BlockMemory::with_size(0x0800)),
+        &mut apu,
+        None::<&mut Joypad>,
+        None::<&mut Joypad>,
+        output,
+      );
+      bus.ppu.set_render_mode(*mode);
+
+      bus.write_addr(0x2006, 0x20);
+      bus.write_addr(0x2006, 0x00);
+      for _ in 0..0x3C0 {
+        bus.write_addr(0x2007, 0x01);
+      }
+      bus.write_addr(0x2006, 0x3F);
+      bus.write_addr(0x2006, 0x00);
+      for &value in [0x0F, 0x01, 0x02, 0x03].iter() {
+        bus.write_addr(0x2007, value);
+      }
+      bus.write_addr(0x2000, 0x00);
+      bus.write_addr(0x2005, 0);
+      bus.write_addr(0x2005, 0);
+      bus.write_addr(0x2001, 0x0A);
+
+      for _ in 0..CYCLES_PER_FRAME * 2 {
+        bus.cycle_ppu();
+      }
+      bus.write_addr(0x7FFE, 1);
+      for _ in 0..CYCLES_PER_FRAME * 2 {
+        bus.cycle_ppu();
+      }
+
+      let frames = frames.lock().unwrap();
+      assert_eq!(frames[1][10 * 256 + 40], 0x01, "{:?}", mode);
+      last_frames.push(frames[3].clone());
+    }
+
+    assert_eq!(last_frames[0][10 * 256 + 40], 0x02);
+    assert!(last_frames[0] == last_frames[1]);
+  }
+}
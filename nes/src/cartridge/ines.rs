@@ -26,12 +26,14 @@
 //! 7        | 1      | Control byte 2
 //!          |        | 76543210
 //!          |        | ||||||||
-//!          |        | ||||++++- Reserved for future use,
-//!          |        | ||||      should all be 0
+//!          |        | ||||||++- Reserved for future use,
+//!          |        | ||||||    should all be 0
+//!          |        | ||||++--- 2: NES 2.0 header
 //!          |        | ++++----- Upper bits of mapper number
 //! ---------|--------|--------------------------------------------
 //! 8        | 1      | Number of 8KB RAM banks. If 0, assume 1 for
-//!          |        | backwards compatibility.
+//!          |        | backwards compatibility. NES 2.0 headers
+//!          |        | have the submapper in the upper 4 bits.
 //! ---------|--------|--------------------------------------------
 //! 9        | 7      | Reserved for future use. Should be 0.
 //! ---------|--------|--------------------------------------------
@@ -58,6 +60,7 @@ const IDX_NUM_PRG_ROM: usize = 4;
 const IDX_NUM_CHR_ROM: usize = 5;
 const IDX_CB1: usize = 6;
 const IDX_CB2: usize = 7;
+const IDX_SUBMAPPER: usize = 8;
 
 const CB1_BIT_MIRRORING: u8 = 0x01;
 const CB1_BIT_BATTERY_RAM: u8 = 0x02;
@@ -65,12 +68,30 @@ const CB1_BIT_TRAINER: u8 = 0x04;
 const CB1_BIT_FOUR_SCREEN_MIRRORING: u8 = 0x08;
 const CB1_MASK_MAPPER: u8 = 0xF0;
 const CB2_MASK_MAPPER: u8 = 0xF0;
+const CB2_MASK_NES2: u8 = 0x0C;
+const CB2_NES2: u8 = 0x08;
 
 const MAPPER_NROM: u8 = 0;
 const MAPPER_NINTENDO_MMC1: u8 = 1;
+const MAPPER_NINTENDO_MMC3: u8 = 4;
+const MAPPER_NINTENDO_MMC2: u8 = 9;
+const MAPPER_NINTENDO_MMC4: u8 = 10;
+const MAPPER_UXROM: u8 = 2;
 const MAPPER_CNROM_SWITCH: u8 = 3;
+const MAPPER_AXROM: u8 = 7;
+const MAPPER_COLOR_DREAMS: u8 = 11;
+const MAPPER_NAMCO_163: u8 = 19;
+const MAPPER_BNROM: u8 = 34;
+const MAPPER_KONAMI_VRC6A: u8 = 24;
+const MAPPER_KONAMI_VRC6B: u8 = 26;
+const MAPPER_GXROM: u8 = 66;
+const MAPPER_SUNSOFT_FME7: u8 = 69;
+const MAPPER_KONAMI_VRC7: u8 = 85;
 const MAPPER_INES_211: u8 = 211;
 
+/// Mapper 4 boards with the old revision of the MMC3
+const SUBMAPPER_MMC3A: u8 = 4;
+
 const SIZE_PRG_ROM_BANK: usize = 16 * 1024;
 const SIZE_CHR_ROM_BANK: usize = 8 * 1024;
 
@@ -153,6 +174,15 @@ fn has_four_screen_mirroring(data: &[u8]) -> bool {
   data[IDX_CB1] & CB1_BIT_FOUR_SCREEN_MIRRORING != 0
 }
 
+/// The NES 2.0 submapper, or 0 for iNES headers which don't have one
+fn submapper(data: &[u8]) -> u8 {
+  if data[IDX_CB2] & CB2_MASK_NES2 == CB2_NES2 && data.len() > IDX_SUBMAPPER {
+    data[IDX_SUBMAPPER] >> 4
+  } else {
+    0
+  }
+}
+
 fn detect_mapper(data: &[u8]) -> Result<MapperType, ParseErrorReason> {
   let mapper_num = (data[IDX_CB1] & CB1_MASK_MAPPER) >> 4 | (data[IDX_CB2] & CB2_MASK_MAPPER);
 
@@ -160,7 +190,21 @@ fn detect_mapper(data: &[u8]) -> Result<MapperType, ParseErrorReason> {
   match mapper_num {
     MAPPER_NROM => Ok(MapperType::NROM),
     MAPPER_NINTENDO_MMC1 => Ok(MapperType::NintendoMMC1),
+    MAPPER_NINTENDO_MMC3 if submapper(data) == SUBMAPPER_MMC3A => Ok(MapperType::NintendoMMC3A),
+    MAPPER_NINTENDO_MMC3 => Ok(MapperType::NintendoMMC3),
+    MAPPER_NINTENDO_MMC2 => Ok(MapperType::NintendoMMC2),
+    MAPPER_NINTENDO_MMC4 => Ok(MapperType::NintendoMMC4),
+    MAPPER_UXROM => Ok(MapperType::UxROM),
     MAPPER_CNROM_SWITCH => Ok(MapperType::CNROMSwitch),
+    MAPPER_AXROM => Ok(MapperType::AxROM),
+    MAPPER_COLOR_DREAMS => Ok(MapperType::ColorDreams),
+    MAPPER_NAMCO_163 => Ok(MapperType::Namco163),
+    MAPPER_BNROM => Ok(MapperType::BNROM),
+    MAPPER_KONAMI_VRC6A => Ok(MapperType::KonamiVRC6a),
+    MAPPER_KONAMI_VRC6B => Ok(MapperType::KonamiVRC6b),
+    MAPPER_GXROM => Ok(MapperType::GxROM),
+    MAPPER_SUNSOFT_FME7 => Ok(MapperType::SunsoftFME7),
+    MAPPER_KONAMI_VRC7 => Ok(MapperType::KonamiVRC7),
     MAPPER_INES_211 => Ok(MapperType::INESMapper211),
     _ => Err(ParseErrorReason::UnknownMapper),
   }
@@ -274,12 +318,71 @@ mod tests {
     assert_eq!(detect_mapper(&data), Ok(MapperType::NintendoMMC1));
   }
 
+  #[test]
+  pub fn test_detect_mapper_mmc3() {
+    let data = [0x4e, 0x45, 0x53, 0x1a, 0x10, 0x20, 0x40, 0x00];
+    assert_eq!(detect_mapper(&data), Ok(MapperType::NintendoMMC3));
+    let data = [0x4e, 0x45, 0x53, 0x1a, 0x10, 0x20, 0x40, 0x08, 0x40];
+    assert_eq!(detect_mapper(&data), Ok(MapperType::NintendoMMC3A));
+    // Not a NES 2.0 header, so byte 8 is the number of RAM banks
+    let data = [0x4e, 0x45, 0x53, 0x1a, 0x10, 0x20, 0x40, 0x00, 0x40];
+    assert_eq!(detect_mapper(&data), Ok(MapperType::NintendoMMC3));
+  }
+
+  #[test]
+  pub fn test_detect_mapper_mmc2_mmc4() {
+    let data = [0x4e, 0x45, 0x53, 0x1a, 0x08, 0x10, 0x90, 0x00];
+    assert_eq!(detect_mapper(&data), Ok(MapperType::NintendoMMC2));
+    let data = [0x4e, 0x45, 0x53, 0x1a, 0x10, 0x10, 0xA2, 0x00];
+    assert_eq!(detect_mapper(&data), Ok(MapperType::NintendoMMC4));
+  }
+
   #[test]
   pub fn test_detect_mapper_cnrom_switch() {
     let data = [0x4e, 0x45, 0x53, 0x1a, 0x02, 0x02, 0x31, 0x00];
     assert_eq!(detect_mapper(&data), Ok(MapperType::CNROMSwitch));
   }
 
+  #[test]
+  pub fn test_detect_mapper_discrete() {
+    let data = [0x4e, 0x45, 0x53, 0x1a, 0x08, 0x00, 0x20, 0x00];
+    assert_eq!(detect_mapper(&data), Ok(MapperType::UxROM));
+    let data = [0x4e, 0x45, 0x53, 0x1a, 0x08, 0x00, 0x70, 0x00];
+    assert_eq!(detect_mapper(&data), Ok(MapperType::AxROM));
+    let data = [0x4e, 0x45, 0x53, 0x1a, 0x08, 0x08, 0xB0, 0x00];
+    assert_eq!(detect_mapper(&data), Ok(MapperType::ColorDreams));
+    let data = [0x4e, 0x45, 0x53, 0x1a, 0x08, 0x00, 0x20, 0x20];
+    assert_eq!(detect_mapper(&data), Ok(MapperType::BNROM));
+    let data = [0x4e, 0x45, 0x53, 0x1a, 0x08, 0x08, 0x20, 0x40];
+    assert_eq!(detect_mapper(&data), Ok(MapperType::GxROM));
+  }
+
+  #[test]
+  pub fn test_detect_mapper_namco163() {
+    let data = [0x4e, 0x45, 0x53, 0x1a, 0x10, 0x10, 0x30, 0x10];
+    assert_eq!(detect_mapper(&data), Ok(MapperType::Namco163));
+  }
+
+  #[test]
+  pub fn test_detect_mapper_vrc6() {
+    let data = [0x4e, 0x45, 0x53, 0x1a, 0x10, 0x20, 0x80, 0x10];
+    assert_eq!(detect_mapper(&data), Ok(MapperType::KonamiVRC6a));
+    let data = [0x4e, 0x45, 0x53, 0x1a, 0x10, 0x20, 0xA0, 0x10];
+    assert_eq!(detect_mapper(&data), Ok(MapperType::KonamiVRC6b));
+  }
+
+  #[test]
+  pub fn test_detect_mapper_fme7() {
+    let data = [0x4e, 0x45, 0x53, 0x1a, 0x10, 0x20, 0x50, 0x40];
+    assert_eq!(detect_mapper(&data), Ok(MapperType::SunsoftFME7));
+  }
+
+  #[test]
+  pub fn test_detect_mapper_vrc7() {
+    let data = [0x4e, 0x45, 0x53, 0x1a, 0x08, 0x10, 0x50, 0x50];
+    assert_eq!(detect_mapper(&data), Ok(MapperType::KonamiVRC7));
+  }
+
   #[test]
   pub fn test_detect_mapper_ines211() {
     let data = [0x4e, 0x45, 0x53, 0x1a, 0x10, 0x20, 0x30, 0xd0];
@@ -1,26 +1,152 @@
+use apu::expansion::{VoiceLevels, MAX_VOICES};
+use cartridge::mappers::discrete::{Discrete, DiscreteBoard};
+use cartridge::mappers::fme7::Fme7;
+use cartridge::mappers::mmc1::MMC1;
+use cartridge::mappers::mmc2::{MMC2Chip, MMC2};
+use cartridge::mappers::mmc3::{MMC3Revision, MMC3};
+use cartridge::mappers::namco163::Namco163;
 use cartridge::mappers::nrom::NROM;
+use cartridge::mappers::vrc6::{Vrc6, Vrc6Wiring};
+use cartridge::mappers::vrc7::Vrc7;
+use cartridge::mirroring::Mirroring;
 use memory::{ReadAddr, WriteAddr};
 
 #[derive(PartialEq, Debug, Clone)]
 pub enum MapperType {
   NROM, // No mapper
   NintendoMMC1,
+  NintendoMMC2,
+  NintendoMMC3,
+  NintendoMMC3A, // The old revision, https://wiki.nesdev.com/w/index.php/MMC3#Hardware
+  NintendoMMC4,
+  UxROM,
   CNROMSwitch,
+  AxROM,
+  ColorDreams,
+  BNROM, // Or NINA-001, https://wiki.nesdev.com/w/index.php/INES_Mapper_034
+  GxROM,
+  KonamiVRC6a,
+  KonamiVRC6b,
+  KonamiVRC7,
+  Namco163,
+  SunsoftFME7,
   INESMapper211, // https://wiki.nesdev.com/w/index.php/INES_Mapper_211
 }
 
-pub trait Mapper: ReadAddr + WriteAddr {}
+pub trait Mapper: ReadAddr + WriteAddr {
+  /// Read from the pattern tables ($0000-$1FFF in the PPU address space)
+  fn read_chr(&mut self, addr: u16) -> u8;
+
+  /// Write to the pattern tables, ignored unless the cartridge has CHR-RAM
+  fn write_chr(&mut self, _addr: u16, _value: u8) {}
+
+  /// Whether a CPU write to `addr` can switch CHR banks, which makes the
+  /// PPU throw away the tiles it has cached. Most mappers only have
+  /// registers at $8000-$FFFF.
+  fn write_switches_chr(&self, addr: u16) -> bool {
+    addr >= 0x8000
+  }
+
+  /// Whether reading the pattern tables can switch CHR banks, in which
+  /// case the PPU has to read every tile it draws rather than cache them
+  fn chr_reads_switch_banks(&self) -> bool {
+    false
+  }
+
+  /// Read from the name tables ($2000-$3EFF) for mappers which can put
+  /// CHR-ROM there, or `None` to read the console's name table RAM
+  fn read_nametable(&mut self, _addr: u16) -> Option<u8> {
+    None
+  }
+
+  /// Write to the name tables, returning whether the mapper took the
+  /// write rather than the console's name table RAM
+  fn write_nametable(&mut self, _addr: u16, _value: u8) -> bool {
+    false
+  }
+
+  /// Watch an address the PPU puts on its address bus, for mappers which
+  /// count scanlines from the pattern table fetches
+  fn ppu_address(&mut self, _addr: u16) {}
+
+  /// Name table mirroring set by the mapper, or `None` to use the mirroring
+  /// from the ROM header
+  fn mirroring(&self) -> Option<Mirroring> {
+    None
+  }
+
+  /// Run a single CPU cycle, for mappers with IRQ counters or sound chips
+  fn cycle(&mut self) {}
+
+  /// Whether the mapper is asserting the CPU's IRQ line
+  fn irq(&self) -> bool {
+    false
+  }
+
+  /// The current level of each voice of the cartridge's expansion audio,
+  /// if any (see `apu::expansion`)
+  fn expansion_audio(&self) -> VoiceLevels {
+    [0.0; MAX_VOICES]
+  }
+}
 
 impl Mapper {
   pub fn create(
     t: MapperType,
     prg_rom_data: Vec<u8>,
-    _chr_rom_data: Vec<u8>,
+    chr_rom_data: Vec<u8>,
     num_prg_rom_banks: u8,
     _num_chr_rom_banks: u8,
   ) -> Box<Mapper> {
     match t {
-      MapperType::NROM => Box::new(NROM::new(prg_rom_data, num_prg_rom_banks)),
+      MapperType::NROM => Box::new(NROM::new(prg_rom_data, chr_rom_data, num_prg_rom_banks)),
+      MapperType::NintendoMMC1 => Box::new(MMC1::new(prg_rom_data, chr_rom_data)),
+      MapperType::NintendoMMC2 => Box::new(MMC2::new(MMC2Chip::MMC2, prg_rom_data, chr_rom_data)),
+      MapperType::NintendoMMC3 => {
+        Box::new(MMC3::new(prg_rom_data, chr_rom_data, MMC3Revision::Sharp))
+      }
+      MapperType::NintendoMMC3A => {
+        Box::new(MMC3::new(prg_rom_data, chr_rom_data, MMC3Revision::NEC))
+      }
+      MapperType::NintendoMMC4 => Box::new(MMC2::new(MMC2Chip::MMC4, prg_rom_data, chr_rom_data)),
+      MapperType::UxROM => Box::new(Discrete::new(
+        DiscreteBoard::UxROM,
+        prg_rom_data,
+        chr_rom_data,
+      )),
+      MapperType::CNROMSwitch => Box::new(Discrete::new(
+        DiscreteBoard::CNROM,
+        prg_rom_data,
+        chr_rom_data,
+      )),
+      MapperType::AxROM => Box::new(Discrete::new(
+        DiscreteBoard::AxROM,
+        prg_rom_data,
+        chr_rom_data,
+      )),
+      MapperType::ColorDreams => Box::new(Discrete::new(
+        DiscreteBoard::ColorDreams,
+        prg_rom_data,
+        chr_rom_data,
+      )),
+      MapperType::BNROM => {
+        let board = if chr_rom_data.len() > 8 * 1024 {
+          DiscreteBoard::NINA001
+        } else {
+          DiscreteBoard::BNROM
+        };
+        Box::new(Discrete::new(board, prg_rom_data, chr_rom_data))
+      }
+      MapperType::GxROM => Box::new(Discrete::new(
+        DiscreteBoard::GxROM,
+        prg_rom_data,
+        chr_rom_data,
+      )),
+      MapperType::KonamiVRC6a => Box::new(Vrc6::new(prg_rom_data, chr_rom_data, Vrc6Wiring::Vrc6a)),
+      MapperType::KonamiVRC6b => Box::new(Vrc6::new(prg_rom_data, chr_rom_data, Vrc6Wiring::Vrc6b)),
+      MapperType::KonamiVRC7 => Box::new(Vrc7::new(prg_rom_data, chr_rom_data)),
+      MapperType::Namco163 => Box::new(Namco163::new(prg_rom_data, chr_rom_data)),
+      MapperType::SunsoftFME7 => Box::new(Fme7::new(prg_rom_data, chr_rom_data)),
       _ => panic!("Mapper not implemented."),
     }
   }
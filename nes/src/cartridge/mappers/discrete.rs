@@ -0,0 +1,322 @@
+//! Discrete logic mappers (mappers 2, 3, 7, 11, 34 and 66)
+//!
+//! Boards which switch banks with a simple latch rather than a custom
+//! chip. Writing anywhere in $8000-$FFFF sets the latch. Most of them
+//! don't stop the ROM from driving the data bus at the same time, so the
+//! value written is ANDed with the ROM byte at the address (a [bus
+//! conflict][conflicts]). Games avoid trouble by writing to a byte which
+//! holds the same value.
+//!
+//!  Mapper | Board        | Latch      | PRG-ROM        | CHR     | Conflicts
+//! --------|--------------|------------|----------------|---------|----------
+//!  2      | UxROM        | PPPP       | 16KB + last    | RAM     | Yes
+//!  3      | CNROM        | CCCC CCCC  | 16 or 32KB     | 8KB     | Yes
+//!  7      | AxROM        | ...S .PPP  | 32KB           | RAM     | No
+//!  11     | Color Dreams | CCCC ..PP  | 32KB           | 8KB     | Yes
+//!  34     | BNROM        | PPPP PPPP  | 32KB           | RAM     | Yes
+//!  34     | NINA-001     | see below  | 32KB           | 2 × 4KB | No
+//!  66     | GxROM        | ..PP ..CC  | 32KB           | 8KB     | Yes
+//!
+//! UxROM's last 16KB bank is fixed at $C000. AxROM selects a Single screen
+//! of name table RAM, the rest use the mirroring from the ROM header.
+//!
+//! Mapper 34 is two different boards, told apart by their CHR: BNROM has
+//! CHR-RAM, and NINA-001 has more than 8KB of CHR-ROM. NINA-001 has 8KB
+//! of PRG-RAM at $6000-$7FFF, and its registers at the end of it: $7FFD
+//! is the 32KB PRG bank, and $7FFE and $7FFF the 4KB CHR banks.
+//!
+//! AMROM, one of the AxROM boards, does have bus conflicts, but the games
+//! written for AOROM (which doesn't) would break with them.
+//!
+//! [conflicts]: https://wiki.nesdev.com/w/index.php/Bus_conflict
+
+use cartridge::mapper::Mapper;
+use cartridge::mappers::{bank_addr, chr_rom_or_ram};
+use cartridge::mirroring::Mirroring;
+use memory::{ReadAddr, WriteAddr};
+
+const SIZE_CHR_BANK: usize = 4 * 1024;
+const SIZE_PRG_RAM: usize = 8 * 1024;
+
+/// The board, which decides what the latch does
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiscreteBoard {
+  /// Mapper 2
+  UxROM,
+  /// Mapper 3
+  CNROM,
+  /// Mapper 7
+  AxROM,
+  /// Mapper 11
+  ColorDreams,
+  /// Mapper 34, with CHR-RAM
+  BNROM,
+  /// Mapper 34, with CHR-ROM
+  NINA001,
+  /// Mapper 66
+  GxROM,
+}
+
+impl DiscreteBoard {
+  fn has_bus_conflicts(self) -> bool {
+    self != DiscreteBoard::AxROM && self != DiscreteBoard::NINA001
+  }
+}
+
+pub struct Discrete {
+  board: DiscreteBoard,
+  prg_rom: Vec<u8>,
+  chr: Vec<u8>,
+  chr_is_ram: bool,
+  prg_ram: Vec<u8>,
+
+  prg_bank: u8,
+  /// 4KB CHR banks at $0000 and $1000
+  chr_banks: [u8; 2],
+  mirroring: Option<Mirroring>,
+}
+
+impl Discrete {
+  pub fn new(board: DiscreteBoard, prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Self {
+    let chr_is_ram = chr_rom.is_empty();
+    Discrete {
+      board,
+      prg_rom,
+      chr: chr_rom_or_ram(chr_rom),
+      chr_is_ram,
+      prg_ram: vec![0; SIZE_PRG_RAM],
+      prg_bank: 0,
+      chr_banks: [0, 1],
+      mirroring: if board == DiscreteBoard::AxROM {
+        Some(Mirroring::SingleScreenLower)
+      } else {
+        None
+      },
+    }
+  }
+
+  fn read_prg(&self, addr: u16) -> u8 {
+    let offset = usize::from(addr - 0x8000);
+    let addr = match self.board {
+      DiscreteBoard::UxROM => {
+        let size = 16 * 1024;
+        let bank = if addr < 0xC000 {
+          usize::from(self.prg_bank)
+        } else {
+          self.prg_rom.len() / size - 1
+        };
+        bank * size + offset % size
+      }
+      DiscreteBoard::CNROM => offset,
+      _ => usize::from(self.prg_bank) * 32 * 1024 + offset,
+    };
+    self.prg_rom[addr % self.prg_rom.len()]
+  }
+
+  fn chr_addr(&self, addr: u16) -> usize {
+    let bank = usize::from(self.chr_banks[usize::from(addr >> 12) & 1]);
+    bank_addr(bank, SIZE_CHR_BANK, self.chr.len(), addr)
+  }
+
+  /// Select an 8KB CHR bank
+  fn set_chr_bank_8k(&mut self, bank: u8) {
+    self.chr_banks = [bank.wrapping_mul(2), bank.wrapping_mul(2).wrapping_add(1)];
+  }
+
+  fn write_latch(&mut self, value: u8) {
+    match self.board {
+      DiscreteBoard::UxROM | DiscreteBoard::BNROM => self.prg_bank = value,
+      DiscreteBoard::CNROM => self.set_chr_bank_8k(value),
+      DiscreteBoard::AxROM => {
+        self.prg_bank = value & 0x07;
+        self.mirroring = Some(if value & 0x10 == 0 {
+          Mirroring::SingleScreenLower
+        } else {
+          Mirroring::SingleScreenUpper
+        });
+      }
+      DiscreteBoard::ColorDreams => {
+        self.prg_bank = value & 0x03;
+        self.set_chr_bank_8k(value >> 4);
+      }
+      DiscreteBoard::GxROM => {
+        self.prg_bank = (value >> 4) & 0x03;
+        self.set_chr_bank_8k(value & 0x03);
+      }
+      DiscreteBoard::NINA001 => {}
+    }
+  }
+}
+
+impl Mapper for Discrete {
+  fn read_chr(&mut self, addr: u16) -> u8 {
+    self.chr[self.chr_addr(addr)]
+  }
+
+  fn write_chr(&mut self, addr: u16, value: u8) {
+    if self.chr_is_ram {
+      let addr = self.chr_addr(addr);
+      self.chr[addr] = value;
+    }
+  }
+
+  fn write_switches_chr(&self, addr: u16) -> bool {
+    match self.board {
+      DiscreteBoard::NINA001 => addr == 0x7FFE || addr == 0x7FFF,
+      _ => addr >= 0x8000,
+    }
+  }
+
+  fn mirroring(&self) -> Option<Mirroring> {
+    self.mirroring
+  }
+}
+
+impl ReadAddr for Discrete {
+  fn read_addr(&mut self, addr: u16) -> u8 {
+    match addr {
+      0x6000...0x7FFF if self.board == DiscreteBoard::NINA001 => {
+        self.prg_ram[usize::from(addr - 0x6000)]
+      }
+      0x8000...0xFFFF => self.read_prg(addr),
+      // Open bus
+      _ => 0,
+    }
+  }
+}
+
+impl WriteAddr for Discrete {
+  fn write_addr(&mut self, addr: u16, value: u8) -> u8 {
+    match addr {
+      0x6000...0x7FFF if self.board == DiscreteBoard::NINA001 => {
+        self.prg_ram[usize::from(addr - 0x6000)] = value;
+        match addr {
+          0x7FFD => self.prg_bank = value & 0x01,
+          0x7FFE => self.chr_banks[0] = value & 0x0F,
+          0x7FFF => self.chr_banks[1] = value & 0x0F,
+          _ => {}
+        }
+      }
+      0x8000...0xFFFF => {
+        let value = if self.board.has_bus_conflicts() {
+          value & self.read_prg(addr)
+        } else {
+          value
+        };
+        self.write_latch(value);
+      }
+      _ => {}
+    }
+    0
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use cartridge::mappers::numbered_banks;
+
+  /// A board with each PRG bank of a size and 4KB CHR bank filled with
+  /// its number, apart from $FF at the end of each PRG bank
+  fn discrete(
+    board: DiscreteBoard,
+    prg_banks: u8,
+    prg_bank_size: usize,
+    chr_banks: u8,
+  ) -> Discrete {
+    let mut prg = numbered_banks(prg_banks, prg_bank_size);
+    for bank in prg.chunks_mut(prg_bank_size) {
+      bank[prg_bank_size - 1] = 0xFF;
+    }
+    Discrete::new(board, prg, numbered_banks(chr_banks, SIZE_CHR_BANK))
+  }
+
+  #[test]
+  fn uxrom() {
+    let mut mapper = discrete(DiscreteBoard::UxROM, 8, 0x4000, 0);
+    assert_eq!(mapper.read_addr(0x8000), 0);
+    assert_eq!(mapper.read_addr(0xC000), 7);
+    mapper.write_addr(0xBFFF, 5);
+    assert_eq!(mapper.read_addr(0x8000), 5);
+    assert_eq!(mapper.read_addr(0xC000), 7);
+    assert_eq!(mapper.mirroring(), None);
+
+    mapper.write_chr(0x1234, 0x42);
+    assert_eq!(mapper.read_chr(0x1234), 0x42);
+  }
+
+  #[test]
+  fn bus_conflicts() {
+    let mut mapper = discrete(DiscreteBoard::UxROM, 8, 0x4000, 0);
+    // The ROM byte at $8000 is 0, and at $C000 is 7
+    mapper.write_addr(0x8000, 5);
+    assert_eq!(mapper.read_addr(0x8000), 0);
+    mapper.write_addr(0xC000, 6);
+    assert_eq!(mapper.read_addr(0x8000), 6);
+  }
+
+  #[test]
+  fn cnrom() {
+    let mut mapper = discrete(DiscreteBoard::CNROM, 1, 0x4000, 8);
+    assert_eq!(mapper.read_addr(0xC000), 0);
+    mapper.write_addr(0xFFFF, 2);
+    assert_eq!(mapper.read_chr(0x0000), 4);
+    assert_eq!(mapper.read_chr(0x1FFF), 5);
+  }
+
+  #[test]
+  fn axrom() {
+    let mut mapper = discrete(DiscreteBoard::AxROM, 4, 0x8000, 0);
+    assert_eq!(mapper.mirroring(), Some(Mirroring::SingleScreenLower));
+    // No bus conflicts
+    mapper.write_addr(0x8000, 0x13);
+    assert_eq!(mapper.read_addr(0x8000), 3);
+    assert_eq!(mapper.mirroring(), Some(Mirroring::SingleScreenUpper));
+  }
+
+  #[test]
+  fn color_dreams() {
+    let mut mapper = discrete(DiscreteBoard::ColorDreams, 4, 0x8000, 16);
+    mapper.write_addr(0xFFFF, 0x32);
+    assert_eq!(mapper.read_addr(0x8000), 2);
+    assert_eq!(mapper.read_chr(0x0000), 6);
+    assert_eq!(mapper.read_chr(0x1000), 7);
+  }
+
+  #[test]
+  fn bnrom() {
+    let mut mapper = discrete(DiscreteBoard::BNROM, 4, 0x8000, 0);
+    mapper.write_addr(0xFFFF, 3);
+    assert_eq!(mapper.read_addr(0x8000), 3);
+    assert_eq!(mapper.read_addr(0xFFFE), 3);
+  }
+
+  #[test]
+  fn nina001() {
+    let mut mapper = discrete(DiscreteBoard::NINA001, 2, 0x8000, 16);
+    mapper.write_addr(0x7FFD, 1);
+    mapper.write_addr(0x7FFE, 9);
+    mapper.write_addr(0x7FFF, 4);
+    assert_eq!(mapper.read_addr(0x8000), 1);
+    assert_eq!(mapper.read_chr(0x0000), 9);
+    assert_eq!(mapper.read_chr(0x1000), 4);
+
+    assert!(mapper.write_switches_chr(0x7FFF));
+    assert!(!mapper.write_switches_chr(0x7FFD));
+
+    mapper.write_addr(0x6000, 0x42);
+    assert_eq!(mapper.read_addr(0x6000), 0x42);
+    // Writes to ROM do nothing
+    mapper.write_addr(0x8000, 0);
+    assert_eq!(mapper.read_addr(0x8000), 1);
+  }
+
+  #[test]
+  fn gxrom() {
+    let mut mapper = discrete(DiscreteBoard::GxROM, 4, 0x8000, 8);
+    mapper.write_addr(0xFFFF, 0x21);
+    assert_eq!(mapper.read_addr(0x8000), 2);
+    assert_eq!(mapper.read_chr(0x0000), 2);
+    assert_eq!(mapper.read_chr(0x1000), 3);
+  }
+}
@@ -0,0 +1,299 @@
+//! Sunsoft FME-7 (mapper 69)
+//!
+//! Used by Batman: Return of the Joker, and by Gimmick! as the 5B, which
+//! adds its own sound (see `apu::expansion::sunsoft5b`). The game writes a
+//! command to $8000, then its parameter to $A000. [Read more here][FME-7].
+//!
+//!  Address    | Use
+//! ------------|------------------------------------------------------
+//! $6000-$7FFF | Switchable 8KB PRG-ROM bank, or 8KB PRG-RAM
+//! $8000-$9FFF | Switchable 8KB PRG-ROM bank
+//! $A000-$BFFF | Switchable 8KB PRG-ROM bank
+//! $C000-$DFFF | Switchable 8KB PRG-ROM bank
+//! $E000-$FFFF | Last 8KB PRG-ROM bank
+//!
+//!  Command | Parameter
+//! ---------|-------------------------------------------------------
+//! $0-$7    | 1KB CHR banks 0-7
+//! $8       | ERBB BBBB: RAM Enable, RAM (rather than ROM), bank at $6000
+//! $9-$B    | ..BB BBBB: 8KB PRG banks at $8000, $A000 and $C000
+//! $C       | .... ..MM: Mirroring
+//! $D       | C... ...I: Counter enable, IRQ enable. Acknowledges the IRQ.
+//! $E, $F   | IRQ counter low, high
+//!
+//! The mirroring is 0: vertical, 1: horizontal, 2: single screen lower or
+//! 3: single screen upper. The 16 bit IRQ counter counts down every CPU
+//! cycle while enabled, raising an IRQ (if enabled) when it wraps from 0
+//! to $FFFF.
+//!
+//! [FME-7]: https://wiki.nesdev.com/w/index.php/Sunsoft_FME-7
+
+use apu::expansion::sunsoft5b::Sunsoft5bAudio;
+use apu::expansion::VoiceLevels;
+use cartridge::mapper::Mapper;
+use cartridge::mappers::{bank_addr, chr_rom_or_ram};
+use cartridge::mirroring::Mirroring;
+use memory::{ReadAddr, WriteAddr};
+
+const SIZE_PRG_BANK: usize = 8 * 1024;
+const SIZE_CHR_BANK: usize = 1024;
+const SIZE_PRG_RAM: usize = 8 * 1024;
+
+pub struct Fme7 {
+  prg_rom: Vec<u8>,
+  chr: Vec<u8>,
+  chr_is_ram: bool,
+  prg_ram: Vec<u8>,
+
+  command: u8,
+  /// Banks at $6000, $8000, $A000 and $C000
+  prg_banks: [u8; 4],
+  prg_ram_selected: bool,
+  prg_ram_enabled: bool,
+  chr_banks: [u8; 8],
+  mirroring: Mirroring,
+
+  irq_counter: u16,
+  irq_counter_enabled: bool,
+  irq_enabled: bool,
+  irq_pending: bool,
+
+  audio: Sunsoft5bAudio,
+}
+
+impl Fme7 {
+  pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Self {
+    let chr_is_ram = chr_rom.is_empty();
+    Fme7 {
+      prg_rom,
+      chr: chr_rom_or_ram(chr_rom),
+      chr_is_ram,
+      prg_ram: vec![0; SIZE_PRG_RAM],
+      command: 0,
+      prg_banks: [0; 4],
+      prg_ram_selected: false,
+      prg_ram_enabled: false,
+      chr_banks: [0; 8],
+      mirroring: Mirroring::Vertical,
+      irq_counter: 0,
+      irq_counter_enabled: false,
+      irq_enabled: false,
+      irq_pending: false,
+      audio: Sunsoft5bAudio::default(),
+    }
+  }
+
+  fn read_prg(&self, addr: u16) -> u8 {
+    let num_banks = self.prg_rom.len() / SIZE_PRG_BANK;
+    let bank = match addr {
+      0x6000...0xDFFF => usize::from(self.prg_banks[usize::from((addr - 0x6000) >> 13)]),
+      _ => num_banks - 1,
+    };
+    self.prg_rom[bank_addr(bank, SIZE_PRG_BANK, self.prg_rom.len(), addr)]
+  }
+
+  fn chr_addr(&self, addr: u16) -> usize {
+    let bank = usize::from(self.chr_banks[usize::from(addr >> 10) & 0b111]);
+    bank_addr(bank, SIZE_CHR_BANK, self.chr.len(), addr)
+  }
+
+  fn write_parameter(&mut self, value: u8) {
+    match self.command {
+      command @ 0x0...0x7 => self.chr_banks[usize::from(command)] = value,
+      0x8 => {
+        self.prg_ram_enabled = value & 0x80 != 0;
+        self.prg_ram_selected = value & 0x40 != 0;
+        self.prg_banks[0] = value & 0x3F;
+      }
+      command @ 0x9...0xB => self.prg_banks[usize::from(command - 0x8)] = value & 0x3F,
+      0xC => {
+        self.mirroring = match value & 0b11 {
+          0 => Mirroring::Vertical,
+          1 => Mirroring::Horizontal,
+          2 => Mirroring::SingleScreenLower,
+          _ => Mirroring::SingleScreenUpper,
+        }
+      }
+      0xD => {
+        self.irq_enabled = value & 0x01 != 0;
+        self.irq_counter_enabled = value & 0x80 != 0;
+        self.irq_pending = false;
+      }
+      0xE => self.irq_counter = (self.irq_counter & 0xFF00) | u16::from(value),
+      _ => self.irq_counter = (self.irq_counter & 0x00FF) | (u16::from(value) << 8),
+    }
+  }
+}
+
+impl Mapper for Fme7 {
+  fn read_chr(&mut self, addr: u16) -> u8 {
+    self.chr[self.chr_addr(addr)]
+  }
+
+  fn write_chr(&mut self, addr: u16, value: u8) {
+    if self.chr_is_ram {
+      let addr = self.chr_addr(addr);
+      self.chr[addr] = value;
+    }
+  }
+
+  fn mirroring(&self) -> Option<Mirroring> {
+    Some(self.mirroring)
+  }
+
+  fn cycle(&mut self) {
+    if self.irq_counter_enabled {
+      self.irq_counter = self.irq_counter.wrapping_sub(1);
+      if self.irq_counter == 0xFFFF && self.irq_enabled {
+        self.irq_pending = true;
+      }
+    }
+    self.audio.clock();
+  }
+
+  fn irq(&self) -> bool {
+    self.irq_pending
+  }
+
+  fn expansion_audio(&self) -> VoiceLevels {
+    self.audio.voices()
+  }
+}
+
+impl ReadAddr for Fme7 {
+  fn read_addr(&mut self, addr: u16) -> u8 {
+    match addr {
+      0x6000...0x7FFF if self.prg_ram_selected && self.prg_ram_enabled => {
+        self.prg_ram[usize::from(addr - 0x6000)]
+      }
+      // Open bus while the RAM is disabled
+      0x6000...0x7FFF if self.prg_ram_selected => 0,
+      0x6000...0xFFFF => self.read_prg(addr),
+      // Open bus
+      _ => 0,
+    }
+  }
+}
+
+impl WriteAddr for Fme7 {
+  fn write_addr(&mut self, addr: u16, value: u8) -> u8 {
+    match addr {
+      0x6000...0x7FFF if self.prg_ram_selected && self.prg_ram_enabled => {
+        self.prg_ram[usize::from(addr - 0x6000)] = value;
+      }
+      0x8000...0x9FFF => self.command = value & 0x0F,
+      0xA000...0xBFFF => self.write_parameter(value),
+      0xC000...0xDFFF => self.audio.write_register(value),
+      0xE000...0xFFFF => self.audio.write_data(value),
+      _ => {}
+    }
+    0
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use cartridge::mappers::numbered_banks;
+
+  /// An FME-7 with each 8KB PRG bank and 1KB CHR bank filled with its
+  /// number
+  fn fme7() -> Fme7 {
+    Fme7::new(
+      numbered_banks(16, SIZE_PRG_BANK),
+      numbered_banks(32, SIZE_CHR_BANK),
+    )
+  }
+
+  fn command(mapper: &mut Fme7, command: u8, parameter: u8) {
+    mapper.write_addr(0x8000, command);
+    mapper.write_addr(0xA000, parameter);
+  }
+
+  #[test]
+  fn switches_prg_banks() {
+    let mut mapper = fme7();
+    assert_eq!(mapper.read_addr(0xE000), 15);
+    command(&mut mapper, 0x9, 3);
+    command(&mut mapper, 0xA, 4);
+    command(&mut mapper, 0xB, 5);
+    assert_eq!(mapper.read_addr(0x8000), 3);
+    assert_eq!(mapper.read_addr(0xA000), 4);
+    assert_eq!(mapper.read_addr(0xDFFF), 5);
+    assert_eq!(mapper.read_addr(0xFFFF), 15);
+  }
+
+  #[test]
+  fn switches_chr_banks() {
+    let mut mapper = fme7();
+    for bank in 0..8 {
+      command(&mut mapper, bank, 20 + bank);
+    }
+    assert_eq!(mapper.read_chr(0x0000), 20);
+    assert_eq!(mapper.read_chr(0x0800), 22);
+    assert_eq!(mapper.read_chr(0x1FFF), 27);
+  }
+
+  #[test]
+  fn prg_rom_or_ram_at_6000() {
+    let mut mapper = fme7();
+    command(&mut mapper, 0x8, 7);
+    assert_eq!(mapper.read_addr(0x6000), 7);
+    mapper.write_addr(0x6000, 0x42);
+    assert_eq!(mapper.read_addr(0x6000), 7);
+
+    // RAM selected, but not enabled
+    command(&mut mapper, 0x8, 0x40);
+    mapper.write_addr(0x6000, 0x42);
+    assert_eq!(mapper.read_addr(0x6000), 0);
+
+    command(&mut mapper, 0x8, 0xC0);
+    mapper.write_addr(0x6000, 0x42);
+    assert_eq!(mapper.read_addr(0x6000), 0x42);
+  }
+
+  #[test]
+  fn mirroring() {
+    let mut mapper = fme7();
+    command(&mut mapper, 0xC, 1);
+    assert_eq!(mapper.mirroring(), Some(Mirroring::Horizontal));
+    command(&mut mapper, 0xC, 2);
+    assert_eq!(mapper.mirroring(), Some(Mirroring::SingleScreenLower));
+  }
+
+  #[test]
+  fn irq_when_counter_wraps() {
+    let mut mapper = fme7();
+    command(&mut mapper, 0xE, 2);
+    command(&mut mapper, 0xF, 0);
+    command(&mut mapper, 0xD, 0x81);
+    mapper.cycle();
+    mapper.cycle();
+    assert!(!mapper.irq());
+    mapper.cycle();
+    assert!(mapper.irq());
+
+    command(&mut mapper, 0xD, 0x80);
+    assert!(!mapper.irq());
+
+    // Counting with the IRQ disabled
+    for _ in 0..0x10000 {
+      mapper.cycle();
+    }
+    assert!(!mapper.irq());
+  }
+
+  #[test]
+  fn outputs_audio() {
+    let mut mapper = fme7();
+    assert_eq!(mapper.expansion_audio(), [0.0; 8]);
+    mapper.write_addr(0xC000, 0x07);
+    mapper.write_addr(0xE000, 0x3F);
+    mapper.write_addr(0xC000, 0x08);
+    mapper.write_addr(0xE000, 0x0F);
+    // Only channel A is playing
+    let voices = mapper.expansion_audio();
+    assert!(voices[0] > 0.0);
+    assert_eq!(&voices[1..], &[0.0; 7]);
+  }
+}
@@ -0,0 +1,313 @@
+//! Nintendo MMC1 (mapper 1)
+//!
+//! Used by hundreds of games, like The Legend of Zelda and Metroid. The
+//! registers are written a bit at a time through a serial port: five
+//! writes to $8000-$FFFF shift in bit 0 of each, and the fifth write's
+//! address selects the register. A write with bit 7 set resets the shift
+//! register instead. [Read more here][MMC1].
+//!
+//!  Address    | Use
+//! ------------|------------------------------------------------------
+//! $6000-$7FFF | 8KB PRG-RAM bank, if enabled
+//! $8000-$BFFF | 16KB PRG-ROM bank, switchable or fixed to the first
+//! $C000-$FFFF | 16KB PRG-ROM bank, switchable or fixed to the last
+//!
+//!  Register    | Legend | Bits
+//! -------------|--------|------------------------------------------------
+//! $8000-$9FFF  | CPPMM  | Control: CHR mode, PRG mode, Mirroring
+//! $A000-$BFFF  | CCCCC  | CHR bank 0, 4KB at $0000 (or 8KB, ignoring bit 0)
+//! $C000-$DFFF  | CCCCC  | CHR bank 1, 4KB at $1000 (ignored in 8KB mode)
+//! $E000-$FFFF  | RPPPP  | PRG-RAM disable, 16KB PRG bank
+//!
+//! The mirroring is 0: single screen lower, 1: single screen upper, 2:
+//! vertical or 3: horizontal. The PRG modes are 0 and 1: switch 32KB at
+//! $8000 (ignoring bit 0 of the bank), 2: fix the first bank at $8000 and
+//! switch $C000, or 3: fix the last bank at $C000 and switch $8000.
+//!
+//! Boards with CHR-RAM only need bit 0 of the CHR banks, so some use the
+//! other bits for more memory. SUROM and SXROM use bit 4 to select which
+//! 256KB of their 512KB PRG-ROM is banked, and SOROM and SXROM use bits 2
+//! and 3 to select 8KB of their 32KB PRG-RAM. Boards with CHR-RAM are all
+//! treated as SXROM, which works for the others as their games leave those
+//! bits clear. In 4KB CHR mode, these bits are taken from CHR bank 0.
+//!
+//! The MMC1 ignores a write on the cycle after another, which some games
+//! rely on to reset it with a read-modify-write instruction. Instructions
+//! here only write once, so that isn't emulated.
+//!
+//! [MMC1]: https://wiki.nesdev.com/w/index.php/MMC1
+
+use cartridge::mapper::Mapper;
+use cartridge::mappers::{bank_addr, chr_rom_or_ram};
+use cartridge::mirroring::Mirroring;
+use memory::{ReadAddr, WriteAddr};
+
+const SIZE_PRG_BANK: usize = 16 * 1024;
+const SIZE_CHR_BANK: usize = 4 * 1024;
+const SIZE_PRG_RAM_BANK: usize = 8 * 1024;
+const SIZE_PRG_RAM: usize = 32 * 1024;
+
+/// PRG-ROM banked at once, with any more selected by the CHR banks
+const SIZE_PRG_OUTER_BANK: usize = 256 * 1024;
+
+/// The shift register's initial value, with a marker bit which reaches
+/// bit 0 when it's full
+const SHIFT_RESET: u8 = 0b1_0000;
+
+/// Fix the last PRG bank at $C000, the mode after power on and resets
+const CONTROL_RESET: u8 = 0b0_1100;
+
+pub struct MMC1 {
+  prg_rom: Vec<u8>,
+  chr: Vec<u8>,
+  chr_is_ram: bool,
+  prg_ram: Vec<u8>,
+
+  shift: u8,
+  control: u8,
+  chr_banks: [u8; 2],
+  prg_bank: u8,
+}
+
+impl MMC1 {
+  pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Self {
+    let chr_is_ram = chr_rom.is_empty();
+    MMC1 {
+      prg_rom,
+      chr: chr_rom_or_ram(chr_rom),
+      chr_is_ram,
+      prg_ram: vec![0; SIZE_PRG_RAM],
+      shift: SHIFT_RESET,
+      control: CONTROL_RESET,
+      chr_banks: [0; 2],
+      prg_bank: 0,
+    }
+  }
+
+  fn write_serial(&mut self, addr: u16, value: u8) {
+    if value & 0x80 != 0 {
+      self.shift = SHIFT_RESET;
+      self.control |= CONTROL_RESET;
+      return;
+    }
+
+    let full = self.shift & 1 != 0;
+    self.shift = (self.shift >> 1) | ((value & 1) << 4);
+    if full {
+      let value = self.shift;
+      self.shift = SHIFT_RESET;
+      match addr {
+        0x8000...0x9FFF => self.control = value,
+        0xA000...0xBFFF => self.chr_banks[0] = value,
+        0xC000...0xDFFF => self.chr_banks[1] = value,
+        _ => self.prg_bank = value,
+      }
+    }
+  }
+
+  fn prg_ram_enabled(&self) -> bool {
+    self.prg_bank & 0x10 == 0
+  }
+
+  fn prg_ram_addr(&self, addr: u16) -> usize {
+    let bank = if self.chr_is_ram {
+      usize::from((self.chr_banks[0] >> 2) & 0b11)
+    } else {
+      0
+    };
+    bank * SIZE_PRG_RAM_BANK + usize::from(addr - 0x6000)
+  }
+
+  fn read_prg(&self, addr: u16) -> u8 {
+    let num_banks = self.prg_rom.len() / SIZE_PRG_BANK;
+    let banks_per_outer = SIZE_PRG_OUTER_BANK / SIZE_PRG_BANK;
+    let outer = if self.prg_rom.len() > SIZE_PRG_OUTER_BANK {
+      usize::from((self.chr_banks[0] >> 4) & 1) * banks_per_outer
+    } else {
+      0
+    };
+    let last = num_banks.min(banks_per_outer) - 1;
+
+    let bank = usize::from(self.prg_bank & 0x0F);
+    let high = addr >= 0xC000;
+    let bank = match (self.control >> 2) & 0b11 {
+      0 | 1 => (bank & !1) + usize::from(high),
+      2 if high => bank,
+      2 => 0,
+      _ if high => last,
+      _ => bank,
+    };
+    self.prg_rom[bank_addr(outer + bank, SIZE_PRG_BANK, self.prg_rom.len(), addr)]
+  }
+
+  fn chr_addr(&self, addr: u16) -> usize {
+    let high = usize::from(addr >= 0x1000);
+    let bank = if self.control & 0x10 == 0 {
+      usize::from(self.chr_banks[0] & !1) + high
+    } else {
+      usize::from(self.chr_banks[high])
+    };
+    bank_addr(bank, SIZE_CHR_BANK, self.chr.len(), addr)
+  }
+}
+
+impl Mapper for MMC1 {
+  fn read_chr(&mut self, addr: u16) -> u8 {
+    self.chr[self.chr_addr(addr)]
+  }
+
+  fn write_chr(&mut self, addr: u16, value: u8) {
+    if self.chr_is_ram {
+      let addr = self.chr_addr(addr);
+      self.chr[addr] = value;
+    }
+  }
+
+  fn mirroring(&self) -> Option<Mirroring> {
+    Some(match self.control & 0b11 {
+      0 => Mirroring::SingleScreenLower,
+      1 => Mirroring::SingleScreenUpper,
+      2 => Mirroring::Vertical,
+      _ => Mirroring::Horizontal,
+    })
+  }
+}
+
+impl ReadAddr for MMC1 {
+  fn read_addr(&mut self, addr: u16) -> u8 {
+    match addr {
+      0x6000...0x7FFF if self.prg_ram_enabled() => self.prg_ram[self.prg_ram_addr(addr)],
+      0x8000...0xFFFF => self.read_prg(addr),
+      // Open bus
+      _ => 0,
+    }
+  }
+}
+
+impl WriteAddr for MMC1 {
+  fn write_addr(&mut self, addr: u16, value: u8) -> u8 {
+    match addr {
+      0x6000...0x7FFF if self.prg_ram_enabled() => {
+        let addr = self.prg_ram_addr(addr);
+        self.prg_ram[addr] = value;
+      }
+      0x8000...0xFFFF => self.write_serial(addr, value),
+      _ => {}
+    }
+    0
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use cartridge::mappers::numbered_banks;
+
+  /// An MMC1 with each 16KB PRG bank and 4KB CHR bank filled with its
+  /// number, or CHR-RAM if there are no CHR banks
+  fn mmc1(prg_banks: u8, chr_banks: u8) -> MMC1 {
+    MMC1::new(
+      numbered_banks(prg_banks, SIZE_PRG_BANK),
+      numbered_banks(chr_banks, SIZE_CHR_BANK),
+    )
+  }
+
+  /// Write a register through the serial port
+  fn write(mapper: &mut MMC1, addr: u16, value: u8) {
+    for bit in 0..5 {
+      mapper.write_addr(addr, value >> bit);
+    }
+  }
+
+  #[test]
+  fn serial_writes_and_reset() {
+    let mut mapper = mmc1(8, 8);
+    assert_eq!(mapper.mirroring(), Some(Mirroring::SingleScreenLower));
+    write(&mut mapper, 0x8000, 0b0_1111);
+    assert_eq!(mapper.mirroring(), Some(Mirroring::Horizontal));
+
+    // A reset part way through discards the bits written so far
+    mapper.write_addr(0x8000, 0);
+    mapper.write_addr(0x8000, 0);
+    mapper.write_addr(0x8000, 0x80);
+    write(&mut mapper, 0x8000, 0b0_1110);
+    assert_eq!(mapper.mirroring(), Some(Mirroring::Vertical));
+  }
+
+  #[test]
+  fn prg_modes() {
+    let mut mapper = mmc1(8, 8);
+    // Fix the last bank at $C000 by default
+    write(&mut mapper, 0xE000, 3);
+    assert_eq!(mapper.read_addr(0x8000), 3);
+    assert_eq!(mapper.read_addr(0xC000), 7);
+
+    // Fix the first bank at $8000
+    write(&mut mapper, 0x8000, 0b0_1000);
+    assert_eq!(mapper.read_addr(0x8000), 0);
+    assert_eq!(mapper.read_addr(0xC000), 3);
+
+    // Switch 32KB
+    write(&mut mapper, 0x8000, 0b0_0000);
+    write(&mut mapper, 0xE000, 5);
+    assert_eq!(mapper.read_addr(0x8000), 4);
+    assert_eq!(mapper.read_addr(0xFFFF), 5);
+  }
+
+  #[test]
+  fn chr_modes() {
+    let mut mapper = mmc1(2, 8);
+    // 8KB
+    write(&mut mapper, 0xA000, 5);
+    write(&mut mapper, 0xC000, 7);
+    assert_eq!(mapper.read_chr(0x0000), 4);
+    assert_eq!(mapper.read_chr(0x1000), 5);
+
+    // 4KB
+    write(&mut mapper, 0x8000, 0b1_1100);
+    assert_eq!(mapper.read_chr(0x0000), 5);
+    assert_eq!(mapper.read_chr(0x1000), 7);
+  }
+
+  #[test]
+  fn chr_ram() {
+    let mut mapper = mmc1(2, 0);
+    write(&mut mapper, 0x8000, 0b1_1100);
+    write(&mut mapper, 0xC000, 0);
+    mapper.write_chr(0x0010, 0x42);
+    assert_eq!(mapper.read_chr(0x0010), 0x42);
+    assert_eq!(mapper.read_chr(0x1010), 0x42);
+  }
+
+  #[test]
+  fn prg_ram_enable() {
+    let mut mapper = mmc1(2, 8);
+    mapper.write_addr(0x6000, 0x42);
+    assert_eq!(mapper.read_addr(0x6000), 0x42);
+
+    write(&mut mapper, 0xE000, 0x10);
+    assert_eq!(mapper.read_addr(0x6000), 0);
+    mapper.write_addr(0x6000, 0x24);
+    write(&mut mapper, 0xE000, 0x00);
+    assert_eq!(mapper.read_addr(0x6000), 0x42);
+  }
+
+  #[test]
+  fn sxrom_prg_rom_and_ram_banks() {
+    let mut mapper = mmc1(32, 0);
+    write(&mut mapper, 0xE000, 2);
+    assert_eq!(mapper.read_addr(0x8000), 2);
+    assert_eq!(mapper.read_addr(0xC000), 15);
+    mapper.write_addr(0x6000, 0x42);
+
+    // The second 256KB, and the third 8KB of PRG-RAM
+    write(&mut mapper, 0xA000, 0b1_1000);
+    assert_eq!(mapper.read_addr(0x8000), 18);
+    assert_eq!(mapper.read_addr(0xC000), 31);
+    assert_eq!(mapper.read_addr(0x6000), 0);
+    mapper.write_addr(0x6000, 0x24);
+
+    write(&mut mapper, 0xA000, 0);
+    assert_eq!(mapper.read_addr(0x6000), 0x42);
+  }
+}
@@ -0,0 +1,246 @@
+//! Nintendo MMC2 (mapper 9) and MMC4 (mapper 10)
+//!
+//! The MMC2 is only used by Mike Tyson's Punch-Out!!, and the MMC4 by the
+//! Fire Emblem games and Famicom Wars. They switch CHR banks by themselves
+//! part way through a frame, when the PPU fetches tile $FD or $FE, which
+//! lets a game use more tiles on screen than fit in a pattern table. [Read
+//! more here][MMC2].
+//!
+//!  Address    | MMC2                      | MMC4
+//! ------------|---------------------------|--------------------------
+//! $6000-$7FFF |                           | 8KB PRG-RAM
+//! $8000-$9FFF | 8KB switchable PRG-ROM    | 16KB switchable PRG-ROM
+//! $A000-$FFFF | Last three 8KB PRG-ROM    | ($C000) Last 16KB PRG-ROM
+//!
+//!  Register    | Legend    | Bits
+//! -------------|-----------|-----------------------------------------------
+//! $A000-$AFFF  | .... PPPP | PRG-ROM bank
+//! $B000-$BFFF  | ...C CCCC | 4KB CHR bank at $0000 while latch 0 is $FD
+//! $C000-$CFFF  | ...C CCCC | 4KB CHR bank at $0000 while latch 0 is $FE
+//! $D000-$DFFF  | ...C CCCC | 4KB CHR bank at $1000 while latch 1 is $FD
+//! $E000-$EFFF  | ...C CCCC | 4KB CHR bank at $1000 while latch 1 is $FE
+//! $F000-$FFFF  | .... ...M | Mirroring: 0: vertical, 1: horizontal
+//!
+//! Each pattern table has a latch, which is set to $FD or $FE once the PPU
+//! has fetched part of that tile:
+//!
+//!  Fetch       | Latch 0 (MMC2) | Latch 0 (MMC4) | Latch 1
+//! -------------|----------------|----------------|---------------
+//! Tile $FD     | $0FD8          | $0FD8-$0FDF    | $1FD8-$1FDF
+//! Tile $FE     | $0FE8          | $0FE8-$0FEF    | $1FE8-$1FEF
+//!
+//! The latch changes after the fetch, so the tile that set it still comes
+//! from the old bank.
+//!
+//! [MMC2]: https://wiki.nesdev.com/w/index.php/MMC2
+
+use cartridge::mapper::Mapper;
+use cartridge::mappers::bank_addr;
+use cartridge::mirroring::Mirroring;
+use memory::{ReadAddr, WriteAddr};
+
+const SIZE_CHR_BANK: usize = 4 * 1024;
+const SIZE_PRG_RAM: usize = 8 * 1024;
+
+/// Which chip is on the board
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MMC2Chip {
+  /// Mapper 9
+  MMC2,
+  /// Mapper 10
+  MMC4,
+}
+
+pub struct MMC2 {
+  chip: MMC2Chip,
+  prg_rom: Vec<u8>,
+  chr_rom: Vec<u8>,
+  prg_ram: Vec<u8>,
+
+  prg_bank: u8,
+  /// The banks used while each latch is $FD and $FE
+  chr_banks: [[u8; 2]; 2],
+  /// Whether each latch is $FE rather than $FD
+  latches: [bool; 2],
+  mirroring: Mirroring,
+}
+
+impl MMC2 {
+  pub fn new(chip: MMC2Chip, prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Self {
+    MMC2 {
+      chip,
+      prg_rom,
+      chr_rom,
+      prg_ram: vec![0; SIZE_PRG_RAM],
+      prg_bank: 0,
+      chr_banks: [[0; 2]; 2],
+      latches: [true; 2],
+      mirroring: Mirroring::Vertical,
+    }
+  }
+
+  fn read_prg(&self, addr: u16) -> u8 {
+    let size = match self.chip {
+      MMC2Chip::MMC2 => 8 * 1024,
+      MMC2Chip::MMC4 => 16 * 1024,
+    };
+    let num_banks = self.prg_rom.len() / size;
+    let offset = usize::from(addr - 0x8000);
+    let bank = match offset / size {
+      0 => usize::from(self.prg_bank),
+      // The rest are fixed to the end
+      slot => num_banks - (0x8000 / size - slot),
+    };
+    self.prg_rom[bank_addr(bank, size, self.prg_rom.len(), addr)]
+  }
+
+  fn chr_addr(&self, addr: u16) -> usize {
+    let table = usize::from(addr >> 12) & 1;
+    let bank = usize::from(self.chr_banks[table][usize::from(self.latches[table])]);
+    bank_addr(bank, SIZE_CHR_BANK, self.chr_rom.len(), addr)
+  }
+
+  /// Set a latch if the PPU has just fetched from tile $FD or $FE
+  fn update_latch(&mut self, addr: u16) {
+    let table = usize::from(addr >> 12) & 1;
+    let tile_addr = addr & 0x0FF8;
+    let triggers = match self.chip {
+      MMC2Chip::MMC2 if table == 0 => addr & 0x0007 == 0,
+      _ => true,
+    };
+    if !triggers {
+      return;
+    }
+    match tile_addr {
+      0x0FD8 => self.latches[table] = false,
+      0x0FE8 => self.latches[table] = true,
+      _ => {}
+    }
+  }
+}
+
+impl Mapper for MMC2 {
+  fn read_chr(&mut self, addr: u16) -> u8 {
+    let value = self.chr_rom[self.chr_addr(addr)];
+    self.update_latch(addr);
+    value
+  }
+
+  fn chr_reads_switch_banks(&self) -> bool {
+    true
+  }
+
+  fn mirroring(&self) -> Option<Mirroring> {
+    Some(self.mirroring)
+  }
+}
+
+impl ReadAddr for MMC2 {
+  fn read_addr(&mut self, addr: u16) -> u8 {
+    match addr {
+      0x6000...0x7FFF if self.chip == MMC2Chip::MMC4 => self.prg_ram[usize::from(addr - 0x6000)],
+      0x8000...0xFFFF => self.read_prg(addr),
+      // Open bus
+      _ => 0,
+    }
+  }
+}
+
+impl WriteAddr for MMC2 {
+  fn write_addr(&mut self, addr: u16, value: u8) -> u8 {
+    match addr {
+      0x6000...0x7FFF if self.chip == MMC2Chip::MMC4 => {
+        self.prg_ram[usize::from(addr - 0x6000)] = value;
+      }
+      0xA000...0xAFFF => self.prg_bank = value & 0x0F,
+      0xB000...0xBFFF => self.chr_banks[0][0] = value & 0x1F,
+      0xC000...0xCFFF => self.chr_banks[0][1] = value & 0x1F,
+      0xD000...0xDFFF => self.chr_banks[1][0] = value & 0x1F,
+      0xE000...0xEFFF => self.chr_banks[1][1] = value & 0x1F,
+      0xF000...0xFFFF => {
+        self.mirroring = if value & 1 == 0 {
+          Mirroring::Vertical
+        } else {
+          Mirroring::Horizontal
+        };
+      }
+      _ => {}
+    }
+    0
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use cartridge::mappers::numbered_banks;
+
+  /// A board with each 8KB PRG bank and 4KB CHR bank filled with its
+  /// number
+  fn mmc2(chip: MMC2Chip) -> MMC2 {
+    let prg = numbered_banks(16, 8 * 1024);
+    let mut mapper = MMC2::new(chip, prg, numbered_banks(32, SIZE_CHR_BANK));
+    for (i, addr) in [0xB000, 0xC000, 0xD000, 0xE000].iter().enumerate() {
+      mapper.write_addr(*addr, 10 + i as u8);
+    }
+    mapper
+  }
+
+  #[test]
+  fn mmc2_prg_banks() {
+    let mut mapper = mmc2(MMC2Chip::MMC2);
+    mapper.write_addr(0xA000, 5);
+    assert_eq!(mapper.read_addr(0x8000), 5);
+    assert_eq!(mapper.read_addr(0xA000), 13);
+    assert_eq!(mapper.read_addr(0xC000), 14);
+    assert_eq!(mapper.read_addr(0xFFFF), 15);
+  }
+
+  #[test]
+  fn mmc4_prg_banks_and_ram() {
+    let mut mapper = mmc2(MMC2Chip::MMC4);
+    mapper.write_addr(0xA000, 3);
+    assert_eq!(mapper.read_addr(0x8000), 6);
+    assert_eq!(mapper.read_addr(0xA000), 7);
+    assert_eq!(mapper.read_addr(0xC000), 14);
+    assert_eq!(mapper.read_addr(0xE000), 15);
+
+    mapper.write_addr(0x6000, 0x42);
+    assert_eq!(mapper.read_addr(0x6000), 0x42);
+  }
+
+  #[test]
+  fn latches_switch_chr_banks_after_the_fetch() {
+    let mut mapper = mmc2(MMC2Chip::MMC2);
+    assert_eq!(mapper.read_chr(0x0000), 11);
+    assert_eq!(mapper.read_chr(0x1000), 13);
+
+    assert_eq!(mapper.read_chr(0x0FD8), 11);
+    assert_eq!(mapper.read_chr(0x0000), 10);
+    assert_eq!(mapper.read_chr(0x1FDF), 13);
+    assert_eq!(mapper.read_chr(0x1000), 12);
+
+    assert_eq!(mapper.read_chr(0x1FE8), 12);
+    assert_eq!(mapper.read_chr(0x1000), 13);
+    assert_eq!(mapper.read_chr(0x0000), 10);
+  }
+
+  #[test]
+  fn mmc2_latch_0_only_on_first_address() {
+    let mut mapper = mmc2(MMC2Chip::MMC2);
+    mapper.read_chr(0x0FDA);
+    assert_eq!(mapper.read_chr(0x0000), 11);
+
+    let mut mapper = mmc2(MMC2Chip::MMC4);
+    mapper.read_chr(0x0FDA);
+    assert_eq!(mapper.read_chr(0x0000), 10);
+  }
+
+  #[test]
+  fn mirroring() {
+    let mut mapper = mmc2(MMC2Chip::MMC2);
+    assert_eq!(mapper.mirroring(), Some(Mirroring::Vertical));
+    mapper.write_addr(0xF000, 1);
+    assert_eq!(mapper.mirroring(), Some(Mirroring::Horizontal));
+  }
+}
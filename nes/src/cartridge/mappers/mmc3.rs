@@ -0,0 +1,403 @@
+//! Nintendo MMC3 (mapper 4)
+//!
+//! Used by hundreds of games, like Super Mario Bros. 3 and Kirby's
+//! Adventure. The game selects one of eight bank registers at $8000, then
+//! writes the bank to $8001. [Read more here][MMC3].
+//!
+//!  Address    | Use
+//! ------------|------------------------------------------------------
+//! $6000-$7FFF | 8KB PRG-RAM, if enabled
+//! $8000-$9FFF | 8KB PRG-ROM bank R6, or fixed to the second last bank
+//! $A000-$BFFF | 8KB PRG-ROM bank R7
+//! $C000-$DFFF | 8KB PRG-ROM bank fixed to the second last bank, or R6
+//! $E000-$FFFF | 8KB PRG-ROM bank fixed to the last bank
+//!
+//!  Register | Legend    | Bits
+//! ----------|-----------|-------------------------------------------------
+//! $8000     | CP.. .RRR | CHR inversion, PRG mode, Register for $8001
+//! $8001     | BBBB BBBB | Bank for the selected register
+//! $A000     | .... ...M | Mirroring: 0: vertical, 1: horizontal
+//! $A001     | EW.. .... | PRG-RAM Enable, Write protect
+//! $C000     | LLLL LLLL | IRQ counter reload value (the Latch)
+//! $C001     | .... .... | Reload the IRQ counter on its next clock
+//! $E000     | .... .... | Disable and acknowledge the IRQ
+//! $E001     | .... .... | Enable the IRQ
+//!
+//! Each register is mirrored at every even ($8000) or odd ($8001) address
+//! up to the next. R0 and R1 are 2KB CHR banks at $0000 and $0800 (ignoring
+//! bit 0), and R2-R5 are 1KB CHR banks at $1000-$1C00. CHR inversion swaps
+//! the two halves of the pattern tables.
+//!
+//! The IRQ counter is clocked by the PPU's address line A12 rising, which
+//! happens once a scanline when the background uses the pattern table at
+//! $0000 and the sprites $1000 (or the other way around). The MMC3 filters
+//! out the rises in between the sprite fetches, by only counting a rise
+//! after A12 has been low for a few CPU cycles. When it's clocked, a
+//! counter of 0 (or one that was told to reload) is reloaded from the
+//! latch, otherwise it's decremented. Then the IRQ is raised if the
+//! counter is 0 and the IRQ is enabled.
+//!
+//! That last step differs between the revisions of the chip. The Sharp
+//! MMC3 (B and C) raises the IRQ whenever the counter is 0, so a latch of
+//! 0 raises it on every scanline. The older NEC MMC3A only raises it when
+//! the counter has just been decremented or reloaded to 0, so a latch of
+//! 0 raises it just once.
+//!
+//! [MMC3]: https://wiki.nesdev.com/w/index.php/MMC3
+
+use cartridge::mapper::Mapper;
+use cartridge::mappers::{bank_addr, chr_rom_or_ram};
+use cartridge::mirroring::Mirroring;
+use memory::{ReadAddr, WriteAddr};
+
+const SIZE_PRG_BANK: usize = 8 * 1024;
+const SIZE_CHR_BANK: usize = 1024;
+const SIZE_PRG_RAM: usize = 8 * 1024;
+
+/// CPU cycles A12 has to be low for before a rise clocks the IRQ counter
+const A12_FILTER_CYCLES: u8 = 3;
+
+/// Which chip is on the board, as they raise IRQs a little differently
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MMC3Revision {
+  /// The MMC3B and MMC3C, in most games
+  Sharp,
+  /// The MMC3A
+  NEC,
+}
+
+pub struct MMC3 {
+  revision: MMC3Revision,
+  prg_rom: Vec<u8>,
+  chr: Vec<u8>,
+  chr_is_ram: bool,
+  prg_ram: Vec<u8>,
+
+  bank_select: u8,
+  /// R0-R7
+  banks: [u8; 8],
+  mirroring: Mirroring,
+  prg_ram_enabled: bool,
+  prg_ram_write_protected: bool,
+
+  irq_latch: u8,
+  irq_counter: u8,
+  irq_reload: bool,
+  irq_enabled: bool,
+  irq_pending: bool,
+
+  /// The last level seen on A12, and how many CPU cycles it's been low
+  a12: bool,
+  a12_low_cycles: u8,
+}
+
+impl MMC3 {
+  pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>, revision: MMC3Revision) -> Self {
+    let chr_is_ram = chr_rom.is_empty();
+    MMC3 {
+      revision,
+      prg_rom,
+      chr: chr_rom_or_ram(chr_rom),
+      chr_is_ram,
+      prg_ram: vec![0; SIZE_PRG_RAM],
+      bank_select: 0,
+      banks: [0, 2, 4, 5, 6, 7, 0, 1],
+      mirroring: Mirroring::Vertical,
+      prg_ram_enabled: true,
+      prg_ram_write_protected: false,
+      irq_latch: 0,
+      irq_counter: 0,
+      irq_reload: false,
+      irq_enabled: false,
+      irq_pending: false,
+      a12: false,
+      a12_low_cycles: 0,
+    }
+  }
+
+  fn read_prg(&self, addr: u16) -> u8 {
+    let num_banks = self.prg_rom.len() / SIZE_PRG_BANK;
+    let second_last = num_banks - 2;
+    let prg_mode = self.bank_select & 0x40 != 0;
+    let bank = match addr {
+      0x8000...0x9FFF if prg_mode => second_last,
+      0x8000...0x9FFF => usize::from(self.banks[6]),
+      0xA000...0xBFFF => usize::from(self.banks[7]),
+      0xC000...0xDFFF if prg_mode => usize::from(self.banks[6]),
+      0xC000...0xDFFF => second_last,
+      _ => num_banks - 1,
+    };
+    self.prg_rom[bank_addr(bank, SIZE_PRG_BANK, self.prg_rom.len(), addr)]
+  }
+
+  fn chr_addr(&self, addr: u16) -> usize {
+    let inverted = if self.bank_select & 0x80 != 0 {
+      addr ^ 0x1000
+    } else {
+      addr
+    };
+    let bank = match inverted >> 10 {
+      0 => self.banks[0] & !1,
+      1 => self.banks[0] | 1,
+      2 => self.banks[1] & !1,
+      3 => self.banks[1] | 1,
+      slot => self.banks[usize::from(slot - 2)],
+    };
+    bank_addr(usize::from(bank), SIZE_CHR_BANK, self.chr.len(), addr)
+  }
+
+  fn clock_irq_counter(&mut self) {
+    let reloaded = self.irq_reload;
+    let decremented = self.irq_counter > 0 && !reloaded;
+    if self.irq_counter == 0 || reloaded {
+      self.irq_counter = self.irq_latch;
+      self.irq_reload = false;
+    } else {
+      self.irq_counter -= 1;
+    }
+
+    let raise = match self.revision {
+      MMC3Revision::Sharp => self.irq_counter == 0,
+      MMC3Revision::NEC => self.irq_counter == 0 && (decremented || reloaded),
+    };
+    if raise && self.irq_enabled {
+      self.irq_pending = true;
+    }
+  }
+}
+
+impl Mapper for MMC3 {
+  fn read_chr(&mut self, addr: u16) -> u8 {
+    self.chr[self.chr_addr(addr)]
+  }
+
+  fn write_chr(&mut self, addr: u16, value: u8) {
+    if self.chr_is_ram {
+      let addr = self.chr_addr(addr);
+      self.chr[addr] = value;
+    }
+  }
+
+  fn ppu_address(&mut self, addr: u16) {
+    let a12 = addr & 0x1000 != 0;
+    if a12 && !self.a12 && self.a12_low_cycles >= A12_FILTER_CYCLES {
+      self.clock_irq_counter();
+    }
+    if !a12 && self.a12 {
+      self.a12_low_cycles = 0;
+    }
+    self.a12 = a12;
+  }
+
+  fn mirroring(&self) -> Option<Mirroring> {
+    Some(self.mirroring)
+  }
+
+  fn cycle(&mut self) {
+    if !self.a12 {
+      self.a12_low_cycles = self.a12_low_cycles.saturating_add(1);
+    }
+  }
+
+  fn irq(&self) -> bool {
+    self.irq_pending
+  }
+}
+
+impl ReadAddr for MMC3 {
+  fn read_addr(&mut self, addr: u16) -> u8 {
+    match addr {
+      0x6000...0x7FFF if self.prg_ram_enabled => self.prg_ram[usize::from(addr - 0x6000)],
+      0x8000...0xFFFF => self.read_prg(addr),
+      // Open bus
+      _ => 0,
+    }
+  }
+}
+
+impl WriteAddr for MMC3 {
+  fn write_addr(&mut self, addr: u16, value: u8) -> u8 {
+    let even = addr & 1 == 0;
+    match addr {
+      0x6000...0x7FFF if self.prg_ram_enabled && !self.prg_ram_write_protected => {
+        self.prg_ram[usize::from(addr - 0x6000)] = value;
+      }
+      0x8000...0x9FFF if even => self.bank_select = value,
+      0x8000...0x9FFF => self.banks[usize::from(self.bank_select & 0b111)] = value,
+      0xA000...0xBFFF if even => {
+        self.mirroring = if value & 1 == 0 {
+          Mirroring::Vertical
+        } else {
+          Mirroring::Horizontal
+        };
+      }
+      0xA000...0xBFFF => {
+        self.prg_ram_enabled = value & 0x80 != 0;
+        self.prg_ram_write_protected = value & 0x40 != 0;
+      }
+      0xC000...0xDFFF if even => self.irq_latch = value,
+      0xC000...0xDFFF => {
+        self.irq_counter = 0;
+        self.irq_reload = true;
+      }
+      0xE000...0xFFFF if even => {
+        self.irq_enabled = false;
+        self.irq_pending = false;
+      }
+      0xE000...0xFFFF => self.irq_enabled = true,
+      _ => {}
+    }
+    0
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use cartridge::mappers::numbered_banks;
+
+  /// An MMC3 with each 8KB PRG bank and 1KB CHR bank filled with its
+  /// number
+  fn mmc3(revision: MMC3Revision) -> MMC3 {
+    MMC3::new(
+      numbered_banks(16, SIZE_PRG_BANK),
+      numbered_banks(64, SIZE_CHR_BANK),
+      revision,
+    )
+  }
+
+  fn set_bank(mapper: &mut MMC3, select: u8, bank: u8) {
+    mapper.write_addr(0x8000, select);
+    mapper.write_addr(0x8001, bank);
+  }
+
+  /// Clock the IRQ counter like a scanline would, with A12 low for a while
+  /// then rising
+  fn scanline(mapper: &mut MMC3) {
+    mapper.ppu_address(0x0000);
+    for _ in 0..100 {
+      mapper.cycle();
+    }
+    mapper.ppu_address(0x1000);
+  }
+
+  #[test]
+  fn prg_modes() {
+    let mut mapper = mmc3(MMC3Revision::Sharp);
+    set_bank(&mut mapper, 6, 3);
+    set_bank(&mut mapper, 7, 4);
+    assert_eq!(mapper.read_addr(0x8000), 3);
+    assert_eq!(mapper.read_addr(0xA000), 4);
+    assert_eq!(mapper.read_addr(0xC000), 14);
+    assert_eq!(mapper.read_addr(0xE000), 15);
+
+    mapper.write_addr(0x8000, 0x40);
+    assert_eq!(mapper.read_addr(0x8000), 14);
+    assert_eq!(mapper.read_addr(0xA000), 4);
+    assert_eq!(mapper.read_addr(0xC000), 3);
+    assert_eq!(mapper.read_addr(0xFFFF), 15);
+  }
+
+  #[test]
+  fn chr_banks_and_inversion() {
+    let mut mapper = mmc3(MMC3Revision::Sharp);
+    for (select, bank) in [(0, 9), (1, 20), (2, 30), (3, 31), (4, 32), (5, 33)].iter() {
+      set_bank(&mut mapper, *select, *bank);
+    }
+    let banks: Vec<u8> = (0..8).map(|slot| mapper.read_chr(slot * 0x400)).collect();
+    assert_eq!(banks, [8, 9, 20, 21, 30, 31, 32, 33]);
+
+    mapper.write_addr(0x8000, 0x80);
+    let banks: Vec<u8> = (0..8).map(|slot| mapper.read_chr(slot * 0x400)).collect();
+    assert_eq!(banks, [30, 31, 32, 33, 8, 9, 20, 21]);
+  }
+
+  #[test]
+  fn registers_are_mirrored() {
+    let mut mapper = mmc3(MMC3Revision::Sharp);
+    mapper.write_addr(0x9FFE, 7);
+    mapper.write_addr(0x9001, 5);
+    assert_eq!(mapper.read_addr(0xA000), 5);
+    mapper.write_addr(0xBFFE, 1);
+    assert_eq!(mapper.mirroring(), Some(Mirroring::Horizontal));
+  }
+
+  #[test]
+  fn prg_ram_protect() {
+    let mut mapper = mmc3(MMC3Revision::Sharp);
+    mapper.write_addr(0x6000, 0x42);
+    assert_eq!(mapper.read_addr(0x6000), 0x42);
+
+    mapper.write_addr(0xA001, 0xC0);
+    mapper.write_addr(0x6000, 0x24);
+    assert_eq!(mapper.read_addr(0x6000), 0x42);
+
+    mapper.write_addr(0xA001, 0x00);
+    assert_eq!(mapper.read_addr(0x6000), 0);
+  }
+
+  #[test]
+  fn irq_after_latch_scanlines() {
+    let mut mapper = mmc3(MMC3Revision::Sharp);
+    mapper.write_addr(0xC000, 2);
+    mapper.write_addr(0xC001, 0);
+    mapper.write_addr(0xE001, 0);
+
+    // Reloads to 2, then counts down to 0
+    scanline(&mut mapper);
+    scanline(&mut mapper);
+    assert!(!mapper.irq());
+    scanline(&mut mapper);
+    assert!(mapper.irq());
+
+    mapper.write_addr(0xE000, 0);
+    assert!(!mapper.irq());
+
+    // Disabled
+    for _ in 0..3 {
+      scanline(&mut mapper);
+    }
+    assert!(!mapper.irq());
+  }
+
+  #[test]
+  fn a12_filter() {
+    let mut mapper = mmc3(MMC3Revision::Sharp);
+    mapper.write_addr(0xC000, 1);
+    mapper.write_addr(0xC001, 0);
+    mapper.write_addr(0xE001, 0);
+    scanline(&mut mapper);
+
+    // Sprite fetches only leave A12 low for a cycle or two
+    for _ in 0..8 {
+      mapper.ppu_address(0x2000);
+      mapper.cycle();
+      mapper.cycle();
+      mapper.ppu_address(0x1000);
+    }
+    assert!(!mapper.irq());
+    scanline(&mut mapper);
+    assert!(mapper.irq());
+  }
+
+  #[test]
+  fn latch_of_zero() {
+    for &(revision, irqs) in [(MMC3Revision::Sharp, 4), (MMC3Revision::NEC, 1)].iter() {
+      let mut mapper = mmc3(revision);
+      mapper.write_addr(0xC000, 0);
+      mapper.write_addr(0xC001, 0);
+      mapper.write_addr(0xE001, 0);
+
+      let mut count = 0;
+      for _ in 0..4 {
+        scanline(&mut mapper);
+        if mapper.irq() {
+          count += 1;
+          mapper.write_addr(0xE000, 0);
+          mapper.write_addr(0xE001, 0);
+        }
+      }
+      assert_eq!(count, irqs, "{:?}", revision);
+    }
+  }
+}
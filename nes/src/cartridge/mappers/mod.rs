@@ -1 +1,36 @@
+pub mod discrete;
+pub mod fme7;
+pub mod mmc1;
+pub mod mmc2;
+pub mod mmc3;
+pub mod namco163;
 pub mod nrom;
+pub mod nsf;
+pub mod vrc6;
+pub mod vrc7;
+pub mod vrc_irq;
+
+/// The size of the CHR-RAM on boards without CHR-ROM
+const SIZE_CHR_RAM: usize = 8 * 1024;
+
+/// The CHR-ROM, or blank CHR-RAM if the cartridge has no CHR-ROM
+pub fn chr_rom_or_ram(chr_rom: Vec<u8>) -> Vec<u8> {
+  if chr_rom.is_empty() {
+    vec![0; SIZE_CHR_RAM]
+  } else {
+    chr_rom
+  }
+}
+
+/// The index of an address in a bank of memory `len` bytes long, split
+/// into banks of `size` bytes. Bank numbers past the end wrap around, as
+/// the unused high bits of the bank registers aren't connected.
+pub fn bank_addr(bank: usize, size: usize, len: usize, addr: u16) -> usize {
+  (bank % (len / size)) * size + usize::from(addr) % size
+}
+
+/// `count` banks of `size` bytes, each filled with its number
+#[cfg(test)]
+pub fn numbered_banks(count: u8, size: usize) -> Vec<u8> {
+  (0..count).flat_map(|bank| vec![bank; size]).collect()
+}
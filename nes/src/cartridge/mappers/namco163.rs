@@ -0,0 +1,340 @@
+//! Namco 163 (mapper 19)
+//!
+//! Used by games like Megami Tensei II and Rolling Thunder. Along with
+//! 8KB PRG and 1KB CHR banks, it can put CHR-ROM banks in the name tables,
+//! has a 15 bit IRQ counter, and has its own wavetable sound (see
+//! `apu::expansion::namco163`). [Read more here][N163].
+//!
+//!  Address    | Use
+//! ------------|------------------------------------------------------
+//! $4800-$4FFF | Sound RAM data port
+//! $5000-$5FFF | IRQ counter
+//! $6000-$7FFF | 8KB PRG-RAM
+//! $8000-$9FFF | Switchable 8KB PRG-ROM bank
+//! $A000-$BFFF | Switchable 8KB PRG-ROM bank
+//! $C000-$DFFF | Switchable 8KB PRG-ROM bank
+//! $E000-$FFFF | Last 8KB PRG-ROM bank
+//!
+//! The registers are:
+//!
+//!  Register   | Use
+//! ------------|------------------------------------------------------
+//! $5000-$57FF | IRQ counter low 8 bits
+//! $5800-$5FFF | E... .... + counter high 7 bits: IRQ Enable
+//! $8000-$BFFF | 1KB CHR banks 0-7, one per $800
+//! $C000-$DFFF | Name tables 0-3, one per $800: CHR-ROM bank, or $E0 and
+//!             | above for the console's name table RAM (the low bit)
+//! $E000-$E7FF | .SBB BBBB: Sound disable, 8KB PRG bank at $8000
+//! $E800-$EFFF | HLBB BBBB: CHR-RAM disable for the High and Low pattern
+//!             | table, 8KB PRG bank at $A000
+//! $F000-$F7FF | ..BB BBBB: 8KB PRG bank at $C000
+//! $F800-$FFFF | Sound RAM address, and PRG-RAM write protection: $4X
+//!             | enables writes, except to the 2KB pages set in X
+//!
+//! The counter counts up every CPU cycle while enabled, raising an IRQ at
+//! $7FFF where it stops. Reading either half of it works, and writing
+//! either acknowledges the IRQ.
+//!
+//! CHR banks $E0 and above can also select the console's name table RAM
+//! for the pattern tables, unless disabled at $E800. No game relies on
+//! that, and it isn't supported: they're read from CHR-ROM instead.
+//!
+//! [N163]: https://wiki.nesdev.com/w/index.php/INES_Mapper_019
+
+use apu::expansion::namco163::Namco163Audio;
+use apu::expansion::{VoiceLevels, MAX_VOICES};
+use cartridge::mapper::Mapper;
+use cartridge::mappers::bank_addr;
+use cartridge::mirroring::Mirroring;
+use memory::{ReadAddr, WriteAddr};
+
+const SIZE_PRG_BANK: usize = 8 * 1024;
+const SIZE_CHR_BANK: usize = 1024;
+const SIZE_PRG_RAM: usize = 8 * 1024;
+const SIZE_PRG_RAM_PAGE: usize = 2 * 1024;
+
+/// The counter stops here, raising an IRQ
+const IRQ_COUNTER_MAX: u16 = 0x7FFF;
+
+/// Name table banks from here select the console's name table RAM
+const NAMETABLE_RAM_BANK: u8 = 0xE0;
+
+pub struct Namco163 {
+  prg_rom: Vec<u8>,
+  chr_rom: Vec<u8>,
+  prg_ram: Vec<u8>,
+  /// The protection value written to $F800
+  prg_ram_protect: u8,
+
+  prg_banks: [u8; 3],
+  chr_banks: [u8; 8],
+  nametable_banks: [u8; 4],
+
+  irq_counter: u16,
+  irq_enabled: bool,
+  irq_pending: bool,
+
+  audio: Namco163Audio,
+  audio_disabled: bool,
+}
+
+impl Namco163 {
+  pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Self {
+    Namco163 {
+      prg_rom,
+      chr_rom,
+      prg_ram: vec![0; SIZE_PRG_RAM],
+      prg_ram_protect: 0,
+      prg_banks: [0; 3],
+      chr_banks: [0; 8],
+      nametable_banks: [NAMETABLE_RAM_BANK; 4],
+      irq_counter: 0,
+      irq_enabled: false,
+      irq_pending: false,
+      audio: Namco163Audio::default(),
+      audio_disabled: false,
+    }
+  }
+
+  fn read_prg(&self, addr: u16) -> u8 {
+    let num_banks = self.prg_rom.len() / SIZE_PRG_BANK;
+    let bank = match addr {
+      0x8000...0xDFFF => usize::from(self.prg_banks[usize::from((addr - 0x8000) >> 13)]),
+      _ => num_banks - 1,
+    };
+    self.prg_rom[bank_addr(bank, SIZE_PRG_BANK, self.prg_rom.len(), addr)]
+  }
+
+  /// Read a 1KB bank of CHR-ROM
+  fn read_chr_bank(&self, bank: u8, addr: u16) -> u8 {
+    let len = self.chr_rom.len();
+    self.chr_rom[bank_addr(usize::from(bank), SIZE_CHR_BANK, len, addr)]
+  }
+
+  /// The CHR-ROM bank in the name table at an address, if it isn't the
+  /// console's name table RAM
+  fn nametable_chr_bank(&self, addr: u16) -> Option<u8> {
+    let bank = self.nametable_banks[usize::from((addr >> 10) & 0b11)];
+    if bank < NAMETABLE_RAM_BANK {
+      Some(bank)
+    } else {
+      None
+    }
+  }
+
+  fn prg_ram_writable(&self, addr: u16) -> bool {
+    let page = usize::from(addr - 0x6000) / SIZE_PRG_RAM_PAGE;
+    self.prg_ram_protect & 0xF0 == 0x40 && self.prg_ram_protect & (1 << page) == 0
+  }
+}
+
+impl Mapper for Namco163 {
+  fn read_chr(&mut self, addr: u16) -> u8 {
+    let bank = self.chr_banks[usize::from(addr >> 10) & 0b111];
+    self.read_chr_bank(bank, addr)
+  }
+
+  fn read_nametable(&mut self, addr: u16) -> Option<u8> {
+    self
+      .nametable_chr_bank(addr)
+      .map(|bank| self.read_chr_bank(bank, addr))
+  }
+
+  /// Writes to CHR-ROM name tables are ignored
+  fn write_nametable(&mut self, addr: u16, _value: u8) -> bool {
+    self.nametable_chr_bank(addr).is_some()
+  }
+
+  fn mirroring(&self) -> Option<Mirroring> {
+    let mut tables = [0; 4];
+    for (table, &bank) in tables.iter_mut().zip(self.nametable_banks.iter()) {
+      *table = bank & 1;
+    }
+    Some(Mirroring::Custom(tables))
+  }
+
+  fn cycle(&mut self) {
+    if self.irq_enabled && self.irq_counter < IRQ_COUNTER_MAX {
+      self.irq_counter += 1;
+      if self.irq_counter == IRQ_COUNTER_MAX {
+        self.irq_pending = true;
+      }
+    }
+    if !self.audio_disabled {
+      self.audio.clock();
+    }
+  }
+
+  fn irq(&self) -> bool {
+    self.irq_pending
+  }
+
+  fn expansion_audio(&self) -> VoiceLevels {
+    if self.audio_disabled {
+      [0.0; MAX_VOICES]
+    } else {
+      self.audio.voices()
+    }
+  }
+}
+
+impl ReadAddr for Namco163 {
+  fn read_addr(&mut self, addr: u16) -> u8 {
+    match addr {
+      0x4800...0x4FFF => self.audio.read_data(),
+      0x5000...0x57FF => self.irq_counter as u8,
+      0x5800...0x5FFF => (self.irq_counter >> 8) as u8 | if self.irq_enabled { 0x80 } else { 0 },
+      0x6000...0x7FFF => self.prg_ram[usize::from(addr - 0x6000)],
+      0x8000...0xFFFF => self.read_prg(addr),
+      // Open bus
+      _ => 0,
+    }
+  }
+}
+
+impl WriteAddr for Namco163 {
+  fn write_addr(&mut self, addr: u16, value: u8) -> u8 {
+    match addr {
+      0x4800...0x4FFF => self.audio.write_data(value),
+      0x5000...0x57FF => {
+        self.irq_counter = (self.irq_counter & 0x7F00) | u16::from(value);
+        self.irq_pending = false;
+      }
+      0x5800...0x5FFF => {
+        self.irq_counter = (self.irq_counter & 0x00FF) | (u16::from(value & 0x7F) << 8);
+        self.irq_enabled = value & 0x80 != 0;
+        self.irq_pending = false;
+      }
+      0x6000...0x7FFF if self.prg_ram_writable(addr) => {
+        self.prg_ram[usize::from(addr - 0x6000)] = value;
+      }
+      0x8000...0xBFFF => self.chr_banks[usize::from((addr - 0x8000) >> 11)] = value,
+      0xC000...0xDFFF => self.nametable_banks[usize::from((addr - 0xC000) >> 11)] = value,
+      0xE000...0xE7FF => {
+        self.prg_banks[0] = value & 0x3F;
+        self.audio_disabled = value & 0x40 != 0;
+      }
+      0xE800...0xEFFF => self.prg_banks[1] = value & 0x3F,
+      0xF000...0xF7FF => self.prg_banks[2] = value & 0x3F,
+      0xF800...0xFFFF => {
+        self.prg_ram_protect = value;
+        self.audio.write_address(value);
+      }
+      _ => {}
+    }
+    0
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use cartridge::mappers::numbered_banks;
+
+  /// A Namco 163 with each 8KB PRG bank and 1KB CHR bank filled with its
+  /// number
+  fn namco163() -> Namco163 {
+    Namco163::new(
+      numbered_banks(16, SIZE_PRG_BANK),
+      numbered_banks(64, SIZE_CHR_BANK),
+    )
+  }
+
+  #[test]
+  fn switches_prg_banks() {
+    let mut mapper = namco163();
+    assert_eq!(mapper.read_addr(0xE000), 15);
+    mapper.write_addr(0xE000, 3);
+    mapper.write_addr(0xE800, 4);
+    mapper.write_addr(0xF000, 5);
+    assert_eq!(mapper.read_addr(0x8000), 3);
+    assert_eq!(mapper.read_addr(0xA000), 4);
+    assert_eq!(mapper.read_addr(0xC000), 5);
+    assert_eq!(mapper.read_addr(0xFFFF), 15);
+  }
+
+  #[test]
+  fn switches_chr_banks() {
+    let mut mapper = namco163();
+    for bank in 0..8 {
+      mapper.write_addr(0x8000 + bank * 0x800, 20 + bank as u8);
+    }
+    assert_eq!(mapper.read_chr(0x0000), 20);
+    assert_eq!(mapper.read_chr(0x0400), 21);
+    assert_eq!(mapper.read_chr(0x1FFF), 27);
+  }
+
+  #[test]
+  fn maps_name_tables() {
+    let mut mapper = namco163();
+    mapper.write_addr(0xC000, 0xE1);
+    mapper.write_addr(0xC800, 0xE0);
+    mapper.write_addr(0xD000, 33);
+    mapper.write_addr(0xD800, 0xFF);
+    assert_eq!(mapper.mirroring(), Some(Mirroring::Custom([1, 0, 1, 1])));
+
+    assert_eq!(mapper.read_nametable(0x2000), None);
+    assert_eq!(mapper.read_nametable(0x2800), Some(33));
+    assert_eq!(mapper.read_nametable(0x3BFF), Some(33));
+    assert!(mapper.write_nametable(0x2800, 0));
+    assert!(!mapper.write_nametable(0x2C00, 0));
+  }
+
+  #[test]
+  fn irq_counts_up_to_max() {
+    let mut mapper = namco163();
+    mapper.write_addr(0x5000, 0xFD);
+    mapper.write_addr(0x5800, 0xFF);
+    mapper.cycle();
+    assert!(!mapper.irq());
+    mapper.cycle();
+    assert!(mapper.irq());
+    assert_eq!(mapper.read_addr(0x5000), 0xFF);
+    assert_eq!(mapper.read_addr(0x5800), 0xFF);
+
+    // Stops at the max
+    mapper.cycle();
+    assert_eq!(mapper.read_addr(0x5000), 0xFF);
+
+    mapper.write_addr(0x5000, 0);
+    assert!(!mapper.irq());
+  }
+
+  #[test]
+  fn prg_ram_write_protection() {
+    let mut mapper = namco163();
+    mapper.write_addr(0x6000, 0x42);
+    assert_eq!(mapper.read_addr(0x6000), 0);
+
+    // Enabled, except the second 2KB page
+    mapper.write_addr(0xF800, 0x42);
+    mapper.write_addr(0x6000, 0x42);
+    mapper.write_addr(0x6800, 0x42);
+    assert_eq!(mapper.read_addr(0x6000), 0x42);
+    assert_eq!(mapper.read_addr(0x6800), 0);
+  }
+
+  #[test]
+  fn sound_ram_port_and_audio() {
+    let mut mapper = namco163();
+    mapper.write_addr(0xF800, 0x80);
+    mapper.write_addr(0x4800, 0xFF);
+    mapper.write_addr(0x4800, 0xFF);
+    // Channel 7, at full volume
+    mapper.write_addr(0xF800, 0xFF);
+    mapper.write_addr(0x4800, 0x0F);
+    mapper.write_addr(0xF800, 0x80);
+    assert_eq!(mapper.read_addr(0x4800), 0xFF);
+
+    for _ in 0..15 {
+      mapper.cycle();
+    }
+    // Channel 7 is the first voice
+    let voices = mapper.expansion_audio();
+    assert!(voices[0] > 0.0);
+    assert_eq!(&voices[1..], &[0.0; 7]);
+
+    mapper.write_addr(0xE000, 0x40);
+    assert_eq!(mapper.expansion_audio(), [0.0; 8]);
+  }
+}
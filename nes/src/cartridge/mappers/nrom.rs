@@ -3,30 +3,59 @@
 //! NROM is the simplist mapper to implement as it is just the natural
 //! behaviour of the NES system.
 //!
-//! NROM supports either 1 or 2 banks of PRG-ROM and no CHR-ROM.
+//! NROM supports either 1 or 2 banks of PRG-ROM and a single 8KB bank of
+//! CHR-ROM. Cartridges without CHR-ROM get 8KB of CHR-RAM instead.
+//!
+//! Family Basic boards have PRG-RAM at $6000-$7FFF, and test ROMs use it
+//! to report their results, so 8KB is always mapped there.
 
 use cartridge::mapper::Mapper;
 use memory::{ReadAddr, WriteAddr};
 
+const SIZE_CHR: usize = 8 * 1024;
+const SIZE_PRG_RAM: usize = 8 * 1024;
+
 pub struct NROM {
   prg_rom: Vec<u8>,
+  prg_ram: Vec<u8>,
+  chr: Vec<u8>,
+  chr_is_ram: bool,
   num_prg_rom_banks: u8,
 }
 
 impl NROM {
-  pub fn new(prg_rom: Vec<u8>, num_prg_rom_banks: u8) -> NROM {
+  pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>, num_prg_rom_banks: u8) -> NROM {
+    let chr_is_ram = chr_rom.is_empty();
     NROM {
       prg_rom: prg_rom,
+      prg_ram: vec![0; SIZE_PRG_RAM],
+      chr: if chr_is_ram {
+        vec![0; SIZE_CHR]
+      } else {
+        chr_rom
+      },
+      chr_is_ram: chr_is_ram,
       num_prg_rom_banks: num_prg_rom_banks,
     }
   }
 }
 
-impl Mapper for NROM {}
+impl Mapper for NROM {
+  fn read_chr(&mut self, addr: u16) -> u8 {
+    self.chr[usize::from(addr) % self.chr.len()]
+  }
+
+  fn write_chr(&mut self, addr: u16, value: u8) {
+    if self.chr_is_ram {
+      self.chr[usize::from(addr) % SIZE_CHR] = value;
+    }
+  }
+}
 
 impl ReadAddr for NROM {
   fn read_addr(&mut self, r_addr: u16) -> u8 {
     match r_addr {
+      0x6000...0x7FFF => self.prg_ram[(r_addr - 0x6000) as usize],
       // $8000-$FFFF is PRG-ROM data.
       // $8000-$BFFF is the first bank of PRG-ROM data.
       0x8000...0xBFFF => self.prg_rom[(r_addr - 0x8000) as usize],
@@ -40,8 +69,12 @@ impl ReadAddr for NROM {
 }
 
 impl WriteAddr for NROM {
-  fn write_addr(&mut self, w_addr: u16, _value: u8) -> u8 {
-    panic!("Attempted write to ${:04X} on NROM", w_addr);
+  fn write_addr(&mut self, w_addr: u16, value: u8) -> u8 {
+    match w_addr {
+      0x6000...0x7FFF => self.prg_ram[(w_addr - 0x6000) as usize] = value,
+      _ => panic!("Attempted write to ${:04X} on NROM", w_addr),
+    }
+    0
   }
 }
 
@@ -52,7 +85,7 @@ mod tests {
   #[test]
   fn nrom_read_addr_prg() {
     let prg_rom = vec![0x01, 0x4c, 0xb8, 0xe3, 0x94, 0x00, 0xed, 0xdf];
-    let mut nrom = NROM::new(prg_rom, 1);
+    let mut nrom = NROM::new(prg_rom, vec![], 1);
 
     let byte_read = nrom.read_addr(0x8003);
 
@@ -62,10 +95,36 @@ mod tests {
   #[test]
   fn nrom_read_mirrored() {
     let prg_rom = vec![0x01, 0x4c, 0xb8, 0xe3, 0x94, 0x00, 0xed, 0xdf];
-    let mut nrom = NROM::new(prg_rom, 1);
+    let mut nrom = NROM::new(prg_rom, vec![], 1);
 
     let byte_read = nrom.read_addr(0xC000 + 0x0004);
 
     assert_eq!(byte_read, 0x94);
   }
+
+  #[test]
+  fn nrom_prg_ram() {
+    let mut nrom = NROM::new(vec![], vec![], 1);
+    nrom.write_addr(0x6000, 0x12);
+    nrom.write_addr(0x7FFF, 0x34);
+
+    assert_eq!(nrom.read_addr(0x6000), 0x12);
+    assert_eq!(nrom.read_addr(0x7FFF), 0x34);
+  }
+
+  #[test]
+  fn nrom_chr_rom_is_read_only() {
+    let mut nrom = NROM::new(vec![], vec![0x12; SIZE_CHR], 1);
+    nrom.write_chr(0x0010, 0x34);
+
+    assert_eq!(nrom.read_chr(0x0010), 0x12);
+  }
+
+  #[test]
+  fn nrom_chr_ram_without_chr_rom() {
+    let mut nrom = NROM::new(vec![], vec![], 1);
+    nrom.write_chr(0x1FFF, 0x34);
+
+    assert_eq!(nrom.read_chr(0x1FFF), 0x34);
+  }
 }
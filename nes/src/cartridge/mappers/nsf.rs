@@ -0,0 +1,414 @@
+//! NSF "cartridge"
+//!
+//! Maps an NSF's data into $8000-$FFFF, in 4KB banks switched by writing
+//! $5FF8-$5FFF if the NSF is bankswitched, along with 8KB of RAM at
+//! $6000-$7FFF. A small driver at $4100 takes the place of the game's own
+//! code: on reset it calls INIT with the song and region, then idles, and
+//! each NMI calls PLAY (skipping the call if INIT or PLAY haven't returned
+//! yet). The player raises an NMI at the rate PLAY should be called.
+//!
+//! The expansion audio chips the NSF uses (other than the FDS and MMC5's)
+//! are on the "cartridge" too, with their registers where the games'
+//! mappers have them. If there's more than one, their voices are mixed
+//! together by number.
+//!
+//!  Address     | Use
+//! -------------|-----------------------------------------------
+//! $4100-$41FF  | Driver, which the NMI and reset vectors point to
+//! $4800        | N163 data
+//! $5FF8-$5FFF  | Bank for each 4KB of $8000-$FFFF (write only)
+//! $6000-$7FFF  | RAM
+//! $8000-$FFFF  | NSF data
+//! $9000-$B002  | VRC6 registers (write only)
+//! $9010, $9030 | VRC7 register select and data (write only)
+//! $C000, $E000 | 5B register select and data (write only)
+//! $F800        | N163 address (write only)
+
+use apu::expansion::namco163::Namco163Audio;
+use apu::expansion::opll::Opll;
+use apu::expansion::sunsoft5b::Sunsoft5bAudio;
+use apu::expansion::vrc6::Vrc6Audio;
+use apu::expansion::{VoiceLevels, MAX_VOICES};
+use apu::Region;
+use cartridge::mapper::Mapper;
+use cartridge::mappers::bank_addr;
+use cartridge::nsf::{Nsf, CHIP_5B, CHIP_N163, CHIP_VRC6, CHIP_VRC7};
+use memory::{ReadAddr, WriteAddr};
+
+const SIZE_BANK: usize = 4 * 1024;
+const SIZE_RAM: usize = 8 * 1024;
+
+const DRIVER_ADDRESS: u16 = 0x4100;
+const NMI_ADDRESS: u16 = DRIVER_ADDRESS + 0x24;
+
+/// Offset of the byte the driver uses to tell whether INIT or PLAY are
+/// running ($FF) or not ($00)
+const BUSY: usize = 0xFF;
+
+/// Assembled driver, where the song, region, INIT and PLAY are filled in
+/// by `driver`
+#[rustfmt::skip]
+const DRIVER: [u8; 0x33] = [
+  // Reset:
+  0x78,             // SEI
+  0xD8,             // CLD
+  0xA2, 0xFF,       // LDX #$FF
+  0x9A,             // TXS
+  0xCE, 0xFF, 0x41, // DEC busy
+  0xA9, 0x00,       // LDA #$00
+  0x8D, 0x15, 0x40, // STA $4015
+  0xA9, 0x0F,       // LDA #$0F
+  0x8D, 0x15, 0x40, // STA $4015
+  0xA9, 0x40,       // LDA #$40
+  0x8D, 0x17, 0x40, // STA $4017
+  0xA9, 0x00,       // LDA #song
+  0xA2, 0x00,       // LDX #region
+  0x20, 0x00, 0x00, // JSR init
+  0xEE, 0xFF, 0x41, // INC busy
+  0x4C, 0x21, 0x41, // JMP * (idle)
+  // NMI:
+  0x2C, 0xFF, 0x41, // BIT busy
+  0x30, 0x09,       // BMI return
+  0xCE, 0xFF, 0x41, // DEC busy
+  0x20, 0x00, 0x00, // JSR play
+  0xEE, 0xFF, 0x41, // INC busy
+  0x40,             // RTI (return)
+];
+
+pub struct NsfMapper {
+  prg: Vec<u8>,
+  banks: [u8; 8],
+  bankswitched: bool,
+  ram: Vec<u8>,
+  driver: Vec<u8>,
+
+  vrc6: Option<Vrc6Audio>,
+  vrc7: Option<Opll>,
+  n163: Option<Namco163Audio>,
+  sunsoft5b: Option<Sunsoft5bAudio>,
+}
+
+impl NsfMapper {
+  /// Load an NSF to play a song (counting from 0) in a region
+  pub fn new(nsf: &Nsf, song: u8, region: Region) -> Self {
+    let (prg, banks) = match nsf.banks {
+      Some(banks) => {
+        // The data is loaded into the banks at the load address's offset
+        // from a 4KB boundary
+        let mut prg = vec![0; usize::from(nsf.load_address) % SIZE_BANK];
+        prg.extend_from_slice(&nsf.data);
+        let len = prg.len().div_ceil(SIZE_BANK) * SIZE_BANK;
+        prg.resize(len.max(SIZE_BANK), 0);
+        (prg, banks)
+      }
+      None => {
+        let mut prg = vec![0; 8 * SIZE_BANK];
+        let start = usize::from(nsf.load_address.max(0x8000) - 0x8000);
+        let len = nsf.data.len().min(prg.len() - start);
+        prg[start..start + len].copy_from_slice(&nsf.data[..len]);
+        (prg, [0, 1, 2, 3, 4, 5, 6, 7])
+      }
+    };
+
+    NsfMapper {
+      prg,
+      banks,
+      bankswitched: nsf.banks.is_some(),
+      ram: vec![0; SIZE_RAM],
+      driver: driver(nsf, song, region),
+      vrc6: chip(nsf, CHIP_VRC6, Vrc6Audio::default),
+      vrc7: chip(nsf, CHIP_VRC7, Opll::vrc7),
+      n163: chip(nsf, CHIP_N163, Namco163Audio::default),
+      sunsoft5b: chip(nsf, CHIP_5B, Sunsoft5bAudio::default),
+    }
+  }
+
+  /// Forward a write to the registers of the expansion chips
+  fn write_expansion(&mut self, addr: u16, value: u8) {
+    if let Some(ref mut vrc6) = self.vrc6 {
+      // Ignores addresses outside its registers
+      vrc6.write(addr, value);
+    }
+    if let Some(ref mut vrc7) = self.vrc7 {
+      match addr {
+        0x9010 => vrc7.write_address(value),
+        0x9030 => vrc7.write_data(value),
+        _ => {}
+      }
+    }
+    if let Some(ref mut n163) = self.n163 {
+      match addr {
+        0x4800 => n163.write_data(value),
+        0xF800 => n163.write_address(value),
+        _ => {}
+      }
+    }
+    if let Some(ref mut sunsoft5b) = self.sunsoft5b {
+      match addr {
+        0xC000 => sunsoft5b.write_register(value),
+        0xE000 => sunsoft5b.write_data(value),
+        _ => {}
+      }
+    }
+  }
+
+  fn read_prg(&self, addr: u16) -> u8 {
+    let bank = usize::from(self.banks[usize::from((addr - 0x8000) / 0x1000)]);
+    self.prg[bank_addr(bank, SIZE_BANK, self.prg.len(), addr)]
+  }
+}
+
+/// Create an expansion chip if the NSF uses it
+fn chip<T, F: Fn() -> T>(nsf: &Nsf, flag: u8, create: F) -> Option<T> {
+  if nsf.chips & flag != 0 {
+    Some(create())
+  } else {
+    None
+  }
+}
+
+fn driver(nsf: &Nsf, song: u8, region: Region) -> Vec<u8> {
+  let mut driver = DRIVER.to_vec();
+  driver[0x18] = song;
+  driver[0x1A] = match region {
+    Region::Ntsc => 0,
+    Region::Pal => 1,
+  };
+  driver[0x1C] = nsf.init_address as u8;
+  driver[0x1D] = (nsf.init_address >> 8) as u8;
+  driver[0x2D] = nsf.play_address as u8;
+  driver[0x2E] = (nsf.play_address >> 8) as u8;
+  driver.resize(0x100, 0);
+  driver
+}
+
+impl Mapper for NsfMapper {
+  fn read_chr(&mut self, _addr: u16) -> u8 {
+    0
+  }
+
+  fn cycle(&mut self) {
+    if let Some(ref mut vrc6) = self.vrc6 {
+      vrc6.clock();
+    }
+    if let Some(ref mut vrc7) = self.vrc7 {
+      vrc7.clock();
+    }
+    if let Some(ref mut n163) = self.n163 {
+      n163.clock();
+    }
+    if let Some(ref mut sunsoft5b) = self.sunsoft5b {
+      sunsoft5b.clock();
+    }
+  }
+
+  fn expansion_audio(&self) -> VoiceLevels {
+    let chips = [
+      self.vrc6.as_ref().map(Vrc6Audio::voices),
+      self.vrc7.as_ref().map(Opll::voices),
+      self.n163.as_ref().map(Namco163Audio::voices),
+      self.sunsoft5b.as_ref().map(Sunsoft5bAudio::voices),
+    ];
+    let mut voices = [0.0; MAX_VOICES];
+    for chip in chips.iter().flatten() {
+      for (voice, level) in voices.iter_mut().zip(chip.iter()) {
+        *voice += level;
+      }
+    }
+    voices
+  }
+}
+
+impl ReadAddr for NsfMapper {
+  fn read_addr(&mut self, addr: u16) -> u8 {
+    match addr {
+      0x4100...0x41FF => self.driver[usize::from(addr - DRIVER_ADDRESS)],
+      0x4800 => match self.n163 {
+        Some(ref mut n163) => n163.read_data(),
+        // Open bus
+        None => 0,
+      },
+      0x6000...0x7FFF => self.ram[usize::from(addr - 0x6000)],
+      // The driver's vectors replace any in the data
+      0xFFFA => NMI_ADDRESS as u8,
+      0xFFFB => (NMI_ADDRESS >> 8) as u8,
+      0xFFFC => DRIVER_ADDRESS as u8,
+      0xFFFD => (DRIVER_ADDRESS >> 8) as u8,
+      0x8000...0xFFFF => self.read_prg(addr),
+      // Open bus
+      _ => 0,
+    }
+  }
+}
+
+impl WriteAddr for NsfMapper {
+  fn write_addr(&mut self, addr: u16, value: u8) -> u8 {
+    match addr {
+      0x41FF => self.driver[BUSY] = value,
+      0x5FF8...0x5FFF if self.bankswitched => {
+        self.banks[usize::from(addr - 0x5FF8)] = value;
+      }
+      0x6000...0x7FFF => self.ram[usize::from(addr - 0x6000)] = value,
+      // Expansion audio registers, or writes to ROM
+      _ => self.write_expansion(addr, value),
+    }
+    0
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use apu::processor::ApuImpl;
+  use cartridge::nsf;
+  use cartridge::Cartridge;
+  use clock::MASTER_FREQUENCY;
+  use console::Console;
+  use controller::joypad::Joypad;
+  use io::audio::NullAudioOutput;
+  use io::video::IndexedVideoOutput;
+
+  fn nsf(load: u16, data: &[u8]) -> Nsf {
+    nsf::parse(&nsf::test_nsf(load, 0x8000, 0x8007, data)).unwrap()
+  }
+
+  #[test]
+  fn loads_unbanked_data_at_load_address() {
+    let mut mapper = NsfMapper::new(&nsf(0x8010, &[1, 2, 3]), 0, Region::Ntsc);
+    assert_eq!(mapper.read_addr(0x800F), 0);
+    assert_eq!(mapper.read_addr(0x8010), 1);
+    assert_eq!(mapper.read_addr(0x8012), 3);
+
+    // Banks can't be switched
+    mapper.write_addr(0x5FF8, 1);
+    assert_eq!(mapper.read_addr(0x8010), 1);
+  }
+
+  #[test]
+  fn switches_banks() {
+    let mut data = vec![0x11; SIZE_BANK - 0x10];
+    data.extend_from_slice(&[0x22; SIZE_BANK]);
+    let mut nsf = nsf(0x8010, &data);
+    nsf.banks = Some([1, 0, 0, 0, 0, 0, 0, 0]);
+
+    let mut mapper = NsfMapper::new(&nsf, 0, Region::Ntsc);
+    assert_eq!(mapper.read_addr(0x8000), 0x22);
+    assert_eq!(mapper.read_addr(0x9000), 0x00);
+    assert_eq!(mapper.read_addr(0x9010), 0x11);
+
+    mapper.write_addr(0x5FF8, 0);
+    mapper.write_addr(0x5FFF, 1);
+    assert_eq!(mapper.read_addr(0x8010), 0x11);
+    assert_eq!(mapper.read_addr(0xF000), 0x22);
+  }
+
+  #[test]
+  fn vectors_point_to_driver() {
+    let mut mapper = NsfMapper::new(&nsf(0x8000, &[0xFF; 0x8000]), 0, Region::Ntsc);
+    assert_eq!(mapper.read_addr(0xFFFC), 0x00);
+    assert_eq!(mapper.read_addr(0xFFFD), 0x41);
+    assert_eq!(mapper.read_addr(0xFFFA), 0x24);
+    assert_eq!(mapper.read_addr(NMI_ADDRESS), 0x2C);
+    assert_eq!(mapper.read_addr(0xFFFE), 0xFF);
+  }
+
+  #[test]
+  fn mixes_expansion_chips_by_voice() {
+    let mut nsf = nsf(0x8000, &[]);
+    nsf.chips = CHIP_VRC6 | CHIP_5B;
+    let mut mapper = NsfMapper::new(&nsf, 0, Region::Ntsc);
+    assert_eq!(mapper.expansion_audio(), [0.0; 8]);
+
+    // VRC6 pulse 1, held high at full volume
+    mapper.write_addr(0x9000, 0x8F);
+    mapper.write_addr(0x9002, 0x80);
+    mapper.cycle();
+    let vrc6 = mapper.expansion_audio()[0];
+    assert!(vrc6 > 0.0);
+
+    // 5B channel A, held high at full volume
+    mapper.write_addr(0xC000, 0x07);
+    mapper.write_addr(0xE000, 0x3F);
+    mapper.write_addr(0xC000, 0x08);
+    mapper.write_addr(0xE000, 0x0F);
+    let voices = mapper.expansion_audio();
+    assert!(voices[0] > vrc6);
+    assert_eq!(&voices[1..], &[0.0; 7]);
+  }
+
+  #[test]
+  fn forwards_vrc7_and_n163_registers() {
+    let mut nsf = nsf(0x8000, &[]);
+    nsf.chips = CHIP_VRC7 | CHIP_N163;
+    let mut mapper = NsfMapper::new(&nsf, 0, Region::Ntsc);
+
+    // N163 RAM, with auto increment
+    mapper.write_addr(0xF800, 0x80);
+    mapper.write_addr(0x4800, 0x42);
+    mapper.write_addr(0xF800, 0x80);
+    assert_eq!(mapper.read_addr(0x4800), 0x42);
+
+    // A VRC7 note on channel 0
+    for &(register, value) in &[(0x10, 0x20), (0x30, 0x30), (0x20, 0x19)] {
+      mapper.write_addr(0x9010, register);
+      mapper.write_addr(0x9030, value);
+    }
+    let peak = (0..20_000).fold(0.0f32, |peak, _| {
+      mapper.cycle();
+      peak.max(mapper.expansion_audio()[0].abs())
+    });
+    assert!(peak > 0.01);
+  }
+
+  #[test]
+  fn ignores_chips_it_doesnt_use() {
+    let mut mapper = NsfMapper::new(&nsf(0x8000, &[]), 0, Region::Ntsc);
+    mapper.write_addr(0x9000, 0x8F);
+    mapper.write_addr(0x9002, 0x80);
+    mapper.cycle();
+    assert_eq!(mapper.expansion_audio(), [0.0; 8]);
+    assert_eq!(mapper.read_addr(0x4800), 0);
+  }
+
+  #[test]
+  fn calls_init_then_play_on_nmi() {
+    // INIT stores the song and region, PLAY counts how often it's called
+    let nsf = nsf(
+      0x8000,
+      &[
+        0x8D, 0x00, 0x60, // STA $6000
+        0x8E, 0x01, 0x60, // STX $6001
+        0x60, // RTS
+        0xEE, 0x02, 0x60, // INC $6002
+        0x60, // RTS
+      ],
+    );
+
+    let mut cartridge = Cartridge::from_nsf(&nsf, 2, Region::Pal);
+    let mut apu = ApuImpl::create(NullAudioOutput, 48_000);
+    {
+      let mut console = Console::new(
+        &mut apu,
+        &mut cartridge,
+        None::<&mut Joypad>,
+        None::<&mut Joypad>,
+        IndexedVideoOutput::new(|_, _| {}),
+      );
+      console.set_throttled(false);
+      console.reset();
+      for _ in 0..3 {
+        for _ in 0..MASTER_FREQUENCY / 60 {
+          console.tick();
+        }
+        console.nmi();
+      }
+      for _ in 0..MASTER_FREQUENCY / 60 {
+        console.tick();
+      }
+    }
+
+    assert_eq!(cartridge.mapper.read_addr(0x6000), 2);
+    assert_eq!(cartridge.mapper.read_addr(0x6001), 1);
+    assert_eq!(cartridge.mapper.read_addr(0x6002), 3);
+  }
+}
@@ -0,0 +1,296 @@
+//! Konami VRC6 (mappers 24 and 26)
+//!
+//! Used by Akumajou Densetsu (mapper 24, VRC6a) and Esper Dream 2 and
+//! Mouryou Senki Madara (mapper 26, VRC6b, which swaps address lines A0
+//! and A1). Along with bank switching and a VRC IRQ counter, it has its
+//! own sound channels (see `apu::expansion::vrc6`). [Read more here][VRC6].
+//!
+//!  Address    | Use
+//! ------------|------------------------------------------------------
+//! $6000-$7FFF | 8KB PRG-RAM, if enabled
+//! $8000-$BFFF | Switchable 16KB PRG-ROM bank
+//! $C000-$DFFF | Switchable 8KB PRG-ROM bank
+//! $E000-$FFFF | Last 8KB PRG-ROM bank
+//!
+//! The registers (as wired on VRC6a) are:
+//!
+//!  Register   | Use
+//! ------------|------------------------------------------------------
+//! $8000-$8003 | 16KB PRG bank at $8000
+//! $9000-$B002 | Audio
+//! $B003       | R... MMPP: PRG-RAM enable, Mirroring, PPU banking mode
+//! $C000-$C003 | 8KB PRG bank at $C000
+//! $D000-$E003 | 1KB CHR banks 0-7
+//! $F000-$F002 | IRQ latch, control and acknowledge (see `VrcIrq`)
+//!
+//! Only the PPU banking mode the games use (1KB CHR banks, with name
+//! tables from the console's RAM) is supported. Its mirroring is 0:
+//! vertical, 1: horizontal, 2: single screen lower or 3: single screen
+//! upper.
+//!
+//! [VRC6]: https://wiki.nesdev.com/w/index.php/VRC6
+
+use apu::expansion::vrc6::Vrc6Audio;
+use apu::expansion::VoiceLevels;
+use cartridge::mapper::Mapper;
+use cartridge::mappers::vrc_irq::VrcIrq;
+use cartridge::mappers::{bank_addr, chr_rom_or_ram};
+use cartridge::mirroring::Mirroring;
+use memory::{ReadAddr, WriteAddr};
+
+const SIZE_PRG_BANK: usize = 8 * 1024;
+const SIZE_CHR_BANK: usize = 1024;
+const SIZE_PRG_RAM: usize = 8 * 1024;
+
+/// How the board connects the CPU's address lines to the VRC6's
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Vrc6Wiring {
+  /// Mapper 24
+  Vrc6a,
+  /// Mapper 26, with A0 and A1 swapped
+  Vrc6b,
+}
+
+impl Vrc6Wiring {
+  /// Map a CPU address to the register it selects, as numbered on VRC6a
+  fn register(self, addr: u16) -> u16 {
+    let addr = addr & 0xF003;
+    match self {
+      Vrc6Wiring::Vrc6a => addr,
+      Vrc6Wiring::Vrc6b => (addr & 0xF000) | ((addr & 0b01) << 1) | ((addr & 0b10) >> 1),
+    }
+  }
+}
+
+pub struct Vrc6 {
+  wiring: Vrc6Wiring,
+  prg_rom: Vec<u8>,
+  chr: Vec<u8>,
+  chr_is_ram: bool,
+  prg_ram: Vec<u8>,
+  prg_ram_enabled: bool,
+
+  /// 16KB bank at $8000
+  prg_bank_16k: u8,
+  /// 8KB bank at $C000
+  prg_bank_8k: u8,
+  chr_banks: [u8; 8],
+  mirroring: Mirroring,
+
+  irq: VrcIrq,
+  audio: Vrc6Audio,
+}
+
+impl Vrc6 {
+  pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>, wiring: Vrc6Wiring) -> Self {
+    let chr_is_ram = chr_rom.is_empty();
+    Vrc6 {
+      wiring,
+      prg_rom,
+      chr: chr_rom_or_ram(chr_rom),
+      chr_is_ram,
+      prg_ram: vec![0; SIZE_PRG_RAM],
+      prg_ram_enabled: false,
+      prg_bank_16k: 0,
+      prg_bank_8k: 0,
+      chr_banks: [0; 8],
+      mirroring: Mirroring::Vertical,
+      irq: VrcIrq::default(),
+      audio: Vrc6Audio::default(),
+    }
+  }
+
+  fn read_prg(&self, addr: u16) -> u8 {
+    let num_banks = self.prg_rom.len() / SIZE_PRG_BANK;
+    let bank = match addr {
+      0x8000...0xBFFF => usize::from(self.prg_bank_16k) * 2 + usize::from((addr - 0x8000) >> 13),
+      0xC000...0xDFFF => usize::from(self.prg_bank_8k),
+      _ => num_banks - 1,
+    };
+    self.prg_rom[bank_addr(bank, SIZE_PRG_BANK, self.prg_rom.len(), addr)]
+  }
+
+  fn chr_addr(&self, addr: u16) -> usize {
+    let bank = usize::from(self.chr_banks[usize::from(addr >> 10) & 0b111]);
+    bank_addr(bank, SIZE_CHR_BANK, self.chr.len(), addr)
+  }
+
+  fn write_banking_control(&mut self, value: u8) {
+    self.prg_ram_enabled = value & 0x80 != 0;
+    self.mirroring = match (value >> 2) & 0b11 {
+      0 => Mirroring::Vertical,
+      1 => Mirroring::Horizontal,
+      2 => Mirroring::SingleScreenLower,
+      _ => Mirroring::SingleScreenUpper,
+    };
+  }
+}
+
+impl Mapper for Vrc6 {
+  fn read_chr(&mut self, addr: u16) -> u8 {
+    self.chr[self.chr_addr(addr)]
+  }
+
+  fn write_chr(&mut self, addr: u16, value: u8) {
+    if self.chr_is_ram {
+      let addr = self.chr_addr(addr);
+      self.chr[addr] = value;
+    }
+  }
+
+  fn mirroring(&self) -> Option<Mirroring> {
+    Some(self.mirroring)
+  }
+
+  fn cycle(&mut self) {
+    self.irq.cycle();
+    self.audio.clock();
+  }
+
+  fn irq(&self) -> bool {
+    self.irq.irq()
+  }
+
+  fn expansion_audio(&self) -> VoiceLevels {
+    self.audio.voices()
+  }
+}
+
+impl ReadAddr for Vrc6 {
+  fn read_addr(&mut self, addr: u16) -> u8 {
+    match addr {
+      0x6000...0x7FFF if self.prg_ram_enabled => self.prg_ram[usize::from(addr - 0x6000)],
+      0x8000...0xFFFF => self.read_prg(addr),
+      // Open bus
+      _ => 0,
+    }
+  }
+}
+
+impl WriteAddr for Vrc6 {
+  fn write_addr(&mut self, addr: u16, value: u8) -> u8 {
+    if addr < 0x8000 {
+      if let 0x6000...0x7FFF = addr {
+        if self.prg_ram_enabled {
+          self.prg_ram[usize::from(addr - 0x6000)] = value;
+        }
+      }
+      return 0;
+    }
+
+    match self.wiring.register(addr) {
+      0x8000...0x8003 => self.prg_bank_16k = value & 0x0F,
+      register @ 0x9000...0xB002 => self.audio.write(register, value),
+      0xB003 => self.write_banking_control(value),
+      0xC000...0xC003 => self.prg_bank_8k = value & 0x1F,
+      register @ 0xD000...0xD003 => self.chr_banks[usize::from(register & 0b11)] = value,
+      register @ 0xE000...0xE003 => self.chr_banks[4 + usize::from(register & 0b11)] = value,
+      0xF000 => self.irq.write_latch(value),
+      0xF001 => self.irq.write_control(value),
+      0xF002 => self.irq.acknowledge(),
+      _ => {}
+    }
+    0
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use cartridge::mappers::numbered_banks;
+
+  /// A VRC6 with each 8KB PRG bank and 1KB CHR bank filled with its number
+  fn vrc6(wiring: Vrc6Wiring) -> Vrc6 {
+    Vrc6::new(
+      numbered_banks(16, SIZE_PRG_BANK),
+      numbered_banks(32, SIZE_CHR_BANK),
+      wiring,
+    )
+  }
+
+  #[test]
+  fn switches_prg_banks() {
+    let mut mapper = vrc6(Vrc6Wiring::Vrc6a);
+    assert_eq!(mapper.read_addr(0xE000), 15);
+    assert_eq!(mapper.read_addr(0xFFFF), 15);
+
+    mapper.write_addr(0x8000, 3);
+    assert_eq!(mapper.read_addr(0x8000), 6);
+    assert_eq!(mapper.read_addr(0xBFFF), 7);
+
+    mapper.write_addr(0xC003, 9);
+    assert_eq!(mapper.read_addr(0xC000), 9);
+    assert_eq!(mapper.read_addr(0xE000), 15);
+  }
+
+  #[test]
+  fn switches_chr_banks() {
+    let mut mapper = vrc6(Vrc6Wiring::Vrc6a);
+    for register in 0..4 {
+      mapper.write_addr(0xD000 + register, 10 + register as u8);
+      mapper.write_addr(0xE000 + register, 20 + register as u8);
+    }
+    assert_eq!(mapper.read_chr(0x0000), 10);
+    assert_eq!(mapper.read_chr(0x0C00), 13);
+    assert_eq!(mapper.read_chr(0x1000), 20);
+    assert_eq!(mapper.read_chr(0x1FFF), 23);
+  }
+
+  #[test]
+  fn vrc6b_swaps_address_lines() {
+    let mut mapper = vrc6(Vrc6Wiring::Vrc6b);
+    mapper.write_addr(0xD001, 5);
+    mapper.write_addr(0xD002, 6);
+    assert_eq!(mapper.read_chr(0x0400), 6);
+    assert_eq!(mapper.read_chr(0x0800), 5);
+
+    // $B003 on VRC6a
+    mapper.write_addr(0xB003, 0b1000_0100);
+    assert_eq!(mapper.mirroring(), Some(Mirroring::Horizontal));
+  }
+
+  #[test]
+  fn mirroring_and_prg_ram() {
+    let mut mapper = vrc6(Vrc6Wiring::Vrc6a);
+    mapper.write_addr(0x6000, 0x42);
+    assert_eq!(mapper.read_addr(0x6000), 0);
+
+    mapper.write_addr(0xB003, 0b1000_1000);
+    assert_eq!(mapper.mirroring(), Some(Mirroring::SingleScreenLower));
+    mapper.write_addr(0x6000, 0x42);
+    assert_eq!(mapper.read_addr(0x6000), 0x42);
+
+    mapper.write_addr(0xB003, 0b0000_1100);
+    assert_eq!(mapper.mirroring(), Some(Mirroring::SingleScreenUpper));
+    assert_eq!(mapper.read_addr(0x6000), 0);
+  }
+
+  #[test]
+  fn irq_in_cpu_cycle_mode() {
+    let mut mapper = vrc6(Vrc6Wiring::Vrc6a);
+    mapper.write_addr(0xF000, 0xFC);
+    mapper.write_addr(0xF001, 0b110);
+    for _ in 0..3 {
+      mapper.cycle();
+    }
+    assert!(!mapper.irq());
+    mapper.cycle();
+    assert!(mapper.irq());
+
+    mapper.write_addr(0xF002, 0);
+    assert!(!mapper.irq());
+  }
+
+  #[test]
+  fn outputs_audio() {
+    let mut mapper = vrc6(Vrc6Wiring::Vrc6a);
+    assert_eq!(mapper.expansion_audio(), [0.0; 8]);
+    mapper.write_addr(0x9000, 0x8F);
+    mapper.write_addr(0x9002, 0x80);
+    mapper.cycle();
+    // Only pulse 1 is playing
+    let voices = mapper.expansion_audio();
+    assert!(voices[0] > 0.0);
+    assert_eq!(&voices[1..], &[0.0; 7]);
+  }
+}
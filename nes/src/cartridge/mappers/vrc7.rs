@@ -0,0 +1,301 @@
+//! Konami VRC7 (mapper 85)
+//!
+//! Used by Lagrange Point, which also uses its FM sound (see
+//! `apu::expansion::opll`), and Tiny Toon Adventures 2, which doesn't.
+//! The two boards connect different address lines to the VRC7's second
+//! register select, A4 and A3, so both are decoded. [Read more here][VRC7].
+//!
+//!  Address    | Use
+//! ------------|------------------------------------------------------
+//! $6000-$7FFF | 8KB PRG-RAM, if enabled
+//! $8000-$9FFF | Switchable 8KB PRG-ROM bank
+//! $A000-$BFFF | Switchable 8KB PRG-ROM bank
+//! $C000-$DFFF | Switchable 8KB PRG-ROM bank
+//! $E000-$FFFF | Last 8KB PRG-ROM bank
+//!
+//! The registers (as wired on Lagrange Point) are:
+//!
+//!  Register      | Use
+//! ---------------|---------------------------------------------------
+//! $8000, $8010   | 8KB PRG banks at $8000, $A000
+//! $9000          | 8KB PRG bank at $C000
+//! $9010, $9030   | Audio register select, data
+//! $A000-$D010    | 1KB CHR banks 0-7, two per $1000
+//! $E000          | RS.. ..MM: PRG-RAM enable, Silence (and reset) the
+//!                | audio, Mirroring
+//! $E010-$F010    | IRQ latch, control and acknowledge (see `VrcIrq`)
+//!
+//! The mirroring is 0: vertical, 1: horizontal, 2: single screen lower or
+//! 3: single screen upper.
+//!
+//! [VRC7]: https://wiki.nesdev.com/w/index.php/VRC7
+
+use apu::expansion::opll::Opll;
+use apu::expansion::VoiceLevels;
+use cartridge::mapper::Mapper;
+use cartridge::mappers::vrc_irq::VrcIrq;
+use cartridge::mappers::{bank_addr, chr_rom_or_ram};
+use cartridge::mirroring::Mirroring;
+use memory::{ReadAddr, WriteAddr};
+
+const SIZE_PRG_BANK: usize = 8 * 1024;
+const SIZE_CHR_BANK: usize = 1024;
+const SIZE_PRG_RAM: usize = 8 * 1024;
+
+/// Map a CPU address to the register it selects, as numbered on Lagrange
+/// Point, taking either A4 or A3 as the second register select
+fn register(addr: u16) -> u16 {
+  let select = if addr & 0x18 != 0 { 0x10 } else { 0 };
+  (addr & 0xF000) | select
+}
+
+pub struct Vrc7 {
+  prg_rom: Vec<u8>,
+  chr: Vec<u8>,
+  chr_is_ram: bool,
+  prg_ram: Vec<u8>,
+  prg_ram_enabled: bool,
+
+  prg_banks: [u8; 3],
+  chr_banks: [u8; 8],
+  mirroring: Mirroring,
+
+  irq: VrcIrq,
+  audio: Opll,
+  audio_silenced: bool,
+}
+
+impl Vrc7 {
+  pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Self {
+    let chr_is_ram = chr_rom.is_empty();
+    Vrc7 {
+      prg_rom,
+      chr: chr_rom_or_ram(chr_rom),
+      chr_is_ram,
+      prg_ram: vec![0; SIZE_PRG_RAM],
+      prg_ram_enabled: false,
+      prg_banks: [0; 3],
+      chr_banks: [0; 8],
+      mirroring: Mirroring::Vertical,
+      irq: VrcIrq::default(),
+      audio: Opll::vrc7(),
+      audio_silenced: false,
+    }
+  }
+
+  fn read_prg(&self, addr: u16) -> u8 {
+    let num_banks = self.prg_rom.len() / SIZE_PRG_BANK;
+    let bank = match addr {
+      0x8000...0xDFFF => usize::from(self.prg_banks[usize::from((addr - 0x8000) >> 13)]),
+      _ => num_banks - 1,
+    };
+    self.prg_rom[bank_addr(bank, SIZE_PRG_BANK, self.prg_rom.len(), addr)]
+  }
+
+  fn chr_addr(&self, addr: u16) -> usize {
+    let bank = usize::from(self.chr_banks[usize::from(addr >> 10) & 0b111]);
+    bank_addr(bank, SIZE_CHR_BANK, self.chr.len(), addr)
+  }
+
+  fn write_control(&mut self, value: u8) {
+    self.prg_ram_enabled = value & 0x80 != 0;
+    self.audio_silenced = value & 0x40 != 0;
+    if self.audio_silenced {
+      self.audio.reset();
+    }
+    self.mirroring = match value & 0b11 {
+      0 => Mirroring::Vertical,
+      1 => Mirroring::Horizontal,
+      2 => Mirroring::SingleScreenLower,
+      _ => Mirroring::SingleScreenUpper,
+    };
+  }
+}
+
+impl Mapper for Vrc7 {
+  fn read_chr(&mut self, addr: u16) -> u8 {
+    self.chr[self.chr_addr(addr)]
+  }
+
+  fn write_chr(&mut self, addr: u16, value: u8) {
+    if self.chr_is_ram {
+      let addr = self.chr_addr(addr);
+      self.chr[addr] = value;
+    }
+  }
+
+  fn mirroring(&self) -> Option<Mirroring> {
+    Some(self.mirroring)
+  }
+
+  fn cycle(&mut self) {
+    self.irq.cycle();
+    if !self.audio_silenced {
+      self.audio.clock();
+    }
+  }
+
+  fn irq(&self) -> bool {
+    self.irq.irq()
+  }
+
+  fn expansion_audio(&self) -> VoiceLevels {
+    self.audio.voices()
+  }
+}
+
+impl ReadAddr for Vrc7 {
+  fn read_addr(&mut self, addr: u16) -> u8 {
+    match addr {
+      0x6000...0x7FFF if self.prg_ram_enabled => self.prg_ram[usize::from(addr - 0x6000)],
+      0x8000...0xFFFF => self.read_prg(addr),
+      // Open bus
+      _ => 0,
+    }
+  }
+}
+
+impl WriteAddr for Vrc7 {
+  fn write_addr(&mut self, addr: u16, value: u8) -> u8 {
+    if addr < 0x8000 {
+      if let 0x6000...0x7FFF = addr {
+        if self.prg_ram_enabled {
+          self.prg_ram[usize::from(addr - 0x6000)] = value;
+        }
+      }
+      return 0;
+    }
+
+    match register(addr) {
+      0x8000 => self.prg_banks[0] = value & 0x3F,
+      0x8010 => self.prg_banks[1] = value & 0x3F,
+      0x9000 => self.prg_banks[2] = value & 0x3F,
+      0x9010 if self.audio_silenced => {}
+      0x9010 if addr & 0x20 != 0 => self.audio.write_data(value),
+      0x9010 => self.audio.write_address(value),
+      register @ 0xA000...0xD010 => {
+        let bank = usize::from((register - 0xA000) >> 12) * 2 + usize::from(register & 0x10 != 0);
+        self.chr_banks[bank] = value;
+      }
+      0xE000 => self.write_control(value),
+      0xE010 => self.irq.write_latch(value),
+      0xF000 => self.irq.write_control(value),
+      0xF010 => self.irq.acknowledge(),
+      _ => {}
+    }
+    0
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use cartridge::mappers::numbered_banks;
+
+  /// A VRC7 with each 8KB PRG bank and 1KB CHR bank filled with its number
+  fn vrc7() -> Vrc7 {
+    Vrc7::new(
+      numbered_banks(16, SIZE_PRG_BANK),
+      numbered_banks(32, SIZE_CHR_BANK),
+    )
+  }
+
+  #[test]
+  fn switches_prg_banks() {
+    let mut mapper = vrc7();
+    assert_eq!(mapper.read_addr(0xE000), 15);
+
+    mapper.write_addr(0x8000, 3);
+    mapper.write_addr(0x8010, 4);
+    mapper.write_addr(0x9000, 5);
+    assert_eq!(mapper.read_addr(0x8000), 3);
+    assert_eq!(mapper.read_addr(0xA000), 4);
+    assert_eq!(mapper.read_addr(0xDFFF), 5);
+    assert_eq!(mapper.read_addr(0xFFFF), 15);
+
+    // A3 as the second register select
+    mapper.write_addr(0x8008, 6);
+    assert_eq!(mapper.read_addr(0xA000), 6);
+    assert_eq!(mapper.read_addr(0x8000), 3);
+  }
+
+  #[test]
+  fn switches_chr_banks() {
+    let mut mapper = vrc7();
+    for (i, &addr) in [
+      0xA000, 0xA010, 0xB000, 0xB010, 0xC000, 0xC010, 0xD000, 0xD008,
+    ]
+    .iter()
+    .enumerate()
+    {
+      mapper.write_addr(addr, 10 + i as u8);
+    }
+    assert_eq!(mapper.read_chr(0x0000), 10);
+    assert_eq!(mapper.read_chr(0x0400), 11);
+    assert_eq!(mapper.read_chr(0x1000), 14);
+    assert_eq!(mapper.read_chr(0x1FFF), 17);
+  }
+
+  #[test]
+  fn mirroring_and_prg_ram() {
+    let mut mapper = vrc7();
+    mapper.write_addr(0x6000, 0x42);
+    assert_eq!(mapper.read_addr(0x6000), 0);
+
+    mapper.write_addr(0xE000, 0b1000_0001);
+    assert_eq!(mapper.mirroring(), Some(Mirroring::Horizontal));
+    mapper.write_addr(0x6000, 0x42);
+    assert_eq!(mapper.read_addr(0x6000), 0x42);
+
+    mapper.write_addr(0xE000, 0b0000_0011);
+    assert_eq!(mapper.mirroring(), Some(Mirroring::SingleScreenUpper));
+    assert_eq!(mapper.read_addr(0x6000), 0);
+  }
+
+  #[test]
+  fn irq_in_cpu_cycle_mode() {
+    let mut mapper = vrc7();
+    mapper.write_addr(0xE010, 0xFE);
+    mapper.write_addr(0xF000, 0b110);
+    mapper.cycle();
+    assert!(!mapper.irq());
+    mapper.cycle();
+    assert!(mapper.irq());
+
+    mapper.write_addr(0xF010, 0);
+    assert!(!mapper.irq());
+  }
+
+  fn play_note(mapper: &mut Vrc7) {
+    for &(register, value) in &[(0x10, 0x20), (0x30, 0x30), (0x20, 0x19)] {
+      mapper.write_addr(0x9010, register);
+      mapper.write_addr(0x9030, value);
+    }
+  }
+
+  /// The peak level of channel 0, checking the others are silent
+  fn peak(mapper: &mut Vrc7, cycles: usize) -> f32 {
+    (0..cycles).fold(0.0f32, |peak, _| {
+      mapper.cycle();
+      let voices = mapper.expansion_audio();
+      assert_eq!(&voices[1..], &[0.0; 7]);
+      peak.max(voices[0].abs())
+    })
+  }
+
+  #[test]
+  fn outputs_audio_unless_silenced() {
+    let mut mapper = vrc7();
+    play_note(&mut mapper);
+    assert!(peak(&mut mapper, 20_000) > 0.01);
+
+    mapper.write_addr(0xE000, 0x40);
+    assert_eq!(peak(&mut mapper, 1000), 0.0);
+    play_note(&mut mapper);
+    assert_eq!(peak(&mut mapper, 20_000), 0.0);
+
+    mapper.write_addr(0xE000, 0x00);
+    play_note(&mut mapper);
+    assert!(peak(&mut mapper, 20_000) > 0.01);
+  }
+}
@@ -0,0 +1,128 @@
+//! Konami VRC IRQ counter
+//!
+//! Shared by the VRC4, VRC6 and VRC7. An 8 bit counter counts up from a
+//! latched value, raising an IRQ and reloading when it overflows. It's
+//! clocked either every CPU cycle, or (by default) once per scanline,
+//! where a prescaler divides the CPU clock by 113⅔ to approximate the
+//! PPU's 341 dots per scanline. [Read more here][IRQ].
+//!
+//! Register | Legend    | Bits
+//! ---------|-----------|----------------------------------------------
+//! Latch    | LLLL LLLL | Value the counter is reloaded with
+//! Control  | .... .MEA | Mode (1 = CPU cycle), Enable, enable After
+//!          |           | acknowledgement. Writing reloads the counter
+//!          |           | if E is set.
+//! Ack      | .... .... | Acknowledge the IRQ, copying A to E
+//!
+//! [IRQ]: https://wiki.nesdev.com/w/index.php/VRC_IRQ
+
+/// The prescaler counts down by 3 each CPU cycle from 341, so clocks the
+/// counter every 113⅔ cycles on average
+const PRESCALER_PERIOD: i16 = 341;
+
+#[derive(Default)]
+pub struct VrcIrq {
+  latch: u8,
+  counter: u8,
+  prescaler: i16,
+  enabled: bool,
+  enable_after_ack: bool,
+  cycle_mode: bool,
+  pending: bool,
+}
+
+impl VrcIrq {
+  pub fn write_latch(&mut self, value: u8) {
+    self.latch = value;
+  }
+
+  pub fn write_control(&mut self, value: u8) {
+    self.enable_after_ack = value & 0b001 != 0;
+    self.enabled = value & 0b010 != 0;
+    self.cycle_mode = value & 0b100 != 0;
+    self.pending = false;
+    if self.enabled {
+      self.counter = self.latch;
+      self.prescaler = PRESCALER_PERIOD;
+    }
+  }
+
+  pub fn acknowledge(&mut self) {
+    self.pending = false;
+    self.enabled = self.enable_after_ack;
+  }
+
+  /// Clocked every CPU cycle
+  pub fn cycle(&mut self) {
+    if !self.enabled {
+      return;
+    }
+
+    if self.cycle_mode {
+      self.clock_counter();
+    } else {
+      self.prescaler -= 3;
+      if self.prescaler <= 0 {
+        self.prescaler += PRESCALER_PERIOD;
+        self.clock_counter();
+      }
+    }
+  }
+
+  pub fn irq(&self) -> bool {
+    self.pending
+  }
+
+  fn clock_counter(&mut self) {
+    if self.counter == 0xFF {
+      self.counter = self.latch;
+      self.pending = true;
+    } else {
+      self.counter += 1;
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn cycles_until_irq(irq: &mut VrcIrq) -> usize {
+    let mut cycles = 0;
+    while !irq.irq() {
+      irq.cycle();
+      cycles += 1;
+      assert!(cycles < 100_000, "No IRQ");
+    }
+    cycles
+  }
+
+  #[test]
+  fn cycle_mode() {
+    let mut irq = VrcIrq::default();
+    irq.write_latch(0xFE);
+    irq.write_control(0b111);
+    assert_eq!(cycles_until_irq(&mut irq), 2);
+
+    // Stays enabled after acknowledging, and counts from the latch again
+    irq.acknowledge();
+    assert!(!irq.irq());
+    assert_eq!(cycles_until_irq(&mut irq), 2);
+  }
+
+  #[test]
+  fn scanline_mode() {
+    let mut irq = VrcIrq::default();
+    irq.write_latch(0xFD);
+    irq.write_control(0b010);
+    // 3 scanlines of 113⅔ cycles each
+    assert_eq!(cycles_until_irq(&mut irq), 341);
+
+    // Disabled after acknowledging
+    irq.acknowledge();
+    for _ in 0..1000 {
+      irq.cycle();
+    }
+    assert!(!irq.irq());
+  }
+}
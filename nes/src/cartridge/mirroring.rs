@@ -1,5 +1,77 @@
-#[derive(PartialEq, Debug)]
+/// How the 4 logical name tables ($2000, $2400, $2800 and $2C00) are
+/// mapped onto the 2KB of name table RAM in the console.
+#[derive(PartialEq, Debug, Clone, Copy)]
 pub enum Mirroring {
+  /// $2000 = $2400 and $2800 = $2C00, used for vertical scrolling
   Horizontal,
+  /// $2000 = $2800 and $2400 = $2C00, used for horizontal scrolling
   Vertical,
+  /// All 4 name tables are the first 1KB of name table RAM
+  SingleScreenLower,
+  /// All 4 name tables are the second 1KB of name table RAM
+  SingleScreenUpper,
+  /// Each name table is the 1KB of name table RAM given (0 or 1), for
+  /// mappers which choose them individually
+  Custom([u8; 4]),
+}
+
+impl Mirroring {
+  /// Map a name table address ($2000-$3EFF) to an address in the first
+  /// 2KB of name table space ($2000-$27FF).
+  pub fn nametable_addr(self, addr: u16) -> u16 {
+    let addr = (addr - 0x2000) % 0x1000;
+    let table = addr / 0x0400;
+    let offset = addr % 0x0400;
+
+    let physical_table = match self {
+      Mirroring::Horizontal => table / 2,
+      Mirroring::Vertical => table % 2,
+      Mirroring::SingleScreenLower => 0,
+      Mirroring::SingleScreenUpper => 1,
+      Mirroring::Custom(tables) => u16::from(tables[usize::from(table)] & 1),
+    };
+    0x2000 + physical_table * 0x0400 + offset
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn horizontal_nametable_addr() {
+    assert_eq!(Mirroring::Horizontal.nametable_addr(0x2012), 0x2012);
+    assert_eq!(Mirroring::Horizontal.nametable_addr(0x2412), 0x2012);
+    assert_eq!(Mirroring::Horizontal.nametable_addr(0x2812), 0x2412);
+    assert_eq!(Mirroring::Horizontal.nametable_addr(0x2C12), 0x2412);
+  }
+
+  #[test]
+  fn vertical_nametable_addr() {
+    assert_eq!(Mirroring::Vertical.nametable_addr(0x2012), 0x2012);
+    assert_eq!(Mirroring::Vertical.nametable_addr(0x2412), 0x2412);
+    assert_eq!(Mirroring::Vertical.nametable_addr(0x2812), 0x2012);
+    assert_eq!(Mirroring::Vertical.nametable_addr(0x2C12), 0x2412);
+  }
+
+  #[test]
+  fn single_screen_nametable_addr() {
+    assert_eq!(Mirroring::SingleScreenLower.nametable_addr(0x2C12), 0x2012);
+    assert_eq!(Mirroring::SingleScreenUpper.nametable_addr(0x2012), 0x2412);
+    assert_eq!(Mirroring::SingleScreenUpper.nametable_addr(0x2812), 0x2412);
+  }
+
+  #[test]
+  fn custom_nametable_addr() {
+    let mirroring = Mirroring::Custom([1, 0, 0, 1]);
+    assert_eq!(mirroring.nametable_addr(0x2012), 0x2412);
+    assert_eq!(mirroring.nametable_addr(0x2412), 0x2012);
+    assert_eq!(mirroring.nametable_addr(0x2812), 0x2012);
+    assert_eq!(mirroring.nametable_addr(0x2C12), 0x2412);
+  }
+
+  #[test]
+  fn nametable_addr_wraps_mirrors() {
+    assert_eq!(Mirroring::Vertical.nametable_addr(0x3412), 0x2412);
+  }
 }
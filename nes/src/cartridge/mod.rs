@@ -10,10 +10,14 @@
 mod ines;
 mod mapper;
 mod mappers;
-mod mirroring;
+pub mod mirroring;
+pub mod nsf;
 
+use apu::Region;
 use cartridge::mapper::Mapper;
+use cartridge::mappers::nsf::NsfMapper;
 use cartridge::mirroring::Mirroring;
+use cartridge::nsf::Nsf;
 
 pub struct Cartridge {
   pub mirroring: Mirroring,
@@ -22,6 +26,52 @@ pub struct Cartridge {
 }
 
 impl Cartridge {
+  /// Read from the pattern tables ($0000-$1FFF in the PPU address space)
+  pub fn read_chr(&mut self, addr: u16) -> u8 {
+    self.mapper.read_chr(addr)
+  }
+
+  /// Write to the pattern tables, if the cartridge has CHR-RAM
+  pub fn write_chr(&mut self, addr: u16, value: u8) {
+    self.mapper.write_chr(addr, value)
+  }
+
+  /// Whether reading the pattern tables can switch CHR banks
+  pub fn chr_reads_switch_banks(&self) -> bool {
+    self.mapper.chr_reads_switch_banks()
+  }
+
+  /// Read from the name tables, if the mapper has put CHR-ROM there
+  /// rather than the console's name table RAM
+  pub fn read_nametable(&mut self, addr: u16) -> Option<u8> {
+    self.mapper.read_nametable(addr)
+  }
+
+  /// Write to the name tables, returning whether the mapper took the write
+  pub fn write_nametable(&mut self, addr: u16, value: u8) -> bool {
+    self.mapper.write_nametable(addr, value)
+  }
+
+  /// Tell the mapper about an address the PPU put on its address bus
+  pub fn ppu_address(&mut self, addr: u16) {
+    self.mapper.ppu_address(addr)
+  }
+
+  /// The current name table mirroring, which some mappers can switch
+  pub fn mirroring(&self) -> Mirroring {
+    self.mapper.mirroring().unwrap_or(self.mirroring)
+  }
+
+  /// A cartridge which plays a song (counting from 0) from an NSF, see
+  /// `NsfMapper`
+  pub fn from_nsf(nsf: &Nsf, song: u8, region: Region) -> Self {
+    Cartridge {
+      mirroring: Mirroring::Horizontal,
+      battery_ram_present: false,
+      mapper: Box::new(NsfMapper::new(nsf, song, region)),
+    }
+  }
+
   fn try_from_ines(image: ines::Image) -> Result<Self, ParseError> {
     println!("iNES Image: {:?}", image);
 
@@ -39,6 +89,69 @@ impl Cartridge {
   }
 }
 
+/// An NROM cartridge with CHR-RAM, for testing anything that needs a
+/// cartridge plugged in
+#[cfg(test)]
+pub fn test_cartridge(mirroring: Mirroring) -> Cartridge {
+  use cartridge::mappers::nrom::NROM;
+
+  Cartridge {
+    mirroring,
+    battery_ram_present: false,
+    mapper: Box::new(NROM::new(vec![0; 0x4000], vec![], 1)),
+  }
+}
+
+/// A cartridge which counts the rises of A12 on the PPU's address bus
+#[cfg(test)]
+pub fn a12_counting_cartridge() -> (Cartridge, ::std::rc::Rc<::std::cell::Cell<u32>>) {
+  use memory::{ReadAddr, WriteAddr};
+  use std::cell::Cell;
+  use std::rc::Rc;
+
+  struct A12Counter {
+    a12: bool,
+    rises: Rc<Cell<u32>>,
+  }
+
+  impl Mapper for A12Counter {
+    fn read_chr(&mut self, _addr: u16) -> u8 {
+      0
+    }
+
+    fn ppu_address(&mut self, addr: u16) {
+      let a12 = addr & 0x1000 != 0;
+      if a12 && !self.a12 {
+        self.rises.set(self.rises.get() + 1);
+      }
+      self.a12 = a12;
+    }
+  }
+
+  impl ReadAddr for A12Counter {
+    fn read_addr(&mut self, _addr: u16) -> u8 {
+      0
+    }
+  }
+
+  impl WriteAddr for A12Counter {
+    fn write_addr(&mut self, _addr: u16, _value: u8) -> u8 {
+      0
+    }
+  }
+
+  let rises = Rc::new(Cell::new(0));
+  let cartridge = Cartridge {
+    mirroring: Mirroring::Vertical,
+    battery_ram_present: false,
+    mapper: Box::new(A12Counter {
+      a12: false,
+      rises: rises.clone(),
+    }),
+  };
+  (cartridge, rises)
+}
+
 #[derive(PartialEq, Debug)]
 struct UnknownFormat {}
 
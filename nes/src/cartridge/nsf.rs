@@ -0,0 +1,488 @@
+//! NSF and NSFe music files
+//!
+//! An NSF is a rip of just the music code and data from a game, with a
+//! header giving the addresses of an INIT routine (called once with the
+//! song number) and a PLAY routine (called at a fixed rate, usually 60Hz).
+//! [Read more here][NSF].
+//!
+//! Offset | Length | Data
+//! -------|--------|--------------------------------------------------
+//! $00    | 5      | Constant value: 'NESM' 0x1A
+//! $05    | 1      | Version
+//! $06    | 1      | Number of songs
+//! $07    | 1      | Starting song (from 1)
+//! $08    | 2      | Load address of the data ($8000-$FFFF)
+//! $0A    | 2      | Init address
+//! $0C    | 2      | Play address
+//! $0E    | 32     | Title
+//! $2E    | 32     | Artist
+//! $4E    | 32     | Copyright
+//! $6E    | 2      | NTSC play period, in microseconds
+//! $70    | 8      | Initial 4KB banks, all 0 if not bankswitched
+//! $78    | 2      | PAL play period, in microseconds
+//! $7A    | 1      | Region: bit 0 PAL, bit 1 both NTSC and PAL
+//! $7B    | 1      | Expansion audio chips
+//! $80    |        | Data
+//!
+//! An [NSFe][NSFe] holds the same information in chunks (each a 4 byte
+//! length, a 4 byte ID and the chunk's data) after 'NSFE', along with
+//! optional track names and lengths.
+//!
+//! [NSF]: https://wiki.nesdev.com/w/index.php/NSF
+//! [NSFe]: https://wiki.nesdev.com/w/index.php/NSFe
+
+use apu::Region;
+
+const NSF_HEADER: [u8; 5] = [0x4e, 0x45, 0x53, 0x4d, 0x1a];
+const NSFE_HEADER: [u8; 4] = [0x4e, 0x53, 0x46, 0x45];
+
+const LEN_HEADER: usize = 0x80;
+
+/// Default play periods (in microseconds), close to the frame rates
+const NTSC_PERIOD: u16 = 16_639;
+const PAL_PERIOD: u16 = 19_997;
+
+pub const CHIP_VRC6: u8 = 0x01;
+pub const CHIP_VRC7: u8 = 0x02;
+pub const CHIP_FDS: u8 = 0x04;
+pub const CHIP_MMC5: u8 = 0x08;
+pub const CHIP_N163: u8 = 0x10;
+pub const CHIP_5B: u8 = 0x20;
+
+/// The chips which are played (see `cartridge::mappers::nsf`)
+const PLAYED_CHIPS: u8 = CHIP_VRC6 | CHIP_VRC7 | CHIP_N163 | CHIP_5B;
+
+const CHIP_NAMES: [(u8, &str); 6] = [
+  (CHIP_VRC6, "VRC6"),
+  (CHIP_VRC7, "VRC7"),
+  (CHIP_FDS, "FDS"),
+  (CHIP_MMC5, "MMC5"),
+  (CHIP_N163, "Namco 163"),
+  (CHIP_5B, "Sunsoft 5B"),
+];
+
+#[derive(PartialEq, Debug)]
+pub enum ParseError {
+  UnknownFormat,
+  /// The file ended before the end of a header or chunk
+  Truncated,
+  /// An NSFe without this required chunk
+  MissingChunk(&'static str),
+  /// An NSFe chunk needed to play the file which isn't understood
+  UnknownChunk(String),
+}
+
+#[derive(Debug, Default)]
+pub struct Nsf {
+  pub title: String,
+  pub artist: String,
+  pub copyright: String,
+
+  pub songs: u8,
+  /// The song to play first, counting from 0
+  pub starting_song: u8,
+  /// Names of each song, if known
+  pub song_names: Vec<String>,
+  /// Lengths of each song in milliseconds, if known
+  pub song_lengths: Vec<Option<u32>>,
+
+  pub load_address: u16,
+  pub init_address: u16,
+  pub play_address: u16,
+
+  /// Initial banks for $8000-$FFFF, if the data is bankswitched
+  pub banks: Option<[u8; 8]>,
+
+  /// Microseconds between calls to PLAY
+  pub ntsc_period: u16,
+  pub pal_period: u16,
+
+  /// The region the music was written for
+  pub region: Region,
+
+  /// Expansion audio chips used, see the `CHIP_` flags
+  pub chips: u8,
+
+  pub data: Vec<u8>,
+}
+
+impl Nsf {
+  /// Microseconds between calls to PLAY in a region
+  pub fn play_period(&self, region: Region) -> u16 {
+    match region {
+      Region::Ntsc => self.ntsc_period,
+      Region::Pal => self.pal_period,
+    }
+  }
+
+  /// Names of the expansion audio chips used
+  pub fn chip_names(&self) -> Vec<&'static str> {
+    chip_names(self.chips)
+  }
+
+  /// Names of the expansion audio chips used which aren't played
+  pub fn unplayed_chip_names(&self) -> Vec<&'static str> {
+    chip_names(self.chips & !PLAYED_CHIPS)
+  }
+
+  /// The name of a song (from 0) if known, or its number otherwise
+  pub fn song_name(&self, song: u8) -> String {
+    match self.song_names.get(usize::from(song)) {
+      Some(name) if !name.is_empty() => name.clone(),
+      _ => format!("Song {}", song + 1),
+    }
+  }
+}
+
+pub fn check_format(data: &[u8]) -> bool {
+  data.starts_with(&NSF_HEADER) || data.starts_with(&NSFE_HEADER)
+}
+
+pub fn parse(data: &[u8]) -> Result<Nsf, ParseError> {
+  if data.starts_with(&NSF_HEADER) {
+    parse_nsf(data)
+  } else if data.starts_with(&NSFE_HEADER) {
+    parse_nsfe(data)
+  } else {
+    Err(ParseError::UnknownFormat)
+  }
+}
+
+fn parse_nsf(data: &[u8]) -> Result<Nsf, ParseError> {
+  if data.len() < LEN_HEADER {
+    return Err(ParseError::Truncated);
+  }
+
+  let banks = read_banks(&data[0x70..0x78]);
+  Ok(Nsf {
+    title: read_string(&data[0x0E..0x2E]),
+    artist: read_string(&data[0x2E..0x4E]),
+    copyright: read_string(&data[0x4E..0x6E]),
+    songs: data[0x06],
+    starting_song: data[0x07].saturating_sub(1),
+    load_address: read_u16(data, 0x08),
+    init_address: read_u16(data, 0x0A),
+    play_address: read_u16(data, 0x0C),
+    banks,
+    ntsc_period: read_period(data, 0x6E, NTSC_PERIOD),
+    pal_period: read_period(data, 0x78, PAL_PERIOD),
+    region: read_region(data[0x7A]),
+    chips: data[0x7B],
+    data: data[LEN_HEADER..].to_vec(),
+    ..Nsf::default()
+  })
+}
+
+fn parse_nsfe(data: &[u8]) -> Result<Nsf, ParseError> {
+  let mut nsf = Nsf {
+    ntsc_period: NTSC_PERIOD,
+    pal_period: PAL_PERIOD,
+    ..Nsf::default()
+  };
+  let mut has_info = false;
+  let mut has_data = false;
+
+  let mut offset = NSFE_HEADER.len();
+  loop {
+    if data.len() < offset + 8 {
+      return Err(ParseError::Truncated);
+    }
+    let length = read_u32(data, offset) as usize;
+    let id = &data[offset + 4..offset + 8];
+    offset += 8;
+    if data.len() < offset + length {
+      return Err(ParseError::Truncated);
+    }
+    let chunk = &data[offset..offset + length];
+    offset += length;
+
+    match id {
+      b"INFO" => {
+        if chunk.len() < 9 {
+          return Err(ParseError::Truncated);
+        }
+        nsf.load_address = read_u16(chunk, 0);
+        nsf.init_address = read_u16(chunk, 2);
+        nsf.play_address = read_u16(chunk, 4);
+        nsf.region = read_region(chunk[6]);
+        nsf.chips = chunk[7];
+        nsf.songs = chunk[8];
+        nsf.starting_song = chunk.get(9).cloned().unwrap_or(0);
+        has_info = true;
+      }
+      b"DATA" => {
+        nsf.data = chunk.to_vec();
+        has_data = true;
+      }
+      b"BANK" => {
+        let mut banks = [0; 8];
+        banks[..chunk.len().min(8)].copy_from_slice(&chunk[..chunk.len().min(8)]);
+        nsf.banks = Some(banks);
+      }
+      b"RATE" => {
+        if chunk.len() >= 2 {
+          nsf.ntsc_period = read_period(chunk, 0, NTSC_PERIOD);
+        }
+        if chunk.len() >= 4 {
+          nsf.pal_period = read_period(chunk, 2, PAL_PERIOD);
+        }
+      }
+      b"auth" => {
+        let mut strings = chunk.split(|&byte| byte == 0).map(read_string);
+        let game = strings.next().unwrap_or_default();
+        nsf.artist = strings.next().unwrap_or_default();
+        nsf.copyright = strings.next().unwrap_or_default();
+        nsf.title = game;
+      }
+      b"tlbl" => {
+        nsf.song_names = chunk.split(|&byte| byte == 0).map(read_string).collect();
+      }
+      b"time" => {
+        nsf.song_lengths = chunk
+          .chunks(4)
+          .filter(|time| time.len() == 4)
+          .map(|time| {
+            let time = read_u32(time, 0) as i32;
+            if time < 0 {
+              None
+            } else {
+              Some(time as u32)
+            }
+          })
+          .collect();
+      }
+      b"NEND" => break,
+      // Chunks with an upper case first letter must be understood
+      _ if id[0].is_ascii_uppercase() => {
+        return Err(ParseError::UnknownChunk(read_string(id)));
+      }
+      _ => {}
+    }
+  }
+
+  if !has_info {
+    return Err(ParseError::MissingChunk("INFO"));
+  }
+  if !has_data {
+    return Err(ParseError::MissingChunk("DATA"));
+  }
+  Ok(nsf)
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+  u16::from(data[offset]) | u16::from(data[offset + 1]) << 8
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+  u32::from(read_u16(data, offset)) | u32::from(read_u16(data, offset + 2)) << 16
+}
+
+fn chip_names(chips: u8) -> Vec<&'static str> {
+  CHIP_NAMES
+    .iter()
+    .filter(|&&(chip, _)| chips & chip != 0)
+    .map(|&(_, name)| name)
+    .collect()
+}
+
+/// A play period, or the default if it's missing
+fn read_period(data: &[u8], offset: usize, default: u16) -> u16 {
+  match read_u16(data, offset) {
+    0 => default,
+    period => period,
+  }
+}
+
+/// A null terminated (or padded) string
+fn read_string(data: &[u8]) -> String {
+  let end = data
+    .iter()
+    .position(|&byte| byte == 0)
+    .unwrap_or(data.len());
+  String::from_utf8_lossy(&data[..end]).into_owned()
+}
+
+fn read_banks(data: &[u8]) -> Option<[u8; 8]> {
+  if data.iter().all(|&bank| bank == 0) {
+    return None;
+  }
+  let mut banks = [0; 8];
+  banks.copy_from_slice(data);
+  Some(banks)
+}
+
+fn read_region(flags: u8) -> Region {
+  // Files for both regions are played as NTSC
+  if flags & 0b11 == 0b01 {
+    Region::Pal
+  } else {
+    Region::Ntsc
+  }
+}
+
+/// Build an NSF file, for testing
+#[cfg(test)]
+pub fn test_nsf(load: u16, init: u16, play: u16, data: &[u8]) -> Vec<u8> {
+  let mut nsf = vec![0; LEN_HEADER];
+  nsf[..5].copy_from_slice(&NSF_HEADER);
+  nsf[0x05] = 1;
+  nsf[0x06] = 3;
+  nsf[0x07] = 2;
+  nsf[0x08..0x0A].copy_from_slice(&[load as u8, (load >> 8) as u8]);
+  nsf[0x0A..0x0C].copy_from_slice(&[init as u8, (init >> 8) as u8]);
+  nsf[0x0C..0x0E].copy_from_slice(&[play as u8, (play >> 8) as u8]);
+  nsf[0x0E..0x13].copy_from_slice(b"Title");
+  nsf[0x6E..0x70].copy_from_slice(&[0x1A, 0x41]);
+  nsf.extend_from_slice(data);
+  nsf
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn chunk(id: &[u8], data: &[u8]) -> Vec<u8> {
+    let length = data.len() as u32;
+    let mut chunk = vec![
+      length as u8,
+      (length >> 8) as u8,
+      (length >> 16) as u8,
+      (length >> 24) as u8,
+    ];
+    chunk.extend_from_slice(id);
+    chunk.extend_from_slice(data);
+    chunk
+  }
+
+  fn nsfe(chunks: &[Vec<u8>]) -> Vec<u8> {
+    let mut data = NSFE_HEADER.to_vec();
+    for chunk in chunks {
+      data.extend_from_slice(chunk);
+    }
+    data
+  }
+
+  fn info() -> Vec<u8> {
+    chunk(
+      b"INFO",
+      &[0x00, 0x80, 0x03, 0x80, 0x06, 0x80, 0b10, CHIP_VRC6, 4, 1],
+    )
+  }
+
+  #[test]
+  fn parses_nsf_header() {
+    let mut data = test_nsf(0x8000, 0x8003, 0x8006, &[0xEA; 16]);
+    data[0x70] = 1;
+    data[0x7A] = 0b01;
+    data[0x7B] = CHIP_VRC6 | CHIP_N163;
+
+    assert!(check_format(&data));
+    let nsf = parse(&data).unwrap();
+    assert_eq!(nsf.title, "Title");
+    assert_eq!(nsf.songs, 3);
+    assert_eq!(nsf.starting_song, 1);
+    assert_eq!(nsf.load_address, 0x8000);
+    assert_eq!(nsf.init_address, 0x8003);
+    assert_eq!(nsf.play_address, 0x8006);
+    assert_eq!(nsf.ntsc_period, 16666);
+    assert_eq!(nsf.pal_period, PAL_PERIOD);
+    assert_eq!(nsf.banks, Some([1, 0, 0, 0, 0, 0, 0, 0]));
+    assert_eq!(nsf.region, Region::Pal);
+    assert_eq!(nsf.chip_names(), ["VRC6", "Namco 163"]);
+    assert!(nsf.unplayed_chip_names().is_empty());
+    assert_eq!(nsf.data.len(), 16);
+  }
+
+  #[test]
+  fn unbanked_nsf() {
+    let nsf = parse(&test_nsf(0x8000, 0x8003, 0x8006, &[])).unwrap();
+    assert_eq!(nsf.banks, None);
+    assert_eq!(nsf.region, Region::Ntsc);
+  }
+
+  #[test]
+  fn unplayed_chips() {
+    let mut data = test_nsf(0x8000, 0x8003, 0x8006, &[]);
+    data[0x7B] = CHIP_VRC7 | CHIP_FDS | CHIP_MMC5;
+    let nsf = parse(&data).unwrap();
+    assert_eq!(nsf.unplayed_chip_names(), ["FDS", "MMC5"]);
+  }
+
+  #[test]
+  fn truncated_nsf() {
+    let data = test_nsf(0x8000, 0x8003, 0x8006, &[]);
+    assert_eq!(parse(&data[..0x40]).unwrap_err(), ParseError::Truncated);
+  }
+
+  #[test]
+  fn parses_nsfe_chunks() {
+    let data = nsfe(&[
+      info(),
+      chunk(b"DATA", &[0xEA; 8]),
+      chunk(b"BANK", &[0, 1]),
+      chunk(b"auth", b"Game\0Artist\0Copyright\0Ripper\0"),
+      chunk(b"tlbl", b"Intro\0\0Ending\0"),
+      chunk(b"time", &[0xE8, 0x03, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF]),
+      chunk(b"xtra", &[1, 2, 3]),
+      chunk(b"NEND", &[]),
+    ]);
+
+    assert!(check_format(&data));
+    let nsf = parse(&data).unwrap();
+    assert_eq!(nsf.title, "Game");
+    assert_eq!(nsf.artist, "Artist");
+    assert_eq!(nsf.copyright, "Copyright");
+    assert_eq!(nsf.songs, 4);
+    assert_eq!(nsf.starting_song, 1);
+    assert_eq!(nsf.play_address, 0x8006);
+    assert_eq!(nsf.region, Region::Ntsc);
+    assert_eq!(nsf.chips, CHIP_VRC6);
+    assert!(nsf.unplayed_chip_names().is_empty());
+    assert_eq!(nsf.ntsc_period, NTSC_PERIOD);
+    assert_eq!(nsf.banks, Some([0, 1, 0, 0, 0, 0, 0, 0]));
+    assert_eq!(nsf.data, [0xEA; 8]);
+    assert_eq!(nsf.song_lengths, [Some(1000), None]);
+    assert_eq!(nsf.song_name(0), "Intro");
+    assert_eq!(nsf.song_name(1), "Song 2");
+    assert_eq!(nsf.song_name(2), "Ending");
+    assert_eq!(nsf.song_name(3), "Song 4");
+  }
+
+  #[test]
+  fn nsfe_rate_of_zero_is_default() {
+    let data = nsfe(&[
+      info(),
+      chunk(b"DATA", &[]),
+      chunk(b"RATE", &[0x00, 0x00, 0x10, 0x27]),
+      chunk(b"NEND", &[]),
+    ]);
+    let nsf = parse(&data).unwrap();
+    assert_eq!(nsf.ntsc_period, NTSC_PERIOD);
+    assert_eq!(nsf.pal_period, 10000);
+
+    let data = nsfe(&[
+      info(),
+      chunk(b"DATA", &[]),
+      chunk(b"RATE", &[0x10, 0x27, 0x00, 0x00]),
+      chunk(b"NEND", &[]),
+    ]);
+    let nsf = parse(&data).unwrap();
+    assert_eq!(nsf.ntsc_period, 10000);
+    assert_eq!(nsf.pal_period, PAL_PERIOD);
+  }
+
+  #[test]
+  fn nsfe_requires_chunks() {
+    let data = nsfe(&[info(), chunk(b"NEND", &[])]);
+    assert_eq!(parse(&data).unwrap_err(), ParseError::MissingChunk("DATA"));
+
+    let data = nsfe(&[info(), chunk(b"DATA", &[]), chunk(b"NEWS", &[])]);
+    assert_eq!(
+      parse(&data).unwrap_err(),
+      ParseError::UnknownChunk("NEWS".into())
+    );
+
+    let data = nsfe(&[info(), chunk(b"DATA", &[])]);
+    assert_eq!(parse(&data).unwrap_err(), ParseError::Truncated);
+  }
+}
@@ -10,6 +10,9 @@ pub const MASTER_FREQUENCY: u32 = 21_477_272;
 /// Used to calculate the frequency of the CPU = `MASTER_FREQUENCY / CPU_PERIOD`.
 pub const CPU_PERIOD: u8 = 12;
 
+/// NTSC CPU frequency (per second)
+pub const CPU_FREQUENCY: u32 = MASTER_FREQUENCY / CPU_PERIOD as u32;
+
 /// NTSC PPU divisor
 ///
 /// Used to calculate the frequency of the PPU = `MASTER_FREQUENCY / PPU_PERIOD`.
@@ -26,6 +29,7 @@ const NANOS_PER_BATCH: u32 = 1_000_000_000 / BATCHES_PER_SECOND;
 pub struct Clock {
   batch: u32,
   next_batch: Instant,
+  throttled: bool,
 }
 
 impl Default for Clock {
@@ -33,6 +37,7 @@ impl Default for Clock {
     Clock {
       batch: 0,
       next_batch: Instant::now().add(Duration::new(0, NANOS_PER_BATCH)),
+      throttled: true,
     }
   }
 }
@@ -42,7 +47,17 @@ impl Clock {
     Clock::default()
   }
 
+  /// Whether to sleep to keep to real time (the default), or to run as
+  /// fast as possible
+  pub fn set_throttled(&mut self, throttled: bool) {
+    self.throttled = throttled;
+    self.next_batch = Instant::now().add(Duration::new(0, NANOS_PER_BATCH));
+  }
+
   pub fn cycle(&mut self) {
+    if !self.throttled {
+      return;
+    }
     if self.batch != CYCLE_BATCH_SIZE {
       self.batch += 1;
       return;
@@ -8,6 +8,7 @@ use bus::Bus;
 use controller::Controller;
 use cpu;
 use memory::block::BlockMemory;
+use ppu::RenderMode;
 
 pub struct Console<'a, C1: 'a + Controller, C2: 'a + Controller, A1: 'a + Apu> {
   clock: Clock,
@@ -36,6 +37,30 @@ impl<'a, C1: 'a + Controller, C2: 'a + Controller, A1: 'a + Apu> Console<'a, C1,
     }
   }
 
+  /// Choose between the accurate and the faster scanline PPU renderer
+  pub fn set_render_mode(&mut self, mode: RenderMode) {
+    self.bus.ppu.set_render_mode(mode);
+  }
+
+  /// Run in real time (the default), or as fast as possible, eg, when
+  /// rendering audio to a file
+  pub fn set_throttled(&mut self, throttled: bool) {
+    self.clock.set_throttled(throttled);
+  }
+
+  /// Raise an NMI, as the NSF player does to call PLAY
+  pub fn nmi(&mut self) {
+    self.cpu.nmi();
+  }
+
+  /// Read from the CPU address space, for tests which check results the
+  /// program left in memory
+  #[cfg(test)]
+  pub fn peek(&mut self, addr: u16) -> u8 {
+    use memory::ReadAddr;
+    self.bus.read_addr(addr)
+  }
+
   // Power on the console.
   pub fn reset(&mut self) {
     self.cpu.reset(&mut self.bus);
@@ -49,11 +74,23 @@ impl<'a, C1: 'a + Controller, C2: 'a + Controller, A1: 'a + Apu> Console<'a, C1,
     if self.cpu_interval == clock::CPU_PERIOD {
       self.cpu_interval = 0;
       self.cpu.cycle(&mut self.bus);
+      self.bus.cycle_cartridge();
+      self.bus.cycle_apu();
+      self.cpu.set_irq(self.bus.irq());
+
+      let stall_cycles = self.bus.take_stall_cycles();
+      if stall_cycles > 0 {
+        self.cpu.stall(stall_cycles);
+      }
     }
 
     if self.ppu_interval == clock::PPU_PERIOD {
       self.ppu_interval = 0;
-      self.bus.ppu.cycle();
+      self.bus.cycle_ppu();
+
+      if self.bus.ppu.take_nmi() {
+        self.cpu.nmi();
+      }
     }
   }
 }
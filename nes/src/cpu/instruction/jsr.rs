@@ -9,21 +9,14 @@ use memory::WriteAddr;
 ///
 /// Flags affected: None
 #[inline(always)]
-fn jsr(core: &mut Core, memory: &mut WriteAddr, _address: u16) {
-  let pc_plus_1: u16 = core.reg.pc + 1;
-  let pc_plus_2: u16 = core.reg.pc + 2;
+fn jsr(core: &mut Core, memory: &mut WriteAddr, address: u16) {
+  // The PC is past the operand, push the address of its last byte (RTS
+  // adds one when returning)
+  let return_addr = core.reg.pc - 1;
+  core.push_stack(memory, (return_addr >> 8) as u8);
+  core.push_stack(memory, return_addr as u8);
 
-  // push PC + 2
-  let pc_plus_2_hi: u8 = (pc_plus_2 >> 8) as u8;
-  let pc_plus_2_lo: u8 = pc_plus_2 as u8;
-  core.push_stack(memory, pc_plus_2_hi);
-  core.push_stack(memory, pc_plus_2_lo);
-
-  // (PC+1) -> PCL
-  // (PC+2) -> PCH
-  let new_pc_lo = u16::from(memory.read_addr(pc_plus_1));
-  let new_pc_hi = u16::from(memory.read_addr(pc_plus_2));
-  core.reg.pc = new_pc_lo | (new_pc_hi << 8);
+  core.reg.pc = address;
 }
 
 /// Jump to new location saving return address
@@ -47,13 +40,11 @@ mod tests {
     let mut core = Core::new(Registers::empty());
     let mut memory = BlockMemory::with_size(0x0300);
     core.reg.stack = 0xff; // init stack
-    core.reg.pc = 0x0200;
-    memory.write_addr(0x0201, 0x01); // PC + 1
-    memory.write_addr(0x0202, 0xff); // PC + 2
-    jsr(&mut core, &mut memory, 0x0200);
+    core.reg.pc = 0x0203; // after the operand
+    jsr(&mut core, &mut memory, 0xff01);
     assert_eq!(core.reg.pc, 0xff01);
-    assert_eq!(core.pop_stack(&mut memory), 0x02); // PC + 2 (hi)
-    assert_eq!(core.pop_stack(&mut memory), 0x02); // PC + 2 (lo)
+    assert_eq!(core.pop_stack(&mut memory), 0x02); // PC - 1 (lo)
+    assert_eq!(core.pop_stack(&mut memory), 0x02); // PC - 1 (hi)
   }
 
   #[test]
@@ -1,4 +1,8 @@
-use cpu::{instruction::Instruction, pipeline::Pipeline, register::Registers};
+use cpu::{
+  instruction::Instruction,
+  pipeline::Pipeline,
+  register::{Registers, StatusFlags},
+};
 use memory::{ReadAddr, WriteAddr};
 use std::{fmt, u8};
 
@@ -9,9 +13,31 @@ mod register;
 
 const PAGE_SIZE: u16 = 256;
 
+/// Address of the non-maskable interrupt vector
+const NMI_VECTOR: u16 = 0xFFFA;
+
+/// Address of the interrupt request (and BRK) vector
+const IRQ_VECTOR: u16 = 0xFFFE;
+
+/// Number of cycles taken to jump to an interrupt handler
+const INTERRUPT_CYCLES: usize = 7;
+
 pub struct Core {
   reg: Registers,
   pipeline: Pipeline,
+
+  /// Set when an NMI has been signalled, the interrupt is handled once the
+  /// current instruction completes
+  nmi_pending: bool,
+
+  /// The level of the IRQ line, an interrupt is handled after each
+  /// instruction for as long as the line is asserted and interrupts aren't
+  /// disabled
+  irq_line: bool,
+
+  /// Cycles remaining where the CPU is halted (eg, while jumping to an
+  /// interrupt handler or during DMA)
+  stall_cycles: usize,
 }
 
 impl Default for Core {
@@ -43,11 +69,48 @@ impl Core {
     Core {
       reg,
       pipeline: Pipeline::default(),
+      nmi_pending: false,
+      irq_line: false,
+      stall_cycles: 0,
     }
   }
 
+  /// Signal a non-maskable interrupt (eg, from the PPU at the start of
+  /// vertical blanking)
+  pub fn nmi(&mut self) {
+    self.nmi_pending = true;
+  }
+
+  /// Assert or release the IRQ line (eg, from the APU or a mapper)
+  pub fn set_irq(&mut self, asserted: bool) {
+    self.irq_line = asserted;
+  }
+
+  /// Halt the CPU for a number of cycles, eg, while DMA uses the bus
+  pub fn stall(&mut self, cycles: usize) {
+    self.stall_cycles += cycles;
+  }
+
   /// Execute a Core
   pub fn cycle<T: ReadAddr + WriteAddr>(&mut self, memory: &mut T) {
+    if self.stall_cycles > 0 {
+      self.stall_cycles -= 1;
+      return;
+    }
+
+    if self.pipeline.is_empty() && self.nmi_pending {
+      self.nmi_pending = false;
+      self.interrupt(memory, NMI_VECTOR);
+      self.stall_cycles = INTERRUPT_CYCLES - 1;
+      return;
+    }
+
+    if self.pipeline.is_empty() && self.irq_line && !self.reg.status.contains(StatusFlags::I_FLAG) {
+      self.interrupt(memory, IRQ_VECTOR);
+      self.stall_cycles = INTERRUPT_CYCLES - 1;
+      return;
+    }
+
     if self.pipeline.is_empty() {
       let instr: Instruction = memory.read_addr(self.reg.pc).into();
       self
@@ -79,6 +142,22 @@ impl Core {
     memory.read_addr(self.get_stack_address())
   }
 
+  /// Push the program counter and status to the stack, then jump to the
+  /// handler at the given vector. Unlike BRK, the break flag is clear in the
+  /// pushed status.
+  fn interrupt<T: ReadAddr + WriteAddr>(&mut self, memory: &mut T, vector: u16) {
+    let pc = self.reg.pc;
+    self.push_stack(memory, (pc >> 8) as u8);
+    self.push_stack(memory, (pc & 0x00FF) as u8);
+    let status = (self.reg.status | StatusFlags::X_FLAG) & !StatusFlags::B_FLAG;
+    self.push_stack(memory, status.into());
+    self.reg.status |= StatusFlags::I_FLAG;
+
+    let pclo = memory.read_addr(vector);
+    let pchi = memory.read_addr(vector + 1);
+    self.reg.pc = u16::from(pchi) << 8 | u16::from(pclo);
+  }
+
   /// After a system initialization time of six clock cycles, the mask
   /// interrupt flag will be set and the microprocessor will load the
   /// program counter from the memory vector locations FFFC and
@@ -89,6 +168,7 @@ impl Core {
     let pchi: u8 = memory.read_addr(0xFFFD);
 
     self.reg.pc = u16::from(pchi) << 8 | u16::from(pclo);
+    self.reg.status |= StatusFlags::I_FLAG;
   }
 
   /// Immediate addressing allows the use of an 8 bit constant as the arguments to an address.
@@ -334,6 +414,28 @@ mod tests {
     assert_eq!(core.reg.pc, 0xbbaa);
   }
 
+  #[test]
+  fn reset_disables_interrupts() {
+    let mut memory = BlockMemory::with_size(0x10000);
+    memory.write_addr(0xFFFC, 0x00);
+    memory.write_addr(0xFFFD, 0x80);
+    memory.write_addr(0xFFFE, 0x34);
+    memory.write_addr(0xFFFF, 0x12);
+    // NOP
+    memory.write_addr(0x8000, 0xEA);
+    let mut core = Core::new(Registers::empty());
+    core.reg.stack = 0xFF;
+
+    core.reset(&mut memory);
+    assert!(core.reg.status.contains(StatusFlags::I_FLAG));
+
+    // A frame IRQ raised before the game's SEI is ignored
+    core.set_irq(true);
+    core.cycle(&mut memory);
+    core.cycle(&mut memory);
+    assert_eq!(core.reg.pc, 0x8001);
+  }
+
   #[test]
   fn immediate_address() {
     let mut memory = BlockMemory::with_bytes(vec![0x00, 0xff]);
@@ -399,6 +501,69 @@ mod tests {
     assert_eq!(core.reg.pc, 1);
   }
 
+  #[test]
+  fn nmi_jumps_to_vector() {
+    let mut memory = BlockMemory::with_size(0x10000);
+    memory.write_addr(0xFFFA, 0x34);
+    memory.write_addr(0xFFFB, 0x12);
+    let mut core = Core::new(Registers::empty());
+    core.reg.stack = 0xFF;
+    core.reg.pc = 0x8001;
+    core.reg.status = StatusFlags::C_FLAG | StatusFlags::B_FLAG;
+
+    core.nmi();
+    core.cycle(&mut memory);
+
+    assert_eq!(core.reg.pc, 0x1234);
+    assert!(core.reg.status.contains(StatusFlags::I_FLAG));
+    assert_eq!(
+      core.pop_stack(&mut memory),
+      (StatusFlags::C_FLAG | StatusFlags::X_FLAG).into()
+    );
+    assert_eq!(core.pop_stack(&mut memory), 0x01);
+    assert_eq!(core.pop_stack(&mut memory), 0x80);
+  }
+
+  #[test]
+  fn irq_waits_for_interrupts_to_be_enabled() {
+    let mut memory = BlockMemory::with_size(0x10000);
+    memory.write_addr(0xFFFE, 0x34);
+    memory.write_addr(0xFFFF, 0x12);
+    let mut core = Core::new(Registers::empty());
+    core.reg.stack = 0xFF;
+    core.reg.pc = 0x8000;
+    core.reg.status = StatusFlags::I_FLAG;
+
+    // NOP, while interrupts are disabled
+    memory.write_addr(0x8000, 0xEA);
+    core.set_irq(true);
+    core.cycle(&mut memory);
+    core.cycle(&mut memory);
+    assert_eq!(core.reg.pc, 0x8001);
+
+    core.reg.status = StatusFlags::empty();
+    core.cycle(&mut memory);
+    assert_eq!(core.reg.pc, 0x1234);
+    assert!(core.reg.status.contains(StatusFlags::I_FLAG));
+    assert_eq!(core.pop_stack(&mut memory), StatusFlags::X_FLAG.into());
+  }
+
+  #[test]
+  fn stall_skips_cycles() {
+    // LDA #$05
+    let mut memory = BlockMemory::with_bytes(vec![0xa9, 0x05]);
+    let mut core = Core::new(Registers::empty());
+    core.stall(2);
+
+    core.cycle(&mut memory);
+    core.cycle(&mut memory);
+    assert_eq!(core.reg.pc, 0);
+
+    core.cycle(&mut memory);
+    core.cycle(&mut memory);
+    assert_eq!(core.reg.acc, 0x05);
+  }
+
   #[test]
   fn relative_address() {
     let mut memory = BlockMemory::with_bytes(vec![0x00, 0x00, 0x12]);
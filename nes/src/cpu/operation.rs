@@ -174,9 +174,10 @@ impl Operation {
         func.call(core, memory, addr);
       }
 
-      Operation::Indirect(_) => {
-        // TODO: Fix this (and figure out where lo_addr comes from...)
-        unimplemented!();
+      Operation::Indirect(func) => {
+        let lo_addr = core.absolute_addr(memory);
+        let addr = core.indirect_addr(memory, lo_addr);
+        func.call(core, memory, addr);
       }
     }
   }
@@ -1,48 +1,347 @@
+//! # Audio Output
+//!
+//! The APU pushes its samples into an `AudioOutput`, in batches as it
+//! produces them, the same way the PPU pushes pixels into a `VideoOutput`.
+//! Where they go is up to the output:
+//!
+//! Output              | Samples go to
+//! --------------------|-----------------------------------------------
+//! `SampleProducer`    | A sample buffer, played by `NesAudioProcess`
+//! `NullAudioOutput`   | Nowhere, eg, when running headless
+//! `MemoryAudioOutput` | A `Vec` shared with its clones, eg, for tests
+//! `WavAudioOutput`    | A WAV file
+//!
+//! Only `NesAudioProcess` depends on SDL, as the audio device's callback,
+//! which is left out without the `sdl` feature.
+
+use io::sample_buffer::{self, SampleConsumer, SampleProducer};
+use io::wav::WavWriter;
+#[cfg(feature = "sdl")]
 use sdl2::audio::AudioCallback;
-use std::sync::mpsc::Receiver;
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// Receives the samples produced by the APU, from -1.0 to 1.0 at the
+/// sample rate the APU was created with
+pub trait AudioOutput {
+  /// Add a batch of samples following the previous ones
+  fn output_samples(&mut self, samples: &[f32]);
+}
+
+/// Writes the samples into a sample buffer. Any that don't fit are
+/// dropped, and counted by the buffer.
+impl AudioOutput for SampleProducer {
+  fn output_samples(&mut self, samples: &[f32]) {
+    self.push(samples);
+  }
+}
+
+/// An AudioOutput that discards the samples
+pub struct NullAudioOutput;
+
+impl AudioOutput for NullAudioOutput {
+  fn output_samples(&mut self, _samples: &[f32]) {}
+}
+
+/// An AudioOutput that keeps the samples in memory. Clones share the same
+/// samples, so one can be kept to read what the APU has output.
+#[derive(Clone, Default)]
+pub struct MemoryAudioOutput {
+  samples: Arc<Mutex<Vec<f32>>>,
+}
+
+impl MemoryAudioOutput {
+  pub fn new() -> Self {
+    MemoryAudioOutput::default()
+  }
+
+  /// A copy of the samples output so far
+  pub fn samples(&self) -> Vec<f32> {
+    self.samples.lock().unwrap().clone()
+  }
+
+  pub fn len(&self) -> usize {
+    self.samples.lock().unwrap().len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  /// Remove and return the samples output so far
+  pub fn take(&self) -> Vec<f32> {
+    self.samples.lock().unwrap().split_off(0)
+  }
+}
+
+impl AudioOutput for MemoryAudioOutput {
+  fn output_samples(&mut self, samples: &[f32]) {
+    self.samples.lock().unwrap().extend_from_slice(samples);
+  }
+}
+
+/// An AudioOutput that writes the samples to a WAV file. If writing
+/// fails, the error is kept (see `error`) and later samples are discarded.
+pub struct WavAudioOutput {
+  writer: Option<WavWriter<BufWriter<File>>>,
+  error: Option<io::Error>,
+}
+
+impl WavAudioOutput {
+  pub fn create<P: AsRef<Path>>(path: P, sample_rate: u32) -> io::Result<Self> {
+    Ok(WavAudioOutput {
+      writer: Some(WavWriter::create(path, sample_rate)?),
+      error: None,
+    })
+  }
+
+  /// The error that stopped the samples being written, if any
+  pub fn error(&self) -> Option<&io::Error> {
+    self.error.as_ref()
+  }
+
+  /// Update the WAV header, returning the error that stopped the samples
+  /// being written or that updating the header caused
+  pub fn finish(&mut self) -> io::Result<()> {
+    if let Some(error) = self.error.take() {
+      return Err(error);
+    }
+    match self.writer {
+      Some(ref mut writer) => writer.finish(),
+      None => Ok(()),
+    }
+  }
+}
+
+impl AudioOutput for WavAudioOutput {
+  fn output_samples(&mut self, samples: &[f32]) {
+    let result = match self.writer {
+      Some(ref mut writer) => writer.write_samples(samples),
+      None => return,
+    };
+    if let Err(error) = result {
+      self.writer = None;
+      self.error = Some(error);
+    }
+  }
+}
 
-use apu::channel;
-use apu::channel::ChannelState;
+/// Seconds of audio to keep buffered, enough to cover the audio device's
+/// callbacks and the APU's batches without adding noticeable latency
+const TARGET_LATENCY: f64 = 0.05;
 
+/// The most the playback rate is adjusted by to keep the buffer at its
+/// target, small enough that the change in pitch can't be heard
+const MAX_RATE_ADJUSTMENT: f64 = 0.005;
+
+/// A buffer for the samples played at the audio device's sample rate,
+/// with room for twice the target latency. A smaller rate than the
+/// device's would limit `NesAudioProcess` to a lower target.
+pub fn sample_buffer(sample_rate: u32) -> (SampleProducer, SampleConsumer) {
+  sample_buffer::channel((2.0 * TARGET_LATENCY * f64::from(sample_rate)) as usize)
+}
+
+/// Plays the samples written to a `sample_buffer` on an SDL audio device.
+///
+/// The emulator and the audio device run from different clocks, so the
+/// buffer would slowly fill up or drain. To avoid that, the samples are
+/// resampled at a rate adjusted very slightly up or down to keep the
+/// buffer near its target fill ([dynamic rate control][DRC]). If it does
+/// run dry, the last sample is held until the buffer fills again.
+///
+/// [DRC]: https://github.com/libretro/docs/blob/master/archive/ratecontrol.pdf
 pub struct NesAudioProcess {
-  sample: u64,
-  sample_rate: u32,
-  channels: channel::ApuChannelState,
-  delta_stream: Receiver<channel::ApuChannelDelta>,
+  samples: SampleConsumer,
+  target: usize,
+
+  /// Whether the buffer has filled to the target since starting or the
+  /// last underrun
+  playing: bool,
+
+  /// The last four samples read, interpolated between the middle two
+  history: [f32; 4],
+  /// Position between `history[1]` and `history[2]`, from 0.0 to 1.0
+  position: f64,
 }
 
+#[cfg(feature = "sdl")]
 impl AudioCallback for NesAudioProcess {
   type Channel = f32;
 
-  fn callback(&mut self, samples: &mut [Self::Channel]) {
-    self.apply_transforms();
-
-    for elem in samples.iter_mut() {
-      let config = channel::ChannelTuning {
-        sample: self.sample,
-        sample_rate: self.sample_rate,
-      };
-      *elem = self.channels.signal_at(&config);
-      self.sample += 1;
-    }
+  fn callback(&mut self, out: &mut [Self::Channel]) {
+    self.fill(out);
   }
 }
 
 impl NesAudioProcess {
-  pub fn new(delta_stream: Receiver<channel::ApuChannelDelta>, playback_freq: u32) -> Self {
+  pub fn new(samples: SampleConsumer, playback_freq: u32) -> Self {
+    let target = (TARGET_LATENCY * f64::from(playback_freq)) as usize;
     NesAudioProcess {
-      sample: 0,
-      channels: channel::ApuChannelState::default(),
-      sample_rate: playback_freq,
-      delta_stream: delta_stream,
+      target: target.min(samples.capacity() / 2),
+      samples,
+      playing: false,
+      history: [0.0; 4],
+      position: 1.0,
+    }
+  }
+
+  /// Fill the output with the next samples at the playback rate. This is
+  /// independent of SDL, for other audio APIs to call from their own
+  /// callbacks.
+  pub fn fill(&mut self, out: &mut [f32]) {
+    if !self.playing {
+      if self.samples.len() < self.target {
+        self.hold(out);
+        return;
+      }
+      self.playing = true;
+    }
+
+    let ratio = self.ratio();
+    for (i, elem) in out.iter_mut().enumerate() {
+      while self.position >= 1.0 {
+        match self.samples.pop() {
+          Some(sample) => {
+            self.history = [self.history[1], self.history[2], self.history[3], sample];
+            self.position -= 1.0;
+          }
+          None => {
+            self.playing = false;
+            self.hold(&mut out[i..]);
+            return;
+          }
+        }
+      }
+      *elem = hermite(&self.history, self.position as f32);
+      self.position += ratio;
+    }
+  }
+
+  /// Samples to read for each one played: more than 1 to drain the buffer
+  /// if it's above the target, or less to let it fill if it's below
+  fn ratio(&self) -> f64 {
+    let target = self.target.max(1) as f64;
+    let error = (self.samples.len() as f64 - target) / target;
+    1.0 + (error * MAX_RATE_ADJUSTMENT).clamp(-MAX_RATE_ADJUSTMENT, MAX_RATE_ADJUSTMENT)
+  }
+
+  /// Fill the output with the current level, to avoid popping
+  fn hold(&self, out: &mut [f32]) {
+    let level = hermite(&self.history, self.position.min(1.0) as f32);
+    for elem in out.iter_mut() {
+      *elem = level;
+    }
+  }
+}
+
+/// Cubic Hermite interpolation between `y[1]` and `y[2]`
+fn hermite(y: &[f32; 4], t: f32) -> f32 {
+  let c1 = 0.5 * (y[2] - y[0]);
+  let c2 = y[0] - 2.5 * y[1] + 2.0 * y[2] - 0.5 * y[3];
+  let c3 = 0.5 * (y[3] - y[0]) + 1.5 * (y[1] - y[2]);
+  ((c3 * t + c2) * t + c1) * t + y[1]
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use io::wav::TempWav;
+  use std::fs;
+
+  fn process(target: usize) -> (SampleProducer, NesAudioProcess) {
+    let (producer, consumer) = sample_buffer::channel(target * 2);
+    let mut process = NesAudioProcess::new(consumer, 100);
+    process.target = target;
+    (producer, process)
+  }
+
+  #[test]
+  fn waits_for_target_fill() {
+    let (mut producer, mut process) = process(4);
+    producer.push(&[0.5; 3]);
+    let mut out = [1.0; 2];
+    process.fill(&mut out);
+    assert_eq!(out, [0.0, 0.0]);
+    assert_eq!(producer.stats().len(), 3);
+
+    producer.push(&[0.5]);
+    process.fill(&mut out);
+    assert!(process.playing);
+    assert!(producer.stats().len() < 4);
+  }
+
+  #[test]
+  fn plays_samples_in_order_at_target_fill() {
+    let (mut producer, mut process) = process(8);
+    let samples: Vec<f32> = (0..8).map(|i| i as f32).collect();
+    producer.push(&samples);
+
+    // Delayed by the interpolation's history, but otherwise unchanged
+    let mut out = [0.0; 6];
+    process.fill(&mut out);
+    assert_eq!(out, [0.0, 0.0, 0.0, 1.0, 2.0, 3.0]);
+  }
+
+  #[test]
+  fn adjusts_rate_to_fill() {
+    let (mut producer, process) = process(100);
+    assert!(process.ratio() < 1.0);
+    producer.push(&[0.0; 150]);
+    assert!(process.ratio() > 1.0);
+    producer.push(&[0.0; 50]);
+    assert_eq!(process.ratio(), 1.0 + MAX_RATE_ADJUSTMENT);
+  }
+
+  #[test]
+  fn holds_level_on_underrun() {
+    let (mut producer, mut process) = process(4);
+    producer.push(&[0.25; 4]);
+    let mut out = [0.0; 8];
+    process.fill(&mut out);
+    assert_eq!(out[7], 0.25);
+    assert!(!process.playing);
+    assert_eq!(producer.stats().underruns(), 1);
+  }
+
+  #[test]
+  fn memory_output_shares_samples() {
+    let output = MemoryAudioOutput::new();
+    let mut clone = output.clone();
+    clone.output_samples(&[0.1, 0.2]);
+    clone.output_samples(&[0.3]);
+    assert_eq!(output.samples(), [0.1, 0.2, 0.3]);
+    assert_eq!(output.take(), [0.1, 0.2, 0.3]);
+    assert!(clone.is_empty());
+  }
+
+  #[test]
+  fn wav_output_writes_file() {
+    let wav = TempWav::new("wav_output_writes_file");
+    {
+      let mut output = WavAudioOutput::create(wav.path(), 48_000).unwrap();
+      output.output_samples(&[0.0; 10]);
+      output.finish().unwrap();
     }
+    assert_eq!(fs::metadata(wav.path()).unwrap().len(), 44 + 20);
+  }
+
+  #[cfg(target_os = "linux")]
+  #[test]
+  fn wav_output_keeps_errors() {
+    // Writes to /dev/full fail once the buffered samples are flushed
+    let mut output = WavAudioOutput::create("/dev/full", 48_000).unwrap();
+    output.output_samples(&[0.0; 10]);
+    assert!(output.finish().is_err());
   }
 
-  fn apply_transforms(self: &mut Self) {
-    let channels = self.channels.clone();
-    self.channels = self
-      .delta_stream
-      .try_iter()
-      .fold(channels, |acc, delta| acc.transform(delta));
+  #[test]
+  fn interpolates_smoothly() {
+    let y = [0.0, 1.0, 2.0, 3.0];
+    assert_eq!(hermite(&y, 0.0), 1.0);
+    assert_eq!(hermite(&y, 0.5), 1.5);
+    assert_eq!(hermite(&y, 1.0), 2.0);
   }
 }
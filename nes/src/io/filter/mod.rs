@@ -0,0 +1,182 @@
+//! # Video Filters
+//!
+//! Filters are applied to frames between the emulator and the frontend
+//! texture, eg, to add a scanline effect or to scale the image up with a
+//! pixel art scaler. A filter may change the dimensions of the frame, so
+//! frontends should size their textures with `VideoFilter::output_size`.
+//!
+//! Filters can be chained together by name with `from_name`, eg,
+//! `"scale2x+scanlines"` scales the image up before adding scanlines.
+
+use io::video::VideoFrame;
+use ppu::palette::Color;
+
+mod nearest;
+mod ntsc;
+mod scalex;
+mod scanline;
+mod xbr;
+
+pub use io::filter::nearest::NearestNeighbour;
+pub use io::filter::ntsc::{NtscFilter, NtscSettings};
+pub use io::filter::scalex::ScaleX;
+pub use io::filter::scanline::ScanlineFilter;
+pub use io::filter::xbr::Xbr;
+
+pub trait VideoFilter {
+  /// The dimensions of a filtered frame given the dimensions of the
+  /// input frame
+  fn output_size(&self, width: usize, height: usize) -> (usize, usize);
+
+  /// Filter a frame, producing a new frame
+  fn apply(&mut self, frame: &VideoFrame) -> VideoFrame;
+}
+
+/// Passes frames through untouched
+pub struct NoFilter {}
+
+impl VideoFilter for NoFilter {
+  fn output_size(&self, width: usize, height: usize) -> (usize, usize) {
+    (width, height)
+  }
+
+  fn apply(&mut self, frame: &VideoFrame) -> VideoFrame {
+    VideoFrame {
+      frame_data: frame.frame_data.clone(),
+      index_data: frame.index_data.clone(),
+    }
+  }
+}
+
+/// Applies a list of filters in order
+pub struct FilterChain {
+  filters: Vec<Box<VideoFilter>>,
+}
+
+impl FilterChain {
+  pub fn new(filters: Vec<Box<VideoFilter>>) -> Self {
+    FilterChain { filters }
+  }
+}
+
+impl VideoFilter for FilterChain {
+  fn output_size(&self, width: usize, height: usize) -> (usize, usize) {
+    self
+      .filters
+      .iter()
+      .fold((width, height), |(w, h), filter| filter.output_size(w, h))
+  }
+
+  fn apply(&mut self, frame: &VideoFrame) -> VideoFrame {
+    let mut filters = self.filters.iter_mut();
+    let first = match filters.next() {
+      Some(filter) => filter.apply(frame),
+      None => return NoFilter {}.apply(frame),
+    };
+    filters.fold(first, |frame, filter| filter.apply(&frame))
+  }
+}
+
+/// Create a filter from its name, or a chain of filters from names
+/// separated by `+`. Returns `None` if any of the names are unknown.
+///
+/// Name        | Filter
+/// ------------|------------------------------------------------
+/// none        | No filtering
+/// scanlines   | Darkened even lines and blurred neighbouring pixels
+/// nearest{N}x | Integer nearest neighbour scaling, eg, nearest3x
+/// scale2x     | Scale2x (AdvMAME2x) pixel art scaling
+/// scale3x     | Scale3x (AdvMAME3x) pixel art scaling
+/// xbr2x       | 2xBR pixel art scaling
+/// ntsc        | NTSC composite video signal, must be first in a chain
+/// ntsc-svideo | NTSC S-Video signal, must be first in a chain
+///
+/// The NTSC filters' settings can be changed after a colon, eg,
+/// `ntsc:sharpness=0.2,artifacts=0.8,fringing=0` (see
+/// `NtscSettings::from_name`).
+pub fn from_name(name: &str) -> Option<Box<VideoFilter>> {
+  if name.contains('+') {
+    let filters: Option<Vec<_>> = name.split('+').map(from_name).collect();
+    return filters.map(|filters| Box::new(FilterChain::new(filters)) as Box<VideoFilter>);
+  }
+
+  match name {
+    "none" => Some(Box::new(NoFilter {})),
+    "scanlines" => Some(Box::new(ScanlineFilter {})),
+    "scale2x" => Some(Box::new(ScaleX::new(2))),
+    "scale3x" => Some(Box::new(ScaleX::new(3))),
+    "xbr2x" => Some(Box::new(Xbr::new())),
+    _ if name.starts_with("ntsc") => NtscSettings::from_name(name)
+      .map(|settings| Box::new(NtscFilter::new(settings)) as Box<VideoFilter>),
+    _ => {
+      parse_nearest(name).map(|scale| Box::new(NearestNeighbour::new(scale)) as Box<VideoFilter>)
+    }
+  }
+}
+
+fn parse_nearest(name: &str) -> Option<usize> {
+  if !name.starts_with("nearest") || !name.ends_with('x') {
+    return None;
+  }
+  match name["nearest".len()..name.len() - 1].parse() {
+    Ok(scale) if scale > 0 => Some(scale),
+    _ => None,
+  }
+}
+
+/// Read a pixel from a frame, clamping the coordinates to the edges.
+fn clamped_pixel(frame: &VideoFrame, x: isize, y: isize) -> &Color {
+  let max_x = frame.width() as isize - 1;
+  let max_y = frame.height() as isize - 1;
+  let x = x.max(0).min(max_x) as usize;
+  let y = y.max(0).min(max_y) as usize;
+  &frame.frame_data[y][x]
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn no_filter_keeps_frame() {
+    let mut frame = VideoFrame::new(2, 2);
+    frame.frame_data[1][1] = Color(1, 2, 3);
+
+    let filtered = NoFilter {}.apply(&frame);
+    assert_eq!(filtered.frame_data, frame.frame_data);
+  }
+
+  #[test]
+  fn chain_output_size() {
+    let chain = from_name("scale2x+nearest3x+scanlines").unwrap();
+    assert_eq!(chain.output_size(256, 240), (256 * 6, 240 * 6));
+  }
+
+  #[test]
+  fn chain_applies_filters_in_order() {
+    let mut chain = from_name("nearest2x+nearest2x").unwrap();
+    let filtered = chain.apply(&VideoFrame::new(3, 2));
+    assert_eq!((filtered.width(), filtered.height()), (12, 8));
+  }
+
+  #[test]
+  fn from_name_rejects_unknown_filters() {
+    assert!(from_name("blurry").is_none());
+    assert!(from_name("none+blurry").is_none());
+    assert!(from_name("nearest0x").is_none());
+    assert!(from_name("nearestx").is_none());
+    assert!(from_name("ntsc:sharpness=2").is_none());
+  }
+
+  #[test]
+  fn from_name_passes_ntsc_settings() {
+    let chain = from_name("ntsc:artifacts=0,fringing=0+scanlines").unwrap();
+    assert_eq!(chain.output_size(256, 240), (512, 480));
+  }
+
+  #[test]
+  fn parse_nearest_scale() {
+    assert_eq!(parse_nearest("nearest4x"), Some(4));
+    assert_eq!(parse_nearest("nearest4"), None);
+  }
+}
@@ -0,0 +1,58 @@
+use io::filter::VideoFilter;
+use io::video::VideoFrame;
+
+/// Scales frames up by an integer factor, repeating each pixel.
+pub struct NearestNeighbour {
+  scale: usize,
+}
+
+impl NearestNeighbour {
+  pub fn new(scale: usize) -> Self {
+    NearestNeighbour { scale }
+  }
+}
+
+impl VideoFilter for NearestNeighbour {
+  fn output_size(&self, width: usize, height: usize) -> (usize, usize) {
+    (width * self.scale, height * self.scale)
+  }
+
+  fn apply(&mut self, frame: &VideoFrame) -> VideoFrame {
+    let frame_data = frame
+      .frame_data
+      .iter()
+      .flat_map(|line| {
+        let scaled_line: Vec<_> = line
+          .iter()
+          .flat_map(|color| vec![color.clone(); self.scale])
+          .collect();
+        vec![scaled_line; self.scale]
+      })
+      .collect();
+
+    VideoFrame {
+      frame_data,
+      index_data: None,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use ppu::palette::Color;
+
+  #[test]
+  fn repeats_pixels() {
+    let mut frame = VideoFrame::new(2, 1);
+    frame.frame_data[0][1] = Color(1, 2, 3);
+
+    let filtered = NearestNeighbour::new(3).apply(&frame);
+    assert_eq!((filtered.width(), filtered.height()), (6, 3));
+    for y in 0..3 {
+      assert_eq!(filtered.frame_data[y][2], Color(0, 0, 0));
+      assert_eq!(filtered.frame_data[y][3], Color(1, 2, 3));
+      assert_eq!(filtered.frame_data[y][5], Color(1, 2, 3));
+    }
+  }
+}
@@ -0,0 +1,457 @@
+//! # NTSC Composite Video
+//!
+//! The PPU doesn't output RGB, it generates a composite video signal
+//! directly from palette indices. Each pixel lasts 8 master clock cycles,
+//! and the colour generator has 12 phases per cycle of the colour
+//! subcarrier, so a colour's hue is made by the phase at which the signal
+//! switches between a high and low voltage for its luminance level.
+//!
+//! This filter synthesises that signal for every dot of a frame and
+//! decodes it back to RGB the way a TV would, which reproduces the
+//! artifacts games rely on (eg, dithering that blends into transparency,
+//! and colour fringing on sharp edges).
+//!
+//! The signal levels and decoding are described [here][NTSC], and the
+//! settings are similar in spirit to [blargg's nes_ntsc][nes_ntsc].
+//!
+//! [NTSC]: https://wiki.nesdev.com/w/index.php/NTSC_video
+//! [nes_ntsc]: http://slack.net/~ant/libs/ntsc.html
+
+use std::f32::consts::PI;
+
+use io::filter::VideoFilter;
+use io::video::{pack_index, unpack_index, VideoFrame};
+use ppu::palette;
+use ppu::palette::Color;
+
+/// Signal voltage for luminance levels 0-3 when the signal is low
+const LEVELS_LOW: [f32; 4] = [0.228, 0.312, 0.552, 0.880];
+
+/// Signal voltage for luminance levels 0-3 when the signal is high
+const LEVELS_HIGH: [f32; 4] = [0.616, 0.840, 1.100, 1.100];
+
+const BLACK: f32 = LEVELS_LOW[1];
+const WHITE: f32 = LEVELS_HIGH[3];
+
+/// Emphasis attenuates the signal while in the emphasised colour's phase
+const EMPHASIS_ATTENUATION: f32 = 0.746;
+
+/// Signal samples (colour generator phases) per pixel
+const SAMPLES_PER_PIXEL: usize = 8;
+
+/// Colour generator phases per cycle of the colour subcarrier
+const PHASES: usize = 12;
+
+/// Each scanline is 341 dots, so the phase at the start of each
+/// line advances by 341 * 8 % 12
+const LINE_PHASE_STEP: usize = 4;
+
+/// Phase shift between the colour burst and the colour generator
+const HUE_SHIFT: f32 = 3.9;
+
+/// Gamma of the NES signal relative to the display
+const GAMMA: f32 = 2.2 / 1.8;
+
+/// Output pixels per input pixel
+const SCALE: usize = 2;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct NtscSettings {
+  /// Sharpening applied to the decoded image, from -1 (blurry) to
+  /// 1 (sharp).
+  pub sharpness: f32,
+
+  /// How much of the colour signal bleeds into the brightness, from 0 to 1.
+  /// Causes dot crawl and lets dithering blend colours together.
+  pub artifacts: f32,
+
+  /// How much of the brightness bleeds into the colour, from 0 to 1.
+  /// Causes colour fringes along sharp edges.
+  pub fringing: f32,
+}
+
+impl NtscSettings {
+  /// A composite video cable, with all of its artifacts
+  pub fn composite() -> Self {
+    NtscSettings {
+      sharpness: 0.0,
+      artifacts: 0.5,
+      fringing: 0.5,
+    }
+  }
+
+  /// An S-Video cable, which carries brightness and colour separately
+  pub fn svideo() -> Self {
+    NtscSettings {
+      sharpness: 0.2,
+      artifacts: 0.0,
+      fringing: 0.0,
+    }
+  }
+}
+
+impl NtscSettings {
+  /// Parse settings from a preset name (`ntsc` for composite or
+  /// `ntsc-svideo`), optionally followed by a colon and comma separated
+  /// overrides, eg, `ntsc:sharpness=0.2,artifacts=0.8`. Returns `None` if
+  /// the preset or a setting is unknown, or a value is out of range.
+  pub fn from_name(name: &str) -> Option<Self> {
+    let mut parts = name.splitn(2, ':');
+    let mut settings = match parts.next() {
+      Some("ntsc") => NtscSettings::composite(),
+      Some("ntsc-svideo") => NtscSettings::svideo(),
+      _ => return None,
+    };
+
+    if let Some(overrides) = parts.next() {
+      for setting in overrides.split(',') {
+        let mut pair = setting.splitn(2, '=');
+        let key = pair.next().unwrap_or("").trim();
+        let value: f32 = match pair.next().map(|value| value.trim().parse()) {
+          Some(Ok(value)) => value,
+          _ => return None,
+        };
+        let (field, min) = match key {
+          "sharpness" => (&mut settings.sharpness, -1.0),
+          "artifacts" => (&mut settings.artifacts, 0.0),
+          "fringing" => (&mut settings.fringing, 0.0),
+          _ => return None,
+        };
+        if !(min <= value && value <= 1.0) {
+          return None;
+        }
+        *field = value;
+      }
+    }
+    Some(settings)
+  }
+}
+
+impl Default for NtscSettings {
+  fn default() -> Self {
+    NtscSettings::composite()
+  }
+}
+
+pub struct NtscFilter {
+  settings: NtscSettings,
+  /// Phase of the colour generator at the start of the frame. The
+  /// frame is 262 * 341 dots, so this alternates between frames.
+  frame_phase: usize,
+  /// Decoding weights for the I and Q components at each phase
+  i_weights: [f32; PHASES],
+  q_weights: [f32; PHASES],
+}
+
+impl NtscFilter {
+  pub fn new(settings: NtscSettings) -> Self {
+    let mut i_weights = [0.0; PHASES];
+    let mut q_weights = [0.0; PHASES];
+    for phase in 0..PHASES {
+      let angle = PI * (phase as f32 + HUE_SHIFT) / 6.0;
+      i_weights[phase] = angle.cos();
+      q_weights[phase] = angle.sin();
+    }
+
+    NtscFilter {
+      settings,
+      frame_phase: 0,
+      i_weights,
+      q_weights,
+    }
+  }
+
+  /// Synthesise the composite signal for a line of packed palette
+  /// indices, normalised so that black is 0 and white is 1.
+  fn encode_line(&self, line: &[u16], start_phase: usize) -> Vec<f32> {
+    let mut signal = Vec::with_capacity(line.len() * SAMPLES_PER_PIXEL);
+    for (x, pixel) in line.iter().enumerate() {
+      let (index, emphasis) = unpack_index(*pixel);
+      for sample in 0..SAMPLES_PER_PIXEL {
+        let phase = start_phase + x * SAMPLES_PER_PIXEL + sample;
+        let level = signal_level(index, emphasis, phase);
+        signal.push((level - BLACK) / (WHITE - BLACK));
+      }
+    }
+    signal
+  }
+
+  /// Decode a line of the composite signal back into RGB colours.
+  fn decode_line(&self, signal: &[f32], start_phase: usize, output: &mut [Color]) {
+    let read = |pos: isize| {
+      if pos >= 0 && (pos as usize) < signal.len() {
+        signal[pos as usize]
+      } else {
+        0.0
+      }
+    };
+
+    // Running sums make windowed averages cheap
+    let mut sums = Vec::with_capacity(signal.len() + 1);
+    sums.push(0.0);
+    for level in signal.iter() {
+      let last = sums[sums.len() - 1];
+      sums.push(last + level);
+    }
+    let average = |from: isize, to: isize| {
+      let from = from.max(0).min(signal.len() as isize) as usize;
+      let to = to.max(0).min(signal.len() as isize) as usize;
+      (sums[to] - sums[from]) / (PHASES as f32)
+    };
+
+    let step = SAMPLES_PER_PIXEL / SCALE;
+    let half = (PHASES / 2) as isize;
+    let mut yiq = Vec::with_capacity(output.len());
+    for x in 0..output.len() {
+      let center = (x * step + step / 2) as isize;
+
+      // Averaging over a whole subcarrier cycle cancels the colour out
+      // of the brightness; artifacts let some of it back in.
+      let luma = average(center - half, center + half);
+      let local = (read(center - 1) + read(center)) / 2.0;
+      let y = luma + self.settings.artifacts * (local - luma);
+
+      let (mut i, mut q) = (0.0, 0.0);
+      for pos in (center - half)..(center + half) {
+        // Only a comb of the brightness is removed before the colour is
+        // demodulated, fringing leaves some of it behind.
+        let luma_at = average(pos - half, pos + half);
+        let chroma = read(pos) - (1.0 - self.settings.fringing) * luma_at;
+        let phase = (start_phase as isize + pos).rem_euclid(PHASES as isize) as usize;
+        i += chroma * self.i_weights[phase] / (PHASES as f32);
+        q += chroma * self.q_weights[phase] / (PHASES as f32);
+      }
+      yiq.push((y, i, q));
+    }
+
+    for x in 0..output.len() {
+      let (y, i, q) = yiq[x];
+      let prev = yiq[x.saturating_sub(1)].0;
+      let next = yiq[(x + 1).min(yiq.len() - 1)].0;
+      let y = y + self.settings.sharpness * (y - (prev + next) / 2.0);
+      output[x] = to_rgb(y, i, q);
+    }
+  }
+}
+
+impl VideoFilter for NtscFilter {
+  fn output_size(&self, width: usize, height: usize) -> (usize, usize) {
+    (width * SCALE, height * SCALE)
+  }
+
+  fn apply(&mut self, frame: &VideoFrame) -> VideoFrame {
+    // Frames that didn't come straight from the PPU are mapped back to
+    // the palette, so this filter should be first in a chain.
+    let index_data = match frame.index_data {
+      Some(ref index_data) => index_data.clone(),
+      None => frame
+        .frame_data
+        .iter()
+        .map(|line| {
+          line
+            .iter()
+            .map(|c| pack_index(palette::index_of(c).unwrap_or(0x0F), 0))
+            .collect()
+        })
+        .collect(),
+    };
+
+    let (width, height) = self.output_size(frame.width(), frame.height());
+    let mut output = VideoFrame::new(width, height);
+    let mut decoded = vec![Color(0, 0, 0); width];
+    for (y, line) in index_data.iter().enumerate() {
+      let start_phase = (self.frame_phase + y * LINE_PHASE_STEP) % PHASES;
+      let signal = self.encode_line(line, start_phase);
+      self.decode_line(&signal, start_phase, &mut decoded);
+      for row in 0..SCALE {
+        output.frame_data[y * SCALE + row].clone_from_slice(&decoded);
+      }
+    }
+
+    self.frame_phase = (self.frame_phase + LINE_PHASE_STEP) % PHASES;
+    output
+  }
+}
+
+/// Whether the colour generator is high for a colour at a phase
+fn in_color_phase(color: u8, phase: usize) -> bool {
+  (usize::from(color) + phase) % PHASES < PHASES / 2
+}
+
+/// The signal voltage for a palette index and emphasis at a phase
+fn signal_level(index: u8, emphasis: u8, phase: usize) -> f32 {
+  let color = index & 0x0F;
+  // Colours $xE-$xF are forced to black
+  let level = if color > 0x0D {
+    1
+  } else {
+    usize::from((index >> 4) & 0x03)
+  };
+
+  let low = if color == 0x00 {
+    LEVELS_HIGH[level]
+  } else {
+    LEVELS_LOW[level]
+  };
+  let high = if color > 0x0C {
+    low
+  } else {
+    LEVELS_HIGH[level]
+  };
+
+  let signal = if in_color_phase(color, phase) {
+    high
+  } else {
+    low
+  };
+
+  let emphasised = (emphasis & palette::EMPHASIS_RED != 0 && in_color_phase(0x0, phase))
+    || (emphasis & palette::EMPHASIS_GREEN != 0 && in_color_phase(0x4, phase))
+    || (emphasis & palette::EMPHASIS_BLUE != 0 && in_color_phase(0x8, phase));
+
+  if emphasised {
+    signal * EMPHASIS_ATTENUATION
+  } else {
+    signal
+  }
+}
+
+/// Convert a YIQ colour to RGB, with gamma correction
+fn to_rgb(y: f32, i: f32, q: f32) -> Color {
+  let component = |v: f32| {
+    let v = if v <= 0.0 { 0.0 } else { v.powf(GAMMA) };
+    (v * 255.95).clamp(0.0, 255.0) as u8
+  };
+
+  Color(
+    component(y + 0.946_882 * i + 0.623_557 * q),
+    component(y - 0.274_788 * i - 0.635_691 * q),
+    component(y - 1.108_545 * i + 1.709_007 * q),
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn frame_of(index: u8, width: usize, height: usize) -> VideoFrame {
+    let mut frame = VideoFrame::new(width, height);
+    frame.index_data = Some(vec![vec![pack_index(index, 0); width]; height]);
+    frame
+  }
+
+  fn center_of(frame: &VideoFrame) -> Color {
+    frame.frame_data[frame.height() / 2][frame.width() / 2].clone()
+  }
+  #[test]
+  fn settings_from_name() {
+    assert_eq!(
+      NtscSettings::from_name("ntsc"),
+      Some(NtscSettings::composite())
+    );
+    assert_eq!(
+      NtscSettings::from_name("ntsc-svideo"),
+      Some(NtscSettings::svideo())
+    );
+    assert_eq!(
+      NtscSettings::from_name("ntsc:sharpness=-0.5, fringing=0"),
+      Some(NtscSettings {
+        sharpness: -0.5,
+        artifacts: 0.5,
+        fringing: 0.0,
+      })
+    );
+    assert_eq!(
+      NtscSettings::from_name("ntsc-svideo:artifacts=1"),
+      Some(NtscSettings {
+        sharpness: 0.2,
+        artifacts: 1.0,
+        fringing: 0.0,
+      })
+    );
+  }
+
+  #[test]
+  fn settings_from_name_rejects_bad_settings() {
+    assert_eq!(NtscSettings::from_name("pal"), None);
+    assert_eq!(NtscSettings::from_name("ntsc:"), None);
+    assert_eq!(NtscSettings::from_name("ntsc:blur=0.5"), None);
+    assert_eq!(NtscSettings::from_name("ntsc:sharpness"), None);
+    assert_eq!(NtscSettings::from_name("ntsc:artifacts=lots"), None);
+    assert_eq!(NtscSettings::from_name("ntsc:artifacts=-0.1"), None);
+    assert_eq!(NtscSettings::from_name("ntsc:sharpness=1.5"), None);
+  }
+
+  #[test]
+  fn output_size() {
+    let filter = NtscFilter::new(NtscSettings::default());
+    assert_eq!(filter.output_size(256, 240), (512, 480));
+  }
+
+  #[test]
+  fn black_and_white() {
+    let mut filter = NtscFilter::new(NtscSettings::svideo());
+    let black = center_of(&filter.apply(&frame_of(0x0F, 32, 4)));
+    let white = center_of(&filter.apply(&frame_of(0x30, 32, 4)));
+
+    assert_eq!(black, Color(0, 0, 0));
+    assert!(white.0 > 240 && white.1 > 240 && white.2 > 240);
+  }
+
+  #[test]
+  fn greys_have_no_colour() {
+    let mut filter = NtscFilter::new(NtscSettings::composite());
+    let Color(r, g, b) = center_of(&filter.apply(&frame_of(0x10, 32, 4)));
+
+    assert!(r > 100);
+    assert!((i16::from(r) - i16::from(g)).abs() <= 2);
+    assert!((i16::from(r) - i16::from(b)).abs() <= 2);
+  }
+
+  #[test]
+  fn hues_are_decoded() {
+    let mut filter = NtscFilter::new(NtscSettings::svideo());
+
+    // $16 is red
+    let Color(r, g, b) = center_of(&filter.apply(&frame_of(0x16, 32, 4)));
+    assert!(r > g && r > b);
+
+    // $1A is green
+    let Color(r, g, b) = center_of(&filter.apply(&frame_of(0x1A, 32, 4)));
+    assert!(g > r && g > b);
+
+    // $12 is blue
+    let Color(r, g, b) = center_of(&filter.apply(&frame_of(0x12, 32, 4)));
+    assert!(b > r && b > g);
+  }
+
+  #[test]
+  fn frames_without_indices_are_mapped_to_the_palette() {
+    let mut filter = NtscFilter::new(NtscSettings::svideo());
+    let mut frame = VideoFrame::new(32, 4);
+    for line in frame.frame_data.iter_mut() {
+      for pixel in line.iter_mut() {
+        *pixel = Color(0xFF, 0xFF, 0xFF);
+      }
+    }
+
+    let Color(r, g, b) = center_of(&filter.apply(&frame));
+    assert!(r > 240 && g > 240 && b > 240);
+  }
+
+  #[test]
+  fn signal_levels() {
+    // Colour $x0 is always high, $xD always low
+    assert_eq!(signal_level(0x20, 0, 0), LEVELS_HIGH[2]);
+    assert_eq!(signal_level(0x20, 0, 7), LEVELS_HIGH[2]);
+    assert_eq!(signal_level(0x2D, 0, 0), LEVELS_LOW[2]);
+    assert_eq!(signal_level(0x2D, 0, 7), LEVELS_LOW[2]);
+    // Colours $xE-$xF are black
+    assert_eq!(signal_level(0x3E, 0, 0), BLACK);
+    // Other colours are high for half of the phases
+    let high = (0..PHASES)
+      .filter(|p| signal_level(0x21, 0, *p) == LEVELS_HIGH[2])
+      .count();
+    assert_eq!(high, PHASES / 2);
+  }
+}
@@ -0,0 +1,130 @@
+//! Scale2x and Scale3x (also known as AdvMAME2x/3x) pixel art scalers.
+//!
+//! Each pixel is expanded into a 2x2 or 3x3 block, where the corners of
+//! the block take the colour of neighbouring pixels when they form an
+//! edge. [Read more here][Scale2x].
+//!
+//! [Scale2x]: https://www.scale2x.it/algorithm
+
+use io::filter::{clamped_pixel, VideoFilter};
+use io::video::VideoFrame;
+
+pub struct ScaleX {
+  scale: usize,
+}
+
+impl ScaleX {
+  /// Only scales of 2 and 3 are supported.
+  pub fn new(scale: usize) -> Self {
+    if scale != 2 && scale != 3 {
+      panic!("unsupported ScaleX scale: {}", scale);
+    }
+    ScaleX { scale }
+  }
+}
+
+impl VideoFilter for ScaleX {
+  fn output_size(&self, width: usize, height: usize) -> (usize, usize) {
+    (width * self.scale, height * self.scale)
+  }
+
+  fn apply(&mut self, frame: &VideoFrame) -> VideoFrame {
+    let (width, height) = self.output_size(frame.width(), frame.height());
+    let mut output = VideoFrame::new(width, height);
+
+    for y in 0..frame.height() {
+      for x in 0..frame.width() {
+        let p = |dx: isize, dy: isize| clamped_pixel(frame, x as isize + dx, y as isize + dy);
+        let (a, b, c) = (p(-1, -1), p(0, -1), p(1, -1));
+        let (d, e, f) = (p(-1, 0), p(0, 0), p(1, 0));
+        let (g, h, i) = (p(-1, 1), p(0, 1), p(1, 1));
+
+        let pick = |edge: bool, color| if edge { color } else { e };
+        let block = if b == h || d == f {
+          vec![e; self.scale * self.scale]
+        } else if self.scale == 2 {
+          vec![
+            pick(d == b, d),
+            pick(b == f, f),
+            pick(d == h, d),
+            pick(h == f, f),
+          ]
+        } else {
+          vec![
+            pick(d == b, d),
+            pick((d == b && e != c) || (b == f && e != a), b),
+            pick(b == f, f),
+            pick((d == b && e != g) || (d == h && e != a), d),
+            e,
+            pick((b == f && e != i) || (h == f && e != c), f),
+            pick(d == h, d),
+            pick((d == h && e != i) || (h == f && e != g), h),
+            pick(h == f, f),
+          ]
+        };
+
+        for (n, color) in block.into_iter().enumerate() {
+          let out_x = x * self.scale + n % self.scale;
+          let out_y = y * self.scale + n / self.scale;
+          output.frame_data[out_y][out_x] = color.clone();
+        }
+      }
+    }
+    output
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use ppu::palette::Color;
+
+  const W: Color = Color(255, 255, 255);
+  const K: Color = Color(0, 0, 0);
+
+  fn frame_from(lines: Vec<Vec<Color>>) -> VideoFrame {
+    VideoFrame {
+      frame_data: lines,
+      index_data: None,
+    }
+  }
+
+  #[test]
+  fn scale2x_keeps_flat_areas() {
+    let filtered = ScaleX::new(2).apply(&VideoFrame::new(2, 2));
+    assert_eq!((filtered.width(), filtered.height()), (4, 4));
+    assert!(filtered
+      .frame_data
+      .iter()
+      .all(|l| l.iter().all(|c| *c == K)));
+  }
+
+  #[test]
+  fn scale2x_smooths_diagonals() {
+    // A diagonal edge, the white pixel in the centre should get its
+    // top left corner filled in with black.
+    let frame = frame_from(vec![vec![K, K, W], vec![K, W, W], vec![W, W, W]]);
+    let filtered = ScaleX::new(2).apply(&frame);
+    assert_eq!(filtered.frame_data[2][2], K);
+    assert_eq!(filtered.frame_data[2][3], W);
+    assert_eq!(filtered.frame_data[3][2], W);
+    assert_eq!(filtered.frame_data[3][3], W);
+  }
+
+  #[test]
+  fn scale3x_smooths_diagonals() {
+    let frame = frame_from(vec![vec![K, K, W], vec![K, W, W], vec![W, W, W]]);
+    let filtered = ScaleX::new(3).apply(&frame);
+    assert_eq!((filtered.width(), filtered.height()), (9, 9));
+    assert_eq!(filtered.frame_data[3][3], K);
+    assert_eq!(filtered.frame_data[3][4], W);
+    assert_eq!(filtered.frame_data[4][4], W);
+    assert_eq!(filtered.frame_data[5][5], W);
+  }
+
+  #[test]
+  #[should_panic]
+  fn unsupported_scale() {
+    ScaleX::new(4);
+  }
+}
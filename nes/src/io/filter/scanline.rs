@@ -0,0 +1,68 @@
+use io::filter::VideoFilter;
+use io::video::VideoFrame;
+use ppu::palette::Color;
+
+/// Imitates a CRT by darkening every even line and blurring the
+/// colours between neighbouring pixels.
+pub struct ScanlineFilter {}
+
+impl VideoFilter for ScanlineFilter {
+  fn output_size(&self, width: usize, height: usize) -> (usize, usize) {
+    (width, height)
+  }
+
+  fn apply(&mut self, frame: &VideoFrame) -> VideoFrame {
+    let mut output = VideoFrame::new(frame.width(), frame.height());
+    let mut prev_color;
+    for (y, line) in frame.frame_data.iter().enumerate() {
+      prev_color = None;
+      for (x, color) in line.iter().enumerate() {
+        let Color(mut r, mut g, mut b) = color.clone();
+
+        // Add scanline effect
+        if y % 2 == 0 {
+          r = r / 10 * 9;
+          g = g / 10 * 9;
+          b = b / 10 * 9;
+        }
+
+        // Blur colours between pixels
+        if let Some(Color(pr, pg, pb)) = prev_color {
+          r = (r / 2) + (pr / 2) + (r & pr & 1);
+          g = (g / 2) + (pg / 2) + (g & pg & 1);
+          b = (b / 2) + (pb / 2) + (b & pb & 1);
+        }
+
+        output.frame_data[y][x] = Color(r, g, b);
+        prev_color = Some(Color(r, g, b));
+      }
+    }
+    output
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn darkens_even_lines() {
+    let mut frame = VideoFrame::new(1, 2);
+    frame.frame_data[0][0] = Color(100, 100, 100);
+    frame.frame_data[1][0] = Color(100, 100, 100);
+
+    let filtered = ScanlineFilter {}.apply(&frame);
+    assert_eq!(filtered.frame_data[0][0], Color(90, 90, 90));
+    assert_eq!(filtered.frame_data[1][0], Color(100, 100, 100));
+  }
+
+  #[test]
+  fn blurs_neighbouring_pixels() {
+    let mut frame = VideoFrame::new(2, 2);
+    frame.frame_data[1][0] = Color(200, 0, 101);
+    frame.frame_data[1][1] = Color(0, 200, 101);
+
+    let filtered = ScanlineFilter {}.apply(&frame);
+    assert_eq!(filtered.frame_data[1][1], Color(100, 100, 101));
+  }
+}
@@ -0,0 +1,153 @@
+//! 2xBR pixel art scaler, after Hyllian's xBR (scale by rules).
+//!
+//! Each pixel is expanded into a 2x2 block. For each corner of the block,
+//! the colour differences along the two diagonals through that corner are
+//! compared; when the edge runs along the corner's diagonal, the corner is
+//! blended with the closest neighbouring colour. Colour differences are
+//! weighted in YUV space so edges are detected the way the eye sees them.
+//!
+//! This is the simplest ("level 1") variant of the rules.
+
+use io::filter::{clamped_pixel, VideoFilter};
+use io::video::VideoFrame;
+use ppu::palette::Color;
+
+#[derive(Default)]
+pub struct Xbr {}
+
+impl Xbr {
+  pub fn new() -> Self {
+    Xbr {}
+  }
+}
+
+impl VideoFilter for Xbr {
+  fn output_size(&self, width: usize, height: usize) -> (usize, usize) {
+    (width * 2, height * 2)
+  }
+
+  fn apply(&mut self, frame: &VideoFrame) -> VideoFrame {
+    let (width, height) = self.output_size(frame.width(), frame.height());
+    let mut output = VideoFrame::new(width, height);
+
+    for y in 0..frame.height() {
+      for x in 0..frame.width() {
+        // Mirror the neighbourhood so that each corner of the output
+        // block can be treated as the bottom right corner.
+        for &(sx, sy) in [(1, 1), (-1, 1), (1, -1), (-1, -1)].iter() {
+          let p =
+            |dx: isize, dy: isize| clamped_pixel(frame, x as isize + sx * dx, y as isize + sy * dy);
+          let out_x = x * 2 + (sx == 1) as usize;
+          let out_y = y * 2 + (sy == 1) as usize;
+          output.frame_data[out_y][out_x] = corner(&p);
+        }
+      }
+    }
+    output
+  }
+}
+
+/// Calculate the bottom right corner of the 2x2 block for pixel E:
+///
+/// ```text
+///       B
+///    D  E  F  F4
+///    G  H  I  I4
+///          H5 I5
+/// ```
+///
+/// (C is above F.)
+fn corner<'a, P>(p: &P) -> Color
+where
+  P: Fn(isize, isize) -> &'a Color,
+{
+  let (b, c, d) = (p(0, -1), p(1, -1), p(-1, 0));
+  let (e, f, f4) = (p(0, 0), p(1, 0), p(2, 0));
+  let (g, h, i, i4) = (p(-1, 1), p(0, 1), p(1, 1), p(2, 1));
+  let (h5, i5) = (p(0, 2), p(1, 2));
+
+  // Weight of an edge running from bottom left to top right (through E's corner)
+  let along =
+    distance(e, c) + distance(e, g) + distance(i, f4) + distance(i, h5) + 4 * distance(h, f);
+  // Weight of an edge running from top left to bottom right
+  let across =
+    distance(h, d) + distance(h, i5) + distance(f, i4) + distance(f, b) + 4 * distance(e, i);
+
+  if along < across {
+    let closest = if distance(e, f) <= distance(e, h) {
+      f
+    } else {
+      h
+    };
+    blend(e, closest)
+  } else {
+    e.clone()
+  }
+}
+
+/// Weighted YUV difference between two colours.
+fn distance(a: &Color, b: &Color) -> u32 {
+  let (ay, au, av) = yuv(a);
+  let (by, bu, bv) = yuv(b);
+  ((ay - by).abs() * 48 + (au - bu).abs() * 7 + (av - bv).abs() * 6) as u32
+}
+
+fn yuv(color: &Color) -> (i32, i32, i32) {
+  let Color(r, g, b) = *color;
+  let (r, g, b) = (i32::from(r), i32::from(g), i32::from(b));
+  let y = (299 * r + 587 * g + 114 * b) / 1000;
+  let u = (-169 * r - 331 * g + 500 * b) / 1000;
+  let v = (500 * r - 419 * g - 81 * b) / 1000;
+  (y, u, v)
+}
+
+fn blend(a: &Color, b: &Color) -> Color {
+  let mix = |x: u8, y: u8| ((u16::from(x) + u16::from(y)) / 2) as u8;
+  Color(mix(a.0, b.0), mix(a.1, b.1), mix(a.2, b.2))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const W: Color = Color(255, 255, 255);
+  const K: Color = Color(0, 0, 0);
+
+  #[test]
+  fn keeps_flat_areas() {
+    let filtered = Xbr::new().apply(&VideoFrame::new(3, 3));
+    assert_eq!((filtered.width(), filtered.height()), (6, 6));
+    assert!(filtered
+      .frame_data
+      .iter()
+      .all(|l| l.iter().all(|c| *c == K)));
+  }
+
+  #[test]
+  fn smooths_diagonal_edges() {
+    let frame = VideoFrame {
+      frame_data: vec![
+        vec![K, K, K, W],
+        vec![K, K, W, W],
+        vec![K, W, W, W],
+        vec![W, W, W, W],
+      ],
+      index_data: None,
+    };
+    let filtered = Xbr::new().apply(&frame);
+
+    // The black pixel on the edge has its bottom right corner blended
+    // towards white, while its other corners stay black.
+    assert_eq!(filtered.frame_data[3][3], Color(127, 127, 127));
+    assert_eq!(filtered.frame_data[2][2], K);
+    // Pixels away from the edge are untouched.
+    assert_eq!(filtered.frame_data[0][0], K);
+    assert_eq!(filtered.frame_data[7][7], W);
+  }
+
+  #[test]
+  fn distance_is_symmetric() {
+    assert_eq!(distance(&W, &K), distance(&K, &W));
+    assert_eq!(distance(&W, &W), 0);
+  }
+}
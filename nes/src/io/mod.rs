@@ -1,2 +1,6 @@
 pub mod audio;
+pub mod filter;
+pub mod overscan;
+pub mod sample_buffer;
 pub mod video;
+pub mod wav;
@@ -0,0 +1,174 @@
+//! # Overscan
+//!
+//! The PPU always outputs 256x240 pixels, but TVs never showed all of
+//! them: the edges of the picture were hidden behind the bezel, so games
+//! often left garbage there (eg, tiles being updated while scrolling).
+//! An `Overscan` describes how many pixels to crop from each edge of a
+//! frame before it is displayed.
+
+use io::video::{VideoFrame, FRAME_HEIGHT, FRAME_WIDTH};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Overscan {
+  pub top: usize,
+  pub bottom: usize,
+  pub left: usize,
+  pub right: usize,
+}
+
+impl Overscan {
+  /// Show the whole frame
+  pub fn none() -> Self {
+    Overscan {
+      top: 0,
+      bottom: 0,
+      left: 0,
+      right: 0,
+    }
+  }
+
+  /// Typical NTSC TVs hid roughly the top and bottom 8 lines
+  pub fn ntsc() -> Self {
+    Overscan {
+      top: 8,
+      bottom: 8,
+      left: 0,
+      right: 0,
+    }
+  }
+
+  /// The PAL PPU blanks the top line and the two pixels at either side
+  /// itself, but PAL TVs showed the rest of the frame
+  pub fn pal() -> Self {
+    Overscan {
+      top: 1,
+      bottom: 0,
+      left: 2,
+      right: 2,
+    }
+  }
+
+  /// Parse an overscan from either a region name (`none`, `ntsc` or `pal`)
+  /// or four comma separated edges in the order top, bottom, left, right,
+  /// eg, `8,8,4,4`. Returns `None` if the name is unknown or would crop
+  /// away the whole frame.
+  pub fn from_name(name: &str) -> Option<Self> {
+    let overscan = match name {
+      "none" => Overscan::none(),
+      "ntsc" => Overscan::ntsc(),
+      "pal" => Overscan::pal(),
+      _ => {
+        let edges: Vec<usize> = match name.split(',').map(|edge| edge.trim().parse()).collect() {
+          Ok(edges) => edges,
+          Err(_) => return None,
+        };
+        if edges.len() != 4 {
+          return None;
+        }
+        Overscan {
+          top: edges[0],
+          bottom: edges[1],
+          left: edges[2],
+          right: edges[3],
+        }
+      }
+    };
+
+    if overscan.left + overscan.right >= FRAME_WIDTH
+      || overscan.top + overscan.bottom >= FRAME_HEIGHT
+    {
+      return None;
+    }
+    Some(overscan)
+  }
+
+  /// The dimensions of a frame of the given size once cropped
+  pub fn output_size(&self, width: usize, height: usize) -> (usize, usize) {
+    (
+      width.saturating_sub(self.left + self.right),
+      height.saturating_sub(self.top + self.bottom),
+    )
+  }
+
+  /// Copy the visible area out of a frame
+  pub fn crop(&self, frame: &VideoFrame) -> VideoFrame {
+    VideoFrame {
+      frame_data: self.crop_lines(&frame.frame_data),
+      index_data: frame
+        .index_data
+        .as_ref()
+        .map(|lines| self.crop_lines(lines)),
+    }
+  }
+
+  /// Copy the visible area out of any 2D Vec of pixels, where the first
+  /// dimension is the line
+  pub fn crop_lines<T: Clone>(&self, lines: &[Vec<T>]) -> Vec<Vec<T>> {
+    let width = lines.first().map_or(0, |line| line.len());
+    let (width, height) = self.output_size(width, lines.len());
+    lines
+      .iter()
+      .skip(self.top)
+      .take(height)
+      .map(|line| line[self.left..self.left + width].to_vec())
+      .collect()
+  }
+}
+
+impl Default for Overscan {
+  fn default() -> Self {
+    Overscan::ntsc()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use ppu::palette::Color;
+
+  #[test]
+  fn output_size() {
+    assert_eq!(Overscan::none().output_size(256, 240), (256, 240));
+    assert_eq!(Overscan::ntsc().output_size(256, 240), (256, 224));
+    assert_eq!(Overscan::pal().output_size(256, 240), (252, 239));
+  }
+
+  #[test]
+  fn from_name() {
+    assert_eq!(Overscan::from_name("pal"), Some(Overscan::pal()));
+    assert_eq!(
+      Overscan::from_name("1, 2,3,4"),
+      Some(Overscan {
+        top: 1,
+        bottom: 2,
+        left: 3,
+        right: 4,
+      })
+    );
+    assert_eq!(Overscan::from_name("1,2,3"), None);
+    assert_eq!(Overscan::from_name("wide"), None);
+    assert_eq!(Overscan::from_name("0,0,128,128"), None);
+  }
+
+  #[test]
+  fn crop_each_edge() {
+    let mut frame = VideoFrame::new(4, 4);
+    frame.frame_data[1][1] = Color(1, 1, 1);
+    let mut index_data = vec![vec![0; 4]; 4];
+    index_data[1][1] = 0x2A;
+    frame.index_data = Some(index_data);
+
+    let overscan = Overscan {
+      top: 1,
+      bottom: 0,
+      left: 1,
+      right: 2,
+    };
+    let cropped = overscan.crop(&frame);
+
+    assert_eq!((cropped.width(), cropped.height()), (1, 3));
+    assert_eq!(cropped.frame_data[0][0], Color(1, 1, 1));
+    assert_eq!(cropped.frame_data[1][0], Color(0, 0, 0));
+    assert_eq!(cropped.index_data.unwrap()[0], vec![0x2A]);
+  }
+}
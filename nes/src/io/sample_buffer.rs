@@ -0,0 +1,240 @@
+//! # Sample Buffer
+//!
+//! A lock-free, single producer and single consumer ring buffer of audio
+//! samples, passing them from the emulation thread (which writes each
+//! batch as the APU finishes it) to the audio device's callback thread
+//! (which reads them as it plays). Neither side ever blocks: when the
+//! buffer is full the newest samples are dropped (an overrun), and when
+//! it's empty the reader goes without (an underrun). Both are counted, so
+//! they can be reported.
+//!
+//! Samples are stored as the bits of an `f32` in atomics, with the total
+//! number written and read published with release/acquire ordering so
+//! that each side only sees the other's finished work.
+//!
+//! The samples aren't stamped with the time they were produced, because
+//! their position is already their timestamp: the APU resamples onto the
+//! emulated clock at a fixed rate, so the nth sample written is always
+//! from n / rate seconds into the emulation. How many are waiting is then
+//! how far the audio device is behind the emulation, which is what
+//! `NesAudioProcess` corrects. Only overruns break this, by dropping
+//! samples, and those are counted.
+
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+struct Shared {
+  samples: Vec<AtomicU32>,
+
+  /// Totals since the buffer was created, wrapping around. The buffer's
+  /// capacity is a power of two, so they stay in step with the indices.
+  written: AtomicUsize,
+  read: AtomicUsize,
+
+  /// Samples dropped because the buffer was full
+  overruns: AtomicUsize,
+  /// Times the reader found the buffer empty
+  underruns: AtomicUsize,
+}
+
+impl Shared {
+  fn capacity(&self) -> usize {
+    self.samples.len()
+  }
+
+  fn len(&self) -> usize {
+    let written = self.written.load(Ordering::Acquire);
+    let read = self.read.load(Ordering::Acquire);
+    written.wrapping_sub(read)
+  }
+
+  fn slot(&self, count: usize) -> &AtomicU32 {
+    &self.samples[count & (self.capacity() - 1)]
+  }
+}
+
+/// Create a buffer holding at least `capacity` samples, returning its
+/// writing and reading ends
+pub fn channel(capacity: usize) -> (SampleProducer, SampleConsumer) {
+  let shared = Arc::new(Shared {
+    samples: (0..capacity.max(1).next_power_of_two())
+      .map(|_| AtomicU32::new(0))
+      .collect(),
+    written: AtomicUsize::new(0),
+    read: AtomicUsize::new(0),
+    overruns: AtomicUsize::new(0),
+    underruns: AtomicUsize::new(0),
+  });
+  (
+    SampleProducer {
+      shared: shared.clone(),
+    },
+    SampleConsumer { shared },
+  )
+}
+
+/// The writing end of a sample buffer
+pub struct SampleProducer {
+  shared: Arc<Shared>,
+}
+
+impl SampleProducer {
+  /// Append as many of the samples as fit, returning how many did. The
+  /// rest are dropped and counted as overruns.
+  pub fn push(&mut self, samples: &[f32]) -> usize {
+    let shared = &self.shared;
+    let written = shared.written.load(Ordering::Relaxed);
+    let read = shared.read.load(Ordering::Acquire);
+    let free = shared.capacity() - written.wrapping_sub(read);
+    let count = free.min(samples.len());
+
+    for (i, sample) in samples[..count].iter().enumerate() {
+      shared
+        .slot(written.wrapping_add(i))
+        .store(sample.to_bits(), Ordering::Relaxed);
+    }
+    shared
+      .written
+      .store(written.wrapping_add(count), Ordering::Release);
+
+    if count < samples.len() {
+      shared
+        .overruns
+        .fetch_add(samples.len() - count, Ordering::Relaxed);
+    }
+    count
+  }
+
+  /// A handle for watching the buffer from any thread
+  pub fn stats(&self) -> BufferStats {
+    BufferStats {
+      shared: self.shared.clone(),
+    }
+  }
+}
+
+/// The reading end of a sample buffer
+pub struct SampleConsumer {
+  shared: Arc<Shared>,
+}
+
+impl SampleConsumer {
+  /// Take the oldest sample, or count an underrun if there are none
+  pub fn pop(&mut self) -> Option<f32> {
+    let shared = &self.shared;
+    let read = shared.read.load(Ordering::Relaxed);
+    if shared.written.load(Ordering::Acquire) == read {
+      shared.underruns.fetch_add(1, Ordering::Relaxed);
+      return None;
+    }
+
+    let sample = f32::from_bits(shared.slot(read).load(Ordering::Relaxed));
+    shared.read.store(read.wrapping_add(1), Ordering::Release);
+    Some(sample)
+  }
+
+  /// The number of samples waiting to be read
+  pub fn len(&self) -> usize {
+    self.shared.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  pub fn capacity(&self) -> usize {
+    self.shared.capacity()
+  }
+
+  pub fn stats(&self) -> BufferStats {
+    BufferStats {
+      shared: self.shared.clone(),
+    }
+  }
+}
+
+/// How full a sample buffer is, and how often it has over or underrun
+#[derive(Clone)]
+pub struct BufferStats {
+  shared: Arc<Shared>,
+}
+
+impl BufferStats {
+  pub fn len(&self) -> usize {
+    self.shared.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  pub fn capacity(&self) -> usize {
+    self.shared.capacity()
+  }
+
+  /// Samples dropped because the buffer was full
+  pub fn overruns(&self) -> usize {
+    self.shared.overruns.load(Ordering::Relaxed)
+  }
+
+  /// Times a sample was needed but the buffer was empty
+  pub fn underruns(&self) -> usize {
+    self.shared.underruns.load(Ordering::Relaxed)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::thread;
+
+  #[test]
+  fn reads_samples_in_order() {
+    let (mut producer, mut consumer) = channel(4);
+    assert_eq!(producer.push(&[0.1, 0.2, 0.3]), 3);
+    assert_eq!(consumer.len(), 3);
+    assert_eq!(consumer.pop(), Some(0.1));
+    assert_eq!(consumer.pop(), Some(0.2));
+
+    // Wraps around the end
+    assert_eq!(producer.push(&[0.4, 0.5, 0.6]), 3);
+    let samples: Vec<_> = (0..4).filter_map(|_| consumer.pop()).collect();
+    assert_eq!(samples, [0.3, 0.4, 0.5, 0.6]);
+  }
+
+  #[test]
+  fn counts_overruns_and_underruns() {
+    let (mut producer, mut consumer) = channel(3);
+    assert_eq!(consumer.capacity(), 4);
+    assert_eq!(producer.push(&[1.0; 6]), 4);
+    assert_eq!(producer.stats().overruns(), 2);
+
+    for _ in 0..4 {
+      assert_eq!(consumer.pop(), Some(1.0));
+    }
+    assert_eq!(consumer.pop(), None);
+    assert_eq!(consumer.stats().underruns(), 1);
+    assert!(consumer.is_empty());
+  }
+
+  #[test]
+  fn passes_samples_between_threads() {
+    let (mut producer, mut consumer) = channel(64);
+    let writer = thread::spawn(move || {
+      let mut next = 0;
+      while next < 10_000 {
+        let batch: Vec<f32> = (next..next + 16).map(|i| i as f32).collect();
+        next += producer.push(&batch);
+      }
+    });
+
+    let mut expected = 0;
+    while expected < 10_000 {
+      if let Some(sample) = consumer.pop() {
+        assert_eq!(sample, expected as f32);
+        expected += 1;
+      }
+    }
+    writer.join().unwrap();
+  }
+}
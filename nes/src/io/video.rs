@@ -1,6 +1,14 @@
+use io::overscan::Overscan;
+use ppu::palette;
 use ppu::palette::Color;
 use std::sync::mpsc;
 
+/// Width of a frame in pixels
+pub const FRAME_WIDTH: usize = 256;
+
+/// Height of a frame in pixels
+pub const FRAME_HEIGHT: usize = 240;
+
 /// A video output simulates a composite video output,
 /// outputting one pixel at a time as well as extra
 /// pulses to mark the beginning of new scanlines
@@ -9,6 +17,22 @@ pub trait VideoOutput {
   /// Add a pixel and move on to the next
   fn output_pixel(&mut self, Color);
 
+  /// Add a pixel as a raw 6 bit palette index along with the colour
+  /// emphasis bits, and move on to the next. Outputs which only deal
+  /// with RGB colours can rely on the default conversion.
+  fn output_palette_index(&mut self, index: u8, emphasis: u8) {
+    self.output_pixel(palette::to_color(index, emphasis));
+  }
+
+  /// Add a run of pixels as palette indices which share the same colour
+  /// emphasis bits. Outputs can override this to copy whole scanlines at
+  /// once rather than a pixel at a time.
+  fn output_palette_indices(&mut self, indices: &[u8], emphasis: u8) {
+    for &index in indices {
+      self.output_palette_index(index, emphasis);
+    }
+  }
+
   /// Mark the beginning of a new scanline
   fn horizontal_sync(&mut self);
 
@@ -19,76 +43,106 @@ pub trait VideoOutput {
 pub struct VideoFrame {
   /// 2D Vec of pixel values where the first dimension is the line
   pub frame_data: Vec<Vec<Color>>,
+
+  /// The same frame as packed palette indices (see `pack_index`), when
+  /// the frame came straight from the PPU rather than through a filter
+  pub index_data: Option<Vec<Vec<u16>>>,
 }
 
 impl VideoFrame {
+  /// Create a black frame of the given dimensions
+  pub fn new(width: usize, height: usize) -> Self {
+    VideoFrame {
+      frame_data: vec![vec![Color(0, 0, 0); width]; height],
+      index_data: None,
+    }
+  }
+
+  pub fn width(&self) -> usize {
+    self.frame_data.first().map_or(0, |line| line.len())
+  }
+
+  pub fn height(&self) -> usize {
+    self.frame_data.len()
+  }
+
   /// Write a video frame to a flat array of RGB values (eg,
   /// to raw texture data)
   pub fn write_to_buffer(&self, buf: &mut [u8], pitch: usize) {
-    let mut prev_color;
     for (y, line) in self.frame_data.iter().enumerate() {
-      prev_color = None;
       for (x, color) in line.iter().enumerate() {
         let offset: usize = (y * pitch) + x * 3;
-        let Color(mut r, mut g, mut b) = color.clone();
-
-        // Add scanline effect
-        if y % 2 == 0 {
-          r = r / 10 * 9;
-          g = g / 10 * 9;
-          b = b / 10 * 9;
-        }
-
-        // Blur colours between pixels
-        if let Some(Color(pr, pg, pb)) = prev_color {
-          r = (r / 2) + (pr / 2) + (r & pr & 1);
-          g = (g / 2) + (pg / 2) + (g & pg & 1);
-          b = (b / 2) + (pb / 2) + (b & pb & 1);
-        }
+        let Color(r, g, b) = color.clone();
 
         buf[offset] = r;
         buf[offset + 1] = g;
         buf[offset + 2] = b;
-
-        prev_color = Some(Color(r, g, b));
       }
     }
   }
 }
 
-/// A VideoOutput that sends frames over a synchronous channel
+/// A VideoOutput that sends frames over a synchronous channel, cropped
+/// to the visible area
 pub struct ChannelVideoOutput {
   sender: mpsc::SyncSender<VideoFrame>,
   frame_data: Vec<Vec<Color>>,
+  index_data: Vec<Vec<u16>>,
+  overscan: Overscan,
   col: usize,
   line: usize,
 }
 
 impl ChannelVideoOutput {
+  /// Create an output which sends whole frames
   pub fn new() -> (Self, mpsc::Receiver<VideoFrame>) {
+    ChannelVideoOutput::with_overscan(Overscan::none())
+  }
+
+  /// Create an output which crops the overscan from frames before
+  /// sending them
+  pub fn with_overscan(overscan: Overscan) -> (Self, mpsc::Receiver<VideoFrame>) {
     let (send, recv) = mpsc::sync_channel(2);
     (
       ChannelVideoOutput {
         sender: send,
         col: 0,
         line: 0,
-        frame_data: vec![vec![Color(0, 0, 0); 256]; 240],
+        frame_data: vec![vec![Color(0, 0, 0); FRAME_WIDTH]; FRAME_HEIGHT],
+        index_data: vec![vec![0; FRAME_WIDTH]; FRAME_HEIGHT],
+        overscan,
       },
       recv,
     )
   }
-}
 
-impl VideoOutput for ChannelVideoOutput {
-  fn output_pixel(&mut self, c: Color) {
-    if self.col >= 256 || self.line >= 240 {
+  /// The dimensions of the frames this output sends
+  pub fn frame_size(&self) -> (usize, usize) {
+    self.overscan.output_size(FRAME_WIDTH, FRAME_HEIGHT)
+  }
+
+  fn output_color(&mut self, c: Color, index: u16) {
+    if self.col >= FRAME_WIDTH || self.line >= FRAME_HEIGHT {
       // Overscan, ignore
       return;
     }
     self.frame_data[self.line][self.col] = c;
+    self.index_data[self.line][self.col] = index;
 
     self.col += 1;
   }
+}
+
+impl VideoOutput for ChannelVideoOutput {
+  fn output_pixel(&mut self, c: Color) {
+    let index = palette::index_of(&c).unwrap_or(0x0F);
+    self.output_color(c, pack_index(index, 0));
+  }
+
+  fn output_palette_index(&mut self, index: u8, emphasis: u8) {
+    let c = palette::to_color(index, emphasis);
+    self.output_color(c, pack_index(index, emphasis));
+  }
 
   fn horizontal_sync(&mut self) {
     self.line += 1;
@@ -104,8 +158,196 @@ impl VideoOutput for ChannelVideoOutput {
     self
       .sender
       .send(VideoFrame {
-        frame_data: self.frame_data.clone(),
+        frame_data: self.overscan.crop_lines(&self.frame_data),
+        index_data: Some(self.overscan.crop_lines(&self.index_data)),
       })
       .unwrap();
   }
 }
+
+/// Pack a palette index and emphasis bits into a single pixel value,
+/// with the index in bits 0-5 and the emphasis in bits 6-8.
+pub fn pack_index(index: u8, emphasis: u8) -> u16 {
+  u16::from(index & 0x3F) | (u16::from(emphasis & 0b111) << 6)
+}
+
+/// Split a packed pixel value into its palette index and emphasis bits.
+pub fn unpack_index(pixel: u16) -> (u8, u8) {
+  ((pixel & 0x3F) as u8, ((pixel >> 6) & 0b111) as u8)
+}
+
+/// Called with the frame number and the packed pixels of each frame
+pub type FrameCallback = Box<FnMut(u64, &[u16]) + Send>;
+
+/// A VideoOutput that collects raw palette indices (see `pack_index`)
+/// into a flat 256x240 buffer, calling back with the frame number and
+/// the buffer every time a frame is complete.
+///
+/// Doesn't depend on SDL, so can be used for headless runs (eg, to hash
+/// frames independent of the palette).
+pub struct IndexedVideoOutput {
+  frame_data: Vec<u16>,
+  frame_number: u64,
+  col: usize,
+  line: usize,
+  on_frame: FrameCallback,
+}
+
+impl IndexedVideoOutput {
+  pub fn new<F>(on_frame: F) -> Self
+  where
+    F: FnMut(u64, &[u16]) + Send + 'static,
+  {
+    IndexedVideoOutput {
+      frame_data: vec![0; FRAME_WIDTH * FRAME_HEIGHT],
+      frame_number: 0,
+      col: 0,
+      line: 0,
+      on_frame: Box::new(on_frame),
+    }
+  }
+}
+
+impl VideoOutput for IndexedVideoOutput {
+  /// RGB colours are mapped back to their palette index where possible,
+  /// or to black otherwise.
+  fn output_pixel(&mut self, c: Color) {
+    let index = palette::index_of(&c).unwrap_or(0x0F);
+    self.output_palette_index(index, 0);
+  }
+
+  fn output_palette_index(&mut self, index: u8, emphasis: u8) {
+    if self.col >= FRAME_WIDTH || self.line >= FRAME_HEIGHT {
+      // Overscan, ignore
+      return;
+    }
+    self.frame_data[self.line * FRAME_WIDTH + self.col] = pack_index(index, emphasis);
+
+    self.col += 1;
+  }
+
+  fn output_palette_indices(&mut self, indices: &[u8], emphasis: u8) {
+    if self.line >= FRAME_HEIGHT {
+      return;
+    }
+    let len = indices.len().min(FRAME_WIDTH - self.col.min(FRAME_WIDTH));
+    let start = self.line * FRAME_WIDTH + self.col;
+    for (pixel, &index) in self.frame_data[start..start + len].iter_mut().zip(indices) {
+      *pixel = pack_index(index, emphasis);
+    }
+
+    self.col += len;
+  }
+
+  fn horizontal_sync(&mut self) {
+    self.line += 1;
+    self.col = 0;
+  }
+
+  fn vertical_sync(&mut self) {
+    self.col = 0;
+    self.line = 0;
+
+    (self.on_frame)(self.frame_number, &self.frame_data);
+    self.frame_number += 1;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::sync::{Arc, Mutex};
+
+  #[test]
+  fn frame_dimensions() {
+    let frame = VideoFrame::new(3, 2);
+    assert_eq!(frame.width(), 3);
+    assert_eq!(frame.height(), 2);
+    assert_eq!(VideoFrame::new(0, 0).width(), 0);
+  }
+
+  #[test]
+  fn write_frame_to_buffer() {
+    let mut frame = VideoFrame::new(2, 2);
+    frame.frame_data[0][1] = Color(1, 2, 3);
+    frame.frame_data[1][0] = Color(4, 5, 6);
+
+    let pitch = 8;
+    let mut buf = [0u8; 16];
+    frame.write_to_buffer(&mut buf, pitch);
+
+    assert_eq!(buf[3..6], [1, 2, 3]);
+    assert_eq!(buf[pitch..pitch + 3], [4, 5, 6]);
+  }
+
+  #[test]
+  fn pack_and_unpack_index() {
+    assert_eq!(pack_index(0x2A, 0b101), 0b101_101010);
+    assert_eq!(unpack_index(0b101_101010), (0x2A, 0b101));
+    assert_eq!(pack_index(0xFF, 0xFF), 0b111_111111);
+  }
+
+  #[test]
+  fn channel_output_crops_overscan() {
+    let (mut output, receiver) = ChannelVideoOutput::with_overscan(Overscan::ntsc());
+    assert_eq!(output.frame_size(), (FRAME_WIDTH, FRAME_HEIGHT - 16));
+
+    for line in 0..FRAME_HEIGHT {
+      for _ in 0..FRAME_WIDTH {
+        output.output_palette_index(line as u8, 0);
+      }
+      output.horizontal_sync();
+    }
+    output.vertical_sync();
+
+    let frame = receiver.recv().unwrap();
+    assert_eq!((frame.width(), frame.height()), output.frame_size());
+    let index_data = frame.index_data.unwrap();
+    assert_eq!(index_data[0][0], 8);
+    assert_eq!(index_data[FRAME_HEIGHT - 17][0], (FRAME_HEIGHT - 9) as u16 & 0x3F);
+  }
+
+  #[test]
+  fn indexed_output_collects_frames() {
+    let frames = Arc::new(Mutex::new(vec![]));
+    let frames_out = frames.clone();
+    let mut output = IndexedVideoOutput::new(move |number, data: &[u16]| {
+      frames_out.lock().unwrap().push((number, data.to_vec()));
+    });
+
+    for frame in 0..2 {
+      for _ in 0..FRAME_HEIGHT {
+        for x in 0..(FRAME_WIDTH + 1) {
+          output.output_palette_index((x as u8).wrapping_add(frame), 0b001);
+        }
+        output.horizontal_sync();
+      }
+      output.vertical_sync();
+    }
+
+    let frames = frames.lock().unwrap();
+    assert_eq!(frames.len(), 2);
+    assert_eq!(frames[0].0, 0);
+    assert_eq!(frames[1].0, 1);
+    assert_eq!(frames[0].1.len(), FRAME_WIDTH * FRAME_HEIGHT);
+    assert_eq!(unpack_index(frames[0].1[FRAME_WIDTH + 3]), (3, 0b001));
+    assert_eq!(unpack_index(frames[1].1[FRAME_WIDTH + 3]), (4, 0b001));
+  }
+
+  #[test]
+  fn indexed_output_maps_colors_to_indices() {
+    let frames = Arc::new(Mutex::new(vec![]));
+    let frames_out = frames.clone();
+    let mut output = IndexedVideoOutput::new(move |_, data: &[u16]| {
+      frames_out.lock().unwrap().push(data.to_vec());
+    });
+
+    output.output_pixel(Color(0x00, 0xAB, 0x00));
+    output.output_pixel(Color(0x01, 0x02, 0x03));
+    output.vertical_sync();
+
+    let frames = frames.lock().unwrap();
+    assert_eq!(frames[0][0], 0x1A);
+    assert_eq!(frames[0][1], 0x0F);
+  }
+}
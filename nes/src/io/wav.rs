@@ -0,0 +1,327 @@
+//! # WAV Recording
+//!
+//! Writes audio to mono 16 bit PCM WAV files, either just the mixed APU
+//! output or also a "stem" per APU channel, captured before mixing. The
+//! header is brought up to date after each second of audio and when the
+//! writer is dropped, so the files can be played even if the emulator is
+//! killed without finishing the recording (losing at most a second).
+//!
+//! [Read more here][WAV].
+//!
+//! [WAV]: http://soundfile.sapp.org/doc/WaveFormat/
+
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+const HEADER_SIZE: u32 = 44;
+const CHANNELS: u16 = 1;
+const BITS_PER_SAMPLE: u16 = 16;
+const BYTES_PER_SAMPLE: u32 = (BITS_PER_SAMPLE / 8) as u32;
+
+/// The sizes in the header are 32 bit, so a file holds at most 4GB, about
+/// 12 hours at 48kHz
+const MAX_SAMPLES: u32 = (u32::MAX - (HEADER_SIZE - 8)) / BYTES_PER_SAMPLE;
+
+/// The names of the APU channels, in the order of their stems
+pub const STEM_NAMES: [&str; 5] = ["pulse1", "pulse2", "triangle", "noise", "dmc"];
+
+/// Writes samples from -1.0 to 1.0 (clamping any outside that range) to a
+/// mono 16 bit PCM WAV file
+pub struct WavWriter<W: Write + Seek> {
+  writer: W,
+  samples: u32,
+  /// Samples written between updates of the header
+  header_interval: u32,
+  /// `samples` when the header was last updated
+  header_samples: u32,
+}
+
+impl WavWriter<BufWriter<File>> {
+  pub fn create<P: AsRef<Path>>(path: P, sample_rate: u32) -> io::Result<Self> {
+    WavWriter::new(BufWriter::new(File::create(path)?), sample_rate)
+  }
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+  pub fn new(mut writer: W, sample_rate: u32) -> io::Result<Self> {
+    let block_align = CHANNELS * BITS_PER_SAMPLE / 8;
+    let byte_rate = sample_rate * u32::from(block_align);
+
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&u32_bytes(HEADER_SIZE - 8))?;
+    writer.write_all(b"WAVE")?;
+
+    writer.write_all(b"fmt ")?;
+    writer.write_all(&u32_bytes(16))?;
+    writer.write_all(&u16_bytes(1))?; // PCM
+    writer.write_all(&u16_bytes(CHANNELS))?;
+    writer.write_all(&u32_bytes(sample_rate))?;
+    writer.write_all(&u32_bytes(byte_rate))?;
+    writer.write_all(&u16_bytes(block_align))?;
+    writer.write_all(&u16_bytes(BITS_PER_SAMPLE))?;
+
+    writer.write_all(b"data")?;
+    writer.write_all(&u32_bytes(0))?;
+
+    Ok(WavWriter {
+      writer,
+      samples: 0,
+      header_interval: sample_rate.max(1),
+      header_samples: 0,
+    })
+  }
+
+  /// Append samples to the file, updating the header if a second's worth
+  /// have been written since it last was. Once the file is full, the
+  /// samples that don't fit are dropped and an error is returned.
+  pub fn write_samples(&mut self, samples: &[f32]) -> io::Result<()> {
+    let space = (MAX_SAMPLES - self.samples) as usize;
+    for &sample in samples.iter().take(space) {
+      let value = (sample.clamp(-1.0, 1.0) * f32::from(i16::MAX)) as i16;
+      self.writer.write_all(&u16_bytes(value as u16))?;
+    }
+    self.samples += samples.len().min(space) as u32;
+
+    if samples.len() > space {
+      self.update_header()?;
+      return Err(io::Error::new(
+        io::ErrorKind::WriteZero,
+        "the WAV file has reached its 4GB limit",
+      ));
+    }
+    if self.samples - self.header_samples >= self.header_interval {
+      self.update_header()?;
+    }
+    Ok(())
+  }
+
+  /// The number of samples written so far
+  pub fn samples(&self) -> u32 {
+    self.samples
+  }
+
+  /// Update the header to include every sample written, which happens
+  /// anyway when the writer is dropped, but without reporting errors
+  pub fn finish(&mut self) -> io::Result<()> {
+    self.update_header()
+  }
+
+  fn update_header(&mut self) -> io::Result<()> {
+    self.header_samples = self.samples;
+    let data_size = self.samples * BYTES_PER_SAMPLE;
+    self.writer.seek(SeekFrom::Start(4))?;
+    self
+      .writer
+      .write_all(&u32_bytes(HEADER_SIZE - 8 + data_size))?;
+    self
+      .writer
+      .seek(SeekFrom::Start(u64::from(HEADER_SIZE) - 4))?;
+    self.writer.write_all(&u32_bytes(data_size))?;
+    self.writer.seek(SeekFrom::End(0))?;
+    self.writer.flush()
+  }
+}
+
+impl<W: Write + Seek> Drop for WavWriter<W> {
+  fn drop(&mut self) {
+    let _ = self.update_header();
+  }
+}
+
+fn u16_bytes(value: u16) -> [u8; 2] {
+  [value as u8, (value >> 8) as u8]
+}
+
+fn u32_bytes(value: u32) -> [u8; 4] {
+  [
+    value as u8,
+    (value >> 8) as u8,
+    (value >> 16) as u8,
+    (value >> 24) as u8,
+  ]
+}
+
+/// Records the mixed APU output to a WAV file, and optionally each channel
+/// to its own WAV file alongside it (eg, `music.wav` has the stems
+/// `music.pulse1.wav`, `music.pulse2.wav`, etc)
+pub struct WavRecorder {
+  mix: WavWriter<BufWriter<File>>,
+  stems: Option<Vec<WavWriter<BufWriter<File>>>>,
+}
+
+impl WavRecorder {
+  pub fn create<P: AsRef<Path>>(path: P, sample_rate: u32, stems: bool) -> io::Result<Self> {
+    let path = path.as_ref();
+    let stems = if stems {
+      let writers = STEM_NAMES
+        .iter()
+        .map(|name| WavWriter::create(stem_path(path, name), sample_rate))
+        .collect::<io::Result<_>>()?;
+      Some(writers)
+    } else {
+      None
+    };
+
+    Ok(WavRecorder {
+      mix: WavWriter::create(path, sample_rate)?,
+      stems,
+    })
+  }
+
+  /// Whether each channel is being recorded as well as the mix
+  pub fn has_stems(&self) -> bool {
+    self.stems.is_some()
+  }
+
+  pub fn write_mix(&mut self, samples: &[f32]) -> io::Result<()> {
+    self.mix.write_samples(samples)
+  }
+
+  /// Write the samples of each channel, in the order of `STEM_NAMES`.
+  /// Does nothing if the stems aren't being recorded.
+  pub fn write_stems(&mut self, channels: &[Vec<f32>]) -> io::Result<()> {
+    if let Some(ref mut stems) = self.stems {
+      for (stem, samples) in stems.iter_mut().zip(channels) {
+        stem.write_samples(samples)?;
+      }
+    }
+    Ok(())
+  }
+
+  /// Update the headers of the mix and the stems, reporting any errors
+  pub fn finish(&mut self) -> io::Result<()> {
+    self.mix.finish()?;
+    if let Some(ref mut stems) = self.stems {
+      for stem in stems {
+        stem.finish()?;
+      }
+    }
+    Ok(())
+  }
+}
+
+/// The path of a channel's stem, next to the mix's path
+pub fn stem_path(path: &Path, name: &str) -> PathBuf {
+  let stem = path.file_stem().map_or("recording".into(), |stem| {
+    stem.to_string_lossy().into_owned()
+  });
+  path.with_file_name(format!("{}.{}.wav", stem, name))
+}
+
+/// A path for a test's WAV file in the temp directory, unique to the
+/// process and test, which is removed along with any stems when dropped
+#[cfg(test)]
+pub struct TempWav(PathBuf);
+
+#[cfg(test)]
+impl TempWav {
+  pub fn new(test: &str) -> Self {
+    let name = format!("nes-{}-{}.wav", ::std::process::id(), test);
+    TempWav(::std::env::temp_dir().join(name))
+  }
+
+  pub fn path(&self) -> &Path {
+    &self.0
+  }
+}
+
+#[cfg(test)]
+impl Drop for TempWav {
+  fn drop(&mut self) {
+    let _ = ::std::fs::remove_file(&self.0);
+    for name in STEM_NAMES.iter() {
+      let _ = ::std::fs::remove_file(stem_path(&self.0, name));
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::io::Cursor;
+
+  fn u32_at(data: &[u8], offset: usize) -> u32 {
+    data[offset..offset + 4]
+      .iter()
+      .rev()
+      .fold(0, |value, &byte| value << 8 | u32::from(byte))
+  }
+
+  #[test]
+  fn writes_header() {
+    let mut data = Cursor::new(vec![]);
+    WavWriter::new(&mut data, 48_000).unwrap();
+    let data = data.into_inner();
+    assert_eq!(data.len(), HEADER_SIZE as usize);
+    assert_eq!(&data[0..4], b"RIFF");
+    assert_eq!(u32_at(&data, 4), 36);
+    assert_eq!(&data[8..16], b"WAVEfmt ");
+    assert_eq!(u32_at(&data, 24), 48_000);
+    assert_eq!(u32_at(&data, 28), 96_000);
+    assert_eq!(&data[36..40], b"data");
+    assert_eq!(u32_at(&data, 40), 0);
+  }
+
+  #[test]
+  fn writes_samples_and_sizes() {
+    let mut data = Cursor::new(vec![]);
+    {
+      let mut writer = WavWriter::new(&mut data, 48_000).unwrap();
+      writer.write_samples(&[0.0, 1.0]).unwrap();
+      writer.write_samples(&[-1.0, 2.0]).unwrap();
+      assert_eq!(writer.samples(), 4);
+    }
+
+    let data = data.into_inner();
+    assert_eq!(data.len(), HEADER_SIZE as usize + 8);
+    assert_eq!(u32_at(&data, 4), 36 + 8);
+    assert_eq!(u32_at(&data, 40), 8);
+    assert_eq!(
+      &data[44..],
+      [0x00, 0x00, 0xFF, 0x7F, 0x01, 0x80, 0xFF, 0x7F]
+    );
+  }
+
+  #[test]
+  fn updates_header_each_second() {
+    let mut writer = WavWriter::new(Cursor::new(vec![]), 4).unwrap();
+    writer.write_samples(&[0.0; 3]).unwrap();
+    assert_eq!(u32_at(writer.writer.get_ref(), 40), 0);
+
+    writer.write_samples(&[0.0; 2]).unwrap();
+    assert_eq!(u32_at(writer.writer.get_ref(), 40), 10);
+
+    writer.write_samples(&[0.0]).unwrap();
+    writer.finish().unwrap();
+    assert_eq!(u32_at(writer.writer.get_ref(), 40), 12);
+    assert_eq!(writer.writer.get_ref().len(), HEADER_SIZE as usize + 12);
+  }
+
+  #[test]
+  fn stops_at_size_limit() {
+    let mut writer = WavWriter::new(Cursor::new(vec![]), 48_000).unwrap();
+    writer.samples = MAX_SAMPLES - 1;
+    assert!(writer.write_samples(&[0.5, 0.5]).is_err());
+    assert_eq!(writer.samples(), MAX_SAMPLES);
+    assert_eq!(writer.writer.get_ref().len(), HEADER_SIZE as usize + 2);
+
+    let data = writer.writer.get_ref();
+    assert_eq!(u32_at(data, 4), u32::MAX - 1);
+    assert_eq!(u32_at(data, 40), MAX_SAMPLES * BYTES_PER_SAMPLE);
+    assert!(writer.write_samples(&[0.5]).is_err());
+  }
+
+  #[test]
+  fn stem_paths() {
+    assert_eq!(
+      stem_path(Path::new("out/music.wav"), "pulse1"),
+      Path::new("out/music.pulse1.wav")
+    );
+    assert_eq!(
+      stem_path(Path::new("music"), "dmc"),
+      Path::new("music.dmc.wav")
+    );
+  }
+}
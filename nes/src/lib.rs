@@ -9,7 +9,7 @@ extern crate bitflags;
 
 extern crate bytes;
 extern crate core;
-extern crate rand;
+#[cfg(feature = "sdl")]
 extern crate sdl2;
 
 pub mod apu;
@@ -22,3 +22,6 @@ pub mod cpu;
 pub mod io;
 pub mod memory;
 pub mod ppu;
+
+#[cfg(test)]
+mod test_rom;
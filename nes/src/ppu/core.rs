@@ -1,30 +1,242 @@
+use cartridge::Cartridge;
 use io::video::VideoOutput;
 use memory::{ReadAddr, WriteAddr};
+use ppu::latch::Latch;
 use ppu::palette::Color;
+use ppu::pattern;
+use ppu::pattern::TileCache;
+use ppu::sprite;
+use ppu::sprite::{Sprite, SpriteRow};
 use ppu::vram;
 
+/// Number of cycles (dots) in each scanline
+const DOTS_PER_SCANLINE: u16 = 341;
+
+/// Number of scanlines in each frame, including vertical blanking
+const SCANLINES_PER_FRAME: u16 = 262;
+
+/// Control register 1 ($2000) flags
+const CR1_INCREMENT_32: u8 = 0b0000_0100;
+const CR1_SPRITE_TABLE: u8 = 0b0000_1000;
+const CR1_BACKGROUND_TABLE: u8 = 0b0001_0000;
+const CR1_SPRITE_8X16: u8 = 0b0010_0000;
+const CR1_NMI: u8 = 0b1000_0000;
+
+/// Control register 2 ($2001) flags
+const CR2_GREYSCALE: u8 = 0b0000_0001;
+const CR2_BACKGROUND_LEFT: u8 = 0b0000_0010;
+const CR2_SPRITES_LEFT: u8 = 0b0000_0100;
+const CR2_BACKGROUND: u8 = 0b0000_1000;
+const CR2_SPRITES: u8 = 0b0001_0000;
+
+/// Status register ($2002) flags
+const SR_VBLANK: u8 = 0b1000_0000;
+const SR_SPRITE_0_HIT: u8 = 0b0100_0000;
+const SR_SPRITE_OVERFLOW: u8 = 0b0010_0000;
+const SR_MASK: u8 = SR_VBLANK | SR_SPRITE_0_HIT | SR_SPRITE_OVERFLOW;
+
+/// Bits 2-4 of sprite attributes are unimplemented and always read as 0
+const OAM_ATTRIBUTE_MASK: u8 = 0b1110_0011;
+
+/// Palette entries are 6 bits, the top 2 bits of a palette read are open bus
+const PALETTE_MASK: u8 = 0b0011_1111;
+
+/// How the PPU turns its memory into pixels
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RenderMode {
+  /// Fetch tiles and output pixels one dot at a time like the real PPU,
+  /// so changes made in the middle of a scanline show up straight away.
+  Accurate,
+
+  /// Render each visible scanline all at once on its first dot, using
+  /// decoded tiles from a cache. Much faster, but changes made in the
+  /// middle of a scanline only show up on the next one. Vertical blanking,
+  /// NMIs and sprite 0 hits still happen on the same dots as `Accurate`.
+  Scanline,
+}
+
 pub struct Core {
   scanline: u16,
   cycle: u16,
+  /// Total number of cycles since power on
+  total_cycles: u64,
+  /// Number of frames since power on, odd frames are one cycle shorter
+  frame: u64,
+  mode: RenderMode,
   video_output: Box<VideoOutput>,
   vram: vram::Memory,
   spr_ram: [u8; 0x0100],
   reg: Registers,
+  latch: Latch,
+
+  /// Set when an NMI should be sent to the CPU
+  nmi: bool,
+
+  background: Background,
+
+  /// Sprites found on this scanline which will be shown on the next
+  next_sprites: Vec<Sprite>,
+
+  /// Sprite rows fetched for the next scanline
+  sprite_rows: Vec<SpriteRow>,
+
+  /// Low bit plane of the sprite row being fetched
+  sprite_lo: u8,
+
+  /// Sprite pixels for the current scanline (see `sprite::render_line`)
+  sprite_line: [u8; 256],
+
+  /// In scanline mode, the dot on which the sprite 0 hit flag gets set
+  sprite_0_hit_dot: Option<u16>,
+
+  tile_cache: TileCache,
 }
 
+#[derive(Default)]
 struct Registers {
   cr1: u8,
   cr2: u8,
   sr: u8,
+
+  /// OAM (sprite RAM) address, set through $2003
+  oam_addr: u8,
+
+  /// Current VRAM address (15 bits)
+  vram_addr: u16,
+
+  /// Temporary VRAM address (15 bits), set through $2005 and $2006
+  temp_addr: u16,
+
+  /// Fine X scroll (3 bits)
+  fine_x: u8,
+
+  /// Toggles between the first and second write to $2005 and $2006
+  write_toggle: bool,
+
+  /// Reads of $2007 (outside of the palettes) are delayed through this buffer
+  read_buffer: u8,
 }
 
-impl Default for Registers {
-  fn default() -> Self {
-    Registers {
-      cr1: 0b0000_0000,
-      cr2: 0b0000_0000,
-      sr: 0b0000_0000,
+/// During rendering the VRAM address doubles as the scroll position:
+///
+/// ```text
+/// yyy NN YYYYY XXXXX
+/// ||| || ||||| +++++-- coarse X scroll
+/// ||| || +++++-------- coarse Y scroll
+/// ||| ++-------------- name table select
+/// +++----------------- fine Y scroll
+/// ```
+impl Registers {
+  /// Copy the horizontal scroll position from the temporary address
+  fn copy_x(&mut self) {
+    self.vram_addr = (self.vram_addr & !0x041F) | (self.temp_addr & 0x041F);
+  }
+
+  /// Copy the vertical scroll position from the temporary address
+  fn copy_y(&mut self) {
+    self.vram_addr = (self.vram_addr & !0x7BE0) | (self.temp_addr & 0x7BE0);
+  }
+
+  /// Move the VRAM address down a row of pixels, wrapping from the bottom
+  /// of a name table into the one below it
+  fn increment_y(&mut self) {
+    let addr = self.vram_addr;
+    if addr & 0x7000 != 0x7000 {
+      self.vram_addr = addr + 0x1000;
+      return;
     }
+
+    let addr = addr & !0x7000;
+    let coarse_y = (addr & 0x03E0) >> 5;
+    let (coarse_y, addr) = match coarse_y {
+      29 => (0, addr ^ 0x0800),
+      // Out of bounds scroll values wrap without switching name table
+      31 => (0, addr),
+      _ => (coarse_y + 1, addr),
+    };
+    self.vram_addr = (addr & !0x03E0) | (coarse_y << 5);
+  }
+}
+
+/// Move a VRAM address across to the next tile, wrapping from the right of
+/// a name table into the one next to it
+fn next_tile(addr: u16) -> u16 {
+  if addr & 0x001F == 31 {
+    (addr & !0x001F) ^ 0x0400
+  } else {
+    addr + 1
+  }
+}
+
+/// Address of the attribute byte for the tile at a VRAM address
+fn attribute_addr(addr: u16) -> u16 {
+  0x23C0 | (addr & 0x0C00) | ((addr >> 4) & 0x38) | ((addr >> 2) & 0x07)
+}
+
+/// Each attribute byte holds the palettes for 4 quadrants of 2x2 tiles
+fn attribute_palette(attribute: u8, addr: u16) -> u8 {
+  let shift = ((addr >> 4) & 0x04) | (addr & 0x02);
+  (attribute >> shift) & 0x03
+}
+
+/// Choose between a background pixel and a sprite pixel from the sprite
+/// line, returning the palette entry to show and whether the pixels
+/// caused a sprite 0 hit (which can't happen on the last dot)
+fn priority(background: u8, sprite: u8) -> (u8, bool) {
+  let background_opaque = background & 0x03 != 0;
+  let sprite_opaque = sprite & 0x03 != 0;
+
+  let hit = background_opaque && sprite_opaque && sprite & sprite::LINE_SPRITE_0 != 0;
+
+  let entry =
+    if sprite_opaque && (!background_opaque || sprite & sprite::LINE_BEHIND_BACKGROUND == 0) {
+      0x10 | (sprite & 0x0F)
+    } else if background_opaque {
+      background & 0x0F
+    } else {
+      0x00
+    };
+  (entry, hit)
+}
+
+/// Background tile fetches and the shift registers which feed pixels out
+/// on each dot, in accurate mode.
+#[derive(Default)]
+struct Background {
+  next_tile: u8,
+  next_palette: u8,
+  next_lo: u8,
+  next_hi: u8,
+  shift_lo: u16,
+  shift_hi: u16,
+  shift_palette_lo: u16,
+  shift_palette_hi: u16,
+}
+
+impl Background {
+  fn shift(&mut self) {
+    self.shift_lo <<= 1;
+    self.shift_hi <<= 1;
+    self.shift_palette_lo <<= 1;
+    self.shift_palette_hi <<= 1;
+  }
+
+  /// Load the fetched tile into the low bytes of the shift registers
+  fn load(&mut self) {
+    let expand = |bit: u8| if bit != 0 { 0x00FF } else { 0x0000 };
+    self.shift_lo = (self.shift_lo & 0xFF00) | u16::from(self.next_lo);
+    self.shift_hi = (self.shift_hi & 0xFF00) | u16::from(self.next_hi);
+    self.shift_palette_lo = (self.shift_palette_lo & 0xFF00) | expand(self.next_palette & 0x01);
+    self.shift_palette_hi = (self.shift_palette_hi & 0xFF00) | expand(self.next_palette & 0x02);
+  }
+
+  /// The current pixel value in bits 0-1 and its palette in bits 2-3
+  fn pixel(&self, fine_x: u8) -> u8 {
+    let bit = 15 - u16::from(fine_x);
+    let pixel = ((self.shift_lo >> bit) & 1) | (((self.shift_hi >> bit) & 1) << 1);
+    let palette =
+      ((self.shift_palette_lo >> bit) & 1) | (((self.shift_palette_hi >> bit) & 1) << 1);
+    (pixel | (palette << 2)) as u8
   }
 }
 
@@ -48,41 +260,502 @@ impl Core {
       // Start on pre-render scanline
       scanline: 261,
       cycle: 0,
+      total_cycles: 0,
+      frame: 0,
+      mode: RenderMode::Accurate,
       video_output,
       vram: vram::Memory::default(),
       spr_ram: [0x00; 0x0100],
       reg: Registers::default(),
+      latch: Latch::default(),
+      nmi: false,
+      background: Background::default(),
+      next_sprites: Vec::with_capacity(sprite::MAX_SPRITES),
+      sprite_rows: Vec::with_capacity(sprite::MAX_SPRITES),
+      sprite_lo: 0x00,
+      sprite_line: [0x00; 256],
+      sprite_0_hit_dot: None,
+      tile_cache: TileCache::default(),
+    }
+  }
+
+  pub fn render_mode(&self) -> RenderMode {
+    self.mode
+  }
+
+  pub fn set_render_mode(&mut self, mode: RenderMode) {
+    self.mode = mode;
+    self.sprite_0_hit_dot = None;
+  }
+
+  /// Returns true (once) when the PPU has signalled an NMI to the CPU
+  pub fn take_nmi(&mut self) -> bool {
+    let nmi = self.nmi;
+    self.nmi = false;
+    nmi
+  }
+
+  /// Throw away decoded tiles, eg, after the mapper switches CHR banks
+  pub fn invalidate_tile_cache(&mut self) {
+    self.tile_cache.invalidate();
+  }
+
+  fn rendering_enabled(&self) -> bool {
+    self.reg.cr2 & (CR2_BACKGROUND | CR2_SPRITES) != 0
+  }
+
+  fn sprite_height(&self) -> u16 {
+    if self.reg.cr1 & CR1_SPRITE_8X16 != 0 {
+      16
+    } else {
+      8
+    }
+  }
+
+  fn sprite_table(&self) -> u16 {
+    if self.reg.cr1 & CR1_SPRITE_TABLE != 0 {
+      0x1000
+    } else {
+      0x0000
+    }
+  }
+
+  fn background_table(&self) -> u16 {
+    if self.reg.cr1 & CR1_BACKGROUND_TABLE != 0 {
+      0x1000
+    } else {
+      0x0000
+    }
+  }
+
+  /// Read from the PPU address space
+  fn read_memory(&mut self, addr: u16, cartridge: &mut Cartridge) -> u8 {
+    let addr = addr & 0x3FFF;
+    cartridge.ppu_address(addr);
+    match addr {
+      0x0000...0x1FFF => cartridge.read_chr(addr),
+      0x2000...0x3EFF => self.read_name_table(addr, cartridge),
+      _ => self.vram.read_addr(addr),
+    }
+  }
+
+  /// Read a name table or attribute byte without showing the address to
+  /// the cartridge
+  fn read_name_table(&mut self, addr: u16, cartridge: &mut Cartridge) -> u8 {
+    match cartridge.read_nametable(addr) {
+      Some(value) => value,
+      None => self
+        .vram
+        .read_addr(cartridge.mirroring().nametable_addr(addr)),
+    }
+  }
+
+  /// Write to the PPU address space
+  fn write_memory(&mut self, addr: u16, value: u8, cartridge: &mut Cartridge) {
+    let addr = addr & 0x3FFF;
+    cartridge.ppu_address(addr);
+    match addr {
+      0x0000...0x1FFF => {
+        cartridge.write_chr(addr, value);
+        self.tile_cache.invalidate();
+      }
+      0x2000...0x3EFF => {
+        if !cartridge.write_nametable(addr, value) {
+          self
+            .vram
+            .write_addr(cartridge.mirroring().nametable_addr(addr), value);
+        }
+      }
+      _ => {
+        self.vram.write_addr(addr, value);
+      }
+    }
+  }
+
+  /// Vertical blanking starts on the second cycle of scanline 241
+  fn start_vblank(&mut self) {
+    self.reg.sr |= SR_VBLANK;
+    if self.reg.cr1 & CR1_NMI != 0 {
+      self.nmi = true;
+    }
+  }
+
+  /// Odd frames skip the last cycle of the prerender scanline when
+  /// rendering is enabled
+  fn skip_odd_frame_cycle(&mut self) {
+    if self.frame % 2 == 1 {
+      self.cycle = 340;
     }
   }
 
-  /// The prerender scanline loads
   fn cycle_vblank(&mut self) {
+    if self.scanline == 241 && self.cycle == 1 {
+      self.start_vblank();
+    }
+  }
+
+  /// The prerender scanline clears the status flags on its second cycle,
+  /// and fetches the first tiles for scanline 0
+  fn cycle_prerender(&mut self, cartridge: &mut Cartridge) {
     if self.cycle == 1 {
-      // Set vblank flag
+      self.reg.sr &= !SR_MASK;
+    }
+
+    self.cycle_render(cartridge, false);
+
+    if !self.rendering_enabled() {
+      return;
+    }
+    if self.cycle >= 280 && self.cycle <= 304 {
+      self.reg.copy_y();
+    }
+    if self.cycle == 339 {
+      self.skip_odd_frame_cycle();
+    }
+  }
+
+  /// Rendering work shared by the visible and prerender scanlines
+  fn cycle_render(&mut self, cartridge: &mut Cartridge, visible: bool) {
+    let rendering = self.rendering_enabled();
+
+    if visible && self.cycle == 1 {
+      sprite::render_line(&self.sprite_rows, &mut self.sprite_line);
+    }
+    if rendering {
+      self.fetch_background(cartridge);
+    }
+    if visible && self.cycle >= 1 && self.cycle <= 256 {
+      self.output_dot();
+    }
+
+    if !rendering {
+      return;
+    }
+    if self.cycle == 256 {
+      self.reg.increment_y();
+    }
+    if self.cycle == 257 {
+      self.reg.copy_x();
+      self.evaluate_sprites(visible);
+    }
+    if self.cycle >= 257 && self.cycle <= 320 {
+      self.fetch_sprite_slot(cartridge);
+    }
+  }
+
+  /// Scanline mode only does work on the handful of dots where something
+  /// visible to the CPU happens, rendering each scanline on its first dot.
+  fn cycle_scanline_mode(&mut self, cartridge: &mut Cartridge) {
+    if self.sprite_0_hit_dot == Some(self.cycle) {
+      self.sprite_0_hit_dot = None;
+      self.reg.sr |= SR_SPRITE_0_HIT;
+    }
+
+    let visible = self.scanline < 240;
+    let prerender = self.scanline == 261;
+    match self.cycle {
+      1 if visible => {
+        sprite::render_line(&self.sprite_rows, &mut self.sprite_line);
+        self.render_scanline(cartridge);
+      }
+      1 if prerender => {
+        self.reg.sr &= !SR_MASK;
+        self.sprite_0_hit_dot = None;
+        // The background is fetched but not drawn, which only the
+        // cartridge can see
+        if self.rendering_enabled() {
+          cartridge.ppu_address(self.background_table());
+        }
+      }
+      1 if self.scanline == 241 => self.start_vblank(),
+      256...339 if (visible || prerender) && self.rendering_enabled() => match self.cycle {
+        256 => self.reg.increment_y(),
+        257 => {
+          self.reg.copy_x();
+          self.evaluate_sprites(visible);
+          self.fetch_all_sprites(cartridge);
+        }
+        304 if prerender => self.reg.copy_y(),
+        339 if prerender => self.skip_odd_frame_cycle(),
+        _ => (),
+      },
+      _ => (),
     }
   }
-  fn cycle_visible(&mut self, _render: bool) {
-    self
-      .video_output
-      .output_pixel(Color(self.cycle as u8, (self.cycle >> 8) as u8, 0x30));
+
+  /// Fetch background tiles into the shift registers, one memory access
+  /// every other dot like the real PPU.
+  fn fetch_background(&mut self, cartridge: &mut Cartridge) {
+    let cycle = self.cycle;
+    if (2..=257).contains(&cycle) || (321..=337).contains(&cycle) {
+      self.background.shift();
+
+      let addr = self.reg.vram_addr;
+      let pattern_addr =
+        self.background_table() + u16::from(self.background.next_tile) * 16 + ((addr >> 12) & 0x07);
+      match (cycle - 1) % 8 {
+        0 => {
+          self.background.load();
+          self.background.next_tile = self.read_memory(0x2000 | (addr & 0x0FFF), cartridge);
+        }
+        2 => {
+          let attribute = self.read_memory(attribute_addr(addr), cartridge);
+          self.background.next_palette = attribute_palette(attribute, addr);
+        }
+        4 => self.background.next_lo = self.read_memory(pattern_addr, cartridge),
+        6 => self.background.next_hi = self.read_memory(pattern_addr + 8, cartridge),
+        7 => self.reg.vram_addr = next_tile(addr),
+        _ => (),
+      }
+    }
+
+    // Unused name table fetches at the end of the scanline
+    if cycle == 338 || cycle == 340 {
+      let addr = 0x2000 | (self.reg.vram_addr & 0x0FFF);
+      self.read_memory(addr, cartridge);
+    }
+  }
+
+  /// Find the sprites for the next scanline. Nothing is shown on the first
+  /// scanline, as the prerender scanline doesn't evaluate sprites.
+  fn evaluate_sprites(&mut self, visible: bool) {
+    self.next_sprites.clear();
+    self.sprite_rows.clear();
+    if !visible {
+      return;
+    }
+
+    let evaluation = sprite::evaluate(&self.spr_ram, self.scanline, self.sprite_height());
+    if evaluation.overflow {
+      self.reg.sr |= SR_SPRITE_OVERFLOW;
+    }
+    self.next_sprites = evaluation.sprites;
+  }
+
+  /// Fetch the patterns for the sprites on the next scanline, one sprite
+  /// slot every 8 dots with the pattern fetched on the last 4. Empty slots
+  /// still fetch a (discarded) pattern.
+  fn fetch_sprite_slot(&mut self, cartridge: &mut Cartridge) {
+    let height = self.sprite_height();
+    let table = self.sprite_table();
+    let slot = usize::from(self.cycle - 257) / 8;
+    let sprite = self.next_sprites.get(slot).cloned();
+    let addr = match sprite {
+      Some(ref sprite) => sprite.pattern_addr(self.scanline, height, table),
+      None => sprite::empty_pattern_addr(height, table),
+    };
+
+    match (self.cycle - 257) % 8 {
+      5 => self.sprite_lo = self.read_memory(addr, cartridge),
+      7 => {
+        let hi = self.read_memory(addr + 8, cartridge);
+        if let Some(sprite) = sprite {
+          let row = SpriteRow::new(&sprite, self.sprite_lo, hi);
+          self.sprite_rows.push(row);
+        }
+      }
+      _ => (),
+    }
+  }
+
+  /// Fetch the patterns for all the sprites on the next scanline at once.
+  /// Empty slots aren't fetched, but their address is still shown to the
+  /// cartridge for mappers which count scanlines.
+  fn fetch_all_sprites(&mut self, cartridge: &mut Cartridge) {
+    let height = self.sprite_height();
+    let table = self.sprite_table();
+    for i in 0..self.next_sprites.len() {
+      let sprite = self.next_sprites[i];
+      let addr = sprite.pattern_addr(self.scanline, height, table);
+      let lo = self.read_memory(addr, cartridge);
+      let hi = self.read_memory(addr + 8, cartridge);
+      self.sprite_rows.push(SpriteRow::new(&sprite, lo, hi));
+    }
+    if self.next_sprites.len() < sprite::MAX_SPRITES {
+      cartridge.ppu_address(sprite::empty_pattern_addr(height, table));
+    }
+  }
+
+  /// Combine a background pixel with a sprite pixel from the sprite line,
+  /// returning the palette entry to show and whether the pixels caused a
+  /// sprite 0 hit.
+  fn compose(&self, x: usize, background: u8, sprite: u8) -> (u8, bool) {
+    let (show_background, show_sprites) = self.shown_layers(x);
+    let background = if show_background { background } else { 0 };
+    let sprite = if show_sprites { sprite } else { 0 };
+    let (entry, hit) = priority(background, sprite);
+    (entry, hit && x != 255)
+  }
+
+  /// Whether the background and the sprites are shown at a dot
+  fn shown_layers(&self, x: usize) -> (bool, bool) {
+    (
+      self.reg.cr2 & CR2_BACKGROUND != 0 && (x >= 8 || self.reg.cr2 & CR2_BACKGROUND_LEFT != 0),
+      self.reg.cr2 & CR2_SPRITES != 0 && (x >= 8 || self.reg.cr2 & CR2_SPRITES_LEFT != 0),
+    )
   }
 
-  pub fn cycle(&mut self) {
+  /// With rendering disabled, the backdrop colour is shown unless the VRAM
+  /// address points into the palettes, in which case that colour is shown.
+  fn backdrop_entry(&self) -> u8 {
+    if self.reg.vram_addr & 0x3F00 == 0x3F00 {
+      (self.reg.vram_addr & 0x1F) as u8
+    } else {
+      0x00
+    }
+  }
+
+  fn output_palette_entry(&mut self, entry: u8) {
+    let mut index = self.vram.read_addr(0x3F00 | u16::from(entry)) & PALETTE_MASK;
+    if self.reg.cr2 & CR2_GREYSCALE != 0 {
+      index &= 0x30;
+    }
+    let emphasis = self.reg.cr2 >> 5;
+    self.video_output.output_palette_index(index, emphasis);
+  }
+
+  /// Output a single pixel from the background shift registers and the
+  /// sprite line (accurate mode)
+  fn output_dot(&mut self) {
+    let x = usize::from(self.cycle - 1);
+    if !self.rendering_enabled() {
+      let entry = self.backdrop_entry();
+      self.output_palette_entry(entry);
+      return;
+    }
+
+    let background = self.background.pixel(self.reg.fine_x);
+    let (entry, hit) = self.compose(x, background, self.sprite_line[x]);
+    if hit {
+      self.reg.sr |= SR_SPRITE_0_HIT;
+    }
+    self.output_palette_entry(entry);
+  }
+
+  /// Render a whole scanline from the current scroll position (scanline
+  /// mode). Sprite 0 hits are scheduled for the dot they would have
+  /// happened on in accurate mode.
+  fn render_scanline(&mut self, cartridge: &mut Cartridge) {
+    let mut palettes = [0x00; 0x20];
+    for (entry, index) in palettes.iter_mut().enumerate() {
+      *index = self.vram.read_addr(0x3F00 | entry as u16) & PALETTE_MASK;
+      if self.reg.cr2 & CR2_GREYSCALE != 0 {
+        *index &= 0x30;
+      }
+    }
+    let emphasis = self.reg.cr2 >> 5;
+
+    let mut line = [0x00; 256];
+    if !self.rendering_enabled() {
+      let backdrop = palettes[usize::from(self.backdrop_entry())];
+      for index in line.iter_mut() {
+        *index = backdrop;
+      }
+      self.video_output.output_palette_indices(&line, emphasis);
+      return;
+    }
+
+    // The background patterns come from the tile cache, so show the
+    // cartridge where they would have been fetched from
+    cartridge.ppu_address(self.background_table());
+
+    // 33 tiles cover the scanline when it's scrolled part way into a tile
+    let mut background = [0x00; 33 * 8];
+    if self.reg.cr2 & CR2_BACKGROUND != 0 {
+      self.render_background(cartridge, &mut background);
+    }
+    let fine_x = usize::from(self.reg.fine_x);
+    let background = &mut background[fine_x..fine_x + 256];
+
+    // Hide the layers which aren't shown, once for the whole line rather
+    // than on every dot
+    let mut sprites = self.sprite_line;
+    for x in 0..256 {
+      let (show_background, show_sprites) = self.shown_layers(x);
+      if show_background && show_sprites {
+        break;
+      }
+      if !show_background {
+        background[x] = 0;
+      }
+      if !show_sprites {
+        sprites[x] = 0;
+      }
+    }
+
+    self.sprite_0_hit_dot = None;
+    for (x, index) in line.iter_mut().enumerate() {
+      let (entry, hit) = priority(background[x], sprites[x]);
+      if hit && x != 255 && self.sprite_0_hit_dot.is_none() && self.reg.sr & SR_SPRITE_0_HIT == 0 {
+        self.sprite_0_hit_dot = Some(x as u16 + 1);
+      }
+      *index = palettes[usize::from(entry)];
+    }
+    self.video_output.output_palette_indices(&line, emphasis);
+  }
+
+  /// Render the 33 background tiles from the current scroll position,
+  /// without moving the VRAM address on. Tiles come from the tile cache,
+  /// unless the mapper needs to see every fetch to switch banks.
+  ///
+  /// The cartridge has already been shown the background table, so the
+  /// name table and attribute bytes are read without showing it their
+  /// addresses, and each attribute byte is only read once for the 4 tiles
+  /// it covers.
+  fn render_background(&mut self, cartridge: &mut Cartridge, line: &mut [u8; 33 * 8]) {
+    let table = self.background_table();
+    let switches_banks = cartridge.chr_reads_switch_banks();
+    let mut addr = self.reg.vram_addr;
+    let fine_y = (addr >> 12) & 0x07;
+    let mut attribute = 0x00;
+
+    for (i, tile) in line.chunks_mut(8).enumerate() {
+      let index = self.read_name_table(0x2000 | (addr & 0x0FFF), cartridge);
+      if i == 0 || addr & 0x03 == 0 {
+        attribute = self.read_name_table(attribute_addr(addr), cartridge);
+      }
+      let palette = attribute_palette(attribute, addr) << 2;
+
+      let pattern_addr = table + u16::from(index) * 16 + fine_y;
+      let row = if switches_banks {
+        let lo = self.read_memory(pattern_addr, cartridge);
+        let hi = self.read_memory(pattern_addr + 8, cartridge);
+        pattern::decode_row(lo, hi)
+      } else {
+        self.tile_cache.row(pattern_addr, cartridge)
+      };
+      // Transparent pixels keep their palette, as in the shift registers,
+      // which saves an unpredictable branch per pixel
+      for (pixel, &value) in tile.iter_mut().zip(row.iter()) {
+        *pixel = value | palette;
+      }
+      addr = next_tile(addr);
+    }
+  }
+
+  pub fn cycle(&mut self, cartridge: &mut Cartridge) {
+    self.total_cycles += 1;
     self.cycle += 1;
-    if self.cycle == 342 {
+    if self.cycle == DOTS_PER_SCANLINE {
       self.cycle = 0;
       self.scanline += 1;
       self.video_output.horizontal_sync();
 
-      if self.scanline == 262 {
+      if self.scanline == SCANLINES_PER_FRAME {
         self.scanline = 0;
+        self.frame += 1;
         self.video_output.vertical_sync();
       }
     }
 
+    if self.mode == RenderMode::Scanline {
+      self.cycle_scanline_mode(cartridge);
+      return;
+    }
+
     match self.scanline {
       // Prerender - same as a visible scanline but nothing is drawn
-      261 => self.cycle_visible(false),
+      261 => self.cycle_prerender(cartridge),
 
       // Postrender - PPU just idles on this scanline
       240 => (),
@@ -91,33 +764,559 @@ impl Core {
       s if s > 240 => self.cycle_vblank(),
 
       // Visible scanline (0-240)
-      _ => self.cycle_visible(true),
+      _ => self.cycle_render(cartridge, true),
     }
   }
-}
 
-impl ReadAddr for Core {
-  fn read_addr(&mut self, addr: u16) -> u8 {
+  /// Move the VRAM address on after an access through $2007
+  fn increment_vram_addr(&mut self) {
+    let increment = if self.reg.cr1 & CR1_INCREMENT_32 != 0 {
+      32
+    } else {
+      1
+    };
+    self.reg.vram_addr = (self.reg.vram_addr + increment) & 0x7FFF;
+  }
+
+  fn read_status(&mut self) -> u8 {
+    let status = self.reg.sr & SR_MASK;
+    self.reg.sr &= !SR_VBLANK;
+    self.reg.write_toggle = false;
+
+    // Only the status bits are driven, the rest are open bus
+    self.latch.refresh(status, SR_MASK, self.total_cycles);
+    self.latch.read(self.total_cycles)
+  }
+
+  fn read_oam(&mut self) -> u8 {
+    let addr = self.reg.oam_addr;
+    let value = match addr % 4 {
+      2 => self.spr_ram[usize::from(addr)] & OAM_ATTRIBUTE_MASK,
+      _ => self.spr_ram[usize::from(addr)],
+    };
+    self.latch.refresh(value, 0xFF, self.total_cycles);
+    value
+  }
+
+  fn read_data(&mut self, cartridge: &mut Cartridge) -> u8 {
+    let addr = self.reg.vram_addr & 0x3FFF;
+    self.increment_vram_addr();
+
+    if addr >= 0x3F00 {
+      // Palettes are read immediately, but the buffer is still filled
+      // with the name table "underneath" the palettes
+      self.reg.read_buffer = self.read_memory(addr - 0x1000, cartridge);
+      let value = self.read_memory(addr, cartridge) & PALETTE_MASK;
+      self.latch.refresh(value, PALETTE_MASK, self.total_cycles);
+      self.latch.read(self.total_cycles)
+    } else {
+      let value = self.reg.read_buffer;
+      self.reg.read_buffer = self.read_memory(addr, cartridge);
+      self.latch.refresh(value, 0xFF, self.total_cycles);
+      value
+    }
+  }
+
+  fn write_control(&mut self, value: u8) {
+    // Enabling NMIs during vertical blanking triggers one straight away
+    if self.reg.cr1 & CR1_NMI == 0 && value & CR1_NMI != 0 && self.reg.sr & SR_VBLANK != 0 {
+      self.nmi = true;
+    }
+    self.reg.cr1 = value;
+
+    // The base name table also goes to bits 10-11 of the temporary address
+    self.reg.temp_addr = (self.reg.temp_addr & 0x73FF) | (u16::from(value & 0x03) << 10);
+  }
+
+  fn write_scroll(&mut self, value: u8) {
+    if !self.reg.write_toggle {
+      self.reg.temp_addr = (self.reg.temp_addr & 0x7FE0) | u16::from(value >> 3);
+      self.reg.fine_x = value & 0x07;
+    } else {
+      let fine_y = u16::from(value & 0x07) << 12;
+      let coarse_y = u16::from(value >> 3) << 5;
+      self.reg.temp_addr = (self.reg.temp_addr & 0x0C1F) | fine_y | coarse_y;
+    }
+    self.reg.write_toggle = !self.reg.write_toggle;
+  }
+
+  /// The new address goes straight out on the PPU's address bus, which
+  /// mappers watching it see
+  fn write_addr_register(&mut self, value: u8, cartridge: &mut Cartridge) {
+    if !self.reg.write_toggle {
+      self.reg.temp_addr = (self.reg.temp_addr & 0x00FF) | (u16::from(value & 0x3F) << 8);
+    } else {
+      self.reg.temp_addr = (self.reg.temp_addr & 0x7F00) | u16::from(value);
+      self.reg.vram_addr = self.reg.temp_addr;
+      cartridge.ppu_address(self.reg.vram_addr & 0x3FFF);
+    }
+    self.reg.write_toggle = !self.reg.write_toggle;
+  }
+
+  fn write_data(&mut self, value: u8, cartridge: &mut Cartridge) {
+    let addr = self.reg.vram_addr & 0x3FFF;
+    self.write_memory(addr, value, cartridge);
+    self.increment_vram_addr();
+  }
+
+  /// Read one of the registers at $2000-$2007, which may access the
+  /// cartridge through $2007.
+  pub fn read_register(&mut self, addr: u16, cartridge: &mut Cartridge) -> u8 {
     match addr {
-      0x2002 => self.reg.sr,
+      0x2002 => self.read_status(),
+      0x2004 => self.read_oam(),
+      0x2007 => self.read_data(cartridge),
+      // Write only registers read back the I/O latch
+      0x2000 | 0x2001 | 0x2003 | 0x2005 | 0x2006 => self.latch.read(self.total_cycles),
       _ => panic!("ppu read: {:04X}", addr),
     }
   }
-}
 
-impl WriteAddr for Core {
-  fn write_addr(&mut self, addr: u16, value: u8) -> u8 {
+  /// Write one of the registers at $2000-$2007, which may access the
+  /// cartridge through $2007.
+  pub fn write_register(&mut self, addr: u16, value: u8, cartridge: &mut Cartridge) {
+    // Any write fills the I/O latch, even to the read only status register
+    self.latch.refresh(value, 0xFF, self.total_cycles);
+
     match addr {
-      0x2000 => {
-        self.reg.cr1 = value;
-        0x00
-      }
-      0x2001 => {
-        self.reg.cr2 = value;
-        0x00
+      0x2000 => self.write_control(value),
+      0x2001 => self.reg.cr2 = value,
+      0x2002 => (),
+      0x2003 => self.reg.oam_addr = value,
+      0x2004 => {
+        self.spr_ram[usize::from(self.reg.oam_addr)] = value;
+        self.reg.oam_addr = self.reg.oam_addr.wrapping_add(1);
       }
-      0x2002 => panic!("illegal write to PPU status register {:04X}", addr),
+      0x2005 => self.write_scroll(value),
+      0x2006 => self.write_addr_register(value, cartridge),
+      0x2007 => self.write_data(value, cartridge),
       _ => panic!("ppu write: {:04X}", addr),
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use cartridge::mirroring::Mirroring;
+  use cartridge::{a12_counting_cartridge, parse_rom_file, test_cartridge};
+  use io::video::{unpack_index, IndexedVideoOutput};
+  use ppu::latch::DECAY_CYCLES;
+  use std::sync::{Arc, Mutex};
+
+  const CYCLES_PER_FRAME: u64 = 341 * 262;
+
+  struct Ppu {
+    core: Core,
+    cartridge: Cartridge,
+  }
+
+  impl Ppu {
+    fn new() -> Self {
+      Ppu {
+        core: Core::default(),
+        cartridge: test_cartridge(Mirroring::Vertical),
+      }
+    }
+
+    /// A PPU whose frames are collected as palette indices
+    fn with_frames() -> (Self, Arc<Mutex<Vec<Vec<u8>>>>) {
+      let frames = Arc::new(Mutex::new(vec![]));
+      let output_frames = frames.clone();
+      let output = IndexedVideoOutput::new(move |_, frame| {
+        let indices = frame.iter().map(|&pixel| unpack_index(pixel).0).collect();
+        output_frames.lock().unwrap().push(indices);
+      });
+
+      let ppu = Ppu {
+        core: Core::new(Box::new(output)),
+        cartridge: test_cartridge(Mirroring::Vertical),
+      };
+      (ppu, frames)
+    }
+
+    fn read(&mut self, addr: u16) -> u8 {
+      self.core.read_register(addr, &mut self.cartridge)
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+      self.core.write_register(addr, value, &mut self.cartridge)
+    }
+
+    fn write_memory(&mut self, addr: u16, values: &[u8]) {
+      self.write(0x2006, (addr >> 8) as u8);
+      self.write(0x2006, addr as u8);
+      for &value in values {
+        self.write(0x2007, value);
+      }
+    }
+
+    fn run(&mut self, cycles: u64) {
+      for _ in 0..cycles {
+        self.core.cycle(&mut self.cartridge);
+      }
+    }
+
+    /// Run until the given scanline and dot have just been processed
+    fn run_to(&mut self, scanline: u16, cycle: u16) {
+      while self.core.scanline != scanline || self.core.cycle != cycle {
+        self.core.cycle(&mut self.cartridge);
+      }
+    }
+
+    /// Set up a scene with a scrolled background and some sprites, with
+    /// sprite 0 over the background
+    fn load_scene(&mut self) {
+      // Tile 1 is solid colour 1, tile 2 has colour 3 in its left half
+      self.write_memory(0x0010, &[0xFF; 8]);
+      self.write_memory(0x0020, &[0xF0; 16]);
+      self.write_memory(0x3F00, &[0x0F, 0x01, 0x02, 0x03, 0x0F, 0x05, 0x06, 0x07]);
+      self.write_memory(0x3F10, &[0x0F, 0x11, 0x12, 0x13, 0x0F, 0x15, 0x16, 0x17]);
+
+      let nametable: Vec<u8> = (0..0x3C0).map(|i| (i % 3) as u8).collect();
+      self.write_memory(0x2000, &nametable);
+      self.write_memory(0x23C0, &[0b1110_0100; 0x40]);
+      self.write_memory(0x2400, &[0x02; 0x3C0]);
+
+      let sprites = [[40, 2, 0x01, 83], [50, 1, 0x20, 10], [60, 2, 0x40, 250]];
+      self.write(0x2003, 0x00);
+      for sprite in sprites.iter() {
+        for &byte in sprite.iter() {
+          self.write(0x2004, byte);
+        }
+      }
+      for _ in sprites.len() * 4..0x100 {
+        self.write(0x2004, 0xFF);
+      }
+
+      // Writing $2006 left the second name table selected
+      self.write(0x2000, 0x00);
+      self.write(0x2005, 13);
+      self.write(0x2005, 5);
+      self.write(0x2001, CR2_BACKGROUND | CR2_SPRITES | CR2_BACKGROUND_LEFT);
+    }
+  }
+
+  #[test]
+  fn write_only_registers_read_latch() {
+    let mut ppu = Ppu::new();
+    ppu.write(0x2000, 0x5A);
+    for addr in [0x2000, 0x2001, 0x2003, 0x2005, 0x2006].iter() {
+      assert_eq!(ppu.read(*addr), 0x5A);
+    }
+  }
+
+  #[test]
+  fn status_low_bits_read_latch() {
+    let mut ppu = Ppu::new();
+    ppu.write(0x2002, 0xFF);
+    ppu.core.reg.sr = SR_VBLANK;
+
+    assert_eq!(ppu.read(0x2002), 0x9F);
+    // Reading the status refreshed the top bits of the latch
+    assert_eq!(ppu.read(0x2000), 0x9F);
+    assert_eq!(ppu.read(0x2002), 0x1F);
+  }
+
+  #[test]
+  fn status_read_clears_vblank_and_toggle() {
+    let mut ppu = Ppu::new();
+    ppu.core.reg.sr = SR_VBLANK | SR_SPRITE_0_HIT;
+    ppu.core.reg.write_toggle = true;
+
+    assert_eq!(ppu.read(0x2002) & SR_MASK, SR_VBLANK | SR_SPRITE_0_HIT);
+    assert_eq!(ppu.read(0x2002) & SR_MASK, SR_SPRITE_0_HIT);
+    assert!(!ppu.core.reg.write_toggle);
+  }
+
+  #[test]
+  fn oam_read_refreshes_latch() {
+    let mut ppu = Ppu::new();
+    ppu.write(0x2003, 0x02);
+    ppu.write(0x2004, 0xFF);
+    ppu.write(0x2003, 0x02);
+
+    // Unimplemented attribute bits read back as 0
+    assert_eq!(ppu.read(0x2004), 0xE3);
+    assert_eq!(ppu.read(0x2000), 0xE3);
+  }
+
+  #[test]
+  fn data_reads_are_buffered() {
+    let mut ppu = Ppu::new();
+    ppu.write_memory(0x2000, &[0x11, 0x22]);
+
+    ppu.write(0x2006, 0x20);
+    ppu.write(0x2006, 0x00);
+    ppu.read(0x2007);
+    assert_eq!(ppu.read(0x2007), 0x11);
+    assert_eq!(ppu.read(0x2007), 0x22);
+    // The data read refreshed the latch
+    assert_eq!(ppu.read(0x2000), 0x22);
+  }
+
+  #[test]
+  fn data_increments_by_32() {
+    let mut ppu = Ppu::new();
+    ppu.write(0x2000, CR1_INCREMENT_32);
+    ppu.write_memory(0x2000, &[0x11]);
+    assert_eq!(ppu.core.reg.vram_addr, 0x2020);
+  }
+
+  #[test]
+  fn data_goes_through_cartridge() {
+    let mut ppu = Ppu::new();
+    ppu.write_memory(0x0123, &[0x45]);
+    assert_eq!(ppu.cartridge.read_chr(0x0123), 0x45);
+
+    // Vertical mirroring
+    ppu.write_memory(0x2C00, &[0x67]);
+    ppu.write(0x2006, 0x24);
+    ppu.write(0x2006, 0x00);
+    ppu.read(0x2007);
+    assert_eq!(ppu.read(0x2007), 0x67);
+  }
+
+  #[test]
+  fn palette_reads_top_bits_from_latch() {
+    let mut ppu = Ppu::new();
+    ppu.write_memory(0x3F00, &[0x2A]);
+
+    ppu.write(0x2006, 0x3F);
+    ppu.write(0x2006, 0x00);
+    ppu.write(0x2002, 0xC0);
+    assert_eq!(ppu.read(0x2007), 0xEA);
+  }
+
+  #[test]
+  fn scroll_and_addr_share_temporary_address() {
+    let mut ppu = Ppu::new();
+    ppu.write(0x2000, 0x03);
+    ppu.write(0x2005, 0b0111_1101);
+    ppu.write(0x2005, 0b0101_1110);
+    // Fine Y 110, name table 11, coarse Y 01011, coarse X 01111
+    assert_eq!(ppu.core.reg.temp_addr, 0b0110_1101_0110_1111);
+    assert_eq!(ppu.core.reg.fine_x, 0b101);
+
+    ppu.write(0x2006, 0x3D);
+    ppu.write(0x2006, 0xF0);
+    assert_eq!(ppu.core.reg.vram_addr, 0x3DF0);
+  }
+
+  #[test]
+  fn increment_y_wraps_name_tables() {
+    let mut reg = Registers {
+      vram_addr: 0x7000 | (29 << 5),
+      ..Registers::default()
+    };
+    reg.increment_y();
+    assert_eq!(reg.vram_addr, 0x0800);
+
+    reg.vram_addr = 0x7000 | (31 << 5);
+    reg.increment_y();
+    assert_eq!(reg.vram_addr, 0x0000);
+  }
+
+  #[test]
+  fn next_tile_wraps_name_tables() {
+    assert_eq!(next_tile(0x0001), 0x0002);
+    assert_eq!(next_tile(0x001F), 0x0400);
+    assert_eq!(next_tile(0x041F), 0x0000);
+  }
+
+  #[test]
+  fn latch_decays() {
+    let mut ppu = Ppu::new();
+    ppu.write(0x2000, 0xFF);
+    ppu.run(DECAY_CYCLES - 1);
+    assert_eq!(ppu.read(0x2000), 0xFF);
+    ppu.run(1);
+    assert_eq!(ppu.read(0x2000), 0x00);
+  }
+
+  #[test]
+  fn vblank_flag_timing() {
+    let mut ppu = Ppu::new();
+    // Run from the start of the prerender scanline to scanline 241, cycle 1
+    ppu.run(341 + 241 * 341);
+    assert_eq!(ppu.core.reg.sr & SR_VBLANK, 0);
+    ppu.run(1);
+    assert_eq!(ppu.core.reg.sr & SR_VBLANK, SR_VBLANK);
+
+    // Cleared on the second cycle of the prerender scanline
+    ppu.run(20 * 341 - 1);
+    assert_eq!(ppu.core.reg.sr & SR_VBLANK, SR_VBLANK);
+    ppu.run(1);
+    assert_eq!(ppu.core.reg.sr & SR_VBLANK, 0);
+  }
+
+  #[test]
+  fn nmi_at_vblank() {
+    let mut ppu = Ppu::new();
+    ppu.write(0x2000, CR1_NMI);
+    ppu.run_to(241, 0);
+    assert!(!ppu.core.take_nmi());
+    ppu.run(1);
+    assert!(ppu.core.take_nmi());
+    assert!(!ppu.core.take_nmi());
+  }
+
+  #[test]
+  fn nmi_when_enabled_during_vblank() {
+    let mut ppu = Ppu::new();
+    ppu.run_to(250, 0);
+    assert!(!ppu.core.take_nmi());
+
+    ppu.write(0x2000, CR1_NMI);
+    assert!(ppu.core.take_nmi());
+    // Only on the transition of the enable bit
+    ppu.write(0x2000, CR1_NMI);
+    assert!(!ppu.core.take_nmi());
+  }
+
+  #[test]
+  fn odd_frames_are_shorter_when_rendering() {
+    let mut ppu = Ppu::new();
+    ppu.write(0x2001, CR2_BACKGROUND);
+    ppu.run_to(0, 0);
+
+    let start = ppu.core.total_cycles;
+    ppu.run_to(261, 0);
+    ppu.run_to(0, 0);
+    let first = ppu.core.total_cycles - start;
+    ppu.run_to(261, 0);
+    ppu.run_to(0, 0);
+    let second = ppu.core.total_cycles - start - first;
+
+    assert_eq!(first.min(second), CYCLES_PER_FRAME - 1);
+    assert_eq!(first.max(second), CYCLES_PER_FRAME);
+  }
+
+  #[test]
+  fn renders_background_and_sprites() {
+    let (mut ppu, frames) = Ppu::with_frames();
+    ppu.load_scene();
+    ppu.run(CYCLES_PER_FRAME * 2);
+
+    let frames = frames.lock().unwrap();
+    let frame = &frames[1];
+    let pixel = |x: usize, y: usize| frame[y * 256 + x];
+
+    // Scrolled 13 pixels right, so the first pixel is tile 1 in column 1
+    assert_eq!(pixel(0, 0), 0x01);
+    // Tile 2 in column 2 has colour 3 in its left half, and uses the
+    // palette for the top right of the attribute's area
+    assert_eq!(pixel(3, 0), 0x07);
+    assert_eq!(pixel(7, 0), 0x0F);
+    // Sprite 0 at (83, 41) uses sprite palette 1
+    assert_eq!(pixel(83, 41), 0x17);
+    assert_eq!(pixel(87, 41), pixel(87, 40));
+    // Sprite 1 is behind the background, so only shows through colour 0
+    assert_eq!(pixel(15, 51), 0x11);
+    assert_ne!(pixel(11, 51), 0x11);
+  }
+
+  #[test]
+  fn render_modes_match() {
+    let (mut accurate, accurate_frames) = Ppu::with_frames();
+    let (mut scanline, scanline_frames) = Ppu::with_frames();
+    scanline.core.set_render_mode(RenderMode::Scanline);
+    accurate.load_scene();
+    scanline.load_scene();
+
+    accurate.run(CYCLES_PER_FRAME * 2);
+    scanline.run(CYCLES_PER_FRAME * 2);
+
+    let accurate_frames = accurate_frames.lock().unwrap();
+    let scanline_frames = scanline_frames.lock().unwrap();
+    assert_eq!(accurate_frames.len(), 2);
+    assert!(accurate_frames[1] == scanline_frames[1]);
+  }
+
+  #[test]
+  fn render_modes_match_with_chr_latches() {
+    // An MMC4 cartridge, whose 4KB CHR bank 0 has tile 1 in colour 1 and
+    // bank 1 has it in colour 2
+    let mut rom = vec![0x4E, 0x45, 0x53, 0x1A, 0x02, 0x02, 0xA0, 0x00];
+    rom.resize(16 + 0x8000, 0);
+    let mut chr = vec![0; 0x4000];
+    chr[0x0010..0x0018].copy_from_slice(&[0xFF; 8]);
+    chr[0x1018..0x1020].copy_from_slice(&[0xFF; 8]);
+    rom.extend(chr);
+
+    let mut frames = vec![];
+    for mode in [RenderMode::Accurate, RenderMode::Scanline].iter() {
+      let (mut ppu, output) = Ppu::with_frames();
+      ppu.cartridge = parse_rom_file(&rom).unwrap();
+      ppu.cartridge.mapper.write_addr(0xB000, 0);
+      ppu.cartridge.mapper.write_addr(0xC000, 1);
+      ppu.core.set_render_mode(*mode);
+
+      // Tile $FD switches to bank 0 and $FE to bank 1 part way along
+      let row: Vec<u8> = (0..32)
+        .map(|x| match x {
+          0 => 0xFD,
+          16 => 0xFE,
+          _ => 0x01,
+        })
+        .collect();
+      for y in 0..30 {
+        ppu.write_memory(0x2000 + y * 32, &row);
+      }
+      ppu.write_memory(0x3F00, &[0x0F, 0x01, 0x02, 0x03]);
+      ppu.write(0x2000, 0x00);
+      ppu.write(0x2005, 0);
+      ppu.write(0x2005, 0);
+      ppu.write(0x2001, CR2_BACKGROUND | CR2_BACKGROUND_LEFT);
+      ppu.run(CYCLES_PER_FRAME * 2);
+
+      let output = output.lock().unwrap();
+      frames.push(output[1].clone());
+    }
+
+    let pixel = |x: usize, y: usize| frames[0][y * 256 + x];
+    assert_eq!(pixel(40, 10), 0x01);
+    assert_eq!(pixel(200, 10), 0x02);
+    assert!(frames[0] == frames[1]);
+  }
+
+  #[test]
+  fn sprite_0_hit_on_same_dot() {
+    for mode in [RenderMode::Accurate, RenderMode::Scanline].iter() {
+      let mut ppu = Ppu::new();
+      ppu.core.set_render_mode(*mode);
+      ppu.load_scene();
+      ppu.run_to(0, 0);
+
+      // Sprite 0 is at (83, 41) and its first opaque pixel is over tile 1
+      ppu.run_to(41, 83);
+      assert_eq!(ppu.core.reg.sr & SR_SPRITE_0_HIT, 0, "{:?}", mode);
+      ppu.run(1);
+      assert_eq!(
+        ppu.core.reg.sr & SR_SPRITE_0_HIT,
+        SR_SPRITE_0_HIT,
+        "{:?}",
+        mode
+      );
+    }
+  }
+
+  #[test]
+  fn a12_rises_once_a_scanline() {
+    for mode in [RenderMode::Accurate, RenderMode::Scanline].iter() {
+      let (cartridge, rises) = a12_counting_cartridge();
+      let mut ppu = Ppu::new();
+      ppu.cartridge = cartridge;
+      ppu.core.set_render_mode(*mode);
+      ppu.load_scene();
+      ppu.write(0x2000, CR1_SPRITE_TABLE);
+      ppu.run_to(0, 0);
+
+      rises.set(0);
+      ppu.run(1);
+      ppu.run_to(0, 0);
+      // The visible scanlines and the prerender scanline
+      assert_eq!(rises.get(), 241, "{:?}", mode);
+    }
+  }
+}
@@ -0,0 +1,92 @@
+//! # I/O Data Latch
+//!
+//! The PPU has an 8 bit latch on its data bus to the CPU, which holds the
+//! value of the last register read or write. Reading a write only register
+//! returns the latch (the PPU's "open bus"), as do the unused bits of
+//! some readable registers.
+//!
+//! The latch is dynamic memory, so each bit decays to zero if it isn't
+//! refreshed for roughly 600 milliseconds. [Read more here][Bus].
+//!
+//! [Bus]: https://wiki.nesdev.com/w/index.php/Open_bus_behavior#PPU_open_bus
+
+use clock::{MASTER_FREQUENCY, PPU_PERIOD};
+
+/// Number of PPU cycles (about 600ms) before a bit of the latch decays
+pub const DECAY_CYCLES: u64 = (MASTER_FREQUENCY / PPU_PERIOD as u32) as u64 * 600 / 1000;
+
+#[derive(Default)]
+pub struct Latch {
+  value: u8,
+  /// PPU cycle on which each bit was last refreshed
+  refreshed_at: [u64; 8],
+}
+
+impl Latch {
+  /// Refresh the bits of the latch selected by the mask with the bits of
+  /// a value, on the given PPU cycle.
+  pub fn refresh(&mut self, value: u8, mask: u8, cycle: u64) {
+    self.value = (self.value & !mask) | (value & mask);
+    for bit in 0..8 {
+      if mask & (1 << bit) != 0 {
+        self.refreshed_at[bit] = cycle;
+      }
+    }
+  }
+
+  /// Read the latch on the given PPU cycle, after letting any bits which
+  /// haven't been refreshed in time decay.
+  pub fn read(&mut self, cycle: u64) -> u8 {
+    for bit in 0..8 {
+      if cycle.saturating_sub(self.refreshed_at[bit]) >= DECAY_CYCLES {
+        self.value &= !(1 << bit);
+      }
+    }
+    self.value
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use test_rom;
+
+  #[test]
+  fn decay_is_about_600ms() {
+    assert_eq!(DECAY_CYCLES, 3_221_590);
+  }
+
+  #[test]
+  fn read_refreshed_value() {
+    let mut latch = Latch::default();
+    latch.refresh(0xA5, 0xFF, 10);
+    assert_eq!(latch.read(10), 0xA5);
+    assert_eq!(latch.read(10 + DECAY_CYCLES - 1), 0xA5);
+  }
+
+  #[test]
+  fn refresh_masked_bits() {
+    let mut latch = Latch::default();
+    latch.refresh(0xFF, 0xFF, 0);
+    latch.refresh(0x00, 0xE0, 0);
+    assert_eq!(latch.read(0), 0x1F);
+  }
+
+  #[test]
+  fn bits_decay() {
+    let mut latch = Latch::default();
+    latch.refresh(0xFF, 0xFF, 0);
+    latch.refresh(0xFF, 0xE0, DECAY_CYCLES / 2);
+
+    // Only the bits refreshed recently remain
+    assert_eq!(latch.read(DECAY_CYCLES), 0xE0);
+    assert_eq!(latch.read(DECAY_CYCLES / 2 + DECAY_CYCLES), 0x00);
+  }
+
+  /// blargg's ppu_open_bus, from `roms/ppu_open_bus/`
+  #[test]
+  #[ignore]
+  fn ppu_open_bus_rom() {
+    test_rom::run_file("ppu_open_bus/ppu_open_bus.nes").unwrap();
+  }
+}
@@ -1,5 +1,8 @@
 pub mod core;
+pub mod latch;
 pub mod palette;
+pub mod pattern;
+pub mod sprite;
 pub mod vram;
 
-pub use ppu::core::Core;
+pub use ppu::core::{Core, RenderMode};
@@ -5,15 +5,19 @@
 //! http://nesdev.com/NESDoc.pdf (page 45)
 //!
 //! Maps a colour palette entry to an RGB value.
+//!
+//! The PPU outputs 6 bit palette indices along with 3 colour emphasis
+//! bits (bits 5-7 of $2001). Emphasis darkens the colour components that
+//! are not emphasised, which is approximated here by scaling them down.
 
-#[derive(Clone, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Color(pub u8, pub u8, pub u8);
 
 static PALETTE: &'static [Color] = &[
   // 0x00
   Color(0x75, 0x75, 0x75),
   Color(0x27, 0x1B, 0x8F),
-  Color(0x00, 0x00, 0x00),
+  Color(0x00, 0x00, 0xAB),
   Color(0x47, 0x00, 0x9F),
   Color(0x8F, 0x00, 0x77),
   Color(0xAB, 0x00, 0x13),
@@ -41,9 +45,88 @@ static PALETTE: &'static [Color] = &[
   Color(0x00, 0x97, 0x00),
   // 1A
   Color(0x00, 0xAB, 0x00),
-  // TODO: 1B - 3F
+  Color(0x00, 0x93, 0x3B),
+  Color(0x00, 0x83, 0x8B),
+  Color(0x00, 0x00, 0x00),
+  Color(0x00, 0x00, 0x00),
+  Color(0x00, 0x00, 0x00),
+  // 20
+  Color(0xFF, 0xFF, 0xFF),
+  Color(0x3F, 0xBF, 0xFF),
+  Color(0x5F, 0x97, 0xFF),
+  Color(0xA7, 0x8B, 0xFD),
+  Color(0xF7, 0x7B, 0xFF),
+  Color(0xFF, 0x77, 0xB7),
+  Color(0xFF, 0x77, 0x63),
+  Color(0xFF, 0x9B, 0x3B),
+  Color(0xF3, 0xBF, 0x3F),
+  Color(0x83, 0xD3, 0x13),
+  // 2A
+  Color(0x4F, 0xDF, 0x4B),
+  Color(0x58, 0xF8, 0x98),
+  Color(0x00, 0xEB, 0xDB),
+  Color(0x00, 0x00, 0x00),
+  Color(0x00, 0x00, 0x00),
+  Color(0x00, 0x00, 0x00),
+  // 30
+  Color(0xFF, 0xFF, 0xFF),
+  Color(0xAB, 0xE7, 0xFF),
+  Color(0xC7, 0xD7, 0xFF),
+  Color(0xD7, 0xCB, 0xFF),
+  Color(0xFF, 0xC7, 0xFF),
+  Color(0xFF, 0xC7, 0xDB),
+  Color(0xFF, 0xBF, 0xB3),
+  Color(0xFF, 0xDB, 0xAB),
+  Color(0xFF, 0xE7, 0xA3),
+  Color(0xE3, 0xFF, 0xA3),
+  // 3A
+  Color(0xAB, 0xF3, 0xBF),
+  Color(0xB3, 0xFF, 0xCF),
+  Color(0x9F, 0xFF, 0xF3),
+  Color(0x00, 0x00, 0x00),
+  Color(0x00, 0x00, 0x00),
+  Color(0x00, 0x00, 0x00),
 ];
 
+/// Number of entries in the palette, i.e. the number of 6 bit indices.
+pub const PALETTE_SIZE: usize = 64;
+
+pub const EMPHASIS_RED: u8 = 0b001;
+pub const EMPHASIS_GREEN: u8 = 0b010;
+pub const EMPHASIS_BLUE: u8 = 0b100;
+
+/// Attenuation applied to the components that are not emphasised
+/// (as a fraction of 256).
+const EMPHASIS_ATTENUATION: u16 = 191;
+
+/// Convert a palette index and the emphasis bits to an RGB colour.
+pub fn to_color(index: u8, emphasis: u8) -> Color {
+  let Color(r, g, b) = PALETTE[usize::from(index) % PALETTE_SIZE].clone();
+  let emphasis = emphasis & 0b111;
+  if emphasis == 0 {
+    return Color(r, g, b);
+  }
+
+  let attenuate = |component: u8, bit: u8| {
+    if emphasis & bit != 0 {
+      component
+    } else {
+      ((u16::from(component) * EMPHASIS_ATTENUATION) >> 8) as u8
+    }
+  };
+
+  Color(
+    attenuate(r, EMPHASIS_RED),
+    attenuate(g, EMPHASIS_GREEN),
+    attenuate(b, EMPHASIS_BLUE),
+  )
+}
+
+/// Find the first palette index with the given colour, if any.
+pub fn index_of(color: &Color) -> Option<u8> {
+  PALETTE.iter().position(|c| c == color).map(|i| i as u8)
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -52,4 +135,30 @@ mod tests {
   fn palette_contains_green() {
     assert!(PALETTE[0x1A] == Color(0x00, 0xAB, 0x00));
   }
+
+  #[test]
+  fn palette_is_complete() {
+    assert_eq!(PALETTE.len(), PALETTE_SIZE);
+  }
+
+  #[test]
+  fn to_color_without_emphasis() {
+    assert_eq!(to_color(0x1A, 0), Color(0x00, 0xAB, 0x00));
+    assert_eq!(to_color(0x40 | 0x1A, 0), Color(0x00, 0xAB, 0x00));
+  }
+
+  #[test]
+  fn to_color_with_emphasis() {
+    assert_eq!(to_color(0x30, EMPHASIS_RED), Color(0xFF, 0xBE, 0xBE));
+    assert_eq!(
+      to_color(0x30, EMPHASIS_GREEN | EMPHASIS_BLUE),
+      Color(0xBE, 0xFF, 0xFF)
+    );
+  }
+
+  #[test]
+  fn index_of_color() {
+    assert_eq!(index_of(&Color(0x00, 0xAB, 0x00)), Some(0x1A));
+    assert_eq!(index_of(&Color(0x01, 0x02, 0x03)), None);
+  }
 }
@@ -0,0 +1,116 @@
+//! # Pattern Tables
+//!
+//! Tiles are 8x8 pixels with 2 bits per pixel, stored as two 8 byte bit
+//! planes. The low bits of row `y` of the tile at `addr` are at `addr + y`
+//! and the high bits are at `addr + y + 8`, with the leftmost pixel in the
+//! most significant bit.
+//!
+//! There are two pattern tables of 256 tiles, at $0000 and $1000 in the
+//! PPU address space, which usually map to CHR-ROM or CHR-RAM on the
+//! cartridge.
+
+use cartridge::Cartridge;
+
+/// Number of tile rows in both pattern tables (512 tiles of 8 rows)
+const NUM_ROWS: usize = 512 * 8;
+
+/// Decode a row of a tile from its two bit planes into 2 bit pixel values,
+/// from left to right.
+pub fn decode_row(lo: u8, hi: u8) -> [u8; 8] {
+  let mut row = [0; 8];
+  for (col, pixel) in row.iter_mut().enumerate() {
+    let bit = 7 - col;
+    *pixel = ((lo >> bit) & 1) | (((hi >> bit) & 1) << 1);
+  }
+  row
+}
+
+/// Cache of decoded tile rows, used by the scanline renderer so that
+/// each row only needs to be fetched and decoded once.
+///
+/// The cache must be invalidated whenever the pattern tables might have
+/// changed, ie, on writes to CHR-RAM or when the mapper switches banks.
+pub struct TileCache {
+  /// Decoded rows along with the generation they were decoded in
+  rows: Vec<(u32, [u8; 8])>,
+
+  /// Rows from older generations are stale
+  generation: u32,
+}
+
+impl Default for TileCache {
+  fn default() -> Self {
+    TileCache {
+      rows: vec![(0, [0; 8]); NUM_ROWS],
+      generation: 1,
+    }
+  }
+}
+
+impl TileCache {
+  /// Get the decoded tile row whose low bit plane is at `addr`
+  pub fn row(&mut self, addr: u16, cartridge: &mut Cartridge) -> [u8; 8] {
+    let index = row_index(addr);
+    let (generation, row) = self.rows[index];
+    if generation == self.generation {
+      return row;
+    }
+
+    let row = decode_row(cartridge.read_chr(addr), cartridge.read_chr(addr + 8));
+    self.rows[index] = (self.generation, row);
+    row
+  }
+
+  /// Mark all the cached rows as stale
+  pub fn invalidate(&mut self) {
+    self.generation = self.generation.wrapping_add(1);
+    if self.generation == 0 {
+      // Don't confuse rows from long ago with fresh ones
+      for row in self.rows.iter_mut() {
+        row.0 = 0;
+      }
+      self.generation = 1;
+    }
+  }
+}
+
+fn row_index(addr: u16) -> usize {
+  usize::from(((addr & 0x1FF0) >> 1) | (addr & 0x0007))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use cartridge::mirroring::Mirroring;
+  use cartridge::test_cartridge;
+
+  #[test]
+  fn decode_row_combines_planes() {
+    assert_eq!(
+      decode_row(0b1010_0001, 0b1100_0001),
+      [3, 2, 1, 0, 0, 0, 0, 3]
+    );
+  }
+
+  #[test]
+  fn row_index_skips_high_planes() {
+    assert_eq!(row_index(0x0000), 0);
+    assert_eq!(row_index(0x0007), 7);
+    assert_eq!(row_index(0x0010), 8);
+    assert_eq!(row_index(0x1FF7), NUM_ROWS - 1);
+  }
+
+  #[test]
+  fn cache_keeps_rows_until_invalidated() {
+    let mut cartridge = test_cartridge(Mirroring::Horizontal);
+    let mut cache = TileCache::default();
+    cartridge.write_chr(0x0012, 0xFF);
+    assert_eq!(cache.row(0x0012, &mut cartridge), [1; 8]);
+
+    cartridge.write_chr(0x001A, 0xFF);
+    assert_eq!(cache.row(0x0012, &mut cartridge), [1; 8]);
+
+    cache.invalidate();
+    assert_eq!(cache.row(0x0012, &mut cartridge), [3; 8]);
+  }
+}
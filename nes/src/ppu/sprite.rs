@@ -0,0 +1,269 @@
+//! # Sprites
+//!
+//! OAM (sprite RAM) holds 64 sprites of 4 bytes each:
+//!
+//! Byte | Contents
+//! -----|---------------------------------------------------------------
+//! 0    | Y position of the top of the sprite, minus 1
+//! 1    | Tile index (for 8x16 sprites, bit 0 selects the pattern table)
+//! 2    | Attributes (see the `ATTR_` constants)
+//! 3    | X position of the left of the sprite
+//!
+//! During each scanline the PPU evaluates which sprites are on the next
+//! scanline (at most 8 of them), then fetches their patterns. [Read more
+//! here][Sprites].
+//!
+//! [Sprites]: https://wiki.nesdev.com/w/index.php/PPU_sprite_evaluation
+
+use ppu::pattern;
+
+pub const ATTR_PALETTE: u8 = 0b0000_0011;
+pub const ATTR_BEHIND_BACKGROUND: u8 = 0b0010_0000;
+pub const ATTR_FLIP_HORIZONTAL: u8 = 0b0100_0000;
+pub const ATTR_FLIP_VERTICAL: u8 = 0b1000_0000;
+
+/// The PPU only has room for 8 sprites on each scanline
+pub const MAX_SPRITES: usize = 8;
+
+/// Flags for pixels in a rendered sprite line (see `render_line`), the
+/// low 4 bits hold the pixel value and palette.
+pub const LINE_BEHIND_BACKGROUND: u8 = ATTR_BEHIND_BACKGROUND;
+pub const LINE_SPRITE_0: u8 = 0b0100_0000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sprite {
+  /// Position of the sprite in OAM
+  pub index: u8,
+  pub y: u8,
+  pub tile: u8,
+  pub attr: u8,
+  pub x: u8,
+}
+
+pub struct Evaluation {
+  pub sprites: Vec<Sprite>,
+
+  /// More than 8 sprites were found on the scanline
+  pub overflow: bool,
+}
+
+/// Find the sprites which are on the scanline after `scanline`.
+///
+/// Unlike the real PPU, this doesn't emulate the buggy search used to set
+/// the overflow flag, so the flag is only set when there really are more
+/// than 8 sprites on the scanline.
+pub fn evaluate(oam: &[u8], scanline: u16, height: u16) -> Evaluation {
+  let mut sprites = Vec::with_capacity(MAX_SPRITES);
+  let mut overflow = false;
+
+  for (index, bytes) in oam.chunks(4).enumerate() {
+    let y = u16::from(bytes[0]);
+    if scanline < y || scanline >= y + height {
+      continue;
+    }
+
+    if sprites.len() == MAX_SPRITES {
+      overflow = true;
+      break;
+    }
+    sprites.push(Sprite {
+      index: index as u8,
+      y: bytes[0],
+      tile: bytes[1],
+      attr: bytes[2],
+      x: bytes[3],
+    });
+  }
+
+  Evaluation { sprites, overflow }
+}
+
+impl Sprite {
+  /// Address of the low bit plane of the row of this sprite shown on the
+  /// scanline after `scanline`. 8x8 sprites use the pattern table at
+  /// `table`, 8x16 sprites select their own.
+  pub fn pattern_addr(&self, scanline: u16, height: u16, table: u16) -> u16 {
+    let mut row = scanline - u16::from(self.y);
+    if self.attr & ATTR_FLIP_VERTICAL != 0 {
+      row = height - 1 - row;
+    }
+
+    if height == 16 {
+      let table = u16::from(self.tile & 0x01) * 0x1000;
+      let tile = u16::from(self.tile & 0xFE) + row / 8;
+      table + tile * 16 + row % 8
+    } else {
+      table + u16::from(self.tile) * 16 + row
+    }
+  }
+}
+
+/// The PPU fetches tile $FF for each empty sprite slot
+pub fn empty_pattern_addr(height: u16, table: u16) -> u16 {
+  if height == 16 {
+    0x1000 + 0xFE * 16
+  } else {
+    table + 0xFF * 16
+  }
+}
+
+/// A row of a sprite's pixels, ready to be rendered
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpriteRow {
+  pub x: u8,
+  pub attr: u8,
+  pub pixels: [u8; 8],
+  pub is_sprite_0: bool,
+}
+
+impl SpriteRow {
+  /// Decode a row of a sprite from its two bit planes
+  pub fn new(sprite: &Sprite, lo: u8, hi: u8) -> Self {
+    let mut pixels = pattern::decode_row(lo, hi);
+    if sprite.attr & ATTR_FLIP_HORIZONTAL != 0 {
+      pixels.reverse();
+    }
+
+    SpriteRow {
+      x: sprite.x,
+      attr: sprite.attr,
+      pixels,
+      is_sprite_0: sprite.index == 0,
+    }
+  }
+}
+
+/// Render sprite rows into a scanline, where each pixel holds the pixel
+/// value in bits 0-1, the palette in bits 2-3 and the `LINE_` flags.
+/// Transparent pixels are 0, and earlier sprites are drawn over later ones.
+pub fn render_line(rows: &[SpriteRow], line: &mut [u8; 256]) {
+  for pixel in line.iter_mut() {
+    *pixel = 0;
+  }
+
+  for row in rows.iter().rev() {
+    let mut flags = ((row.attr & ATTR_PALETTE) << 2) | (row.attr & LINE_BEHIND_BACKGROUND);
+    if row.is_sprite_0 {
+      flags |= LINE_SPRITE_0;
+    }
+
+    for (col, &pixel) in row.pixels.iter().enumerate() {
+      let x = usize::from(row.x) + col;
+      if x < line.len() && pixel != 0 {
+        line[x] = pixel | flags;
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn oam_with(sprites: &[[u8; 4]]) -> [u8; 256] {
+    let mut oam = [0xFF; 256];
+    for (i, sprite) in sprites.iter().enumerate() {
+      oam[i * 4..i * 4 + 4].copy_from_slice(sprite);
+    }
+    oam
+  }
+
+  #[test]
+  fn evaluate_finds_sprites_on_next_line() {
+    let oam = oam_with(&[[10, 1, 0, 0], [20, 2, 0, 0], [3, 3, 0, 0]]);
+
+    let evaluation = evaluate(&oam, 10, 8);
+    assert_eq!(evaluation.sprites.len(), 2);
+    assert_eq!(evaluation.sprites[0].tile, 1);
+    assert_eq!(evaluation.sprites[1].index, 2);
+    assert!(!evaluation.overflow);
+
+    assert_eq!(evaluate(&oam, 18, 8).sprites.len(), 0);
+    assert_eq!(evaluate(&oam, 18, 16).sprites.len(), 2);
+  }
+
+  #[test]
+  fn evaluate_overflow() {
+    let oam = oam_with(&[[0, 0, 0, 0]; 9]);
+
+    let evaluation = evaluate(&oam, 0, 8);
+    assert_eq!(evaluation.sprites.len(), MAX_SPRITES);
+    assert!(evaluation.overflow);
+  }
+
+  #[test]
+  fn pattern_addr_8x8() {
+    let sprite = Sprite {
+      index: 0,
+      y: 10,
+      tile: 0x12,
+      attr: 0,
+      x: 0,
+    };
+    assert_eq!(sprite.pattern_addr(13, 8, 0x1000), 0x1123);
+
+    let flipped = Sprite {
+      attr: ATTR_FLIP_VERTICAL,
+      ..sprite
+    };
+    assert_eq!(flipped.pattern_addr(13, 8, 0x1000), 0x1124);
+  }
+
+  #[test]
+  fn pattern_addr_8x16() {
+    let sprite = Sprite {
+      index: 0,
+      y: 10,
+      tile: 0x13,
+      attr: 0,
+      x: 0,
+    };
+    assert_eq!(sprite.pattern_addr(13, 16, 0x0000), 0x1123);
+    assert_eq!(sprite.pattern_addr(21, 16, 0x0000), 0x1133);
+
+    let flipped = Sprite {
+      attr: ATTR_FLIP_VERTICAL,
+      ..sprite
+    };
+    assert_eq!(flipped.pattern_addr(10, 16, 0x0000), 0x1137);
+  }
+
+  #[test]
+  fn sprite_row_flips_horizontally() {
+    let sprite = Sprite {
+      index: 0,
+      y: 0,
+      tile: 0,
+      attr: ATTR_FLIP_HORIZONTAL,
+      x: 0,
+    };
+    let row = SpriteRow::new(&sprite, 0b1000_0000, 0);
+    assert_eq!(row.pixels, [0, 0, 0, 0, 0, 0, 0, 1]);
+    assert!(row.is_sprite_0);
+  }
+
+  #[test]
+  fn render_line_priority() {
+    let front = SpriteRow {
+      x: 4,
+      attr: 0b01,
+      pixels: [0, 1, 1, 1, 1, 1, 1, 1],
+      is_sprite_0: true,
+    };
+    let back = SpriteRow {
+      x: 0,
+      attr: 0b10 | ATTR_BEHIND_BACKGROUND,
+      pixels: [2; 8],
+      is_sprite_0: false,
+    };
+    let mut line = [0xFF; 256];
+    render_line(&[front, back], &mut line);
+
+    assert_eq!(line[3], 0b1010 | LINE_BEHIND_BACKGROUND);
+    // Transparent pixels of the front sprite show the one behind
+    assert_eq!(line[4], 0b1010 | LINE_BEHIND_BACKGROUND);
+    assert_eq!(line[5], 0b0101 | LINE_SPRITE_0);
+    assert_eq!(line[11], 0b0101 | LINE_SPRITE_0);
+    assert_eq!(line[12], 0);
+  }
+}
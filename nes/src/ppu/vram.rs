@@ -56,11 +56,10 @@ fn wrapped_addr(addr: Addr) -> Addr {
     0x3000...0x3EFF => 0x2000 + ((addr - 0x3000) % 0x0EFF),
 
     // Palette mirroring
-    0x3F20...0x3FFF => 0x3F00 + ((addr - 0x3F20) % 0x0020),
+    0x3F00...0x3FFF => palette_addr(addr),
 
     // Physical memory addressing
     0x0000...0x2FFF => addr,
-    0x3F00...0x3F1F => addr,
 
     // Remaining address space mirroring
     0x4000...0xFFFF => wrapped_addr(addr % 0x4000),
@@ -68,6 +67,18 @@ fn wrapped_addr(addr: Addr) -> Addr {
   }
 }
 
+/// Palettes are mirrored every 32 bytes, and the first entry of each
+/// sprite palette is a mirror of the first entry of the matching
+/// background palette.
+fn palette_addr(addr: Addr) -> Addr {
+  let addr = 0x3F00 + (addr % 0x0020);
+  if addr & 0x0013 == 0x0010 {
+    addr - 0x0010
+  } else {
+    addr
+  }
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -86,6 +97,14 @@ mod tests {
     assert_eq!(wrapped_addr(0x7F82), 0x3F02);
   }
 
+  #[test]
+  fn wrapped_addr_wraps_sprite_backdrops() {
+    assert_eq!(wrapped_addr(0x3F10), 0x3F00);
+    assert_eq!(wrapped_addr(0x3F14), 0x3F04);
+    assert_eq!(wrapped_addr(0x3F3C), 0x3F0C);
+    assert_eq!(wrapped_addr(0x3F11), 0x3F11);
+  }
+
   #[test]
   fn wrapped_addr_wraps_non_physical_address_space() {
     assert_eq!(wrapped_addr(0x4000), 0x0000);
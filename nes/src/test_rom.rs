@@ -0,0 +1,148 @@
+//! Runs test ROMs which report their results the way blargg's do
+//!
+//! While the test runs, $6000 holds $80. Once it's finished, $6000 holds
+//! the result code (0 for a pass) and a message follows as a zero
+//! terminated string from $6004. $81 asks for the reset button to be
+//! pressed in a little while. $6001-$6003 hold $DE $B0 $61 once the
+//! result is valid.
+//!
+//! The ROMs aren't in the repository. Tests using them are `#[ignore]`d,
+//! and fail if the ROM isn't under `roms/`. Run them with
+//! `cargo test -- --ignored`.
+
+use std::fs;
+use std::path::PathBuf;
+
+use apu::processor::ApuImpl;
+use cartridge::parse_rom_file;
+use clock::MASTER_FREQUENCY;
+use console::Console;
+use controller::joypad::Joypad;
+use io::audio::NullAudioOutput;
+use io::video::IndexedVideoOutput;
+
+const STATUS_RUNNING: u8 = 0x80;
+const STATUS_RESET: u8 = 0x81;
+const SIGNATURE: [u8; 3] = [0xDE, 0xB0, 0x61];
+
+/// Master clock cycles in a frame (roughly)
+const FRAME: u32 = MASTER_FREQUENCY / 60;
+
+/// Give up on tests which haven't finished after this many frames
+const TIMEOUT_FRAMES: u32 = 60 * 60;
+
+/// Run a test ROM under `roms/` until it finishes, returning its message
+/// if it failed or an error if it's missing
+pub fn run_file(name: &str) -> Result<(), String> {
+  let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+    .join("../roms")
+    .join(name);
+  if !path.exists() {
+    return Err(format!("{} is missing, it should be under roms/", name));
+  }
+  run(&path)
+}
+
+/// Run a test ROM until it finishes, returning its message if it failed
+fn run(path: &PathBuf) -> Result<(), String> {
+  let data = fs::read(path).map_err(|e| e.to_string())?;
+  run_rom(&data).map_err(|message| format!("{}: {}", path.display(), message))
+}
+
+fn run_rom(data: &[u8]) -> Result<(), String> {
+  let mut cartridge = parse_rom_file(data).map_err(|e| format!("{:?}", e))?;
+  let mut apu = ApuImpl::create(NullAudioOutput, 48_000);
+  let mut console = Console::new(
+    &mut apu,
+    &mut cartridge,
+    None::<&mut Joypad>,
+    None::<&mut Joypad>,
+    IndexedVideoOutput::new(|_, _| {}),
+  );
+  console.set_throttled(false);
+  console.reset();
+
+  let mut reset_in = None;
+  for _ in 0..TIMEOUT_FRAMES {
+    for _ in 0..FRAME {
+      console.tick();
+    }
+
+    let signature = [
+      console.peek(0x6001),
+      console.peek(0x6002),
+      console.peek(0x6003),
+    ];
+    if signature != SIGNATURE {
+      continue;
+    }
+    match console.peek(0x6000) {
+      STATUS_RUNNING => (),
+      // Hold the reset button for a few frames
+      STATUS_RESET => match reset_in {
+        None => reset_in = Some(6),
+        Some(0) => {
+          reset_in = None;
+          console.reset();
+        }
+        Some(frames) => reset_in = Some(frames - 1),
+      },
+      0 => return Ok(()),
+      code => {
+        let message: Vec<u8> = (0x6004..0x7000)
+          .map(|addr| console.peek(addr))
+          .take_while(|&byte| byte != 0)
+          .collect();
+        return Err(format!(
+          "failed with code {}: {}",
+          code,
+          String::from_utf8_lossy(&message).trim()
+        ));
+      }
+    }
+  }
+  Err("timed out".to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// An MMC1 ROM which writes the signature and a message, then finishes
+  /// with a result code
+  fn rom(code: u8) -> Vec<u8> {
+    let mut bank = vec![0; 16 * 1024];
+    #[rustfmt::skip]
+    let program = [
+      0xA2, 0x00,       // LDX #0
+      0xBD, 0x20, 0xC0, // LDA message,X
+      0x9D, 0x01, 0x60, // STA $6001,X
+      0xE8,             // INX
+      0xE0, 0x08,       // CPX #8
+      0xD0, 0xF5,       // BNE
+      0xA9, code,       // LDA #code
+      0x8D, 0x00, 0x60, // STA $6000
+      0x4C, 0x12, 0xC0, // JMP *
+    ];
+    bank[..program.len()].copy_from_slice(&program);
+    bank[0x20..0x28].copy_from_slice(&[0xDE, 0xB0, 0x61, b'F', b'a', b'i', b'l', 0]);
+    bank[0x3FFA..].copy_from_slice(&[0x12, 0xC0, 0x00, 0xC0, 0x12, 0xC0]);
+
+    let mut data = vec![0x4E, 0x45, 0x53, 0x1A, 0x02, 0x01, 0x10, 0x00];
+    data.resize(16, 0);
+    data.extend_from_slice(&bank);
+    data.extend_from_slice(&bank);
+    data.extend_from_slice(&[0; 8 * 1024]);
+    data
+  }
+
+  #[test]
+  fn reports_pass() {
+    assert_eq!(run_rom(&rom(0)), Ok(()));
+  }
+
+  #[test]
+  fn reports_failure_message() {
+    assert_eq!(run_rom(&rom(3)), Err("failed with code 3: Fail".to_owned()));
+  }
+}